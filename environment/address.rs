@@ -137,11 +137,13 @@ impl fmt::Display for VAddr {
 	}
 }
 
+// The functions return -1 when they hit a fault which can't be resolved, and
+// -2 when the page couldn't be allocated.
 extern "C" {
-	fn copy_from_user(dst: *mut u8, src: *const u8, len: usize);
-	fn strncpy_from_user(dst: *mut u8, src: *const u8, max_len: usize) -> usize;
-	fn copy_to_user(dst: *mut u8, src: *const u8, len: usize);
-	fn memset_user(dst: *mut u8, value: u8, len: usize);
+	fn copy_from_user(dst: *mut u8, src: *const u8, len: usize) -> isize;
+	fn strncpy_from_user(dst: *mut u8, src: *const u8, max_len: usize) -> isize;
+	fn copy_to_user(dst: *mut u8, src: *const u8, len: usize) -> isize;
+	fn memset_user(dst: *mut u8, value: u8, len: usize) -> isize;
 }
 
 fn call_usercopy_hook() {
@@ -149,8 +151,22 @@ fn call_usercopy_hook() {
 	system().usercopy_hook();
 }
 
+/// Converts the return value of a usercopy function.
+fn usercopy_result(ret: isize) -> Result<usize, AccessError> {
+	match ret {
+		-2 => Err(AccessError::OutOfMemory),
+		_ if ret < 0 => Err(AccessError::Fault),
+		_ => Ok(ret as usize),
+	}
+}
+
 #[derive(Debug)]
-pub struct AccessError;
+pub enum AccessError {
+	/// The address isn't mapped or the access isn't allowed.
+	Fault,
+	/// The page backing the address couldn't be allocated.
+	OutOfMemory,
+}
 
 #[derive(Debug)]
 pub struct NullUserPointerError;
@@ -214,9 +230,9 @@ impl UserVAddr {
 	pub fn access_ok(self, len: usize) -> Result<(), AccessError> {
 		match self.value().checked_add(len) {
 			Some(end) if end <= KERNEL_BASE_ADDR => Ok(()),
-			Some(_end) => Err(AccessError),
+			Some(_end) => Err(AccessError::Fault),
 			// Overflow.
-			None => Err(AccessError),
+			None => Err(AccessError::Fault),
 		}
 	}
 
@@ -231,9 +247,9 @@ impl UserVAddr {
 	pub fn read_bytes(self, buf: &mut [u8]) -> Result<(), AccessError> {
 		call_usercopy_hook();
 		self.access_ok(buf.len())?;
-		unsafe {
-			copy_from_user(buf.as_mut_ptr(), self.value() as *const u8, buf.len());
-		}
+		let ret = unsafe { copy_from_user(buf.as_mut_ptr(), self.value() as *const u8, buf.len()) };
+		usercopy_result(ret)?;
+
 		Ok(())
 	}
 
@@ -246,7 +262,7 @@ impl UserVAddr {
 		self.access_ok(buf.len())?;
 		let read_len =
 			unsafe { strncpy_from_user(buf.as_mut_ptr(), self.value() as *const u8, buf.len()) };
-		Ok(read_len)
+		usercopy_result(read_len)
 	}
 
 	pub fn write<T>(self, buf: &T) -> Result<usize, AccessError> {
@@ -258,18 +274,18 @@ impl UserVAddr {
 	pub fn write_bytes(self, buf: &[u8]) -> Result<usize, AccessError> {
 		call_usercopy_hook();
 		self.access_ok(buf.len())?;
-		unsafe {
-			copy_to_user(self.value() as *mut u8, buf.as_ptr(), buf.len());
-		}
+		let ret = unsafe { copy_to_user(self.value() as *mut u8, buf.as_ptr(), buf.len()) };
+		usercopy_result(ret)?;
+
 		Ok(buf.len())
 	}
 
 	pub fn fill(self, value: u8, len: usize) -> Result<usize, AccessError> {
 		call_usercopy_hook();
 		self.access_ok(len)?;
-		unsafe {
-			memset_user(self.value() as *mut u8, value, len);
-		}
+		let ret = unsafe { memset_user(self.value() as *mut u8, value, len) };
+		usercopy_result(ret)?;
+
 		Ok(len)
 	}
 }
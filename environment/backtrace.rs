@@ -75,25 +75,34 @@ fn resolve_symbol(vaddr: VAddr) -> Option<Symbol> {
 	}
 }
 
+fn print_frame(index: usize, vaddr: VAddr) {
+	if let Some(symbol) = resolve_symbol(vaddr) {
+		warn!(
+			"    {index}: {vaddr} {symbol_name}()+0x{offset:x}",
+			index = index,
+			vaddr = vaddr,
+			symbol_name = symbol.name,
+			offset = vaddr.value() - symbol.addr.value(),
+		);
+	} else {
+		warn!(
+			"    {index}: {vaddr} (symbol unknown)",
+			index = index,
+			vaddr = vaddr,
+		);
+	}
+}
+
 /// Prints a backtrace.
 pub fn backtrace() {
-	Backtrace::current_frame().traverse(|i, vaddr| {
-		if let Some(symbol) = resolve_symbol(vaddr) {
-			warn!(
-				"    {index}: {vaddr} {symbol_name}()+0x{offset:x}",
-				index = i,
-				vaddr = vaddr,
-				symbol_name = symbol.name,
-				offset = vaddr.value() - symbol.addr.value(),
-			);
-		} else {
-			warn!(
-				"    {index}: {vaddr} (symbol unknown)",
-				index = i,
-				vaddr = vaddr,
-			);
-		}
-	});
+	Backtrace::current_frame().traverse(print_frame);
+}
+
+/// Prints the backtrace of an interrupted context: `ip` followed by the
+/// callers found from its frame pointer `bp`.
+pub fn backtrace_from(ip: VAddr, bp: usize) {
+	print_frame(0, ip);
+	Backtrace::from_frame(bp).traverse(|i, vaddr| print_frame(i + 1, vaddr));
 }
 
 pub struct CapturedBacktraceFrame {
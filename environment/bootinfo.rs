@@ -28,4 +28,6 @@ pub struct BootInfo {
 	pub framebuffer: Framebuffer,
 	pub pci_enabled: bool,
 	pub use_second_serialport: bool,
+	/// Whether to randomize the layout of user processes.
+	pub aslr_enabled: bool,
 }
@@ -1,5 +1,7 @@
 use core::{
-	alloc::Layout,
+	alloc::{GlobalAlloc, Layout},
+	cmp::max,
+	ptr::{self, NonNull},
 	sync::atomic::{AtomicBool, Ordering},
 };
 
@@ -9,14 +11,63 @@ use utils::alignment::align_up;
 use crate::{
 	arch::PAGE_SIZE,
 	page_allocator::{alloc_pages, AllocPageFlags},
+	slab, system,
 };
 
 const ORDER: usize = 32;
-const KERNEL_HEAP_CHUNK_SIZE: usize = 1024 * 1024 * 64; // 1MiB
+const KERNEL_HEAP_CHUNK_SIZE: usize = 1024 * 1024 * 64; // 64MiB
+/// The smallest chunk the heap grows by when memory is too scarce or too
+/// fragmented for a whole `KERNEL_HEAP_CHUNK_SIZE`.
+const KERNEL_HEAP_MIN_CHUNK_SIZE: usize = 1024 * 256; // 256KiB
+
+/// Serves small objects from the slab caches and the rest from the heap.
+struct KernelAllocator;
 
 #[global_allocator]
-static ALLOCATOR: LockedHeapWithRescue<ORDER> = LockedHeapWithRescue::new(expand_kernel_heap);
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+static HEAP: LockedHeapWithRescue<ORDER> = LockedHeapWithRescue::new(expand_kernel_heap);
 static KERNEL_HEAP_ENABLED: AtomicBool = AtomicBool::new(false);
+/// Set while the kernel frees memory for an allocation which failed.
+static IN_OUT_OF_MEMORY: AtomicBool = AtomicBool::new(false);
+
+impl KernelAllocator {
+	unsafe fn try_alloc(&self, layout: &Layout) -> *mut u8 {
+		if !slab::is_slab_layout(layout) {
+			return HEAP.alloc(layout);
+		}
+
+		match slab::cache_for(layout).alloc() {
+			Some(ptr) => {
+				KERNEL_HEAP_ENABLED.store(true, Ordering::Release);
+				ptr.as_ptr()
+			}
+			None => ptr::null_mut(),
+		}
+	}
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let ptr = self.try_alloc(&layout);
+		if !ptr.is_null() || IN_OUT_OF_MEMORY.swap(true, Ordering::AcqRel) {
+			return ptr;
+		}
+
+		// Let the kernel free its caches and try again. The heap isn't locked
+		// anymore, but the kernel might allocate from there: don't recurse.
+		system().on_out_of_memory();
+		IN_OUT_OF_MEMORY.store(false, Ordering::Release);
+		self.try_alloc(&layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		if slab::is_slab_layout(&layout) {
+			slab::free(NonNull::new_unchecked(ptr));
+		} else {
+			HEAP.dealloc(ptr, layout);
+		}
+	}
+}
 
 pub fn is_kernel_heap_enabled() -> bool {
 	KERNEL_HEAP_ENABLED.load(Ordering::Acquire)
@@ -30,13 +81,29 @@ fn expand_kernel_heap(heap: &mut Heap<ORDER>, layout: &Layout) {
 		);
 	}
 
-	let num_pages = align_up(KERNEL_HEAP_CHUNK_SIZE, PAGE_SIZE) / PAGE_SIZE;
-	let start = alloc_pages(num_pages, AllocPageFlags::KERNEL)
-		.expect("run out of memory: failed to expand the kernel heap")
-		.as_vaddr()
-		.value();
-	let end = start + KERNEL_HEAP_CHUNK_SIZE;
+	// Halve the chunk until it fits in the free memory, giving the empty
+	// slabs back before the last tries. If nothing's left, the allocation
+	// fails, and `KernelAllocator` asks the kernel to free some memory once
+	// the heap is unlocked. Don't log anything here: we hold the heap lock.
+	let min_chunk_size = max(
+		KERNEL_HEAP_MIN_CHUNK_SIZE,
+		(layout.size() + layout.align()).next_power_of_two(),
+	);
+	let mut chunk_size = KERNEL_HEAP_CHUNK_SIZE;
+	let start = loop {
+		let num_pages = align_up(chunk_size, PAGE_SIZE) / PAGE_SIZE;
+		if let Ok(paddr) = alloc_pages(num_pages, AllocPageFlags::KERNEL) {
+			break paddr.as_vaddr().value();
+		}
+
+		if chunk_size > min_chunk_size {
+			chunk_size = max(chunk_size / 2, min_chunk_size);
+		} else if slab::shrink_all() == 0 {
+			return;
+		}
+	};
 
+	let end = start + chunk_size;
 	unsafe {
 		heap.add_to_heap(start, end);
 	}
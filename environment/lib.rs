@@ -6,7 +6,7 @@ extern crate alloc;
 
 #[macro_use]
 extern crate log;
-use address::UserVAddr;
+use address::{AccessError, UserVAddr};
 use ps2_mouse::MouseState;
 use utils::static_cell::StaticCell;
 
@@ -19,12 +19,23 @@ pub trait System: Sync {
   fn on_mouse_event(&self, mouse_state: MouseState);
   fn on_irq(&self, irq: u8);
   fn on_timer_irq(&self);
+  /// Called at the end of an IRQ which interrupted the user mode, right
+  /// before returning to it.
+  fn on_irq_return_to_user(&self);
+  /// Called on a page fault in the user mode or in usercopy. A fault in
+  /// usercopy which can't be resolved returns an error, and the copy fails
+  /// with it.
   fn on_page_fault(
     &self,
     unaligned_vaddr: Option<UserVAddr>,
-    ip: usize,
-    _reason: arch::PageFaultReason,
-  );
+    reason: arch::PageFaultReason,
+    frame: &arch::InterruptFrame,
+  ) -> Result<(), AccessError>;
+  /// Called on a general protection fault in the user mode.
+  fn on_general_protection_fault(&self, frame: &arch::InterruptFrame);
+  /// Called when the kernel heap can't grow anymore. The allocation is
+  /// retried once the kernel has freed what it can spare.
+  fn on_out_of_memory(&self);
 
   #[allow(clippy::too_many_arguments)]
   fn on_syscall(
@@ -56,14 +67,21 @@ impl System for NopSystem {
 
   fn on_timer_irq(&self) {}
 
+  fn on_irq_return_to_user(&self) {}
+
   fn on_page_fault(
     &self,
     _unaligned_vaddr: Option<UserVAddr>,
-    _ip: usize,
     _reason: arch::PageFaultReason,
-  ) {
+    _frame: &arch::InterruptFrame,
+  ) -> Result<(), AccessError> {
+    Ok(())
   }
 
+  fn on_general_protection_fault(&self, _frame: &arch::InterruptFrame) {}
+
+  fn on_out_of_memory(&self) {}
+
   fn on_syscall(
     &self,
     _a1: usize,
@@ -97,14 +115,15 @@ pub mod arch {
     backtrace::Backtrace,
     cpu_local::cpu_local_head,
     idle::{halt, idle},
-    interrupt::SavedInterruptStatus,
+    interrupt::{InterruptFrame, SavedInterruptStatus},
     ioapic::enable_irq,
-    paging::PageTable,
+    paging::{map_straight, remap_straight_page, unmap_straight_page, PageProt, PageTable},
     profile::read_clock_counter,
     serial::SERIAL0,
     syscall::PtRegs,
     tss::TSS,
-    PageFaultReason, KERNEL_BASE_ADDR, KERNEL_STRAIGHT_MAP_PADDR_END, PAGE_SIZE, TICK_HZ,
+    PageFaultReason, BOOT_STRAIGHT_MAP_PADDR_END, KERNEL_BASE_ADDR, KERNEL_STRAIGHT_MAP_PADDR_END,
+    PAGE_SIZE, TICK_HZ,
   };
 
   pub mod x64 {
@@ -119,4 +138,5 @@ pub mod global_allocator;
 pub mod logger;
 pub mod page_allocator;
 pub mod profile;
+pub mod slab;
 pub mod spinlock;
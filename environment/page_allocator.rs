@@ -1,64 +1,131 @@
-use core::{
-	ops::Deref,
-	sync::atomic::{AtomicUsize, Ordering},
-};
+use core::ops::Deref;
 
 use crate::{
-	address::PAddr, arch::PAGE_SIZE, bootinfo::RamArea, spinlock::SpinLock,
+	address::PAddr,
+	arch::{
+		map_straight, BOOT_STRAIGHT_MAP_PADDR_END, KERNEL_STRAIGHT_MAP_PADDR_END,
+		PAGE_SIZE,
+	},
+	bootinfo::RamArea,
+	spinlock::SpinLock,
 };
 use arrayvec::ArrayVec;
 use bitflags::bitflags;
-use utils::{alignment::is_aligned, byte_size::ByteSize};
+use utils::{
+	alignment::{align_down, is_aligned},
+	buddy_allocator::{BuddyAllocator as Allocator, MAX_ORDER},
+	byte_size::ByteSize,
+};
 
-use utils::bitmap_allocator::BitMapAllocator as Allocator;
+/// Where the DMA32 zone ends.
+const DMA32_END: usize = 0x1_0000_0000;
+/// The pages user allocations leave free so that the kernel can still grow
+/// its heap and allocate page tables when processes eat up the memory.
+const KERNEL_RESERVED_PAGES: usize = 2048;
 
-// TODO: Fix bugs in use the buddy allocator.
-// use kerla_utils::buddy_allocator::BuddyAllocator as Allocator;
+static ZONES: SpinLock<ArrayVec<Zone, 16>> =
+	SpinLock::new_untraced(ArrayVec::new_const());
 
-// Comment out the following line to use BumpAllocator.
-// use kerla_utils::bump_allocator::BumpAllocator as Allocator;
+fn num_pages_to_order(num_pages: usize) -> usize {
+	num_pages.next_power_of_two().trailing_zeros() as usize
+}
 
-static ZONES: SpinLock<ArrayVec<Allocator, 8>> =
-	SpinLock::new(ArrayVec::new_const());
-static NUM_FREE_PAGES: AtomicUsize = AtomicUsize::new(0);
-static NUM_TOTAL_PAGES: AtomicUsize = AtomicUsize::new(0);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneKind {
+	/// Memory below 4 GiB, which devices limited to 32-bit DMA can reach.
+	Dma32,
+	/// Memory above 4 GiB.
+	Normal,
+}
 
-fn num_pages_to_order(num_pages: usize) -> usize {
-	// TODO: Use log2 instead
-	for order in 0..16 {
-		if num_pages > 1 << order {
-			continue;
-		}
+/// A RAM area, or the part of one, which belongs to a single zone.
+struct Zone {
+	kind: ZoneKind,
+	allocator: Allocator,
+}
 
-		return order;
-	}
+#[derive(Debug, Clone, Copy)]
+pub struct ZoneStats {
+	pub kind: ZoneKind,
+	pub num_free_pages: usize,
+	pub num_total_pages: usize,
+	/// The number of free blocks of each order, which tells how fragmented
+	/// the zone is.
+	pub num_free_blocks: [usize; MAX_ORDER + 1],
+}
 
-	unreachable!();
+impl ZoneStats {
+	fn new(kind: ZoneKind) -> ZoneStats {
+		ZoneStats {
+			kind,
+			num_free_pages: 0,
+			num_total_pages: 0,
+			num_free_blocks: [0; MAX_ORDER + 1],
+		}
+	}
 }
 
 #[derive(Debug)]
 pub struct Stats {
 	pub num_free_pages: usize,
 	pub num_total_pages: usize,
+	pub zones: [ZoneStats; 2],
 }
 
 pub fn read_allocator_stats() -> Stats {
+	let mut zone_stats = [
+		ZoneStats::new(ZoneKind::Dma32),
+		ZoneStats::new(ZoneKind::Normal),
+	];
+	for zone in ZONES.lock().iter() {
+		let stats = zone_stats
+			.iter_mut()
+			.find(|stats| stats.kind == zone.kind)
+			.unwrap();
+
+		stats.num_free_pages += zone.allocator.num_free_pages();
+		stats.num_total_pages += zone.allocator.num_total_pages();
+		for (total, num_blocks) in stats
+			.num_free_blocks
+			.iter_mut()
+			.zip(zone.allocator.num_free_blocks())
+		{
+			*total += num_blocks;
+		}
+	}
+
 	Stats {
-		num_free_pages: NUM_FREE_PAGES.load(Ordering::SeqCst),
-		num_total_pages: NUM_TOTAL_PAGES.load(Ordering::SeqCst),
+		num_free_pages: zone_stats.iter().map(|stats| stats.num_free_pages).sum(),
+		num_total_pages: zone_stats.iter().map(|stats| stats.num_total_pages).sum(),
+		zones: zone_stats,
 	}
 }
 
 bitflags! {
 	pub struct AllocPageFlags: u32 {
-		// TODO: Currently both of them are unused in the allocator.
-
-		/// Allocate pages for the kernel purpose.
-		const KERNEL = 0;
-		/// Allocate pages for the user.
-		const USER = 0;
 		/// If it's not set, allocated pages will be filled with zeroes.
 		const DIRTY_OK = 1 << 0;
+		/// Allocate pages for the kernel purpose. They are often handed to
+		/// devices, so they come from the DMA32 zone unless it's exhausted.
+		const KERNEL = 1 << 1;
+		/// Allocate pages for the user. They come from the normal zone
+		/// unless it's exhausted, leaving the DMA32 zone to the kernel.
+		const USER = 1 << 2;
+		/// Allocate pages below 4 GiB, for devices which can't address more.
+		const DMA32 = 1 << 3;
+	}
+}
+
+impl AllocPageFlags {
+	/// The zones to try, in order.
+	fn zones(self) -> &'static [ZoneKind] {
+		if self.contains(AllocPageFlags::DMA32) {
+			&[ZoneKind::Dma32]
+		} else if self.contains(AllocPageFlags::USER) {
+			&[ZoneKind::Normal, ZoneKind::Dma32]
+		} else {
+			&[ZoneKind::Dma32, ZoneKind::Normal]
+		}
 	}
 }
 
@@ -90,53 +157,55 @@ impl Drop for OwnedPages {
 	}
 }
 
-// TODO: Use alloc_page
-pub fn alloc_pages(
+fn alloc_pages_from_zones(
 	num_pages: usize,
 	flags: AllocPageFlags,
 ) -> Result<PAddr, PageAllocError> {
 	let order = num_pages_to_order(num_pages);
 	let mut zones = ZONES.lock();
-	for zone in zones.iter_mut() {
-		if let Some(paddr) = zone.alloc_pages(order).map(PAddr::new) {
-			if !flags.contains(AllocPageFlags::DIRTY_OK) {
-				unsafe {
-					paddr
-						.as_mut_ptr::<u8>()
-						.write_bytes(0, num_pages * PAGE_SIZE);
+	if flags.contains(AllocPageFlags::USER) {
+		let num_free_pages: usize = zones
+			.iter()
+			.map(|zone| zone.allocator.num_free_pages())
+			.sum();
+		if num_free_pages < (1 << order) + KERNEL_RESERVED_PAGES {
+			return Err(PageAllocError);
+		}
+	}
+
+	for kind in flags.zones() {
+		for zone in zones.iter_mut().filter(|zone| zone.kind == *kind) {
+			if let Some(paddr) = zone.allocator.alloc_pages(order).map(PAddr::new) {
+				if !flags.contains(AllocPageFlags::DIRTY_OK) {
+					unsafe {
+						paddr
+							.as_mut_ptr::<u8>()
+							.write_bytes(0, num_pages * PAGE_SIZE);
+					}
 				}
-			}
 
-			NUM_FREE_PAGES.fetch_sub(num_pages, Ordering::SeqCst);
-			return Ok(paddr);
+				return Ok(paddr);
+			}
 		}
 	}
 
 	Err(PageAllocError)
 }
 
+// TODO: Use alloc_page
+pub fn alloc_pages(
+	num_pages: usize,
+	flags: AllocPageFlags,
+) -> Result<PAddr, PageAllocError> {
+	alloc_pages_from_zones(num_pages, flags)
+}
+
 pub fn alloc_pages_owned(
 	num_pages: usize,
 	flags: AllocPageFlags,
 ) -> Result<OwnedPages, PageAllocError> {
-	let order = num_pages_to_order(num_pages);
-	let mut zones = ZONES.lock();
-	for zone in zones.iter_mut() {
-		if let Some(paddr) = zone.alloc_pages(order).map(PAddr::new) {
-			if !flags.contains(AllocPageFlags::DIRTY_OK) {
-				unsafe {
-					paddr
-						.as_mut_ptr::<u8>()
-						.write_bytes(0, num_pages * PAGE_SIZE);
-				}
-			}
-
-			NUM_FREE_PAGES.fetch_sub(num_pages, Ordering::SeqCst);
-			return Ok(OwnedPages::new(paddr, num_pages));
-		}
-	}
-
-	Err(PageAllocError)
+	alloc_pages_from_zones(num_pages, flags)
+		.map(|paddr| OwnedPages::new(paddr, num_pages))
 }
 
 /// The caller must ensure that the pages are not already freed. Keep holding
@@ -154,16 +223,46 @@ pub fn free_pages(paddr: PAddr, num_pages: usize) {
 	let order = num_pages_to_order(num_pages);
 	let mut zones = ZONES.lock();
 	for zone in zones.iter_mut() {
-		if zone.includes(paddr.value()) {
-			zone.free_pages(paddr.value(), order);
-			NUM_FREE_PAGES.fetch_add(num_pages, Ordering::SeqCst);
+		if zone.allocator.includes(paddr.value()) {
+			zone.allocator.free_pages(paddr.value(), order);
 			return;
 		}
 	}
 }
 
+fn add_zone(kind: ZoneKind, base: PAddr, len: usize) {
+	info!(
+		"{:?} zone: base={:x}, size={}",
+		kind,
+		base.value(),
+		ByteSize::new(len)
+	);
+
+	let allocator =
+		unsafe { Allocator::new(base.as_mut_ptr(), base.value(), len) };
+	ZONES.lock().push(Zone { kind, allocator });
+}
+
+/// Registers `[start, end)`, split into the zones it overlaps.
+fn add_zones(start: usize, end: usize) {
+	let dma32_end = end.min(DMA32_END);
+	if start < dma32_end {
+		add_zone(ZoneKind::Dma32, PAddr::new(start), dma32_end - start);
+	}
+
+	let normal_start = start.max(DMA32_END);
+	if normal_start < end {
+		add_zone(
+			ZoneKind::Normal,
+			PAddr::new(normal_start),
+			end - normal_start,
+		);
+	}
+}
+
 pub fn init(areas: &[RamArea]) {
-	let mut zones = ZONES.lock();
+	// Only the memory below BOOT_STRAIGHT_MAP_PADDR_END is mapped for now.
+	// Register it first: the page tables which map the rest come from there.
 	for area in areas {
 		info!(
 			"available RAM: base={:x}, size={}",
@@ -172,12 +271,39 @@ pub fn init(areas: &[RamArea]) {
 		);
 
 		debug_assert!(is_aligned(area.base.value(), PAGE_SIZE));
-		let allocator = unsafe {
-			Allocator::new(area.base.as_mut_ptr(), area.base.value(), area.len)
-		};
-		NUM_FREE_PAGES.fetch_add(allocator.num_total_pages(), Ordering::SeqCst);
-		NUM_TOTAL_PAGES
-			.fetch_add(allocator.num_total_pages(), Ordering::SeqCst);
-		zones.push(allocator);
+		let start = area.base.value();
+		let end =
+			align_down(start + area.len, PAGE_SIZE).min(BOOT_STRAIGHT_MAP_PADDR_END);
+		if start < end {
+			add_zones(start, end);
+		}
+	}
+
+	for area in areas {
+		let start = area.base.value().max(BOOT_STRAIGHT_MAP_PADDR_END);
+		let end = align_down(area.base.value() + area.len, PAGE_SIZE);
+
+		// RAM past the straight map can't be reached by the kernel.
+		let usable_end = end.min(KERNEL_STRAIGHT_MAP_PADDR_END);
+		if usable_end < end {
+			warn!(
+				"ignoring {} of RAM above the straight map",
+				ByteSize::new(end - usable_end.max(start))
+			);
+		}
+
+		if start >= usable_end {
+			continue;
+		}
+
+		if map_straight(PAddr::new(start), usable_end - start).is_err() {
+			warn!(
+				"failed to map RAM at {:x} into the straight map: out of memory",
+				start
+			);
+			continue;
+		}
+
+		add_zones(start, usable_end);
 	}
 }
@@ -0,0 +1,403 @@
+//! The slab allocator for small kernel objects.
+//!
+//! A cache hands out objects of a single size, carved from slabs of
+//! `SLAB_SIZE` bytes taken from the page allocator. Each slab starts with a
+//! header pointing back to its cache. The page allocator aligns blocks to their
+//! size, so the header of an object is found by aligning its address down.
+
+use core::{
+	alloc::Layout,
+	mem::{align_of, size_of},
+	ptr::{self, NonNull},
+	sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use arrayvec::ArrayVec;
+use utils::alignment::align_down;
+
+use crate::{
+	address::VAddr,
+	arch::PAGE_SIZE,
+	page_allocator::{alloc_pages, free_pages, AllocPageFlags},
+	spinlock::SpinLock,
+};
+
+const SLAB_PAGES: usize = 4;
+const SLAB_SIZE: usize = SLAB_PAGES * PAGE_SIZE;
+/// Larger objects are allocated from the kernel heap.
+pub const MAX_OBJECT_SIZE: usize = 2048;
+const MAX_CACHES: usize = 32;
+
+/// The general-purpose caches, one per power of two from 16 bytes.
+static KMALLOC_CACHES: [SlabCache; 8] = [
+	SlabCache::new("kmalloc-16", 16, 16),
+	SlabCache::new("kmalloc-32", 32, 32),
+	SlabCache::new("kmalloc-64", 64, 64),
+	SlabCache::new("kmalloc-128", 128, 128),
+	SlabCache::new("kmalloc-256", 256, 256),
+	SlabCache::new("kmalloc-512", 512, 512),
+	SlabCache::new("kmalloc-1024", 1024, 1024),
+	SlabCache::new("kmalloc-2048", 2048, 2048),
+];
+
+/// The registered caches, linked through `SlabCache::next`.
+static DEDICATED_CACHES: AtomicPtr<SlabCache> = AtomicPtr::new(ptr::null_mut());
+
+const fn align_up(value: usize, align: usize) -> usize {
+	(value + align - 1) & !(align - 1)
+}
+
+const fn max(a: usize, b: usize) -> usize {
+	if a > b {
+		a
+	} else {
+		b
+	}
+}
+
+struct SlabHeader {
+	cache: *const SlabCache,
+	prev: *mut SlabHeader,
+	next: *mut SlabHeader,
+	/// The first free object. Free objects hold the address of the next one.
+	free_list: *mut usize,
+	num_used: usize,
+}
+
+/// A doubly linked list of slabs.
+struct SlabList {
+	head: *mut SlabHeader,
+	len: usize,
+}
+
+impl SlabList {
+	const fn new() -> SlabList {
+		SlabList {
+			head: ptr::null_mut(),
+			len: 0,
+		}
+	}
+
+	unsafe fn push(&mut self, slab: *mut SlabHeader) {
+		(*slab).prev = ptr::null_mut();
+		(*slab).next = self.head;
+		if !self.head.is_null() {
+			(*self.head).prev = slab;
+		}
+
+		self.head = slab;
+		self.len += 1;
+	}
+
+	unsafe fn remove(&mut self, slab: *mut SlabHeader) {
+		if (*slab).prev.is_null() {
+			self.head = (*slab).next;
+		} else {
+			(*(*slab).prev).next = (*slab).next;
+		}
+
+		if !(*slab).next.is_null() {
+			(*(*slab).next).prev = (*slab).prev;
+		}
+
+		self.len -= 1;
+	}
+
+	unsafe fn pop(&mut self) -> Option<*mut SlabHeader> {
+		let slab = self.head;
+		if slab.is_null() {
+			return None;
+		}
+
+		self.remove(slab);
+		Some(slab)
+	}
+}
+
+struct Slabs {
+	/// Slabs with both used and free objects. Full slabs aren't kept in any
+	/// list: they're found from their objects when those get freed.
+	partial: SlabList,
+	/// Slabs without used objects.
+	empty: SlabList,
+	num_slabs: usize,
+	num_used_objects: usize,
+}
+
+unsafe impl Send for Slabs {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+	pub name: &'static str,
+	pub object_size: usize,
+	pub objects_per_slab: usize,
+	pub num_slabs: usize,
+	pub num_empty_slabs: usize,
+	pub num_used_objects: usize,
+	pub num_total_objects: usize,
+}
+
+/// A cache of objects of a single size.
+pub struct SlabCache {
+	name: &'static str,
+	object_size: usize,
+	align: usize,
+	/// The distance between objects.
+	stride: usize,
+	/// The offset of the first object from the start of a slab.
+	first_offset: usize,
+	objects_per_slab: usize,
+	slabs: SpinLock<Slabs>,
+	registered: AtomicBool,
+	next: AtomicPtr<SlabCache>,
+}
+
+impl SlabCache {
+	pub const fn new(name: &'static str, object_size: usize, align: usize) -> SlabCache {
+		assert!(align.is_power_of_two());
+		let align = max(align, align_of::<usize>());
+		let stride = align_up(max(object_size, size_of::<usize>()), align);
+		let first_offset = align_up(size_of::<SlabHeader>(), align);
+		assert!(first_offset + stride <= SLAB_SIZE, "too large object");
+
+		SlabCache {
+			name,
+			object_size,
+			align,
+			stride,
+			first_offset,
+			objects_per_slab: (SLAB_SIZE - first_offset) / stride,
+			slabs: SpinLock::new_untraced(Slabs {
+				partial: SlabList::new(),
+				empty: SlabList::new(),
+				num_slabs: 0,
+				num_used_objects: 0,
+			}),
+			registered: AtomicBool::new(false),
+			next: AtomicPtr::new(ptr::null_mut()),
+		}
+	}
+
+	/// Creates a cache for the allocations made by `Arc::new::<T>`, that is,
+	/// `T` preceded by the strong and weak counts.
+	pub const fn for_arc<T>(name: &'static str) -> SlabCache {
+		let align = max(align_of::<T>(), align_of::<usize>());
+		let size = align_up(
+			align_up(2 * size_of::<usize>(), align_of::<T>()) + size_of::<T>(),
+			align,
+		);
+		SlabCache::new(name, size, align)
+	}
+
+	/// Lets the kernel heap serve the allocations of this cache's size from
+	/// it, instead of the kmalloc-* caches.
+	pub fn register(&'static self) {
+		if self.registered.swap(true, Ordering::SeqCst) {
+			return;
+		}
+
+		if self.object_size > MAX_OBJECT_SIZE {
+			warn!(
+				"slab: {} is too large to be served by the kernel heap ({} bytes)",
+				self.name, self.object_size
+			);
+			return;
+		}
+
+		let this = self as *const SlabCache as *mut SlabCache;
+		let mut head = DEDICATED_CACHES.load(Ordering::SeqCst);
+		loop {
+			self.next.store(head, Ordering::SeqCst);
+			match DEDICATED_CACHES.compare_exchange(head, this, Ordering::SeqCst, Ordering::SeqCst) {
+				Ok(_) => break,
+				Err(current) => head = current,
+			}
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	pub fn alloc(&self) -> Option<NonNull<u8>> {
+		let mut slabs = self.slabs.lock();
+		let slab = match unsafe { slabs.partial.pop().or_else(|| slabs.empty.pop()) } {
+			Some(slab) => slab,
+			None => {
+				let slab = self.new_slab()?;
+				slabs.num_slabs += 1;
+				slab
+			}
+		};
+
+		unsafe {
+			let object = (*slab).free_list;
+			(*slab).free_list = *object as *mut usize;
+			(*slab).num_used += 1;
+			if (*slab).num_used < self.objects_per_slab {
+				slabs.partial.push(slab);
+			}
+
+			slabs.num_used_objects += 1;
+			Some(NonNull::new_unchecked(object as *mut u8))
+		}
+	}
+
+	/// # Safety
+	///
+	/// `ptr` must be an object allocated from this cache.
+	pub unsafe fn free(&self, ptr: NonNull<u8>) {
+		let slab = slab_of(ptr);
+		debug_assert!(ptr::eq((*slab).cache, self));
+
+		let mut slabs = self.slabs.lock();
+		let object = ptr.as_ptr() as *mut usize;
+		*object = (*slab).free_list as usize;
+		(*slab).free_list = object;
+		if (*slab).num_used == self.objects_per_slab {
+			slabs.partial.push(slab);
+		}
+
+		(*slab).num_used -= 1;
+		slabs.num_used_objects -= 1;
+		if (*slab).num_used > 0 {
+			return;
+		}
+
+		slabs.partial.remove(slab);
+		if slabs.empty.len == 0 {
+			// Keep one empty slab around so that a cache used as a stack
+			// doesn't take and return the same pages over and over.
+			slabs.empty.push(slab);
+		} else {
+			slabs.num_slabs -= 1;
+			drop(slabs);
+			release_slab(slab);
+		}
+	}
+
+	/// Gives the empty slabs back to the page allocator. Returns the number of
+	/// pages freed.
+	pub fn shrink(&self) -> usize {
+		let mut num_pages = 0;
+		let mut slabs = self.slabs.lock();
+		while let Some(slab) = unsafe { slabs.empty.pop() } {
+			slabs.num_slabs -= 1;
+			release_slab(slab);
+			num_pages += SLAB_PAGES;
+		}
+
+		num_pages
+	}
+
+	pub fn stats(&self) -> SlabStats {
+		let slabs = self.slabs.lock();
+		SlabStats {
+			name: self.name,
+			object_size: self.object_size,
+			objects_per_slab: self.objects_per_slab,
+			num_slabs: slabs.num_slabs,
+			num_empty_slabs: slabs.empty.len,
+			num_used_objects: slabs.num_used_objects,
+			num_total_objects: slabs.num_slabs * self.objects_per_slab,
+		}
+	}
+
+	/// Allocates a slab and threads its objects into the free list.
+	fn new_slab(&self) -> Option<*mut SlabHeader> {
+		let paddr = alloc_pages(
+			SLAB_PAGES,
+			AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
+		)
+		.ok()?;
+		let base = paddr.as_mut_ptr::<u8>();
+		let mut free_list = ptr::null_mut();
+		for i in (0..self.objects_per_slab).rev() {
+			unsafe {
+				let object = base.add(self.first_offset + i * self.stride) as *mut usize;
+				*object = free_list as usize;
+				free_list = object;
+			}
+		}
+
+		let slab = base as *mut SlabHeader;
+		unsafe {
+			slab.write(SlabHeader {
+				cache: self,
+				prev: ptr::null_mut(),
+				next: ptr::null_mut(),
+				free_list,
+				num_used: 0,
+			});
+		}
+
+		Some(slab)
+	}
+}
+
+fn slab_of(ptr: NonNull<u8>) -> *mut SlabHeader {
+	align_down(ptr.as_ptr() as usize, SLAB_SIZE) as *mut SlabHeader
+}
+
+fn release_slab(slab: *mut SlabHeader) {
+	free_pages(VAddr::new(slab as usize).as_paddr(), SLAB_PAGES);
+}
+
+fn dedicated_caches() -> impl Iterator<Item = &'static SlabCache> {
+	let mut current = DEDICATED_CACHES.load(Ordering::SeqCst);
+	core::iter::from_fn(move || {
+		// SAFETY: Only `&'static SlabCache`s are registered.
+		let cache = unsafe { current.as_ref()? };
+		current = cache.next.load(Ordering::SeqCst);
+		Some(cache)
+	})
+}
+
+/// Whether the kernel heap serves `layout` from a slab cache.
+pub(crate) fn is_slab_layout(layout: &Layout) -> bool {
+	layout.size() <= MAX_OBJECT_SIZE && layout.align() <= MAX_OBJECT_SIZE
+}
+
+/// Picks the cache for `layout`: a registered cache of the exact size if
+/// any, or the smallest kmalloc-* cache it fits in.
+pub(crate) fn cache_for(layout: &Layout) -> &'static SlabCache {
+	debug_assert!(is_slab_layout(layout));
+	if let Some(cache) = dedicated_caches()
+		.find(|cache| cache.object_size == layout.size() && cache.align >= layout.align())
+	{
+		return cache;
+	}
+
+	let size = max(layout.size(), layout.align()).next_power_of_two();
+	let index = (size.trailing_zeros() as usize).saturating_sub(4);
+	&KMALLOC_CACHES[index]
+}
+
+/// Frees an object allocated from any cache.
+///
+/// # Safety
+///
+/// `ptr` must be an object allocated from a slab cache.
+pub(crate) unsafe fn free(ptr: NonNull<u8>) {
+	let cache = &*(*slab_of(ptr)).cache;
+	cache.free(ptr);
+}
+
+/// Gives the empty slabs of all caches back to the page allocator. Returns
+/// the number of pages freed.
+pub fn shrink_all() -> usize {
+	KMALLOC_CACHES
+		.iter()
+		.chain(dedicated_caches())
+		.map(|cache| cache.shrink())
+		.sum()
+}
+
+pub fn read_slab_stats() -> ArrayVec<SlabStats, MAX_CACHES> {
+	KMALLOC_CACHES
+		.iter()
+		.chain(dedicated_caches())
+		.take(MAX_CACHES)
+		.map(|cache| cache.stats())
+		.collect()
+}
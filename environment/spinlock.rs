@@ -18,6 +18,8 @@ use atomic_refcell::AtomicRefCell;
 pub struct SpinLock<T: ?Sized> {
 	#[cfg(debug_assertions)]
 	locked_by: AtomicRefCell<Option<CapturedBacktrace>>,
+	#[cfg(debug_assertions)]
+	traced: bool,
 	inner: spin::mutex::SpinMutex<T>,
 }
 
@@ -35,6 +37,21 @@ impl<T> SpinLock<T> {
 			inner: spin::mutex::SpinMutex::new(value),
 			#[cfg(debug_assertions)]
 			locked_by: AtomicRefCell::new(None),
+			#[cfg(debug_assertions)]
+			traced: true,
+		}
+	}
+
+	/// Creates a lock which doesn't remember who holds it. Capturing the
+	/// backtrace allocates memory, so the memory allocators use this one to
+	/// avoid calling into themselves.
+	pub const fn new_untraced(value: T) -> SpinLock<T> {
+		SpinLock {
+			inner: spin::mutex::SpinMutex::new(value),
+			#[cfg(debug_assertions)]
+			locked_by: AtomicRefCell::new(None),
+			#[cfg(debug_assertions)]
+			traced: false,
 		}
 	}
 }
@@ -45,6 +62,8 @@ impl<T: Default> Default for SpinLock<T> {
 			inner: Default::default(),
 			#[cfg(debug_assertions)]
 			locked_by: Default::default(),
+			#[cfg(debug_assertions)]
+			traced: true,
 		}
 	}
 }
@@ -85,7 +104,7 @@ impl<T: ?Sized> SpinLock<T> {
 		let guard = self.inner.lock();
 
 		#[cfg(debug_assertions)]
-		if is_kernel_heap_enabled() {
+		if self.traced && is_kernel_heap_enabled() {
 			*self.locked_by.borrow_mut() = Some(CapturedBacktrace::capture());
 		}
 
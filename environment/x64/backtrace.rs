@@ -19,6 +19,13 @@ impl Backtrace {
 		}
 	}
 
+	/// Starts from the frame pointer of an interrupted context.
+	pub fn from_frame(bp: usize) -> Backtrace {
+		Backtrace {
+			frame: bp as *const StackFrame,
+		}
+	}
+
 	pub fn traverse<F>(self, mut callback: F)
 	where
 		F: FnMut(usize, VAddr),
@@ -1,10 +1,11 @@
+use core::sync::atomic::{AtomicBool, Ordering};
 use x86::{
-  controlregs::{self, Cr4, Xcr0},
+  controlregs::{self, Cr0, Cr4, Xcr0},
   cpuid::CpuId,
   io::outb,
 };
 
-use super::{apic, bootinfo, cpu_local, gdt, idt, ioapic, pit, serial, syscall, tss, vga};
+use super::{apic, bootinfo, cpu_local, gdt, idt, ioapic, paging, pit, serial, syscall, tss, vga};
 use crate::{
   address::{PAddr, VAddr},
   bootinfo::BootInfo,
@@ -12,6 +13,11 @@ use crate::{
   x64::{mouse, pc8042},
 };
 
+/// Whether SMAP is enabled, that is, whether usercopy.S and trap.S can use
+/// STAC and CLAC.
+#[export_name = "smap_enabled"]
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
 fn check_cpuid_feature(name: &str, supported: bool) {
   if !supported {
     panic!("{} is not supprted on this machine", name);
@@ -30,12 +36,32 @@ unsafe fn common_setup(cpu_local_area: VAddr) {
     | Cr4::CR4_ENABLE_OS_XSAVE
     | Cr4::CR4_ENABLE_SSE
     | Cr4::CR4_UNMASKED_SSE;
+
+  // Fault on the kernel executing or accessing user pages.
+  if ex_feats.has_smep() {
+    cr4 |= Cr4::CR4_ENABLE_SMEP;
+  } else {
+    warn!("SMEP is not supported");
+  }
+
+  if ex_feats.has_smap() {
+    cr4 |= Cr4::CR4_ENABLE_SMAP;
+    SMAP_ENABLED.store(true, Ordering::Relaxed);
+  } else {
+    warn!("SMAP is not supported");
+  }
+
   controlregs::cr4_write(cr4);
 
+  // Make read-only pages read-only for the kernel too.
+  controlregs::cr0_write(controlregs::cr0() | Cr0::CR0_WRITE_PROTECT);
+
   let mut xcr0 = controlregs::xcr0();
   xcr0 |= Xcr0::XCR0_SSE_STATE | Xcr0::XCR0_AVX_STATE;
   controlregs::xcr0_write(xcr0);
 
+  paging::init_nx();
+
   cpu_local::init(cpu_local_area);
   apic::init();
   ioapic::init();
@@ -89,6 +115,7 @@ unsafe extern "C" fn bsp_early_init(boot_magic: u32, boot_params: u64) -> ! {
   serial::init(boot_info.use_second_serialport);
   init_pic();
   common_setup(VAddr::new(&__bsp_cpu_local as *const _ as usize));
+  paging::protect_kernel();
 
   boot_kernel(&boot_info);
 }
@@ -151,6 +151,7 @@ struct Cmdline {
 	pub virtio_mmio_devices: ArrayVec<VirtioMmioDevice, 4>,
 	pub log_filter: ArrayString<64>,
 	pub use_second_serialport: bool,
+	pub aslr_enabled: bool,
 }
 
 impl Cmdline {
@@ -163,6 +164,7 @@ impl Cmdline {
 		let mut virtio_mmio_devices = ArrayVec::new();
 		let mut log_filter = ArrayString::new();
 		let mut use_second_serialport = false;
+		let mut aslr_enabled = true;
 		if !s.is_empty() {
 			for config in s.split(' ') {
 				let mut words = config.splitn(2, '=');
@@ -171,6 +173,10 @@ impl Cmdline {
 						warn!("bootinfo: PCI disabled");
 						pci_enabled = false;
 					}
+					(Some("aslr"), Some("off")) => {
+						warn!("bootinfo: ASLR disabled");
+						aslr_enabled = false;
+					}
 					(Some("serial1"), Some("on")) => {
 						info!("bootinfo: secondary serial port enabled");
 						use_second_serialport = true;
@@ -222,6 +228,7 @@ impl Cmdline {
 			virtio_mmio_devices,
 			log_filter,
 			use_second_serialport,
+			aslr_enabled,
 		}
 	}
 }
@@ -322,6 +329,7 @@ unsafe fn parse_multiboot2_info(header: &Multiboot2InfoHeader) -> BootInfo {
 		virtio_mmio_devices: cmdline.virtio_mmio_devices,
 		log_filter: cmdline.log_filter,
 		use_second_serialport: cmdline.use_second_serialport,
+		aslr_enabled: cmdline.aslr_enabled,
 		framebuffer: crate::bootinfo::Framebuffer {
 			addr: PAddr::new(0),
 			pitch: 0,
@@ -371,6 +379,7 @@ unsafe fn parse_multiboot_legacy_info(info: &MultibootLegacyInfo) -> BootInfo {
 		virtio_mmio_devices: cmdline.virtio_mmio_devices,
 		log_filter: cmdline.log_filter,
 		use_second_serialport: cmdline.use_second_serialport,
+		aslr_enabled: cmdline.aslr_enabled,
 		framebuffer: crate::bootinfo::Framebuffer {
 			addr: PAddr::new(info.framebuffer_addr as usize),
 			pitch: info.framebuffer_pitch,
@@ -409,6 +418,7 @@ unsafe fn parse_linux_boot_params(boot_params: PAddr) -> BootInfo {
 		virtio_mmio_devices: cmdline.virtio_mmio_devices,
 		log_filter: cmdline.log_filter,
 		use_second_serialport: cmdline.use_second_serialport,
+		aslr_enabled: cmdline.aslr_enabled,
 		framebuffer: crate::bootinfo::Framebuffer {
 			addr: PAddr::new(0),
 			pitch: 0,
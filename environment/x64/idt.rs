@@ -1,7 +1,10 @@
 use super::gdt::KERNEL_CS;
-use super::tss::IST_RSP0;
+use super::tss::{IST_DOUBLE_FAULT, IST_RSP0};
 use core::mem::size_of;
-use x86::dtables::{lidt, DescriptorTablePointer};
+use x86::{
+    dtables::{lidt, DescriptorTablePointer},
+    irq::DOUBLE_FAULT_VECTOR,
+};
 
 const HANDLER_SIZE: usize = 16;
 const NUM_IDT_DESCS: usize = 256;
@@ -40,7 +43,11 @@ pub unsafe fn init() {
         let idt = IDT.as_mut();
         idt[i].offset1 = (handler & 0xffff) as u16;
         idt[i].seg = KERNEL_CS;
-        idt[i].ist = IST_RSP0;
+        idt[i].ist = if i == DOUBLE_FAULT_VECTOR as usize {
+            IST_DOUBLE_FAULT
+        } else {
+            IST_RSP0
+        };
         idt[i].info = 0x8e;
         idt[i].offset2 = ((handler >> 16) & 0xffff) as u16;
         idt[i].offset3 = ((handler >> 32) & 0xffffffff) as u32;
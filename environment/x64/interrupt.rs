@@ -1,10 +1,15 @@
-use crate::{address::UserVAddr, system, x64::mouse::PS2MOUSE_IRQ};
+use crate::{
+  address::{AccessError, UserVAddr, VAddr},
+  backtrace::backtrace_from,
+  system,
+  x64::mouse::PS2MOUSE_IRQ,
+};
 
 use core::fmt;
 
 use super::{
   apic::ack_interrupt, ioapic::VECTOR_IRQ_BASE, pc8042::PS2KBD_IRQ, serial::SERIAL0_IRQ,
-  PageFaultReason,
+  PageFaultReason, KERNEL_BASE_ADDR, PAGE_SIZE,
 };
 use x86::{
   controlregs::cr2,
@@ -15,7 +20,7 @@ use x86::{
 /// The interrupt stack frame.
 #[derive(Copy, Clone)]
 #[repr(C, packed)]
-struct InterruptFrame {
+pub struct InterruptFrame {
   rax: u64,
   rbx: u64,
   rcx: u64,
@@ -39,25 +44,118 @@ struct InterruptFrame {
   ss: u64,
 }
 
+impl InterruptFrame {
+  pub fn ip(&self) -> usize {
+    self.rip as usize
+  }
+
+  pub fn sp(&self) -> usize {
+    self.rsp as usize
+  }
+
+  pub fn bp(&self) -> usize {
+    self.rbp as usize
+  }
+
+  /// Whether the interrupted code was running in the user mode.
+  pub fn is_user(&self) -> bool {
+    self.cs & 3 == 3
+  }
+}
+
 impl fmt::Debug for InterruptFrame {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    let rip = self.rip;
-    let rsp = self.rsp;
-    let cs = self.cs;
-    let error = self.error;
-    write!(f, "RIP={rip:x}, RSP={rsp:x}, CS={cs:x}, ERR={error:x}")
+    let Self {
+      rax,
+      rbx,
+      rcx,
+      rdx,
+      rsi,
+      rbp,
+      r8,
+      r9,
+      r10,
+      r11,
+      r12,
+      r13,
+      r14,
+      r15,
+      rdi,
+      error,
+      rip,
+      cs,
+      rflags,
+      rsp,
+      ss,
+    } = *self;
+    writeln!(
+      f,
+      "RIP={rip:016x} RSP={rsp:016x} RFLAGS={rflags:08x} ERR={error:x}"
+    )?;
+    writeln!(
+      f,
+      "RAX={rax:016x} RBX={rbx:016x} RCX={rcx:016x} RDX={rdx:016x}"
+    )?;
+    writeln!(
+      f,
+      "RSI={rsi:016x} RDI={rdi:016x} RBP={rbp:016x} R8 ={r8:016x}"
+    )?;
+    writeln!(
+      f,
+      "R9 ={r9:016x} R10={r10:016x} R11={r11:016x} R12={r12:016x}"
+    )?;
+    write!(
+      f,
+      "R13={r13:016x} R14={r14:016x} R15={r15:016x} CS={cs:x} SS={ss:x}"
+    )
   }
 }
 
+/// Reports a fault in the kernel with everything we know about it and stops
+/// the system.
+fn oops(title: &str, frame: &InterruptFrame) -> ! {
+  error!("oops: {}", title);
+  error!("{:?}", frame);
+  error!("CR2={:016x}", cr2());
+  backtrace_from(VAddr::new(frame.ip()), frame.bp());
+  panic!("oops: {}", title);
+}
+
+/// Whether a fault in the kernel hit the stack right below the stack pointer,
+/// that is, the guard page under an overflowing kernel stack.
+fn is_stack_overflow(frame: &InterruptFrame) -> bool {
+  !frame.is_user() && frame.sp().abs_diff(cr2() as usize) < PAGE_SIZE
+}
+
+/// Whether the fault occurred while the kernel was accessing the user memory
+/// on behalf of a system call.
+fn is_usercopy(frame: &InterruptFrame) -> bool {
+  frame.rip == usercopy1 as *const u8 as u64
+    || frame.rip == usercopy2 as *const u8 as u64
+    || frame.rip == usercopy3 as *const u8 as u64
+}
+
+/// Makes the interrupted usercopy return `error` instead of retrying the
+/// access.
+fn fail_usercopy(frame: &mut InterruptFrame, error: AccessError) {
+  debug_assert!(is_usercopy(frame));
+  frame.rip = match error {
+    AccessError::Fault => usercopy_fault as *const u8 as u64,
+    AccessError::OutOfMemory => usercopy_out_of_memory as *const u8 as u64,
+  };
+}
+
 extern "C" {
   fn usercopy1();
   fn usercopy2();
   fn usercopy3();
+  fn usercopy_fault();
+  fn usercopy_out_of_memory();
 }
 
 #[no_mangle]
-unsafe extern "C" fn x64_handle_interrupt(vec: u8, frame: *const InterruptFrame) {
-  let frame = &*frame;
+unsafe extern "C" fn x64_handle_interrupt(vec: u8, frame: *mut InterruptFrame) {
+  let frame = &mut *frame;
 
   // FIXME: Check "Legacy replacement" mapping
   const TIMER_IRQ: u8 = 0;
@@ -96,6 +194,10 @@ unsafe extern "C" fn x64_handle_interrupt(vec: u8, frame: *const InterruptFrame)
           system().on_irq(irq);
         }
       }
+
+      if frame.is_user() {
+        system().on_irq_return_to_user();
+      }
     }
     DIVIDE_ERROR_VECTOR => {
       // TODO:
@@ -130,8 +232,13 @@ unsafe extern "C" fn x64_handle_interrupt(vec: u8, frame: *const InterruptFrame)
       panic!("unsupported exception: DEVICE_NOT_AVAILABLE\n{:?}", frame);
     }
     DOUBLE_FAULT_VECTOR => {
-      // TODO:
-      panic!("unsupported exception: DOUBLE_FAULT\n{:?}", frame);
+      // A page fault on the guard page under a kernel stack ends up here as
+      // the CPU can't push the exception frame onto the stack.
+      if is_stack_overflow(frame) {
+        oops("kernel stack overflow", frame);
+      }
+
+      oops("double fault", frame);
     }
     COPROCESSOR_SEGMENT_OVERRUN_VECTOR => {
       // TODO:
@@ -153,30 +260,40 @@ unsafe extern "C" fn x64_handle_interrupt(vec: u8, frame: *const InterruptFrame)
       panic!("unsupported exception: STACK_SEGEMENT_FAULT\n{:?}", frame);
     }
     GENERAL_PROTECTION_FAULT_VECTOR => {
-      // TODO:
-      panic!(
-        "unsupported exception: GENERAL_PROTECTION_FAULT\n{:?}",
-        frame
-      );
+      // The user can cause this by accessing a non-canonical address, or
+      // by passing one to a system call.
+      if is_usercopy(frame) {
+        fail_usercopy(frame, AccessError::Fault);
+        return;
+      }
+
+      if !frame.is_user() {
+        oops("general protection fault in the kernel", frame);
+      }
+
+      system().on_general_protection_fault(frame);
     }
     PAGE_FAULT_VECTOR => {
       let reason = PageFaultReason::from_bits_truncate(frame.error as u32);
+      if !reason.contains(PageFaultReason::CAUSED_BY_USER) && !is_usercopy(frame) {
+        // SMEP and SMAP catch the kernel touching user pages outside
+        // usercopy.
+        if (cr2() as usize) < KERNEL_BASE_ADDR {
+          oops("the kernel accessed a user address", frame);
+        }
+
+        if is_stack_overflow(frame) {
+          oops("kernel stack overflow", frame);
+        }
 
-      // Panic if it's occurred in the kernel space.
-      let occurred_in_user = reason.contains(PageFaultReason::CAUSED_BY_USER)
-        || frame.rip == usercopy1 as *const u8 as u64
-        || frame.rip == usercopy2 as *const u8 as u64
-        || frame.rip == usercopy3 as *const u8 as u64;
-      if !occurred_in_user {
-        let rip = frame.rip;
-        let rsp = frame.rsp;
-        let cr2 = cr2();
-        panic!("page fault occurred in the kernel: rip={rip:x}, rsp={rsp:x}, vaddr={cr2:x}",);
+        oops("page fault in the kernel", frame);
       }
 
       // Abort if the virtual address points to out of the user's address space.
       let unaligned_vaddr = UserVAddr::new(cr2() as usize);
-      system().on_page_fault(unaligned_vaddr, frame.rip as usize, reason);
+      if let Err(error) = system().on_page_fault(unaligned_vaddr, reason, frame) {
+        fail_usercopy(frame, error);
+      }
     }
     X87_FPU_VECTOR => {
       // TODO:
@@ -35,5 +35,11 @@ pub const TICK_HZ: usize = 1000;
 pub const KERNEL_BASE_ADDR: usize = 0xffff_8000_0000_0000;
 
 /// The end of straight mapping. Any physical address `P` is mapped into the
-/// kernel's virtual memory address `KERNEL_BASE_ADDR + P`.
-pub const KERNEL_STRAIGHT_MAP_PADDR_END: usize = 0x1_0000_0000;
+/// kernel's virtual memory address `KERNEL_BASE_ADDR + P`. It spans 128 PML4
+/// entries, though only the RAM areas past `BOOT_STRAIGHT_MAP_PADDR_END` are
+/// actually mapped.
+pub const KERNEL_STRAIGHT_MAP_PADDR_END: usize = 0x4000_0000_0000;
+
+/// The end of straight mapping set up in boot.S. Everything below it is mapped,
+/// including MMIO areas.
+pub const BOOT_STRAIGHT_MAP_PADDR_END: usize = 0x1_0000_0000;
@@ -1,16 +1,26 @@
-use super::PAGE_SIZE;
+use super::{KERNEL_BASE_ADDR, KERNEL_STRAIGHT_MAP_PADDR_END, PAGE_SIZE};
 use crate::{
   address::{PAddr, UserVAddr},
-  page_allocator::{alloc_pages, AllocPageFlags, PageAllocError},
+  page_allocator::{alloc_pages, free_pages, AllocPageFlags, PageAllocError},
+  spinlock::SpinLock,
 };
 use bitflags::bitflags;
 use core::{
   debug_assert,
+  ops::Range,
   ptr::{self, NonNull},
+  sync::atomic::{AtomicBool, Ordering},
+};
+use utils::alignment::{align_down, align_up, is_aligned};
+use x86::{
+  controlregs::{self, Cr4},
+  cpuid::CpuId,
+  msr::{rdmsr, wrmsr, IA32_EFER},
 };
-use utils::alignment::is_aligned;
 
 const ENTRIES_PER_TABLE: isize = 512;
+/// The No-Execute Enable bit in the EFER.
+const EFER_NXE: u64 = 1 << 11;
 type PageTableEntry = u64;
 
 bitflags! {
@@ -20,6 +30,23 @@ bitflags! {
     const PRESENT = 1 << 0;
     const WRITABLE = 1 << 1;
     const USER = 1 << 2;
+    const HUGE = 1 << 7;
+    const GLOBAL = 1 << 8;
+    /// Ignored by the CPU. Marks the pages of shared mappings, which fork(2)
+    /// must not copy.
+    const SHARED = 1 << 9;
+    const NO_EXECUTE = 1 << 63;
+  }
+}
+
+bitflags! {
+  /// How the user can access a page. The bits are the same as `PROT_*` in
+  /// mmap(2).
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct PageProt: u32 {
+    const READ = 1 << 0;
+    const WRITE = 1 << 1;
+    const EXEC = 1 << 2;
   }
 }
 
@@ -35,6 +62,30 @@ bitflags! {
   }
 }
 
+/// Whether `PageAttrs::NO_EXECUTE` is available.
+static NX_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Serializes the edits of the kernel page tables: kernel stacks split the
+/// huge pages of the straight map and unmap their guard pages at any time.
+static KERNEL_PAGE_TABLE_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// Enables `PageAttrs::NO_EXECUTE` if the CPU supports it.
+pub fn init_nx() {
+  let has_nx = CpuId::new()
+    .get_extended_processor_and_feature_identifiers()
+    .map_or(false, |feats| feats.has_execute_disable());
+  if !has_nx {
+    warn!("NX is not supported: all pages are executable");
+    return;
+  }
+
+  unsafe {
+    wrmsr(IA32_EFER, rdmsr(IA32_EFER) | EFER_NXE);
+  }
+
+  NX_ENABLED.store(true, Ordering::Relaxed);
+}
+
 fn entry_paddr(entry: PageTableEntry) -> PAddr {
   PAddr::new((entry & 0x7ffffffffffff000) as usize)
 }
@@ -43,30 +94,39 @@ fn entry_flags(entry: PageTableEntry) -> PageTableEntry {
   entry & !0x7ffffffffffff000
 }
 
-fn nth_level_table_index(vaddr: UserVAddr, level: usize) -> isize {
-  ((vaddr.value() >> ((((level) - 1) * 9) + 12)) & 0x1ff) as isize
+fn nth_level_table_index(vaddr: usize, level: usize) -> isize {
+  ((vaddr >> ((((level) - 1) * 9) + 12)) & 0x1ff) as isize
+}
+
+/// The size of a page mapped by an entry in the nth-level page table.
+fn nth_level_page_size(level: usize) -> usize {
+  PAGE_SIZE << ((level - 1) * 9)
 }
 
+/// Looks for the last-level entry of `vaddr`, allocating the missing page
+/// tables if `allocate` is set. Fails only if a page table can't be
+/// allocated.
 fn traverse(
   pml4: PAddr,
   vaddr: UserVAddr,
   allocate: bool,
-  attrs: PageAttrs,
-) -> Option<NonNull<PageTableEntry>> {
+) -> Result<Option<NonNull<PageTableEntry>>, PageAllocError> {
   debug_assert!(is_aligned(vaddr.value(), PAGE_SIZE));
+  // Access permissions are enforced by the last-level entry. Keep the
+  // intermediate ones as permissive as possible.
+  let attrs = PageAttrs::PRESENT | PageAttrs::WRITABLE | PageAttrs::USER;
   let mut table = pml4.as_mut_ptr::<PageTableEntry>();
   for level in (2..=4).rev() {
-    let index = nth_level_table_index(vaddr, level);
+    let index = nth_level_table_index(vaddr.value(), level);
     let entry = unsafe { table.offset(index) };
     let mut table_paddr = entry_paddr(unsafe { *entry });
     if table_paddr.value() == 0 {
       // The page table is not yet allocated.
       if !allocate {
-        return None;
+        return Ok(None);
       }
 
-      let new_table =
-        alloc_pages(1, AllocPageFlags::KERNEL).expect("failed to allocate page table");
+      let new_table = alloc_pages(1, AllocPageFlags::KERNEL)?;
       unsafe {
         new_table.as_mut_ptr::<u8>().write_bytes(0, PAGE_SIZE);
         *entry = new_table.value() as u64 | attrs.bits()
@@ -80,10 +140,23 @@ fn traverse(
   }
 
   unsafe {
-    Some(NonNull::new_unchecked(
-      table.offset(nth_level_table_index(vaddr, 1)),
-    ))
+    Ok(Some(NonNull::new_unchecked(
+      table.offset(nth_level_table_index(vaddr.value(), 1)),
+    )))
+  }
+}
+
+fn user_page_attrs(prot: PageProt) -> PageAttrs {
+  let mut attrs = PageAttrs::PRESENT | PageAttrs::USER;
+  if prot.contains(PageProt::WRITE) {
+    attrs |= PageAttrs::WRITABLE;
   }
+
+  if !prot.contains(PageProt::EXEC) && NX_ENABLED.load(Ordering::Relaxed) {
+    attrs |= PageAttrs::NO_EXECUTE;
+  }
+
+  attrs
 }
 
 /// Duplicates entires (and referenced memory pages if `level == 1`) in the
@@ -91,8 +164,24 @@ fn traverse(
 ///
 /// fork(2) uses this funciton to duplicate the memory space.
 fn duplicate_table(original_table_paddr: PAddr, level: usize) -> Result<PAddr, PageAllocError> {
-  let orig_table = original_table_paddr.as_ptr::<PageTableEntry>();
   let new_table_paddr = alloc_pages(1, AllocPageFlags::KERNEL)?;
+  if let Err(err) = duplicate_entries(original_table_paddr, new_table_paddr, level) {
+    free_table(new_table_paddr, level);
+    return Err(err);
+  }
+
+  Ok(new_table_paddr)
+}
+
+/// Fills the empty table `new_table_paddr` with the copies made by
+/// [`duplicate_table`]. On failure, the entries copied so far are left in
+/// place for [`free_table`].
+fn duplicate_entries(
+  original_table_paddr: PAddr,
+  new_table_paddr: PAddr,
+  level: usize,
+) -> Result<(), PageAllocError> {
+  let orig_table = original_table_paddr.as_ptr::<PageTableEntry>();
   let new_table = new_table_paddr.as_mut_ptr::<PageTableEntry>();
 
   debug_assert!(level > 0);
@@ -106,9 +195,13 @@ fn duplicate_table(original_table_paddr: PAddr, level: usize) -> Result<PAddr, P
     }
 
     // Create a deep copy of the page table entry.
-    let new_paddr = if level == 1 {
+    let new_paddr = if level == 1 && entry & PageAttrs::SHARED.bits() != 0 {
+      // Both processes keep writing to the same page.
+      paddr
+    } else if level == 1 {
       // Copy a physical page referenced from the last-level page table.
-      let new_paddr = alloc_pages(1, AllocPageFlags::KERNEL)?;
+      // It's the child's memory: leave the reserve to the kernel.
+      let new_paddr = alloc_pages(1, AllocPageFlags::USER | AllocPageFlags::DIRTY_OK)?;
       unsafe {
         ptr::copy_nonoverlapping::<u8>(paddr.as_ptr(), new_paddr.as_mut_ptr(), PAGE_SIZE);
       }
@@ -130,19 +223,268 @@ fn duplicate_table(original_table_paddr: PAddr, level: usize) -> Result<PAddr, P
     }
   }
 
-  Ok(new_table_paddr)
+  Ok(())
 }
 
-fn allocate_pml4() -> Result<PAddr, PageAllocError> {
+/// Frees a table made by [`duplicate_table`] along with the tables and
+/// pages it owns. The pages of shared mappings and the kernel page tables
+/// are left alone.
+fn free_table(table_paddr: PAddr, level: usize) {
+  let table = table_paddr.as_ptr::<PageTableEntry>();
+  let num_entries = if level == 4 { 0x80 } else { ENTRIES_PER_TABLE };
+  for i in 0..num_entries {
+    let entry = unsafe { *table.offset(i) };
+    let paddr = entry_paddr(entry);
+    if paddr.is_null() {
+      continue;
+    }
+
+    if level > 1 {
+      free_table(paddr, level - 1);
+    } else if entry & PageAttrs::SHARED.bits() == 0 {
+      free_pages(paddr, 1);
+    }
+  }
+
+  free_pages(table_paddr, 1);
+}
+
+fn kernel_pml4() -> PAddr {
   extern "C" {
     static __kernel_pml4: u8;
   }
 
+  PAddr::new(unsafe { &__kernel_pml4 as *const u8 as usize })
+}
+
+/// Replaces the huge page mapped by `entry` in the `level`-th page table
+/// with a table of smaller pages with the same attributes. Returns the new
+/// table. The caller must hold `KERNEL_PAGE_TABLE_LOCK`.
+fn split_huge_page(entry: *mut PageTableEntry, level: usize) -> Result<PAddr, PageAllocError> {
+  let value = unsafe { *entry };
+  let mut flags = entry_flags(value);
+  if level == 2 {
+    // The bit means PAT in the last-level page table.
+    flags &= !PageAttrs::HUGE.bits();
+  }
+
+  let table = alloc_pages(1, AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK)?;
+  let entries = table.as_mut_ptr::<PageTableEntry>();
+  for i in 0..ENTRIES_PER_TABLE {
+    let paddr = entry_paddr(value).value() + i as usize * nth_level_page_size(level - 1);
+    unsafe { *entries.offset(i) = paddr as u64 | flags };
+  }
+
+  unsafe { *entry = table.value() as u64 | (PageAttrs::PRESENT | PageAttrs::WRITABLE).bits() };
+  Ok(table)
+}
+
+/// Returns the entry in the `level`-th page table which maps the kernel
+/// address `vaddr`, allocating the intermediate tables and splitting huge
+/// pages as needed. The caller must hold `KERNEL_PAGE_TABLE_LOCK`.
+fn traverse_kernel(vaddr: usize, level: usize) -> Result<*mut PageTableEntry, PageAllocError> {
+  let mut table = kernel_pml4().as_mut_ptr::<PageTableEntry>();
+  for current in ((level + 1)..=4).rev() {
+    let entry = unsafe { table.offset(nth_level_table_index(vaddr, current)) };
+    let value = unsafe { *entry };
+    let table_paddr = if value & PageAttrs::PRESENT.bits() == 0 {
+      // Tables are zero-filled as we don't pass DIRTY_OK.
+      let new_table = alloc_pages(1, AllocPageFlags::KERNEL)?;
+      unsafe {
+        *entry = new_table.value() as u64 | (PageAttrs::PRESENT | PageAttrs::WRITABLE).bits()
+      };
+      new_table
+    } else if value & PageAttrs::HUGE.bits() != 0 {
+      split_huge_page(entry, current)?
+    } else {
+      entry_paddr(value)
+    };
+
+    table = table_paddr.as_mut_ptr::<PageTableEntry>();
+  }
+
+  Ok(unsafe { table.offset(nth_level_table_index(vaddr, level)) })
+}
+
+/// Makes the page at `paddr` inaccessible through the straight map so that
+/// touching it faults. Used for the guard pages under kernel stacks.
+pub fn unmap_straight_page(paddr: PAddr) -> Result<(), PageAllocError> {
+  let _guard = KERNEL_PAGE_TABLE_LOCK.lock();
+  let vaddr = paddr.as_vaddr().value();
+  let entry = traverse_kernel(vaddr, 1)?;
+  unsafe {
+    *entry &= !PageAttrs::PRESENT.bits();
+    x86::tlb::flush(vaddr);
+  }
+
+  Ok(())
+}
+
+/// Undoes `unmap_straight_page`.
+pub fn remap_straight_page(paddr: PAddr) {
+  let _guard = KERNEL_PAGE_TABLE_LOCK.lock();
+  // The tables down to the page have been allocated by unmap_straight_page.
+  let entry = traverse_kernel(paddr.as_vaddr().value(), 1).unwrap();
+  unsafe {
+    *entry |= PageAttrs::PRESENT.bits();
+  }
+}
+
+/// Maps the physical memory `[paddr, paddr + len)` into the straight map,
+/// using 1 GiB and 2 MiB pages wherever the alignment and the CPU allow.
+///
+/// Process page tables share the kernel's PML4 entries as they were when
+/// the process was created, so this must be called during boot.
+pub fn map_straight(paddr: PAddr, len: usize) -> Result<(), PageAllocError> {
+  debug_assert!(is_aligned(paddr.value(), PAGE_SIZE));
+  debug_assert!(is_aligned(len, PAGE_SIZE));
+
+  let has_1gib_pages = CpuId::new()
+    .get_extended_processor_and_feature_identifiers()
+    .map_or(false, |feats| feats.has_1gib_pages());
+  let largest_level = if has_1gib_pages { 3 } else { 2 };
+
+  let _guard = KERNEL_PAGE_TABLE_LOCK.lock();
+  let mut current = paddr.value();
+  let end = paddr.value() + len;
+  while current < end {
+    let level = (1..=largest_level)
+      .rev()
+      .find(|&level| {
+        let page_size = nth_level_page_size(level);
+        is_aligned(current, page_size) && current + page_size <= end
+      })
+      .unwrap();
+
+    let mut attrs = PageAttrs::PRESENT | PageAttrs::WRITABLE | PageAttrs::GLOBAL;
+    if level > 1 {
+      attrs |= PageAttrs::HUGE;
+    }
+
+    let entry = traverse_kernel(KERNEL_BASE_ADDR + current, level)?;
+    unsafe { *entry = current as u64 | attrs.bits() };
+    current += nth_level_page_size(level);
+  }
+
+  Ok(())
+}
+
+extern "C" {
+  static __kernel_text: u8;
+  static __kernel_text_end: u8;
+  static __kernel_rodata_end: u8;
+  static __kernel_image_end: u8;
+  static __boot_stack_guard: u8;
+}
+
+fn kernel_text() -> Range<usize> {
+  unsafe { (&__kernel_text as *const u8 as usize)..(&__kernel_text_end as *const u8 as usize) }
+}
+
+/// Sets `NO_EXECUTE` on the pages mapped through the `level`-th page table
+/// `table`, which starts at `vaddr`, except the ones overlapping the kernel
+/// text.
+fn set_nx_recursively(table: PAddr, level: usize, vaddr: usize) {
+  let text = kernel_text();
+  let table = table.as_mut_ptr::<PageTableEntry>();
+  for i in 0..ENTRIES_PER_TABLE {
+    let entry = unsafe { &mut *table.offset(i) };
+    if *entry & PageAttrs::PRESENT.bits() == 0 {
+      continue;
+    }
+
+    let page_vaddr = vaddr + i as usize * nth_level_page_size(level);
+    if level == 1 || *entry & PageAttrs::HUGE.bits() != 0 {
+      let page_end = page_vaddr + nth_level_page_size(level);
+      if page_end <= text.start || text.end <= page_vaddr {
+        *entry |= PageAttrs::NO_EXECUTE.bits();
+      }
+    } else {
+      set_nx_recursively(entry_paddr(*entry), level - 1, page_vaddr);
+    }
+  }
+}
+
+/// Remaps the 2 MiB pages covering the kernel image with 4 KiB pages: the
+/// text is read-only, everything else is non-executable, and the guard page
+/// under the boot stack is unmapped.
+fn protect_kernel_image() -> Result<(), PageAllocError> {
+  let text = kernel_text();
+  let rodata_end = unsafe { &__kernel_rodata_end as *const u8 as usize };
+  // A physical address.
+  let image_end = unsafe { &__kernel_image_end as *const u8 as usize };
+  let boot_stack_guard = unsafe { &__boot_stack_guard as *const u8 as usize };
+
+  let huge_page_size = nth_level_page_size(2);
+  let mut huge_page = align_down(text.start, huge_page_size);
+  while huge_page < align_up(KERNEL_BASE_ADDR + image_end, huge_page_size) {
+    let entry = traverse_kernel(huge_page, 2)?;
+    debug_assert!(unsafe { *entry } & PageAttrs::HUGE.bits() != 0);
+
+    let table = alloc_pages(1, AllocPageFlags::KERNEL)?;
+    let entries = table.as_mut_ptr::<PageTableEntry>();
+    for i in 0..ENTRIES_PER_TABLE {
+      let vaddr = huge_page + i as usize * PAGE_SIZE;
+      if vaddr == boot_stack_guard {
+        // Leave it unmapped.
+        continue;
+      }
+
+      let mut attrs = PageAttrs::PRESENT | PageAttrs::GLOBAL;
+      if !(text.start..rodata_end).contains(&vaddr) {
+        attrs |= PageAttrs::WRITABLE;
+      }
+
+      if !text.contains(&vaddr) && NX_ENABLED.load(Ordering::Relaxed) {
+        attrs |= PageAttrs::NO_EXECUTE;
+      }
+
+      unsafe { *entries.offset(i) = (vaddr - KERNEL_BASE_ADDR) as u64 | attrs.bits() };
+    }
+
+    unsafe { *entry = table.value() as u64 | (PageAttrs::PRESENT | PageAttrs::WRITABLE).bits() };
+    huge_page += huge_page_size;
+  }
+
+  Ok(())
+}
+
+/// Enforces W^X in the kernel: the kernel text and rodata become read-only,
+/// and the rest of the straight map non-executable. Called once on boot
+/// after `init_nx`.
+pub fn protect_kernel() {
+  let _guard = KERNEL_PAGE_TABLE_LOCK.lock();
+  // The text is never unmapped nor made non-executable on the way: we're
+  // running on it.
+  protect_kernel_image().expect("failed to remap the kernel image");
+
+  if NX_ENABLED.load(Ordering::Relaxed) {
+    let pml4 = kernel_pml4().as_mut_ptr::<PageTableEntry>();
+    let first = nth_level_table_index(KERNEL_BASE_ADDR, 4);
+    let num_entries = (KERNEL_STRAIGHT_MAP_PADDR_END / nth_level_page_size(4)) as isize;
+    for i in first..(first + num_entries) {
+      let entry = unsafe { *pml4.offset(i) };
+      if entry & PageAttrs::PRESENT.bits() != 0 {
+        let vaddr = KERNEL_BASE_ADDR + (i - first) as usize * nth_level_page_size(4);
+        set_nx_recursively(entry_paddr(entry), 3, vaddr);
+      }
+    }
+  }
+
+  // Reloading CR3 doesn't flush the global pages: toggle CR4.PGE instead.
+  unsafe {
+    let cr4 = controlregs::cr4();
+    controlregs::cr4_write(cr4 - Cr4::CR4_ENABLE_GLOBAL_PAGES);
+    controlregs::cr4_write(cr4);
+  }
+}
+
+fn allocate_pml4() -> Result<PAddr, PageAllocError> {
   let pml4 = alloc_pages(1, AllocPageFlags::KERNEL)?;
 
   // Map kernel pages.
   unsafe {
-    let kernel_pml4 = PAddr::new(&__kernel_pml4 as *const u8 as usize).as_vaddr();
+    let kernel_pml4 = kernel_pml4().as_vaddr();
     pml4.as_mut_ptr::<u8>().write_bytes(0, PAGE_SIZE);
     ptr::copy_nonoverlapping::<u8>(kernel_pml4.as_ptr(), pml4.as_mut_ptr(), PAGE_SIZE);
   }
@@ -183,19 +525,80 @@ impl PageTable {
     }
   }
 
-  pub fn map_user_page(&mut self, vaddr: UserVAddr, paddr: PAddr) {
-    self.map_page(
-      vaddr,
-      paddr,
-      PageAttrs::PRESENT | PageAttrs::USER | PageAttrs::WRITABLE,
-    );
+  /// Maps a user page. Pages are always readable: `PageProt::READ` is up to
+  /// the page fault handler. Fails if a page table can't be allocated.
+  pub fn map_user_page(
+    &mut self,
+    vaddr: UserVAddr,
+    paddr: PAddr,
+    prot: PageProt,
+  ) -> Result<(), PageAllocError> {
+    self.map_page(vaddr, paddr, user_page_attrs(prot))
+  }
+
+  /// Maps a page of a shared mapping: unlike the other pages, it's shared
+  /// with the child process on fork(2) instead of being copied.
+  pub fn map_shared_user_page(
+    &mut self,
+    vaddr: UserVAddr,
+    paddr: PAddr,
+    prot: PageProt,
+  ) -> Result<(), PageAllocError> {
+    self.map_page(vaddr, paddr, user_page_attrs(prot) | PageAttrs::SHARED)
+  }
+
+  /// Makes the page mapped at `vaddr`, if any, read-only.
+  pub fn write_protect_user_page(&mut self, vaddr: UserVAddr) {
+    if let Ok(Some(mut entry)) = traverse(self.pml4, vaddr, false) {
+      unsafe {
+        *entry.as_mut() &= !PageAttrs::WRITABLE.bits();
+        x86::tlb::flush(vaddr.value());
+      }
+    }
+  }
+
+  /// Returns the physical page mapped at `vaddr`, if any.
+  pub fn lookup_user_page(&self, vaddr: UserVAddr) -> Option<PAddr> {
+    let entry = traverse(self.pml4, vaddr, false).ok().flatten()?;
+    let value = unsafe { *entry.as_ptr() };
+    if value & PageAttrs::PRESENT.bits() == 0 {
+      return None;
+    }
+
+    Some(entry_paddr(value))
   }
 
-  fn map_page(&mut self, vaddr: UserVAddr, paddr: PAddr, attrs: PageAttrs) {
+  /// Removes the mapping at `vaddr` and returns the physical page it pointed
+  /// to. Freeing the page is up to the caller.
+  pub fn unmap_user_page(&mut self, vaddr: UserVAddr) -> Option<PAddr> {
+    let mut entry = traverse(self.pml4, vaddr, false).ok().flatten()?;
+    let value = unsafe { *entry.as_ptr() };
+    if value & PageAttrs::PRESENT.bits() == 0 {
+      return None;
+    }
+
+    unsafe {
+      *entry.as_mut() = 0;
+      x86::tlb::flush(vaddr.value());
+    }
+
+    Some(entry_paddr(value))
+  }
+
+  fn map_page(
+    &mut self,
+    vaddr: UserVAddr,
+    paddr: PAddr,
+    attrs: PageAttrs,
+  ) -> Result<(), PageAllocError> {
     debug_assert!(is_aligned(vaddr.value(), PAGE_SIZE));
-    let mut entry = traverse(self.pml4, vaddr, true, attrs).unwrap();
+    let mut entry = traverse(self.pml4, vaddr, true)?.unwrap();
     unsafe {
       *entry.as_mut() = paddr.value() as u64 | attrs.bits();
+      // The page might have been mapped with different attributes before.
+      x86::tlb::flush(vaddr.value());
     }
+
+    Ok(())
   }
 }
@@ -4,8 +4,9 @@ use super::gdt::{KERNEL_CS, USER_CS32};
 use x86::msr::{self, rdmsr, wrmsr};
 
 // Clear IF bit to disable interrupts when we enter the syscall handler
-// or an interrupt occurs before doing SWAPGS.
-const SYSCALL_RFLAGS_MASK: u64 = 0x200;
+// or an interrupt occurs before doing SWAPGS, and AC bit not to let the
+// kernel access user pages outside usercopy.
+const SYSCALL_RFLAGS_MASK: u64 = 0x40200;
 
 #[repr(C, packed)]
 #[derive(Clone, Copy)]
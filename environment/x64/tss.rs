@@ -1,9 +1,17 @@
-use crate::cpu_local;
+use crate::{
+    cpu_local,
+    page_allocator::{alloc_pages, AllocPageFlags},
+};
 
-use super::gdt::TSS_SEG;
+use super::{gdt::TSS_SEG, PAGE_SIZE};
 use x86::{segmentation::SegmentSelector, task::load_tr};
 
 pub const IST_RSP0: u8 = 0;
+/// Double faults are handled on a stack of their own: they're often caused
+/// by a kernel stack overflow.
+pub const IST_DOUBLE_FAULT: u8 = 1;
+
+const DOUBLE_FAULT_STACK_SIZE: usize = PAGE_SIZE * 32;
 
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
@@ -25,6 +33,13 @@ impl Tss {
     pub fn set_rsp0(&mut self, rsp0: u64) {
         self.rsp0 = rsp0;
     }
+
+    /// Sets the stack the CPU switches to on interrupts with IST `index`.
+    pub fn set_ist(&mut self, index: u8, rsp: u64) {
+        let mut ist = self.ist;
+        ist[index as usize - 1] = rsp;
+        self.ist = ist;
+    }
 }
 
 cpu_local! {
@@ -45,5 +60,14 @@ cpu_local! {
 }
 
 pub unsafe fn init() {
+    let double_fault_stack = alloc_pages(
+        DOUBLE_FAULT_STACK_SIZE / PAGE_SIZE,
+        AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
+    )
+    .expect("failed to allocate the double fault stack");
+    let double_fault_sp = double_fault_stack.as_vaddr().add(DOUBLE_FAULT_STACK_SIZE);
+    TSS.as_mut()
+        .set_ist(IST_DOUBLE_FAULT, double_fault_sp.value() as u64);
+
     load_tr(SegmentSelector::from_raw(TSS_SEG));
 }
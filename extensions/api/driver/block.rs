@@ -16,6 +16,20 @@ pub trait BlockDriver: Driver {
 	fn read_sector(&self, sector: usize, buf: &mut [u8]);
 	fn read_sectors(&self, sectors: Range<usize>, buf: &mut [u8]);
 
+	fn write_sector(&self, sector: usize, buf: &[u8]);
+
+	fn write_sectors(&self, sectors: Range<usize>, buf: &[u8]) {
+		let sector_size = self.sector_size();
+		assert!(buf.len() >= sectors.clone().count() * sector_size);
+
+		for (i, sector) in sectors.enumerate() {
+			self.write_sector(sector, &buf[i * sector_size..(i + 1) * sector_size]);
+		}
+	}
+
+	/// Makes sure all the writes submitted so far have reached the medium.
+	fn flush(&self) {}
+
 	fn sector_size(&self) -> usize;
 }
 
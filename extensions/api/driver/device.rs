@@ -0,0 +1,192 @@
+//! The registry of device nodes. Drivers publish their devices here under a
+//! name and a device number, and devfs shows whatever is registered.
+
+use core::{
+	fmt,
+	sync::atomic::{AtomicU32, Ordering},
+};
+
+use alloc::{
+	borrow::ToOwned, boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec,
+};
+use environment::spinlock::SpinLock;
+
+use crate::{vfs, Error, ErrorKind, Result};
+
+/// Majors handed out by `alloc_major`, the range Linux leaves for dynamic
+/// assignment.
+const DYNAMIC_MAJOR_FIRST: u32 = 234;
+const DYNAMIC_MAJOR_LAST: u32 = 254;
+
+static DEVICES: SpinLock<BTreeMap<String, Device>> = SpinLock::new(BTreeMap::new());
+static LISTENERS: SpinLock<Vec<Box<dyn DeviceListener>>> = SpinLock::new(Vec::new());
+static NEXT_DYNAMIC_MAJOR: AtomicU32 = AtomicU32::new(DYNAMIC_MAJOR_LAST);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+	Char,
+	Block,
+}
+
+impl From<DeviceKind> for vfs::FileKind {
+	fn from(kind: DeviceKind) -> Self {
+		match kind {
+			DeviceKind::Char => vfs::FileKind::CharDevice,
+			DeviceKind::Block => vfs::FileKind::BlockDevice,
+		}
+	}
+}
+
+/// The major number picks the driver, the minor number the device among
+/// those of the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceNumber {
+	major: u32,
+	minor: u32,
+}
+
+impl DeviceNumber {
+	pub const fn new(major: u32, minor: u32) -> Self {
+		Self { major, minor }
+	}
+
+	pub const fn major(self) -> u32 {
+		self.major
+	}
+
+	pub const fn minor(self) -> u32 {
+		self.minor
+	}
+}
+
+impl fmt::Display for DeviceNumber {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}", self.major, self.minor)
+	}
+}
+
+/// A registered device node.
+#[derive(Clone)]
+pub struct Device {
+	name: String,
+	kind: DeviceKind,
+	number: DeviceNumber,
+	file: Arc<dyn vfs::File>,
+}
+
+impl fmt::Debug for Device {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Device")
+			.field("name", &self.name)
+			.field("kind", &self.kind)
+			.field("number", &self.number)
+			.finish()
+	}
+}
+
+impl Device {
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn kind(&self) -> DeviceKind {
+		self.kind
+	}
+
+	pub fn number(&self) -> DeviceNumber {
+		self.number
+	}
+
+	/// The file opening the device node leads to.
+	pub fn file(&self) -> &Arc<dyn vfs::File> {
+		&self.file
+	}
+}
+
+pub enum DeviceEvent<'a> {
+	Added(&'a Device),
+	Removed(&'a Device),
+}
+
+/// Gets notified when devices come and go.
+pub trait DeviceListener: Send + Sync {
+	/// Called with the registry locked: registering or unregistering a
+	/// device from here deadlocks.
+	fn on_event(&self, event: DeviceEvent<'_>);
+}
+
+/// Publishes `file` as the device node `name`. Fails with `Busy` if the
+/// name or the number is already taken.
+pub fn register_device(
+	name: &str,
+	kind: DeviceKind,
+	number: DeviceNumber,
+	file: Arc<dyn vfs::File>,
+) -> Result<()> {
+	let mut devices = DEVICES.lock();
+	if devices.contains_key(name) {
+		return Err(Error::with_message(
+			ErrorKind::Busy,
+			"device name already registered",
+		));
+	}
+
+	if devices
+		.values()
+		.any(|device| device.kind == kind && device.number == number)
+	{
+		return Err(Error::with_message(
+			ErrorKind::Busy,
+			"device number already registered",
+		));
+	}
+
+	let device = Device {
+		name: name.to_owned(),
+		kind,
+		number,
+		file,
+	};
+
+	for listener in LISTENERS.lock().iter() {
+		listener.on_event(DeviceEvent::Added(&device));
+	}
+
+	devices.insert(name.to_owned(), device);
+	Ok(())
+}
+
+/// Withdraws the device node `name`, e.g. when its device is unplugged.
+pub fn unregister_device(name: &str) -> Result<Device> {
+	let mut devices = DEVICES.lock();
+	let device = devices.remove(name).ok_or(ErrorKind::NoEntry)?;
+	for listener in LISTENERS.lock().iter() {
+		listener.on_event(DeviceEvent::Removed(&device));
+	}
+
+	Ok(device)
+}
+
+pub fn lookup_device(name: &str) -> Option<Device> {
+	DEVICES.lock().get(name).cloned()
+}
+
+/// Registers `listener`, which is first told about the devices already
+/// registered.
+pub fn register_device_listener(listener: Box<dyn DeviceListener>) {
+	let devices = DEVICES.lock();
+	for device in devices.values() {
+		listener.on_event(DeviceEvent::Added(device));
+	}
+
+	LISTENERS.lock().push(listener);
+}
+
+/// Hands out a major number no well-known driver uses.
+pub fn alloc_major() -> Result<u32> {
+	NEXT_DYNAMIC_MAJOR
+		.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |major| {
+			(major >= DYNAMIC_MAJOR_FIRST).then(|| major - 1)
+		})
+		.map_err(|_| Error::with_message(ErrorKind::Busy, "out of dynamic major numbers"))
+}
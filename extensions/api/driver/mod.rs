@@ -73,11 +73,13 @@ impl fmt::Display for VirtioKind {
 		match self {
 			VirtioKind::NetworkCard => write!(f, "virtio-net"),
 			VirtioKind::BlockDevice => write!(f, "virtio-blk"),
+			VirtioKind::Filesystem => write!(f, "virtio-9p"),
 			_ => unimplemented!("{:?}", self),
 		}
 	}
 }
 
 pub mod block;
+pub mod device;
 pub mod net;
 pub mod pci;
@@ -20,6 +20,12 @@ bitflags! {
 pub struct OpenOptions {
 	pub nonblock: bool,
 	pub close_on_exec: bool,
+	/// Writes go to the end of the file, wherever the position is.
+	pub append: bool,
+	/// Opened with `O_RDONLY` or `O_RDWR`.
+	pub read: bool,
+	/// Opened with `O_WRONLY` or `O_RDWR`.
+	pub write: bool,
 }
 
 impl OpenOptions {
@@ -27,6 +33,9 @@ impl OpenOptions {
 		OpenOptions {
 			nonblock,
 			close_on_exec: cloexec,
+			append: false,
+			read: true,
+			write: true,
 		}
 	}
 
@@ -34,6 +43,9 @@ impl OpenOptions {
 		OpenOptions {
 			nonblock: false,
 			close_on_exec: false,
+			append: false,
+			read: false,
+			write: false,
 		}
 	}
 
@@ -41,15 +53,23 @@ impl OpenOptions {
 		OpenOptions {
 			nonblock: false,
 			close_on_exec: false,
+			append: false,
+			read: true,
+			write: true,
 		}
 	}
 }
 
 impl From<OpenFlags> for OpenOptions {
 	fn from(flags: OpenFlags) -> OpenOptions {
+		// O_RDONLY is 0, so the access mode can't be tested with contains().
+		let access_mode = flags & (OpenFlags::O_WRONLY | OpenFlags::O_RDWR);
 		OpenOptions {
 			nonblock: flags.contains(OpenFlags::O_NONBLOCK),
 			close_on_exec: flags.contains(OpenFlags::O_CLOEXEC),
+			append: flags.contains(OpenFlags::O_APPEND),
+			read: access_mode != OpenFlags::O_WRONLY,
+			write: !access_mode.is_empty(),
 		}
 	}
 }
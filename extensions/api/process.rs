@@ -20,7 +20,7 @@ pub enum ProcessState {
   Runnable,
   /// The process is sleeping. It can be resumed by signals.
   BlockedSignalable,
-  /// The process has exited.
+  /// The process has exited. It holds the status reported by wait4(2).
   Exited(c_int),
 }
 
@@ -41,8 +41,27 @@ pub enum ErrorKind {
 
   EINVAL = 22,
   ENOSYS = 38,
+  ENOSPC = 28,
+  EROFS = 30,
+
+  Busy,
+  NoDevice,
+  PermissionDenied,
+  TooManySymlinks,
+  /// The operation would have to wait, but the caller asked not to.
+  WouldBlock,
+  /// The file to create exists already.
+  AlreadyExists,
 }
 
+/// The errnos of the kinds whose discriminants are taken by other kinds.
+const ENOEXEC: isize = 8;
+const EAGAIN: isize = 11;
+const ENOMEM: isize = 12;
+const EACCES: isize = 13;
+const EFAULT: isize = 14;
+const EEXIST: isize = 17;
+
 pub type Result<T> = ::core::result::Result<T, Error>;
 
 enum ErrorMessage {
@@ -89,7 +108,18 @@ impl Error {
   }
 
   pub fn errno(&self) -> usize {
-    -(self.kind as isize) as usize
+    let errno = match self.kind {
+      // Both mean that we've run out of memory.
+      ErrorKind::AllocationError | ErrorKind::OutOfMemory => ENOMEM,
+      ErrorKind::PageFault => EFAULT,
+      ErrorKind::NotExecutable => ENOEXEC,
+      ErrorKind::AlreadyExists => EEXIST,
+      ErrorKind::WouldBlock => EAGAIN,
+      ErrorKind::PermissionDenied => EACCES,
+      kind => kind as isize,
+    };
+
+    -errno as usize
   }
 }
 
@@ -148,8 +178,11 @@ impl From<ErrorKind> for Error {
 }
 
 impl From<AccessError> for Error {
-  fn from(_error: AccessError) -> Error {
-    Error::new(ErrorKind::PageFault)
+  fn from(error: AccessError) -> Error {
+    match error {
+      AccessError::Fault => Error::new(ErrorKind::PageFault),
+      AccessError::OutOfMemory => Error::new(ErrorKind::OutOfMemory),
+    }
   }
 }
 
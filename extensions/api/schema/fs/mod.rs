@@ -1,26 +1,8 @@
 use core::ops::Range;
 
-use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
-use environment::spinlock::SpinLock;
-
-pub use directory::Directory;
-pub use file::File;
-pub use filesystem::{DirEntry, FileType};
-
-use utils::once::Once;
-
-use crate::kernel::kernel_ops;
-
-use self::vfs::Vfs;
-
-static PARTITION_PROBERS: SpinLock<Vec<Box<dyn PartitionProber>>> = SpinLock::new(vec![]);
-pub static PARTITIONS: SpinLock<BTreeMap<usize, Arc<dyn crate::vfs::Filesystem>>> =
-	SpinLock::new(BTreeMap::new());
-
-pub static VFS: Once<SpinLock<Vfs>> = Once::new();
-
 pub trait Partition: Send + Sync {
 	fn read_sector(&self, sector: usize, buf: &mut [u8]);
+	fn write_sector(&self, sector: usize, buf: &[u8]);
 
 	fn in_sectors(&self, size: usize) -> usize;
 	fn block_size(&self) -> usize;
@@ -35,33 +17,44 @@ pub trait Partition: Send + Sync {
 			self.read_sector(sector, &mut buf[i * block_size..(i + 1) * block_size])
 		}
 	}
-}
 
-pub trait PartitionProber: Send + Sync {
-	fn probe(
-		&self,
-		partition: Arc<SpinLock<dyn Partition>>,
-		number: usize,
-	) -> Option<Arc<dyn crate::vfs::Filesystem>>;
-}
+	fn write_sectors(&self, sectors: Range<usize>, buf: &[u8]) {
+		let block_size = self.block_size();
+		assert!(buf.len() >= sectors.clone().count() * block_size);
 
-pub fn register_partition_prober(prober: Box<dyn PartitionProber>) {
-	PARTITION_PROBERS.lock().push(prober);
-}
+		for (i, sector) in sectors.enumerate() {
+			self.write_sector(sector, &buf[i * block_size..(i + 1) * block_size])
+		}
+	}
 
-pub fn init() {
-	VFS.init(|| SpinLock::new(Vfs::new()));
+	/// Reads `buf.len()` bytes at `offset` from the start of the partition.
+	fn read_bytes(&self, offset: u64, buf: &mut [u8]) {
+		let sector_size = self.block_size() as u64;
+		let first = offset / sector_size;
+		let last = (offset + buf.len() as u64).div_ceil(sector_size);
 
-	for (num, partition) in kernel_ops().request_partitions().iter().enumerate() {
-		for prober in PARTITION_PROBERS.lock().iter() {
-			if let Some(partition) = prober.probe(partition.clone(), num) {
-				PARTITIONS.lock().insert(num, partition);
-			}
-		}
+		let mut tmp = vec![0u8; ((last - first) * sector_size) as usize];
+		self.read_sectors(first as usize..last as usize, &mut tmp);
+
+		let start = (offset - first * sector_size) as usize;
+		buf.copy_from_slice(&tmp[start..start + buf.len()]);
 	}
-}
 
-pub mod directory;
-pub mod file;
-pub mod filesystem;
-pub mod vfs;
+	/// Writes `buf` at `offset` from the start of the partition, keeping the
+	/// rest of the sectors it touches.
+	fn write_bytes(&self, offset: u64, buf: &[u8]) {
+		let sector_size = self.block_size() as u64;
+		let first = offset / sector_size;
+		let last = (offset + buf.len() as u64).div_ceil(sector_size);
+
+		let mut tmp = vec![0u8; ((last - first) * sector_size) as usize];
+		self.read_sectors(first as usize..last as usize, &mut tmp);
+
+		let start = (offset - first * sector_size) as usize;
+		tmp[start..start + buf.len()].copy_from_slice(buf);
+		self.write_sectors(first as usize..last as usize, &tmp);
+	}
+
+	/// Writes back everything that was written to the partition so far.
+	fn sync(&self) {}
+}
@@ -4,7 +4,7 @@ use alloc::{borrow::ToOwned, string::String, sync::Arc};
 
 use crate::schema::unix::PathBuf;
 
-use super::Node;
+use super::{mount::Mount, Node};
 
 #[derive(Clone)]
 pub struct PathComponent {
@@ -14,6 +14,8 @@ pub struct PathComponent {
 	pub name: String,
 	/// The referenced node.
 	pub node: Node,
+	/// The mount `node` belongs to.
+	pub mount: Arc<Mount>,
 }
 
 impl PathComponent {
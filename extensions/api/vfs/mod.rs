@@ -1,27 +1,37 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use alloc::{string::String, sync::Arc};
 use environment::spinlock::SpinLockGuard;
 
 use crate::{
+  address::PAddr,
   ctypes::c_int,
   io,
-  schema::posix::{FileMode, FileSize, INodeNo},
+  schema::{
+    posix::{DevId, FileMode, FileSize, INodeNo},
+    unix::PathBuf,
+  },
   user_buffer::{UserBuffer, UserBufferMut},
   ErrorKind, Process, Result,
 };
 
 use self::opened_file::OpenedFile;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+/// Identifies a filesystem instance. Reported as `st_dev`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 #[repr(transparent)]
-pub struct NodeId(usize);
+pub struct FsId(usize);
 
-impl NodeId {
-  pub const fn new(no: usize) -> Self {
-    Self(no)
+impl FsId {
+  pub const fn new(id: usize) -> Self {
+    Self(id)
   }
 
-  pub const fn as_u64(self) -> u64 {
-    self.0 as u64
+  /// Allocates the ID of a new filesystem instance.
+  pub fn alloc() -> Self {
+    static NEXT_FS_ID: AtomicUsize = AtomicUsize::new(1);
+
+    Self(NEXT_FS_ID.fetch_add(1, Ordering::Relaxed))
   }
 
   pub const fn as_usize(self) -> usize {
@@ -29,6 +39,29 @@ impl NodeId {
   }
 }
 
+/// Identifies a node across all filesystems: inode numbers are only unique
+/// within the filesystem they come from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct NodeId {
+  fs: FsId,
+  ino: usize,
+}
+
+impl NodeId {
+  pub const fn new(fs: FsId, ino: usize) -> Self {
+    Self { fs, ino }
+  }
+
+  pub const fn fs(self) -> FsId {
+    self.fs
+  }
+
+  /// The inode number within the filesystem.
+  pub const fn ino(self) -> usize {
+    self.ino
+  }
+}
+
 const FD_MAX: c_int = 1024;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -52,6 +85,7 @@ impl Fd {
 pub enum FileType {
   Directory,
   RegularFile,
+  Symlink,
 }
 
 pub trait Filesystem: Send + Sync {
@@ -71,6 +105,18 @@ pub trait Directory: Send + Sync + core::fmt::Debug {
   fn read_dir(&self, index: usize) -> Result<Option<DirEntry>>;
 
   fn stat(&self) -> Result<Stat>;
+
+  /// Creates an empty regular file named `name`, with the permission bits
+  /// of `mode`.
+  fn create_file(&self, _name: &str, _mode: FileMode) -> Result<Node> {
+    Err(ErrorKind::NotSupported.into())
+  }
+
+  /// Removes the entry `name`. Processes which have the file opened or
+  /// mapped keep using it.
+  fn unlink(&self, _name: &str) -> Result<()> {
+    Err(ErrorKind::NotSupported.into())
+  }
 }
 
 // pub struct ReadDir<'a> {
@@ -97,12 +143,45 @@ pub trait File: Send + Sync + core::fmt::Debug {
   fn write(&self, offset: usize, buf: UserBuffer<'_>, options: &io::OpenOptions) -> Result<usize>;
 
   fn stat(&self) -> Result<Stat>;
+
+  /// Shrinks or extends the file to `len` bytes. Extended parts read as
+  /// zeroes.
+  fn truncate(&self, _len: usize) -> Result<()> {
+    Err(ErrorKind::NotSupported.into())
+  }
+
+  /// Writes what is cached of the file back to its device.
+  fn fsync(&self) -> Result<()> {
+    Ok(())
+  }
+
+  /// Whether `write` is supported. Shared writable mappings need it to
+  /// write their pages back.
+  fn is_writable(&self) -> bool {
+    true
+  }
+
+  /// Returns the page holding the data at `offset` (page aligned) if the
+  /// file lives in memory pages which shared mappings can map as they are.
+  /// Other files are mapped through the page cache.
+  fn mappable_page(&self, _offset: usize) -> Result<Option<PAddr>> {
+    Ok(None)
+  }
+}
+
+pub trait Symlink: Send + Sync + core::fmt::Debug {
+  /// The path the link points to, relative to the directory containing it
+  /// unless absolute.
+  fn link_target(&self) -> Result<PathBuf>;
+
+  fn stat(&self) -> Result<Stat>;
 }
 
 #[derive(Debug)]
 pub enum Node {
   Directory(Arc<dyn Directory>),
   File(Arc<dyn File>),
+  Symlink(Arc<dyn Symlink>),
 }
 
 impl Clone for Node {
@@ -110,6 +189,7 @@ impl Clone for Node {
     match self {
       Self::Directory(dir) => Self::Directory(dir.clone()),
       Self::File(file) => Self::File(file.clone()),
+      Self::Symlink(link) => Self::Symlink(link.clone()),
     }
   }
 }
@@ -137,10 +217,15 @@ impl Node {
     matches!(self, Node::File(_))
   }
 
+  pub fn is_symlink(&self) -> bool {
+    matches!(self, Node::Symlink(_))
+  }
+
   pub fn stat(&self) -> Result<Stat> {
     match self {
       Node::Directory(dir) => dir.stat(),
       Node::File(file) => file.stat(),
+      Node::Symlink(link) => link.stat(),
     }
   }
 }
@@ -151,6 +236,12 @@ impl From<Arc<dyn File>> for Node {
   }
 }
 
+impl From<Arc<dyn Symlink>> for Node {
+  fn from(link: Arc<dyn Symlink>) -> Self {
+    Self::Symlink(link)
+  }
+}
+
 impl From<Arc<dyn Directory>> for Node {
   fn from(dir: Arc<dyn Directory>) -> Self {
     Self::Directory(dir)
@@ -170,6 +261,7 @@ pub enum FileKind {
   Directory,
   CharDevice,
   BlockDevice,
+  Symlink,
 }
 
 pub const S_IFMT: u32 = 0o170000;
@@ -186,6 +278,7 @@ impl From<FileKind> for u32 {
       FileKind::Directory => S_IFDIR,
       FileKind::CharDevice => S_IFCHR,
       FileKind::BlockDevice => S_IFBLK,
+      FileKind::Symlink => S_IFLNK,
     }
   }
 }
@@ -194,7 +287,8 @@ impl From<Stat> for crate::schema::posix::Stat {
   fn from(stat: Stat) -> Self {
     let mut result = Self::zeroed();
     result.size = FileSize(stat.size as isize);
-    result.inode_no = INodeNo(stat.node_id.as_usize());
+    result.dev = DevId(stat.node_id.fs().as_usize());
+    result.inode_no = INodeNo(stat.node_id.ino());
     result.mode = FileMode(stat.kind.into());
     result
   }
@@ -203,3 +297,4 @@ impl From<Stat> for crate::schema::posix::Stat {
 pub mod interface;
 pub mod mount;
 pub mod opened_file;
+pub mod registry;
@@ -1,35 +1,226 @@
-use alloc::{borrow::ToOwned, string::String, sync::Arc};
+use core::{
+  fmt,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+  borrow::ToOwned,
+  collections::BTreeMap,
+  string::{String, ToString},
+  sync::Arc,
+  vec::Vec,
+};
+use environment::spinlock::SpinLock;
 use hashbrown::HashMap;
 
-use crate::{posix::CwdOrFd, schema::unix::Path, ErrorKind, Result};
+use crate::{
+  bitflags::bitflags,
+  posix::CwdOrFd,
+  schema::{
+    posix::FileMode,
+    unix::{Path, PathBuf},
+  },
+  ErrorKind, Result,
+};
 
 use super::{
   interface::PathComponent, opened_file::OpenedFileTable, Directory, Filesystem, Node, NodeId,
+  Symlink,
 };
 
 const DEFAULT_SYMLINK_FOLLOW_MAX: usize = 8;
+/// The dentry cache is flushed once it holds that many entries.
+const DENTRY_CACHE_MAX: usize = 4096;
+
+/// Bumped by [`invalidate_dentries`]. Dentry caches filled in an older
+/// generation are flushed on their next lookup.
+static DENTRY_GENERATION: AtomicUsize = AtomicUsize::new(0);
 
-pub struct MountPoint {
+bitflags! {
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct MountFlags: u64 {
+    const MS_RDONLY = 1;
+    const MS_NOEXEC = 8;
+    const MS_BIND = 4096;
+  }
+}
+
+bitflags! {
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct UmountFlags: i32 {
+    const MNT_FORCE = 1;
+    const MNT_DETACH = 2;
+    const UMOUNT_NOFOLLOW = 8;
+  }
+}
+
+pub type MountId = usize;
+
+const ROOT_MOUNT_ID: MountId = 0;
+
+/// A filesystem (or, for bind mounts, a directory of one) attached to the
+/// directory tree.
+pub struct Mount {
+  id: MountId,
   fs: Arc<dyn Filesystem>,
+  root: Arc<dyn Directory>,
+  flags: MountFlags,
+  source: String,
+  fs_type: String,
+  /// The directory we're mounted on. `None` for the root mount.
+  mountpoint: Option<Arc<PathComponent>>,
+}
+
+impl Mount {
+  pub fn id(&self) -> MountId {
+    self.id
+  }
+
+  pub fn flags(&self) -> MountFlags {
+    self.flags
+  }
+
+  pub fn is_read_only(&self) -> bool {
+    self.flags.contains(MountFlags::MS_RDONLY)
+  }
+
+  pub fn is_noexec(&self) -> bool {
+    self.flags.contains(MountFlags::MS_NOEXEC)
+  }
+
+  pub fn source(&self) -> &str {
+    &self.source
+  }
+
+  pub fn fs_type(&self) -> &str {
+    &self.fs_type
+  }
+
+  pub fn target(&self) -> PathBuf {
+    match &self.mountpoint {
+      Some(mountpoint) => mountpoint.resolve_absolute_path(),
+      None => PathBuf::from("/"),
+    }
+  }
+}
+
+/// Formats the mount like a line of `/proc/mounts`.
+impl fmt::Display for Mount {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} {} {} {}",
+      self.source,
+      self.target(),
+      self.fs_type,
+      if self.is_read_only() { "ro" } else { "rw" }
+    )?;
+
+    if self.is_noexec() {
+      write!(f, ",noexec")?;
+    }
+
+    write!(f, " 0 0")
+  }
+}
+
+/// Caches the path components found by looking up a name in a directory,
+/// so that resolving a path doesn't go through the filesystem for every
+/// component. Directories are identified by the address of their
+/// `PathComponent`: cached entries keep their parent alive, so the address
+/// can't be reused while it has entries.
+#[derive(Default)]
+struct DentryCache {
+  entries: HashMap<usize, HashMap<String, Arc<PathComponent>>>,
+  len: usize,
+  /// The `DENTRY_GENERATION` the entries were looked up in.
+  generation: usize,
+}
+
+impl DentryCache {
+  fn key(parent: &Arc<PathComponent>) -> usize {
+    Arc::as_ptr(parent) as usize
+  }
+
+  fn get(&mut self, parent: &Arc<PathComponent>, name: &str) -> Option<Arc<PathComponent>> {
+    let generation = DENTRY_GENERATION.load(Ordering::Acquire);
+    if self.generation != generation {
+      self.clear();
+      self.generation = generation;
+    }
+
+    self.entries.get(&Self::key(parent))?.get(name).cloned()
+  }
+
+  fn insert(&mut self, parent: &Arc<PathComponent>, path_comp: Arc<PathComponent>) {
+    if self.len >= DENTRY_CACHE_MAX {
+      self.clear();
+    }
+
+    let previous = self
+      .entries
+      .entry(Self::key(parent))
+      .or_default()
+      .insert(path_comp.name.clone(), path_comp);
+
+    if previous.is_none() {
+      self.len += 1;
+    }
+  }
+
+  fn clear(&mut self) {
+    self.entries.clear();
+    self.len = 0;
+  }
+}
+
+/// Makes every [`Rootfs`] forget its cached lookups, like
+/// [`Rootfs::forget_dentries`]. It takes no locks, so it can be called from
+/// code holding other locks, e.g. a device listener.
+pub fn invalidate_dentries() {
+  DENTRY_GENERATION.fetch_add(1, Ordering::AcqRel);
 }
 
 pub struct Rootfs {
   root_path: Arc<PathComponent>,
   cwd_path: Arc<PathComponent>,
-  mount_points: HashMap<NodeId, MountPoint>,
+  mounts: BTreeMap<MountId, Arc<Mount>>,
+  /// Maps a directory, identified by the mount it was looked up in and its
+  /// node, to the mount covering it.
+  mount_points: HashMap<(MountId, NodeId), MountId>,
+  next_mount_id: MountId,
+  dentries: SpinLock<DentryCache>,
   symlink_follow_limit: usize,
 }
 
 impl Rootfs {
   pub fn new(root: Arc<dyn Filesystem>) -> Result<Rootfs> {
+    let root_dir = root.root()?;
+    let root_mount = Arc::new(Mount {
+      id: ROOT_MOUNT_ID,
+      fs: root,
+      root: root_dir.clone(),
+      flags: MountFlags::empty(),
+      source: "rootfs".to_owned(),
+      fs_type: "rootfs".to_owned(),
+      mountpoint: None,
+    });
+
     let root_path = Arc::new(PathComponent {
       parent_dir: None,
       name: String::new(),
-      node: root.root()?.into(),
+      node: root_dir.into(),
+      mount: root_mount.clone(),
     });
 
+    let mut mounts = BTreeMap::new();
+    mounts.insert(ROOT_MOUNT_ID, root_mount);
+
     Ok(Rootfs {
+      mounts,
       mount_points: HashMap::new(),
+      next_mount_id: ROOT_MOUNT_ID + 1,
+      dentries: SpinLock::new(DentryCache::default()),
       root_path: root_path.clone(),
       cwd_path: root_path,
       symlink_follow_limit: DEFAULT_SYMLINK_FOLLOW_MAX,
@@ -40,13 +231,151 @@ impl Rootfs {
     self.lookup_node(path, true)
   }
 
-  pub fn mount(&mut self, dir: Arc<dyn Directory>, fs: Arc<dyn Filesystem>) -> Result<()> {
-    self
-      .mount_points
-      .insert(dir.stat()?.node_id, MountPoint { fs });
+  /// Mounts the root directory of `fs` on `target`.
+  pub fn mount(
+    &mut self,
+    target: &Path,
+    fs: Arc<dyn Filesystem>,
+    source: &str,
+    fs_type: &str,
+    flags: MountFlags,
+  ) -> Result<()> {
+    let root = fs.root()?;
+    self.attach(
+      target,
+      fs,
+      root,
+      source.to_owned(),
+      fs_type.to_owned(),
+      flags,
+    )
+  }
+
+  /// Makes the directory at `source` visible at `target` as well.
+  pub fn bind_mount(&mut self, source: &Path, target: &Path, flags: MountFlags) -> Result<()> {
+    let source = self.lookup_path(source, true)?;
+    let root = source.node.as_dir()?.clone();
+    let mount = source.mount.clone();
+
+    self.attach(
+      target,
+      mount.fs.clone(),
+      root,
+      mount.source.clone(),
+      mount.fs_type.clone(),
+      flags,
+    )
+  }
+
+  fn attach(
+    &mut self,
+    target: &Path,
+    fs: Arc<dyn Filesystem>,
+    root: Arc<dyn Directory>,
+    source: String,
+    fs_type: String,
+    flags: MountFlags,
+  ) -> Result<()> {
+    // Mounting on top of a mount point covers the topmost mount, which is
+    // what the lookup resolves to.
+    let mountpoint = self.lookup_path(target, true)?;
+    let key = (
+      mountpoint.mount.id,
+      mountpoint.node.as_dir()?.stat()?.node_id,
+    );
+
+    let id = self.next_mount_id;
+    self.next_mount_id += 1;
+
+    self.mounts.insert(
+      id,
+      Arc::new(Mount {
+        id,
+        fs,
+        root,
+        flags: flags - MountFlags::MS_BIND,
+        source,
+        fs_type,
+        mountpoint: Some(mountpoint),
+      }),
+    );
+    self.mount_points.insert(key, id);
+    // Cached lookups may lead to what's now covered by the mount.
+    self.forget_dentries();
+
+    Ok(())
+  }
+
+  /// Unmounts the filesystem mounted on `target`. Fails with `Busy` while
+  /// anything (an opened file, the working directory, another mount) still
+  /// refers to a path inside it, unless `MNT_DETACH` is given, in which
+  /// case the mount and everything below it disappear from the tree right
+  /// away and the filesystem goes away once the last user is done with it.
+  pub fn unmount(&mut self, target: &Path, flags: UmountFlags) -> Result<()> {
+    let path = self.lookup_path(target, !flags.contains(UmountFlags::UMOUNT_NOFOLLOW))?;
+    let mount = path.mount.clone();
+
+    // `target` has to be the root of a mount, not any directory inside it.
+    let is_mount_root = path
+      .parent_dir
+      .as_ref()
+      .map_or(false, |parent| !Arc::ptr_eq(&parent.mount, &mount));
+    drop(path);
+    // Cached path components hold on to their mount.
+    self.forget_dentries();
+
+    if !is_mount_root {
+      return Err(ErrorKind::EINVAL.into());
+    }
+
+    // The mount table and `mount` are the only references left when no
+    // path inside the mount is in use.
+    if !flags.contains(UmountFlags::MNT_DETACH) && Arc::strong_count(&mount) > 2 {
+      return Err(ErrorKind::Busy.into());
+    }
+
+    self.detach(mount.id);
     Ok(())
   }
 
+  fn detach(&mut self, id: MountId) {
+    if self.mounts.remove(&id).is_none() {
+      return;
+    }
+
+    self.mount_points.retain(|_, mount| *mount != id);
+
+    let children = self
+      .mount_points
+      .iter()
+      .filter(|((parent, _), _)| *parent == id)
+      .map(|(_, child)| *child)
+      .collect::<Vec<_>>();
+
+    for child in children {
+      self.detach(child);
+    }
+  }
+
+  /// Drops every cached lookup result. Filesystems whose directories
+  /// change behind the VFS's back (e.g. entries being removed) need this.
+  pub fn forget_dentries(&self) {
+    self.dentries.lock().clear();
+  }
+
+  /// The mount table, ordered by the time filesystems were mounted.
+  pub fn mounts(&self) -> impl Iterator<Item = &Arc<Mount>> {
+    self.mounts.values()
+  }
+
+  /// Formats the mount table like `/proc/mounts`.
+  pub fn mount_table(&self) -> String {
+    self
+      .mounts()
+      .map(|mount| mount.to_string() + "\n")
+      .collect()
+  }
+
   pub fn cwd_path(&mut self) -> &PathComponent {
     &self.cwd_path
   }
@@ -77,6 +406,51 @@ impl Rootfs {
     )
   }
 
+  /// Creates a regular file at `path`, which must not exist yet.
+  pub fn create_file_at<P: AsRef<Path>>(
+    &self,
+    opened_files: &OpenedFileTable,
+    cwd_or_fd: &CwdOrFd,
+    path: &P,
+    mode: FileMode,
+  ) -> Result<Arc<PathComponent>> {
+    let Some((parent, name)) = path.as_ref().parent_and_basename() else {
+      return Err(ErrorKind::EINVAL.into());
+    };
+    let parent_dir = self.lookup_path_at(opened_files, cwd_or_fd, &parent, true)?;
+    if parent_dir.mount.is_read_only() {
+      return Err(ErrorKind::EROFS.into());
+    }
+
+    let node = parent_dir.node.as_dir()?.create_file(name, mode)?;
+    Ok(Arc::new(PathComponent {
+      parent_dir: Some(parent_dir.clone()),
+      name: name.to_owned(),
+      node,
+      mount: parent_dir.mount.clone(),
+    }))
+  }
+
+  pub fn unlink_at<P: AsRef<Path>>(
+    &self,
+    opened_files: &OpenedFileTable,
+    cwd_or_fd: &CwdOrFd,
+    path: &P,
+  ) -> Result<()> {
+    let Some((parent, name)) = path.as_ref().parent_and_basename() else {
+      return Err(ErrorKind::EINVAL.into());
+    };
+    let parent_dir = self.lookup_path_at(opened_files, cwd_or_fd, &parent, true)?;
+    if parent_dir.mount.is_read_only() {
+      return Err(ErrorKind::EROFS.into());
+    }
+
+    parent_dir.node.as_dir()?.unlink(name)?;
+    // The cached lookups might still point to the removed entry.
+    self.forget_dentries();
+    Ok(())
+  }
+
   pub fn resolve_cwd_or_fd<P: AsRef<Path>>(
     &self,
     opened_files: &OpenedFileTable,
@@ -120,8 +494,8 @@ impl Rootfs {
     &self,
     lookup_from: &Arc<PathComponent>,
     path: P,
-    _follow_symlink: bool,
-    _symlink_follow_limit: usize,
+    follow_symlink: bool,
+    symlink_follow_limit: usize,
   ) -> Result<Arc<PathComponent>> {
     let path = path.as_ref();
 
@@ -140,34 +514,29 @@ impl Rootfs {
           .as_ref()
           .unwrap_or(&self.root_path)
           .clone(),
-        _ => {
-          let node = match parent_dir.node.as_dir()?._lookup(name)? {
-            Node::Directory(dir) => match self.lookup_mount_point(&dir)? {
-              Some(mount_point) => mount_point.fs.root()?.into(),
-              None => dir.into(),
-            },
-            node => node,
-          };
-
-          Arc::new(PathComponent {
-            parent_dir: Some(parent_dir.clone()),
-            name: name.to_owned(),
-            node,
-          })
-        }
+        _ => self.lookup_child(&parent_dir, name)?,
       };
 
       if components.peek().is_some() {
         parent_dir = match &path_comp.node {
           Node::Directory(_) => path_comp,
-          // TODO: Node::Symlink
+          Node::Symlink(link) => {
+            let target = self.follow_symlink(&parent_dir, link, symlink_follow_limit)?;
+            if !target.node.is_dir() {
+              return Err(ErrorKind::NotADirectory.into());
+            }
+
+            target
+          }
           Node::File(_) => {
             return Err(ErrorKind::NotADirectory.into());
           }
         }
       } else {
         match &path_comp.node {
-          // TODO: Node::Symlink
+          Node::Symlink(link) if follow_symlink => {
+            return self.follow_symlink(&parent_dir, link, symlink_follow_limit);
+          }
           _ => return Ok(path_comp),
         }
       }
@@ -176,8 +545,85 @@ impl Rootfs {
     Ok(parent_dir)
   }
 
-  fn lookup_mount_point(&self, dir: &Arc<dyn Directory>) -> Result<Option<&MountPoint>> {
-    let stat = dir.stat()?;
-    Ok(self.mount_points.get(&stat.node_id))
+  /// Resolves the target of `link`, found in `parent_dir`.
+  fn follow_symlink(
+    &self,
+    parent_dir: &Arc<PathComponent>,
+    link: &Arc<dyn Symlink>,
+    symlink_follow_limit: usize,
+  ) -> Result<Arc<PathComponent>> {
+    if symlink_follow_limit == 0 {
+      return Err(ErrorKind::TooManySymlinks.into());
+    }
+
+    let target = link.link_target()?;
+    let lookup_from = if target.is_absolute() {
+      self.root_path.clone()
+    } else {
+      parent_dir.clone()
+    };
+
+    self.do_lookup_path(&lookup_from, &target, true, symlink_follow_limit - 1)
+  }
+
+  /// Looks `name` up in `parent_dir`, going through the dentry cache.
+  fn lookup_child(
+    &self,
+    parent_dir: &Arc<PathComponent>,
+    name: &str,
+  ) -> Result<Arc<PathComponent>> {
+    if let Some(path_comp) = self.dentries.lock().get(parent_dir, name) {
+      return Ok(path_comp);
+    }
+
+    let (node, mount) = match parent_dir.node.as_dir()?._lookup(name)? {
+      Node::Directory(dir) => {
+        let (dir, mount) = self.follow_mounts(&parent_dir.mount, dir)?;
+        (dir.into(), mount)
+      }
+      node => (node, parent_dir.mount.clone()),
+    };
+
+    let path_comp = Arc::new(PathComponent {
+      parent_dir: Some(parent_dir.clone()),
+      name: name.to_owned(),
+      node,
+      mount,
+    });
+
+    self.dentries.lock().insert(parent_dir, path_comp.clone());
+    Ok(path_comp)
+  }
+
+  /// Returns the directory actually visible at `dir`, looked up in `mount`,
+  /// and the mount it belongs to. Mounts can be stacked on top of each
+  /// other, the last one wins.
+  fn follow_mounts(
+    &self,
+    mount: &Arc<Mount>,
+    dir: Arc<dyn Directory>,
+  ) -> Result<(Arc<dyn Directory>, Arc<Mount>)> {
+    let mut mount = mount.clone();
+    let mut dir = dir;
+    while let Some(covering) = self.lookup_mount_point(&mount, &dir)? {
+      dir = covering.root.clone();
+      mount = covering.clone();
+    }
+
+    Ok((dir, mount))
+  }
+
+  fn lookup_mount_point(
+    &self,
+    mount: &Mount,
+    dir: &Arc<dyn Directory>,
+  ) -> Result<Option<&Arc<Mount>>> {
+    let key = (mount.id, dir.stat()?.node_id);
+    Ok(
+      self
+        .mount_points
+        .get(&key)
+        .and_then(|id| self.mounts.get(id)),
+    )
   }
 }
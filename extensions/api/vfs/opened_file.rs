@@ -61,25 +61,54 @@ impl OpenedFile {
   }
 
   pub fn read(&self, buf: UserBufferMut<'_>) -> Result<usize> {
-    // Avoid holding self.options and self.pos locks by copying.
-    let options = self.options();
-    let pos = self.pos();
-
-    let written_len = self.as_file()?.read(pos, buf, &options)?;
+    let written_len = self.read_at(self.pos(), buf)?;
     self.pos.fetch_add(written_len);
     Ok(written_len)
   }
 
   pub fn write(&self, buf: UserBuffer<'_>) -> Result<usize> {
-    // Avoid holding self.options and self.pos locks by copying.
-    let options = self.options();
-    let pos = self.pos();
+    if self.options().append {
+      let end = self.size()?;
+      let written_len = self.write_at(end, buf)?;
+      self.pos.store(end + written_len);
+      return Ok(written_len);
+    }
 
-    let written_len = self.as_file()?.write(pos, buf, &options)?;
+    let written_len = self.write_at(self.pos(), buf)?;
     self.pos.fetch_add(written_len);
     Ok(written_len)
   }
 
+  /// Reads at `offset`, leaving the file position alone.
+  pub fn read_at(&self, offset: usize, buf: UserBufferMut<'_>) -> Result<usize> {
+    // Avoid holding the self.options lock by copying.
+    let options = self.options();
+    self.as_file()?.read(offset, buf, &options)
+  }
+
+  /// Writes at `offset`, leaving the file position alone.
+  pub fn write_at(&self, offset: usize, buf: UserBuffer<'_>) -> Result<usize> {
+    if self.path.mount.is_read_only() {
+      return Err(ErrorKind::EROFS.into());
+    }
+
+    // Avoid holding the self.options lock by copying.
+    let options = self.options();
+    self.as_file()?.write(offset, buf, &options)
+  }
+
+  pub fn truncate(&self, len: usize) -> Result<()> {
+    if self.path.mount.is_read_only() {
+      return Err(ErrorKind::EROFS.into());
+    }
+
+    self.as_file()?.truncate(len)
+  }
+
+  pub fn fsync(&self) -> Result<()> {
+    self.as_file()?.fsync()
+  }
+
   pub fn readdir(&self) -> Result<Option<DirEntry>> {
     let pos = self.pos();
 
@@ -98,6 +127,8 @@ impl OpenedFile {
       self.options.borrow_mut().nonblock = true;
     }
 
+    self.options.borrow_mut().append = flags.contains(OpenFlags::O_APPEND);
+
     Ok(())
   }
 }
@@ -145,6 +176,7 @@ impl OpenedFileTable {
             name: opened_file.path.name.clone(),
             parent_dir: opened_file.path.parent_dir.clone(),
             node: new_node.into(),
+            mount: opened_file.path.mount.clone(),
           }),
         })
       }
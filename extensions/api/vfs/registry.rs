@@ -0,0 +1,109 @@
+//! The registry of filesystem types. Extensions register their filesystem
+//! type once; partitions are probed against the registered types at boot,
+//! and `mount` looks types up by name.
+
+use alloc::{
+  borrow::ToOwned, boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec,
+};
+use environment::spinlock::SpinLock;
+
+use crate::{kernel::kernel_ops, schema::fs::Partition, ErrorKind, Result};
+
+use super::Filesystem;
+
+static FILESYSTEM_TYPES: SpinLock<Vec<Box<dyn FilesystemType>>> = SpinLock::new(vec![]);
+static DETECTED: SpinLock<BTreeMap<usize, DetectedFilesystem>> = SpinLock::new(BTreeMap::new());
+
+/// What a filesystem is created from.
+pub enum MountSource {
+  /// A partition, along with its index in the partition list.
+  Partition(Arc<SpinLock<dyn Partition>>, usize),
+  /// No partition, for filesystems living in memory (e.g. `tempfs`) or
+  /// elsewhere. Carries the source passed to `mount`, which names the device
+  /// for the latter (e.g. a virtio-9p mount tag).
+  Nodev(String),
+}
+
+pub trait FilesystemType: Send + Sync {
+  /// The name passed to `mount`, e.g. `ext2`.
+  fn name(&self) -> &'static str;
+
+  /// Whether `fs_type` names this filesystem type.
+  fn accepts(&self, fs_type: &str) -> bool {
+    fs_type == self.name()
+  }
+
+  /// Creates a filesystem from `source`, or returns `None` if `source`
+  /// doesn't hold a filesystem of this type.
+  fn mount(&self, source: MountSource) -> Option<Arc<dyn Filesystem>>;
+}
+
+/// A filesystem found on a partition while probing at boot.
+#[derive(Clone)]
+pub struct DetectedFilesystem {
+  pub fs: Arc<dyn Filesystem>,
+  pub fs_type: &'static str,
+  /// The name of the partition.
+  pub source: String,
+}
+
+pub fn register_filesystem_type(fs_type: Box<dyn FilesystemType>) {
+  FILESYSTEM_TYPES.lock().push(fs_type);
+}
+
+/// Returns the filesystem detected on partition `number`, if any.
+pub fn detected_filesystem(number: usize) -> Option<DetectedFilesystem> {
+  DETECTED.lock().get(&number).cloned()
+}
+
+/// Creates a filesystem of type `fs_type` from `source`, the name of a
+/// partition. Filesystems which don't need one get `source` as is.
+pub fn create_filesystem(fs_type: &str, source: &str) -> Result<Arc<dyn Filesystem>> {
+  let types = FILESYSTEM_TYPES.lock();
+  let Some(fs_type) = types.iter().find(|ty| ty.accepts(fs_type)) else {
+    return Err(ErrorKind::NoDevice.into());
+  };
+
+  let partitions = kernel_ops().request_partitions();
+  let Some((num, partition)) = partitions
+    .iter()
+    .enumerate()
+    .find(|(_, partition)| partition.lock().name() == source)
+  else {
+    return fs_type
+      .mount(MountSource::Nodev(source.to_owned()))
+      .ok_or_else(|| ErrorKind::NoEntry.into());
+  };
+
+  // Share the instance found at boot instead of having two of them
+  // caching the same partition.
+  if let Some(detected) = detected_filesystem(num) {
+    if detected.fs_type == fs_type.name() {
+      return Ok(detected.fs);
+    }
+  }
+
+  fs_type
+    .mount(MountSource::Partition(partition.clone(), num))
+    .ok_or_else(|| ErrorKind::EINVAL.into())
+}
+
+/// Probes every partition against the registered filesystem types.
+pub fn init() {
+  let types = FILESYSTEM_TYPES.lock();
+  for (num, partition) in kernel_ops().request_partitions().iter().enumerate() {
+    for fs_type in types.iter() {
+      if let Some(fs) = fs_type.mount(MountSource::Partition(partition.clone(), num)) {
+        DETECTED.lock().insert(
+          num,
+          DetectedFilesystem {
+            fs,
+            fs_type: fs_type.name(),
+            source: partition.lock().name().to_owned(),
+          },
+        );
+        break;
+      }
+    }
+  }
+}
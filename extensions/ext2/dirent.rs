@@ -1,5 +1,5 @@
 use alloc::string::String;
-use api::schema::fs::{self, DirEntry};
+use api::vfs;
 use utils::bytes_parser::BytesParser;
 
 #[derive(Debug)]
@@ -12,24 +12,6 @@ pub struct Dirent {
 	pub name: String,
 }
 
-impl From<Dirent> for DirEntry {
-	fn from(dirent: Dirent) -> Self {
-		Self {
-			path: dirent.name.into(),
-			ftype: dirent.dirent_type.into(),
-		}
-	}
-}
-
-impl From<&Dirent> for DirEntry {
-	fn from(dirent: &Dirent) -> Self {
-		Self {
-			path: dirent.name.clone().into(),
-			ftype: dirent.dirent_type.into(),
-		}
-	}
-}
-
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum DirentType {
 	Unknown = 0,
@@ -42,7 +24,7 @@ pub enum DirentType {
 	Symlink = 7,
 }
 
-impl From<DirentType> for fs::FileType {
+impl From<DirentType> for vfs::FileType {
 	fn from(dir: DirentType) -> Self {
 		match dir {
 			DirentType::Regular => Self::RegularFile,
@@ -69,12 +51,12 @@ impl From<u8> for DirentType {
 }
 
 impl Dirent {
+	/// Parses a directory entry. Without `has_type` (the filetype feature),
+	/// the type byte is the upper half of the name length and the type is
+	/// left as `Unknown` for the caller to look up in the inode.
 	pub fn parse(parser: &mut BytesParser, has_type: bool) -> Option<Self> {
-		assert!(has_type, "directories without type unsupported");
-
 		let inode = parser.consume_le_u32().unwrap();
 		let total_size = parser.consume_le_u16().unwrap();
-		let name_len = total_size - 8;
 
 		if inode == 0 {
 			let _ = parser.skip(total_size as usize - 6);
@@ -82,9 +64,20 @@ impl Dirent {
 		}
 
 		let name_length_lsb = parser.consume_u8().unwrap();
-		let dirent_type = parser.consume_u8().unwrap().into();
+		let type_or_length_msb = parser.consume_u8().unwrap();
+		let (dirent_type, name_len) = if has_type {
+			(type_or_length_msb.into(), name_length_lsb as usize)
+		} else {
+			(
+				DirentType::Unknown,
+				name_length_lsb as usize | (type_or_length_msb as usize) << 8,
+			)
+		};
 
-		let name = parser.consume_cstr(name_len as usize).unwrap();
+		// The record may be longer than the name, e.g. the last one in a
+		// block or one that absorbed a deleted neighbour.
+		let name = parser.consume_cstr(name_len).unwrap();
+		let _ = parser.skip(total_size as usize - 8 - name_len);
 
 		Some(Self {
 			inode,
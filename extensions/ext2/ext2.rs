@@ -1,6 +1,6 @@
 use core::{fmt, sync::atomic::AtomicUsize};
 
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 use api::{
   dbg,
   driver::block,
@@ -10,12 +10,14 @@ use api::{
   sync::SpinLock,
   trace,
   user_buffer::UserBufWriter,
-  vfs::{self, NodeId},
+  vfs::{self, FsId, NodeId},
+  warn,
 };
 use utils::{alignment::align_up, bytes_parser::BytesParser, once::Once};
 
 use crate::{
-  dirent::Dirent,
+  dirent::{Dirent, DirentType},
+  extent::{self, Extent, ExtentHeader, ExtentIndex},
   inode::Inode,
   structure::{BlockGroupDescriptor, ReadOnlyFeatures, RequiredFeatures, Superblock},
   BlockPointer,
@@ -30,8 +32,12 @@ pub struct Ext2 {
   inode_size: usize,
   dirent_has_type: bool,
   pub large_file_size: bool,
+  read_only: bool,
+  fs_id: FsId,
   next_fid: Arc<AtomicUsize>,
   open_nodes: Arc<SpinLock<Vec<NodeId>>>,
+  /// Serializes the updates of inodes, bitmaps and free counts.
+  metadata_lock: Arc<SpinLock<()>>,
 }
 
 impl fmt::Debug for Ext2 {
@@ -42,6 +48,7 @@ impl fmt::Debug for Ext2 {
       .field("inode_size", &self.inode_size)
       .field("dirent_has_type", &self.dirent_has_type)
       .field("large_file_size", &self.large_file_size)
+      .field("read_only", &self.read_only)
       .field("next_fid", &self.next_fid)
       .finish()
   }
@@ -50,73 +57,74 @@ impl fmt::Debug for Ext2 {
 #[derive(Debug)]
 struct DriveInode {
   id: NodeId,
-  inode: Arc<Inode>,
   ext2: Arc<Ext2>,
 }
 
+impl DriveInode {
+  /// Reads the inode from the disk: it changes under our feet when the file
+  /// is truncated through another node.
+  fn inode(&self) -> Inode {
+    self.ext2.read_inode(self.id.ino())
+  }
+
+  fn file_type(&self) -> DirentType {
+    self.inode().file_type()
+  }
+
+  fn size(&self) -> usize {
+    self.inode().size(self.ext2.large_file_size) as usize
+  }
+}
+
 impl vfs::Directory for DriveInode {
   fn read_dir(&self, offset: usize) -> api::Result<Option<vfs::DirEntry>> {
-    let dirents = self.ext2.read_dirent(&self.inode);
-    let Some(node) = dirents.iter().nth(offset) else {
+    let dirents = self.ext2.read_dirent(&self.inode());
+    let Some(dirent) = dirents.iter().nth(offset) else {
       return Ok(None);
     };
-    match node.dirent_type {
-      crate::dirent::DirentType::Unknown => todo!(),
-      crate::dirent::DirentType::Regular => {
-        return Ok(Some(vfs::DirEntry {
-          name: node.name.clone(),
-          file_type: vfs::FileType::RegularFile,
-          node_id: NodeId::new(node.inode as usize),
-        }));
-      }
-      crate::dirent::DirentType::Directory => {
-        return Ok(Some(vfs::DirEntry {
-          name: node.name.clone(),
-          file_type: vfs::FileType::Directory,
-          node_id: NodeId::new(node.inode as usize),
-        }));
-      }
-      _ => unimplemented!(),
-    }
 
-    Err(api::ErrorKind::NotFound.into())
+    // Without the filetype feature the type has to come from the inode.
+    let dirent_type = match dirent.dirent_type {
+      DirentType::Unknown => self.ext2.read_inode(dirent.inode as usize).file_type(),
+      dirent_type => dirent_type,
+    };
+
+    Ok(Some(vfs::DirEntry {
+      name: dirent.name.clone(),
+      file_type: match dirent_type {
+        DirentType::Directory => vfs::FileType::Directory,
+        _ => vfs::FileType::RegularFile,
+      },
+      node_id: self.ext2.node_id(dirent.inode as usize),
+    }))
   }
 
   fn _lookup(&self, name: &str) -> api::Result<vfs::Node> {
-    for inode in self.ext2.read_dirent(&self.inode) {
-      if inode.name == name {
-        match inode.dirent_type {
-          crate::dirent::DirentType::Unknown => todo!(),
-          crate::dirent::DirentType::Regular => {
-            return Ok(vfs::Node::File(Arc::new(DriveInode {
-              inode: Arc::new(self.ext2.read_inode(inode.inode as usize)),
-              ext2: self.ext2.clone(),
-              id: NodeId::new(inode.inode as usize),
-            })));
-          }
-          crate::dirent::DirentType::Directory => {
-            return Ok(vfs::Node::Directory(Arc::new(DriveInode {
-              inode: Arc::new(self.ext2.read_inode(inode.inode as usize)),
-              ext2: self.ext2.clone(),
-              id: NodeId::new(inode.inode as usize),
-            })));
-          }
-          crate::dirent::DirentType::CharDevice => todo!(),
-          crate::dirent::DirentType::BlockDevice => todo!(),
-          crate::dirent::DirentType::Fifo => todo!(),
-          crate::dirent::DirentType::Socket => todo!(),
-          crate::dirent::DirentType::Symlink => todo!(),
-        }
-      }
-    }
+    let Some(dirent) = self
+      .ext2
+      .read_dirent(&self.inode())
+      .into_iter()
+      .find(|dirent| dirent.name == name)
+    else {
+      return Err(api::ErrorKind::NotFound.into());
+    };
+
+    let node = DriveInode {
+      ext2: self.ext2.clone(),
+      id: self.ext2.node_id(dirent.inode as usize),
+    };
 
-    Err(api::ErrorKind::NotFound.into())
+    match node.file_type() {
+      DirentType::Directory => Ok(vfs::Node::Directory(Arc::new(node))),
+      DirentType::Regular => Ok(vfs::Node::File(Arc::new(node))),
+      _ => Err(api::ErrorKind::NotSupported.into()),
+    }
   }
 
   fn stat(&self) -> api::Result<vfs::Stat> {
     Ok(vfs::Stat {
       node_id: self.id,
-      size: self.inode.lower_size as usize,
+      size: self.size(),
       kind: vfs::FileKind::Directory,
     })
   }
@@ -128,7 +136,6 @@ impl vfs::File for DriveInode {
     self.ext2.open_nodes.lock().push(self.id);
     Ok(Some(Arc::new(DriveInode {
       id: self.id,
-      inode: self.inode.clone(),
       ext2: self.ext2.clone(),
     })))
   }
@@ -139,29 +146,28 @@ impl vfs::File for DriveInode {
     dst: api::user_buffer::UserBufferMut<'_>,
     options: &api::io::OpenOptions,
   ) -> api::Result<usize> {
-    if offset >= self.inode.lower_size as usize {
+    let inode = self.inode();
+    let size = inode.size(self.ext2.large_file_size) as usize;
+    if offset >= size {
       return Ok(0);
     }
 
-    // Calculate the starting block and offset within that block based on the
-    // provided offset
-    let block_index = offset / self.ext2.block_size;
-    let block_offset = offset % self.ext2.block_size;
+    let block_size = self.ext2.block_size;
+    let len = (size - offset).min(dst.len());
+    let first_block = offset / block_size;
+    let last_block = (offset + len).div_ceil(block_size);
 
     // TODO: Get rid of double read. Read directly into user buffer
-    let mut buf = vec![0u8; align_up(self.inode.lower_size as usize, self.ext2.block_size)];
-    let blocks = self.ext2.gather_blocks(&self.inode);
-    // trace!("{:?}", blocks);
-
-    // Read only the relevant blocks based on the offset
-    self.ext2.read_blocks(&blocks[block_index..], &mut buf);
+    let mut buf = vec![0u8; (last_block - first_block) * block_size];
+    let blocks = self.ext2.gather_blocks(&inode);
+    self
+      .ext2
+      .read_blocks(&blocks[first_block..last_block], &mut buf);
 
-    let write_len = (self.inode.lower_size as usize).min(dst.len());
+    let block_offset = offset % block_size;
     let mut writer = api::user_buffer::UserBufWriter::from(dst);
-
-    // Write only the relevant part of the buffer to the user buffer
     writer
-      .write_bytes(&buf[block_offset..block_offset + write_len])
+      .write_bytes(&buf[block_offset..block_offset + len])
       .map_err(|_| api::ErrorKind::BufferError)?;
 
     Ok(writer.written_len())
@@ -173,16 +179,64 @@ impl vfs::File for DriveInode {
     buf: api::user_buffer::UserBuffer<'_>,
     options: &api::io::OpenOptions,
   ) -> api::Result<usize> {
-    todo!()
+    if self.ext2.read_only {
+      return Err(api::ErrorKind::EROFS.into());
+    }
+
+    let end = offset
+      .checked_add(buf.len())
+      .ok_or(api::ErrorKind::TooBig)?;
+    if !self.ext2.large_file_size && end > u32::MAX as usize {
+      return Err(api::ErrorKind::TooBig.into());
+    }
+
+    let block_size = self.ext2.block_size;
+    let mut reader = api::user_buffer::UserBufReader::from(buf);
+    let mut block_buf = vec![0u8; block_size];
+    let mut written = 0;
+    while written < reader.buffer_len() {
+      let pos = offset + written;
+      let block_offset = pos % block_size;
+      let len = (block_size - block_offset).min(reader.remaining_len());
+
+      // Copy from the user buffer before taking the metadata lock, the copy
+      // may fault.
+      reader.read_bytes(&mut block_buf[block_offset..block_offset + len])?;
+      self.ext2.write_inode_block(
+        self.id.ino(),
+        pos / block_size,
+        block_offset,
+        &mut block_buf,
+        len,
+      )?;
+      written += len;
+    }
+
+    Ok(written)
   }
 
   fn stat(&self) -> api::Result<vfs::Stat> {
     Ok(vfs::Stat {
       node_id: self.id,
-      size: self.inode.lower_size as usize,
+      size: self.size(),
       kind: vfs::FileKind::RegularFile,
     })
   }
+
+  fn truncate(&self, len: usize) -> api::Result<()> {
+    self.ext2.truncate_inode(self.id.ino(), len)
+  }
+
+  fn fsync(&self) -> api::Result<()> {
+    // The block cache doesn't know which sectors belong to the file, so the
+    // whole partition is written back.
+    self.ext2.physical_partition.lock().sync();
+    Ok(())
+  }
+
+  fn is_writable(&self) -> bool {
+    !self.ext2.read_only && !self.inode().has_extents()
+  }
 }
 
 impl Clone for Ext2 {
@@ -196,8 +250,11 @@ impl Clone for Ext2 {
       inode_size: self.inode_size.clone(),
       dirent_has_type: self.dirent_has_type.clone(),
       large_file_size: self.large_file_size.clone(),
+      read_only: self.read_only,
+      fs_id: self.fs_id,
       next_fid: self.next_fid.clone(),
       open_nodes: self.open_nodes.clone(),
+      metadata_lock: self.metadata_lock.clone(),
     }
   }
 }
@@ -207,6 +264,7 @@ impl Ext2 {
     physical_partition: Arc<SpinLock<dyn fs::Partition>>,
     partition_number: usize,
     superblock: Superblock,
+    read_only: bool,
   ) -> Self {
     let block_size = superblock.block_size as usize;
     let inode_size = superblock
@@ -244,26 +302,27 @@ impl Ext2 {
       inode_size,
       dirent_has_type,
       large_file_size,
+      read_only,
+      fs_id: FsId::alloc(),
       next_fid: Arc::new(AtomicUsize::new(0)),
       open_nodes: Arc::new(SpinLock::new(vec![])),
+      metadata_lock: Arc::new(SpinLock::new(())),
     }
   }
 
-  pub fn bgd_block(&self) -> BlockPointer {
-    BlockPointer(
-      if self.superblock.block_size == 1024 {
-        2
-      } else {
-        1
-      },
-    )
+  pub fn bgd_block(&self) -> u64 {
+    if self.superblock.block_size == 1024 {
+      2
+    } else {
+      1
+    }
   }
 
   pub fn inode_bgd(&self, inode: usize) -> BlockGroupDescriptor {
     self.block_group_descriptors[(inode - 1) / self.superblock.inodes_per_group as usize].clone()
   }
 
-  pub fn read_dirents(&self, block: BlockPointer, res: &mut Vec<Dirent>) {
+  pub fn read_dirents(&self, block: u64, res: &mut Vec<Dirent>) {
     let mut buf = self.read_block_alloc(block);
     let mut parser = BytesParser::new(&mut buf);
 
@@ -288,35 +347,104 @@ impl Ext2 {
     }
 
     let bgd_count = self.superblock.bgd_count();
-    let blocks_count = align_up(bgd_count, self.block_size) / self.block_size;
+    let table_blocks = self.bgd_table_blocks();
 
-    let mut buf = vec![0u8; blocks_count * self.block_size];
-    self.read_block(self.bgd_block(), &mut buf);
+    let mut buf = vec![0u8; table_blocks * self.block_size];
+    let blocks = (0..table_blocks as u64)
+      .map(|i| self.bgd_block() + i)
+      .collect::<Vec<_>>();
+    self.read_blocks(&blocks, &mut buf);
     let mut parser = BytesParser::new(&buf);
 
     info!("Parsing {} Block Group Descriptors", bgd_count);
+    let descriptor_size = self.superblock.descriptor_size();
     let mut res = vec![];
     for _ in 0..bgd_count {
-      res.push(BlockGroupDescriptor::parse(&mut parser))
+      res.push(BlockGroupDescriptor::parse(&mut parser, descriptor_size))
     }
+
+    if !self.check_group_layout(&res) {
+      warn!("ext2: unexpected block group layout, mounting read-only");
+      self.read_only = true;
+    }
+
     self.block_group_descriptors.init(|| res);
   }
 
-  pub fn read_block(&self, block: BlockPointer, buf: &mut [u8]) {
+  fn bgd_table_blocks(&self) -> usize {
+    (self.superblock.bgd_count() * self.superblock.descriptor_size()).div_ceil(self.block_size)
+  }
+
+  /// Makes sure no group's block bitmap overlaps the superblock and
+  /// descriptor table backup the group is supposed to hold (if any), which
+  /// would mean we got the sparse superblock layout wrong.
+  fn check_group_layout(&self, groups: &[BlockGroupDescriptor]) -> bool {
+    // Flexible block groups pack the bitmaps of several groups together
+    // in the first one, so there's no per-group layout to check.
+    if self.superblock.has_flex_block_groups() {
+      return true;
+    }
+
+    let first_data_block = self.superblock.first_data_block() as usize;
+    let blocks_per_group = self.superblock.blocks_per_group() as usize;
+    let metadata_blocks = 1 + self.bgd_table_blocks();
+
+    groups.iter().enumerate().all(|(group, bgd)| {
+      let group_start = first_data_block + group * blocks_per_group;
+      let reserved = if self.superblock.group_has_superblock(group) {
+        metadata_blocks
+      } else {
+        0
+      };
+
+      bgd.block_usage_bitmap >= (group_start + reserved) as u64
+    })
+  }
+
+  pub fn node_id(&self, inode: usize) -> NodeId {
+    NodeId::new(self.fs_id, inode)
+  }
+
+  pub fn is_read_only(&self) -> bool {
+    self.read_only
+  }
+
+  pub fn read_block(&self, block: u64, buf: &mut [u8]) {
     assert!(buf.len() >= self.block_size);
 
+    // Block 0 in a block map is a hole in a sparse file.
+    if block == 0 {
+      buf[..self.block_size].fill(0);
+      return;
+    }
+
     let partition = self.physical_partition.lock();
 
     let sectors_per_block =
       align_up(self.block_size, partition.block_size()) / partition.block_size();
 
-    let start_sector = *block as usize * sectors_per_block;
+    let start_sector = block as usize * sectors_per_block;
     let end_sector = start_sector + sectors_per_block;
 
     partition.read_sectors(start_sector..end_sector, buf)
   }
 
-  pub fn read_block_alloc(&self, block: BlockPointer) -> Vec<u8> {
+  pub fn write_block(&self, block: u64, buf: &[u8]) {
+    assert!(buf.len() >= self.block_size);
+    assert_ne!(block, 0, "ext2: block 0 is never part of a file");
+
+    let partition = self.physical_partition.lock();
+
+    let sectors_per_block =
+      align_up(self.block_size, partition.block_size()) / partition.block_size();
+
+    let start_sector = block as usize * sectors_per_block;
+    let end_sector = start_sector + sectors_per_block;
+
+    partition.write_sectors(start_sector..end_sector, buf)
+  }
+
+  pub fn read_block_alloc(&self, block: u64) -> Vec<u8> {
     let mut buf = vec![0u8; self.block_size];
 
     self.read_block(block, &mut buf);
@@ -324,27 +452,73 @@ impl Ext2 {
     buf
   }
 
-  pub fn read_inode(&self, inode: usize) -> Inode {
+  /// Returns the block of the inode table holding `inode`, and the offset
+  /// of the inode in that block.
+  fn inode_location(&self, inode: usize) -> (u64, usize) {
     let bgd = self.inode_bgd(inode);
 
     let index = (inode - 1) % self.superblock.inodes_per_group as usize;
-    let containing_block = (index * self.inode_size) / self.block_size;
+    let offset = index * self.inode_size;
+
+    (
+      bgd.inode_table + (offset / self.block_size) as u64,
+      offset % self.block_size,
+    )
+  }
+
+  pub fn read_inode(&self, inode: usize) -> Inode {
+    let (block, offset) = self.inode_location(inode);
+    let buf = self.read_block_alloc(block);
+
+    Inode::parse(&mut BytesParser::new(&buf[offset..]))
+  }
+
+  /// Lets `f` patch the on-disk bytes of `inode`.
+  fn update_inode(&self, inode: usize, f: impl FnOnce(&mut [u8])) {
+    let (block, offset) = self.inode_location(inode);
+    let mut buf = self.read_block_alloc(block);
+    f(&mut buf[offset..offset + self.inode_size]);
+    self.write_block(block, &buf);
+  }
 
-    let buf = self.read_block_alloc(BlockPointer(bgd.inode_table + containing_block as u32));
+  /// Lets `f` patch the on-disk bytes of the descriptor of `group`.
+  fn update_descriptor(&self, group: usize, f: impl FnOnce(&mut [u8])) {
+    let descriptor_size = self.superblock.descriptor_size();
+    let offset = group * descriptor_size;
+    let block = self.bgd_block() + (offset / self.block_size) as u64;
+    let offset = offset % self.block_size;
+
+    let mut buf = self.read_block_alloc(block);
+    f(&mut buf[offset..offset + descriptor_size]);
+    self.write_block(block, &buf);
+  }
+
+  /// Lets `f` patch the on-disk bytes of the superblock. Backups in other
+  /// groups are left alone, like Linux does.
+  fn update_superblock(&self, f: impl FnOnce(&mut [u8])) {
+    let partition = self.physical_partition.lock();
+    let superblock_sectors = partition.in_sectors(1024);
+    let sectors = superblock_sectors..superblock_sectors + superblock_sectors;
 
-    Inode::parse(&mut BytesParser::new(&buf[index * self.inode_size..]))
+    let mut buf = vec![0u8; 1024];
+    partition.read_sectors(sectors.clone(), &mut buf);
+    f(&mut buf);
+    partition.write_sectors(sectors, &buf);
   }
 
   pub fn read_dirent(&self, inode: &Inode) -> Vec<Dirent> {
     let mut res = vec![];
 
-    assert!(inode.direct_pointers.count() == 1, "not yet implemented");
-    self.read_dirents(inode.direct_pointers.at(0), &mut res);
+    for block in self.gather_blocks(inode) {
+      if block != 0 {
+        self.read_dirents(block, &mut res);
+      }
+    }
 
     res
   }
 
-  pub fn read_blocks(&self, blocks: &[BlockPointer], buf: &mut [u8]) {
+  pub fn read_blocks(&self, blocks: &[u64], buf: &mut [u8]) {
     let mut offset = 0;
     for block in blocks {
       self.read_block(*block, &mut buf[offset..offset + self.block_size]);
@@ -352,91 +526,511 @@ impl Ext2 {
     }
   }
 
-  pub fn gather_blocks(&self, inode: &Inode) -> Vec<BlockPointer> {
-    let mut res = vec![];
+  /// Returns the blocks holding the data of `inode` in order. Holes in
+  /// sparse files are returned as block 0.
+  pub fn gather_blocks(&self, inode: &Inode) -> Vec<u64> {
+    let count = (inode.size(self.large_file_size) as usize).div_ceil(self.block_size);
+
+    if inode.has_extents() {
+      let mut res = vec![0; count];
+      self.gather_extents(&inode.block_data, extent::MAX_DEPTH, &mut res);
+      return res;
+    }
+
+    let mut res = Vec::with_capacity(count);
+    res.extend((0..12).map(|i| *inode.direct_pointers.at(i) as u64));
+    self.gather_indirect(inode.singly_pointer, 1, count, &mut res);
+    self.gather_indirect(inode.doubly_pointer, 2, count, &mut res);
+    self.gather_indirect(inode.triply_pointer, 3, count, &mut res);
 
-    res.extend(inode.direct_pointers.iter());
-    res.extend(self.gather_singly(inode));
+    res.truncate(count);
+    res
+  }
 
-    for doubly in self.gather_doubly(inode) {
-      res.extend(self.gather_singly(&self.read_inode(doubly.0 as usize)));
+  /// Appends the blocks referenced by an indirect block of the given depth
+  /// (1 for singly indirect) until `res` contains `count` blocks.
+  fn gather_indirect(&self, block: BlockPointer, depth: u32, count: usize, res: &mut Vec<u64>) {
+    if res.len() >= count {
+      return;
     }
 
-    for triply in self.gather_triply(inode) {
-      for doubly in self.gather_doubly(&self.read_inode(triply.0 as usize)) {
-        res.extend(self.gather_singly(&self.read_inode(doubly.0 as usize)));
-      }
+    let pointers_per_block = self.block_size / core::mem::size_of::<u32>();
+    if *block == 0 {
+      let hole_len = pointers_per_block.pow(depth).min(count - res.len());
+      res.resize(res.len() + hole_len, 0);
+      return;
     }
 
-    res
+    let buf = self.read_block_alloc(*block as u64);
+    for pointer in buf.chunks_exact(core::mem::size_of::<u32>()) {
+      if res.len() >= count {
+        break;
+      }
+
+      let pointer = BlockPointer(u32::from_le_bytes(pointer.try_into().unwrap()));
+      if depth == 1 {
+        res.push(*pointer as u64);
+      } else {
+        self.gather_indirect(pointer, depth - 1, count, res);
+      }
+    }
   }
 
-  pub fn gather_singly(&self, inode: &Inode) -> Vec<BlockPointer> {
-    let mut res = vec![];
+  /// Fills `res`, indexed by logical block, with the physical blocks mapped
+  /// by the extent tree node `node`. Blocks not covered by an initialized
+  /// extent are left as holes.
+  fn gather_extents(&self, node: &[u8], max_depth: u16, res: &mut [u64]) {
+    let mut parser = BytesParser::new(node);
+    let header = match ExtentHeader::parse(&mut parser) {
+      Some(header) if header.depth <= max_depth => header,
+      _ => {
+        warn!("ext2: corrupted extent tree node");
+        return;
+      }
+    };
 
-    if inode.singly_pointer.0 != 0 {
-      let mut buf = self.read_block_alloc(inode.singly_pointer);
-      let mut parser = BytesParser::new(&mut buf);
+    for _ in 0..header.entries {
+      if header.depth == 0 {
+        let Some(extent) = Extent::parse(&mut parser) else {
+          break;
+        };
+
+        if !extent.initialized {
+          continue;
+        }
+
+        let first = extent.first_block as usize;
+        for (i, slot) in res
+          .iter_mut()
+          .skip(first)
+          .take(extent.len as usize)
+          .enumerate()
+        {
+          *slot = extent.start + i as u64;
+        }
+      } else {
+        let Some(index) = ExtentIndex::parse(&mut parser) else {
+          break;
+        };
 
-      loop {
-        let block = parser.consume_le_u32().unwrap();
-        if block == 0 {
+        // Indices are sorted, nothing past the end of the file matters.
+        if index.first_block as usize >= res.len() {
           break;
         }
 
-        res.push(BlockPointer(block));
+        let child = self.read_block_alloc(index.child);
+        self.gather_extents(&child, header.depth - 1, res);
       }
     }
+  }
 
-    res
+  /// Changes the size of `ino` to `len` bytes. The blocks past the new end
+  /// are freed, while growing the file leaves a hole.
+  pub fn truncate_inode(&self, ino: usize, len: usize) -> api::Result<()> {
+    if self.read_only {
+      return Err(api::ErrorKind::EROFS.into());
+    }
+
+    if !self.large_file_size && len > u32::MAX as usize {
+      return Err(api::ErrorKind::TooBig.into());
+    }
+
+    let _guard = self.metadata_lock.lock();
+    let inode = self.read_inode(ino);
+    if inode.has_extents() {
+      return Err(api::Error::with_message(
+        api::ErrorKind::NotSupported,
+        "can't truncate files mapped by extents",
+      ));
+    }
+
+    // Whatever follows the end of the file in its last block must read as
+    // zeroes once the file grows again.
+    let size = inode.size(self.large_file_size) as usize;
+    let blocks = self.gather_blocks(&inode);
+    self.zero_block_tail(&blocks, len.min(size));
+
+    let mut pointers = block_pointers(&inode);
+    let keep = len.div_ceil(self.block_size);
+    let mut freed = Vec::new();
+    for pointer in pointers[keep.min(12)..12].iter_mut() {
+      if *pointer != 0 {
+        freed.push(*pointer as u64);
+        *pointer = 0;
+      }
+    }
+
+    let pointers_per_block = self.block_size / core::mem::size_of::<u32>();
+    let mut start = 12;
+    for depth in 1..=3 {
+      let slot = 11 + depth as usize;
+      if self.release_indirect(pointers[slot], depth, start, keep, &mut freed) {
+        freed.push(pointers[slot] as u64);
+        pointers[slot] = 0;
+      }
+
+      start += pointers_per_block.pow(depth);
+    }
+
+    // i_blocks counts 512-byte sectors, indirect blocks included.
+    let freed_sectors = (freed.len() * self.block_size / 512) as u32;
+    let large_file = self.large_file_size && inode.file_type() == DirentType::Regular;
+    self.update_inode(ino, |raw| {
+      set_le_u32(raw, Inode::SIZE_OFFSET, len as u32);
+      if large_file {
+        set_le_u32(raw, Inode::SIZE_HIGH_OFFSET, (len as u64 >> 32) as u32);
+      }
+
+      let sectors = le_u32(raw, Inode::SECTORS_OFFSET).saturating_sub(freed_sectors);
+      set_le_u32(raw, Inode::SECTORS_OFFSET, sectors);
+
+      for (i, pointer) in pointers.iter().enumerate() {
+        set_le_u32(raw, Inode::BLOCK_OFFSET + i * 4, *pointer);
+      }
+    });
+
+    // The inode no longer points to the blocks, they can go.
+    self.free_blocks(&freed);
+    Ok(())
   }
 
-  pub fn gather_doubly(&self, inode: &Inode) -> Vec<BlockPointer> {
-    let mut res = vec![];
+  /// Writes `len` bytes of `buf`, from `block_offset` on, to logical block
+  /// `index` of `ino`, allocating the block and the indirect blocks leading
+  /// to it as needed, and grows the file up to the written bytes. The rest
+  /// of `buf` is overwritten with the current contents of the block.
+  fn write_inode_block(
+    &self,
+    ino: usize,
+    index: usize,
+    block_offset: usize,
+    buf: &mut [u8],
+    len: usize,
+  ) -> api::Result<()> {
+    let _guard = self.metadata_lock.lock();
+    let inode = self.read_inode(ino);
+    if inode.has_extents() {
+      return Err(api::Error::with_message(
+        api::ErrorKind::NotSupported,
+        "can't write to files mapped by extents",
+      ));
+    }
 
-    if inode.doubly_pointer.0 != 0 {
-      let mut buf = self.read_block_alloc(inode.doubly_pointer);
-      let mut parser = BytesParser::new(&mut buf);
+    let goal_group = (ino - 1) / self.superblock.inodes_per_group as usize;
+    let mut pointers = block_pointers(&inode);
+    let mut allocated = 0;
+    let mapped = self.map_block(&mut pointers, index, goal_group, &mut allocated);
+
+    if let Ok(block) = mapped {
+      // A partial write keeps the bytes around it.
+      if len < self.block_size {
+        let mut current = self.read_block_alloc(block);
+        current[block_offset..block_offset + len]
+          .copy_from_slice(&buf[block_offset..block_offset + len]);
+        buf[..self.block_size].copy_from_slice(&current);
+      }
 
-      loop {
-        let block = parser.consume_le_u32().unwrap();
-        if block == 0 {
-          break;
+      self.write_block(block, buf);
+    }
+
+    // The pointers to the blocks allocated before a failure are recorded as
+    // well, or the blocks would leak.
+    let end = index * self.block_size + block_offset + len;
+    let grow = mapped.is_ok() && end > inode.size(self.large_file_size) as usize;
+    let large_file = self.large_file_size && inode.file_type() == DirentType::Regular;
+    let allocated_sectors = (allocated * self.block_size / 512) as u32;
+    self.update_inode(ino, |raw| {
+      if grow {
+        set_le_u32(raw, Inode::SIZE_OFFSET, end as u32);
+        if large_file {
+          set_le_u32(raw, Inode::SIZE_HIGH_OFFSET, (end as u64 >> 32) as u32);
         }
+      }
+
+      let sectors = le_u32(raw, Inode::SECTORS_OFFSET).wrapping_add(allocated_sectors);
+      set_le_u32(raw, Inode::SECTORS_OFFSET, sectors);
+
+      for (i, pointer) in pointers.iter().enumerate() {
+        set_le_u32(raw, Inode::BLOCK_OFFSET + i * 4, *pointer);
+      }
+    });
+
+    mapped.map(|_| ())
+  }
+
+  /// Returns the block holding logical block `index` of the file mapped by
+  /// `pointers`, allocating it and the indirect blocks leading to it if
+  /// needed. `allocated` counts the allocated blocks.
+  fn map_block(
+    &self,
+    pointers: &mut [u32; 15],
+    index: usize,
+    goal_group: usize,
+    allocated: &mut usize,
+  ) -> api::Result<u64> {
+    if index < 12 {
+      return self.ensure_block(&mut pointers[index], goal_group, allocated);
+    }
 
-        res.push(BlockPointer(block));
+    let pointers_per_block = self.block_size / core::mem::size_of::<u32>();
+    let mut index = index - 12;
+    for depth in 1..=3 {
+      let span = pointers_per_block.pow(depth);
+      if index < span {
+        let slot = 11 + depth as usize;
+        let block = self.ensure_block(&mut pointers[slot], goal_group, allocated)?;
+        return self.map_indirect(block, depth, index, goal_group, allocated);
       }
+
+      index -= span;
     }
 
-    res
+    Err(api::ErrorKind::TooBig.into())
   }
 
-  pub fn gather_triply(&self, inode: &Inode) -> Vec<BlockPointer> {
-    let mut res = vec![];
+  /// Walks down the indirect block `block` of the given depth to the block
+  /// holding the `index`-th logical block it maps, allocating the missing
+  /// blocks on the way.
+  fn map_indirect(
+    &self,
+    block: u64,
+    depth: u32,
+    index: usize,
+    goal_group: usize,
+    allocated: &mut usize,
+  ) -> api::Result<u64> {
+    let pointers_per_block = self.block_size / core::mem::size_of::<u32>();
+    let child_span = pointers_per_block.pow(depth - 1);
+    let offset = index / child_span * core::mem::size_of::<u32>();
 
-    if inode.triply_pointer.0 != 0 {
-      let mut buf = self.read_block_alloc(inode.triply_pointer);
-      let mut parser = BytesParser::new(&mut buf);
+    let mut buf = self.read_block_alloc(block);
+    let mut child = le_u32(&buf, offset);
+    if child == 0 {
+      self.ensure_block(&mut child, goal_group, allocated)?;
+      set_le_u32(&mut buf, offset, child);
+      self.write_block(block, &buf);
+    }
 
-      loop {
-        let block = parser.consume_le_u32().unwrap();
-        if block == 0 {
-          break;
+    if depth == 1 {
+      Ok(child as u64)
+    } else {
+      self.map_indirect(
+        child as u64,
+        depth - 1,
+        index % child_span,
+        goal_group,
+        allocated,
+      )
+    }
+  }
+
+  /// Allocates a block for `pointer` if it's a hole.
+  fn ensure_block(
+    &self,
+    pointer: &mut u32,
+    goal_group: usize,
+    allocated: &mut usize,
+  ) -> api::Result<u64> {
+    if *pointer == 0 {
+      *pointer = self.alloc_block(goal_group)? as u32;
+      *allocated += 1;
+    }
+
+    Ok(*pointer as u64)
+  }
+
+  /// Allocates a zeroed block, looking for a free one from `goal_group` on,
+  /// and updates the free block counts. Like `free_blocks`, only called on
+  /// filesystems without the 64-bit feature.
+  fn alloc_block(&self, goal_group: usize) -> api::Result<u64> {
+    let first_data_block = self.superblock.first_data_block() as u64;
+    let blocks_per_group = self.superblock.blocks_per_group() as u64;
+    let total_blocks = self.superblock.total_blocks();
+    let groups = self.block_group_descriptors.len();
+
+    for i in 0..groups {
+      let group = (goal_group + i) % groups;
+      let group_start = first_data_block + group as u64 * blocks_per_group;
+      let group_len = blocks_per_group.min(total_blocks - group_start);
+
+      let bitmap_block = self.block_group_descriptors[group].block_usage_bitmap;
+      let mut bitmap = self.read_block_alloc(bitmap_block);
+      let Some(bit) = (0..group_len).find(|bit| bitmap[(bit / 8) as usize] & (1 << (bit % 8)) == 0)
+      else {
+        continue;
+      };
+
+      bitmap[(bit / 8) as usize] |= 1 << (bit % 8);
+      self.write_block(bitmap_block, &bitmap);
+      self.update_descriptor(group, |raw| {
+        let free = le_u16(raw, 12).saturating_sub(1);
+        set_le_u16(raw, 12, free);
+      });
+      self.update_superblock(|raw| {
+        let free = le_u32(raw, 12).saturating_sub(1);
+        set_le_u32(raw, 12, free);
+      });
+
+      let block = group_start + bit;
+      self.write_block(block, &vec![0u8; self.block_size]);
+      return Ok(block);
+    }
+
+    Err(api::ErrorKind::ENOSPC.into())
+  }
+
+  /// Zeroes the block holding offset `size` of a file from that offset on.
+  fn zero_block_tail(&self, blocks: &[u64], size: usize) {
+    let offset = size % self.block_size;
+    if offset == 0 {
+      return;
+    }
+
+    let block = blocks[size / self.block_size];
+    if block == 0 {
+      return;
+    }
+
+    let mut buf = self.read_block_alloc(block);
+    buf[offset..].fill(0);
+    self.write_block(block, &buf);
+  }
+
+  /// Releases the blocks from logical block `keep` on, below the indirect
+  /// block `block` of the given depth, which maps the logical blocks from
+  /// `start` on. Returns whether `block` itself is no longer needed.
+  fn release_indirect(
+    &self,
+    block: u32,
+    depth: u32,
+    start: usize,
+    keep: usize,
+    freed: &mut Vec<u64>,
+  ) -> bool {
+    let pointers_per_block = self.block_size / core::mem::size_of::<u32>();
+    if block == 0 || start + pointers_per_block.pow(depth) <= keep {
+      return false;
+    }
+
+    let child_span = pointers_per_block.pow(depth - 1);
+    let mut buf = self.read_block_alloc(block as u64);
+    let mut modified = false;
+    for (i, pointer) in buf
+      .chunks_exact_mut(core::mem::size_of::<u32>())
+      .enumerate()
+    {
+      let child = u32::from_le_bytes((&*pointer).try_into().unwrap());
+      if child == 0 {
+        continue;
+      }
+
+      let child_start = start + i * child_span;
+      let released = if depth == 1 {
+        child_start >= keep
+      } else {
+        self.release_indirect(child, depth - 1, child_start, keep, freed)
+      };
+
+      if released {
+        freed.push(child as u64);
+        pointer.fill(0);
+        modified = true;
+      }
+    }
+
+    // Nothing below the block is kept, the caller releases it as well.
+    if start >= keep {
+      return true;
+    }
+
+    if modified {
+      self.write_block(block as u64, &buf);
+    }
+
+    false
+  }
+
+  /// Marks `blocks` free in the block bitmaps and updates the free block
+  /// counts. Only called on filesystems without the 64-bit feature, whose
+  /// counts fit in the lower halves.
+  fn free_blocks(&self, blocks: &[u64]) {
+    let first_data_block = self.superblock.first_data_block() as u64;
+    let blocks_per_group = self.superblock.blocks_per_group() as u64;
+
+    let mut groups = BTreeMap::<usize, Vec<u64>>::new();
+    for block in blocks {
+      let index = block - first_data_block;
+      groups
+        .entry((index / blocks_per_group) as usize)
+        .or_default()
+        .push(index % blocks_per_group);
+    }
+
+    let mut total = 0;
+    for (group, bits) in groups {
+      let bitmap_block = self.block_group_descriptors[group].block_usage_bitmap;
+      let mut bitmap = self.read_block_alloc(bitmap_block);
+      let mut count = 0;
+      for bit in bits {
+        let (byte, mask) = ((bit / 8) as usize, 1u8 << (bit % 8));
+        if bitmap[byte] & mask == 0 {
+          warn!(
+            "ext2: block {} is already free",
+            first_data_block + group as u64 * blocks_per_group + bit
+          );
+          continue;
         }
 
-        res.push(BlockPointer(block));
+        bitmap[byte] &= !mask;
+        count += 1;
       }
+
+      self.write_block(bitmap_block, &bitmap);
+      self.update_descriptor(group, |raw| {
+        let free = le_u16(raw, 12).wrapping_add(count);
+        set_le_u16(raw, 12, free);
+      });
+      total += count as u32;
     }
 
-    res
+    self.update_superblock(|raw| {
+      let free = le_u32(raw, 12).wrapping_add(total);
+      set_le_u32(raw, 12, free);
+    });
   }
 
   pub fn root(ext2: Arc<Ext2>) -> Arc<dyn vfs::Directory> {
-    let inode = ext2.read_inode(2);
     Arc::new(DriveInode {
       ext2: ext2.clone(),
-      id: NodeId::new(2),
-      inode: Arc::new(inode),
+      id: ext2.node_id(2),
     })
   }
 }
+
+/// Returns the 15 block pointers of a block-mapped inode: 12 direct ones,
+/// then the singly, doubly and triply indirect ones.
+fn block_pointers(inode: &Inode) -> [u32; 15] {
+  let mut pointers = [0u32; 15];
+  for (i, pointer) in pointers[..12].iter_mut().enumerate() {
+    *pointer = *inode.direct_pointers.at(i);
+  }
+  pointers[12] = *inode.singly_pointer;
+  pointers[13] = *inode.doubly_pointer;
+  pointers[14] = *inode.triply_pointer;
+  pointers
+}
+
+fn le_u16(buf: &[u8], offset: usize) -> u16 {
+  u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn set_le_u16(buf: &mut [u8], offset: usize, value: u16) {
+  buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn le_u32(buf: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn set_le_u32(buf: &mut [u8], offset: usize, value: u32) {
+  buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
@@ -0,0 +1,100 @@
+//! ext4 extent trees. Inodes with the extents flag keep the root of a tree
+//! in `i_block` instead of the classic block map. Index nodes point to the
+//! next level of the tree, leaves map runs of logical blocks to physical
+//! ones.
+
+use utils::bytes_parser::BytesParser;
+
+pub const EXTENT_MAGIC: u16 = 0xf30a;
+/// Deepest tree Linux creates.
+pub const MAX_DEPTH: u16 = 5;
+/// Lengths above this mark preallocated, uninitialized extents.
+const MAX_INITIALIZED_LEN: u16 = 32768;
+
+#[derive(Debug)]
+#[allow(unused)]
+pub struct ExtentHeader {
+	pub entries: u16,
+	pub max_entries: u16,
+	/// 0 for leaves.
+	pub depth: u16,
+	generation: u32,
+}
+
+impl ExtentHeader {
+	pub fn parse(parser: &mut BytesParser) -> Option<Self> {
+		let magic = parser.consume_le_u16().ok()?;
+		if magic != EXTENT_MAGIC {
+			return None;
+		}
+
+		let entries = parser.consume_le_u16().ok()?;
+		let max_entries = parser.consume_le_u16().ok()?;
+		let depth = parser.consume_le_u16().ok()?;
+		let generation = parser.consume_le_u32().ok()?;
+
+		if entries > max_entries || depth > MAX_DEPTH {
+			return None;
+		}
+
+		Some(Self {
+			entries,
+			max_entries,
+			depth,
+			generation,
+		})
+	}
+}
+
+#[derive(Debug)]
+pub struct ExtentIndex {
+	/// First logical block covered by the child node.
+	pub first_block: u32,
+	/// Physical block holding the child node.
+	pub child: u64,
+}
+
+impl ExtentIndex {
+	pub fn parse(parser: &mut BytesParser) -> Option<Self> {
+		let first_block = parser.consume_le_u32().ok()?;
+		let child_lo = parser.consume_le_u32().ok()?;
+		let child_hi = parser.consume_le_u16().ok()?;
+		let _unused = parser.consume_le_u16().ok()?;
+
+		Some(Self {
+			first_block,
+			child: ((child_hi as u64) << 32) | child_lo as u64,
+		})
+	}
+}
+
+#[derive(Debug)]
+pub struct Extent {
+	pub first_block: u32,
+	pub len: u16,
+	pub start: u64,
+	/// Uninitialized extents are allocated but read as zeros.
+	pub initialized: bool,
+}
+
+impl Extent {
+	pub fn parse(parser: &mut BytesParser) -> Option<Self> {
+		let first_block = parser.consume_le_u32().ok()?;
+		let len = parser.consume_le_u16().ok()?;
+		let start_hi = parser.consume_le_u16().ok()?;
+		let start_lo = parser.consume_le_u32().ok()?;
+
+		let (len, initialized) = if len > MAX_INITIALIZED_LEN {
+			(len - MAX_INITIALIZED_LEN, false)
+		} else {
+			(len, true)
+		};
+
+		Some(Self {
+			first_block,
+			len,
+			start: ((start_hi as u64) << 32) | start_lo as u64,
+			initialized,
+		})
+	}
+}
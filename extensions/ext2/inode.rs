@@ -4,7 +4,7 @@ use api::posix;
 use bitflags::bitflags;
 use utils::bytes_parser::BytesParser;
 
-use crate::BlockPointer;
+use crate::{dirent::DirentType, BlockPointer};
 
 #[derive(Debug)]
 #[repr(transparent)]
@@ -112,6 +112,7 @@ bitflags! {
   const HASH_INDEX_DIRECTORY = 0x0001_0000;
   const AFS_DIRECTORY = 0x0002_0000;
   const JOURNAL_FILE_DATA = 0x0004_0000;
+  const EXTENTS = 0x0008_0000;
   }
 }
 
@@ -130,6 +131,9 @@ pub struct Inode {
 	drive_sectors: u32,
 	flags: Flags,
 	_osval1: [u8; 4],
+	/// The raw `i_block` area. It holds the block pointers below, or the
+	/// root of an extent tree when the inode uses extents.
+	pub block_data: [u8; 60],
 	pub direct_pointers: BlockPointers,
 	pub singly_pointer: BlockPointer,
 	pub doubly_pointer: BlockPointer,
@@ -142,6 +146,45 @@ pub struct Inode {
 }
 
 impl Inode {
+	const TYPE_MASK: u16 = 0xf000;
+
+	/// Offsets of `i_size`, `i_blocks`, `i_block` and `i_size_high` in the
+	/// on-disk inode.
+	pub const SIZE_OFFSET: usize = 4;
+	pub const SECTORS_OFFSET: usize = 28;
+	pub const BLOCK_OFFSET: usize = 40;
+	pub const SIZE_HIGH_OFFSET: usize = 108;
+
+	/// Returns `true` if `block_data` holds an extent tree instead of block
+	/// pointers.
+	pub fn has_extents(&self) -> bool {
+		self.flags.contains(Flags::EXTENTS)
+	}
+
+	pub fn file_type(&self) -> DirentType {
+		match self.type_and_perms.bits() & Self::TYPE_MASK {
+			0x1000 => DirentType::Fifo,
+			0x2000 => DirentType::CharDevice,
+			0x4000 => DirentType::Directory,
+			0x6000 => DirentType::BlockDevice,
+			0x8000 => DirentType::Regular,
+			0xa000 => DirentType::Symlink,
+			0xc000 => DirentType::Socket,
+			_ => DirentType::Unknown,
+		}
+	}
+
+	/// The size of the file in bytes. With the large file feature, regular
+	/// files keep the upper 32 bits where `i_dir_acl` used to be.
+	pub fn size(&self, large_files: bool) -> u64 {
+		let lower = self.lower_size as u64;
+		if large_files && self.file_type() == DirentType::Regular {
+			((*self.extended_dir_block as u64) << 32) | lower
+		} else {
+			lower
+		}
+	}
+
 	pub fn parse(parser: &mut BytesParser) -> Self {
 		let type_and_perms = parser.consume_le_u16().unwrap();
 		let uid = parser.consume_le_u16().unwrap();
@@ -155,6 +198,7 @@ impl Inode {
 		let drive_sectors = parser.consume_le_u32().unwrap();
 		let flags = parser.consume_le_u32().unwrap();
 		let _osval1 = parser.consume_bytes(4).unwrap().try_into().unwrap();
+		let block_data = parser.peek_bytes(60).unwrap().try_into().unwrap();
 		let direct_pointers = BlockPointers::parse(parser);
 		let singly_pointer = BlockPointer(parser.consume_le_u32().unwrap());
 		let doubly_pointer = BlockPointer(parser.consume_le_u32().unwrap());
@@ -176,8 +220,10 @@ impl Inode {
 			gid,
 			hard_links,
 			drive_sectors,
-			flags: Flags::from_bits(flags).unwrap(),
+			// Newer revisions keep adding flags we don't care about.
+			flags: Flags::from_bits_truncate(flags),
 			_osval1,
+			block_data,
 			direct_pointers,
 			singly_pointer,
 			doubly_pointer,
@@ -8,13 +8,20 @@ use core::ops::{Deref, DerefMut};
 use alloc::{boxed::Box, sync::Arc};
 use api::{
 	info, println,
-	schema::fs::{self, register_partition_prober, PartitionProber},
-	sync::SpinLock,
-	trace, vfs,
+	trace,
+	vfs::{
+		self,
+		registry::{register_filesystem_type, FilesystemType, MountSource},
+	},
+	warn,
 };
 use utils::bytes_parser::BytesParser;
 
-use crate::{ext2::Ext2, filesystem::Ext2Filesystem, structure::Superblock};
+use crate::{
+	ext2::Ext2,
+	filesystem::Ext2Filesystem,
+	structure::{FeatureSupport, Superblock},
+};
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
@@ -36,14 +43,22 @@ impl DerefMut for BlockPointer {
 
 pub enum Ext2Error {}
 
-pub struct Ext2Prober;
+pub struct Ext2Type;
+
+impl FilesystemType for Ext2Type {
+	fn name(&self) -> &'static str {
+		"ext2"
+	}
+
+	fn accepts(&self, fs_type: &str) -> bool {
+		matches!(fs_type, "ext2" | "ext3" | "ext4")
+	}
+
+	fn mount(&self, source: MountSource) -> Option<Arc<dyn vfs::Filesystem>> {
+		let MountSource::Partition(partition, number) = source else {
+			return None;
+		};
 
-impl PartitionProber for Ext2Prober {
-	fn probe(
-		&self,
-		partition: Arc<SpinLock<dyn fs::Partition>>,
-		number: usize,
-	) -> Option<Arc<dyn vfs::Filesystem>> {
 		let part = partition.lock();
 		let superblock_sectors = part.in_sectors(1024);
 		let mut buf = vec![0u8; 1024];
@@ -62,8 +77,24 @@ impl PartitionProber for Ext2Prober {
 		let superblock = Superblock::parse(&mut parser);
 		trace!("{:#?}", superblock);
 
+		let read_only = match superblock.feature_support() {
+			FeatureSupport::ReadWrite => false,
+			FeatureSupport::ReadOnly(reason) => {
+				warn!("Mounting {:?} read-only: {}", part.name(), reason);
+				true
+			}
+			FeatureSupport::Unsupported(reason) => {
+				warn!("Can't mount {:?}: {}", part.name(), reason);
+				return None;
+			}
+		};
+
+		if superblock.has_journal() {
+			info!("{:?} has a clean ext3 journal, ignoring it", part.name());
+		}
+
 		drop(part);
-		let mut ext2 = Ext2::new(partition, number, superblock);
+		let mut ext2 = Ext2::new(partition, number, superblock, read_only);
 		ext2.parse_bgd_table();
 
 		Some(Arc::new(Ext2Filesystem(Arc::new(ext2), number)))
@@ -71,11 +102,12 @@ impl PartitionProber for Ext2Prober {
 }
 
 pub fn init() {
-	register_partition_prober(Box::new(Ext2Prober))
+	register_filesystem_type(Box::new(Ext2Type))
 }
 
 pub mod dirent;
 pub mod ext2;
+pub mod extent;
 pub mod filesystem;
 pub mod inode;
 pub mod structure;
@@ -46,8 +46,9 @@ impl From<u16> for FileSystemState {
 	fn from(val: u16) -> Self {
 		match val {
 			1 => Self::Clean,
-			2 => Self::HasErrors,
-			_ => panic!("out of range"),
+			// Anything else (e.g. a filesystem which wasn't unmounted cleanly)
+			// deserves the same caution as one with errors.
+			_ => Self::HasErrors,
 		}
 	}
 }
@@ -66,7 +67,7 @@ impl From<u16> for ErrorHandling {
 			1 => Self::Ignore,
 			2 => Self::RemountReadOnly,
 			3 => Self::KernelPanic,
-			_ => panic!("out of range"),
+			_ => Self::Ignore,
 		}
 	}
 }
@@ -88,8 +89,7 @@ impl From<u32> for OsId {
 			1 => Self::GnuHurd,
 			2 => Self::MASIX,
 			3 => Self::FreeBSD,
-			4 => Self::Other,
-			_ => panic!("out of range"),
+			_ => Self::Other,
 		}
 	}
 }
@@ -103,6 +103,10 @@ pub struct ExtendedSuperblock {
 	optional_features: OptionalFeatures,
 	pub required_features: RequiredFeatures,
 	pub readonly_features: ReadOnlyFeatures,
+	/// Feature bits we don't know about, kept to decide whether the
+	/// filesystem can be mounted at all.
+	unknown_required_features: u32,
+	unknown_readonly_features: u32,
 	fsid: Uuid,
 	volume_name: String,
 	last_mounted_path: String,
@@ -114,13 +118,33 @@ pub struct ExtendedSuperblock {
 	journal_inode: u32,
 	journal_device: u32,
 	head_orphan: u32,
+	hash_seed: [u8; 16],
+	default_hash_version: u8,
+	journal_backup_type: u8,
+	/// Size of a block group descriptor with the 64-bit feature.
+	pub descriptor_size: u16,
+	default_mount_options: u32,
+	first_meta_block_group: u32,
+	mkfs_time: posix::Timestamp,
+	journal_blocks: [u8; 68],
+	total_blocks_hi: u32,
+	superuser_reserved_hi: u32,
+	total_unallocated_blocks_hi: u32,
+	min_extra_inode_size: u16,
+	want_extra_inode_size: u16,
+	flags: u32,
+	raid_stride: u16,
+	mmp_interval: u16,
+	mmp_block: u64,
+	raid_stripe_width: u32,
+	log_groups_per_flex: u8,
 }
 
 bitflags! {
-  struct OptionalFeatures: u32 {
+  pub struct OptionalFeatures: u32 {
 	const PREALLOCATE_BLOCKS = 0x01;
 	const AFS_INODE = 0x02;
-	const HAS_JOURNAK = 0x04;
+	const HAS_JOURNAL = 0x04;
 	const EXTENDED_ATTR = 0x08;
 	const CAN_RESIZE = 0x10;
 	const DIRS_USE_HASH_IDX = 0x20;
@@ -133,6 +157,12 @@ bitflags! {
 	const DIRENT_TYPE = 0x02;
 	const JOURNAL_REPLAY = 0x04;
 	const JOURNAL_DEVICE = 0x08;
+	const META_BLOCK_GROUPS = 0x10;
+	const EXTENTS = 0x40;
+	const SIXTY_FOUR_BIT = 0x80;
+	const FLEX_BLOCK_GROUPS = 0x200;
+	const CHECKSUM_SEED = 0x2000;
+	const LARGE_DIR = 0x4000;
   }
 }
 
@@ -140,16 +170,24 @@ bitflags! {
   pub struct ReadOnlyFeatures: u32 {
 	const SPARESE_SUPERBLOCK = 0x01;
 	const LARGE_FILE_SIZE = 0x02;
-  const DIRENT_BTREE = 0x04;
+	const DIRENT_BTREE = 0x04;
   }
 }
 
+/// How a filesystem can be mounted given the features it uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureSupport {
+	ReadWrite,
+	ReadOnly(&'static str),
+	Unsupported(&'static str),
+}
+
 #[derive(Debug, Clone)]
 #[allow(unused)]
 pub struct BlockGroupDescriptor {
-	block_usage_bitmap: u32,
-	inode_usage_bitmap: u32,
-	pub inode_table: u32,
+	pub block_usage_bitmap: u64,
+	inode_usage_bitmap: u64,
+	pub inode_table: u64,
 	unallocated_blocks: u16,
 	unallocated_inodes: u16,
 	directories: u16,
@@ -157,7 +195,14 @@ pub struct BlockGroupDescriptor {
 }
 
 impl BlockGroupDescriptor {
-	pub fn parse(parser: &mut BytesParser) -> Self {
+	/// Size of a descriptor without the 64-bit feature.
+	pub const SIZE: usize = 32;
+	/// Smallest descriptor holding the upper halves of the block numbers.
+	pub const SIZE_64: usize = 64;
+
+	/// Parses a descriptor of `size` bytes, which is [`Self::SIZE`] unless
+	/// the filesystem uses the 64-bit feature.
+	pub fn parse(parser: &mut BytesParser, size: usize) -> Self {
 		let block_usage_bitmap = parser.consume_le_u32().unwrap();
 		let inode_usage_bitmap = parser.consume_le_u32().unwrap();
 		let inode_table = parser.consume_le_u32().unwrap();
@@ -166,10 +211,32 @@ impl BlockGroupDescriptor {
 		let directories = parser.consume_le_u16().unwrap();
 		let _reserved = parser.consume_bytes(14).unwrap();
 
+		let (block_usage_bitmap_hi, inode_usage_bitmap_hi, inode_table_hi) =
+			if size >= Self::SIZE_64 {
+				(
+					parser.consume_le_u32().unwrap(),
+					parser.consume_le_u32().unwrap(),
+					parser.consume_le_u32().unwrap(),
+				)
+			} else {
+				(0, 0, 0)
+			};
+
+		// Free counts, checksums and padding we don't use.
+		let parsed = if size >= Self::SIZE_64 {
+			Self::SIZE + 12
+		} else {
+			Self::SIZE
+		};
+		parser.skip(size.saturating_sub(parsed)).unwrap();
+
+		let join =
+			|lo: u32, hi: u32| ((hi as u64) << 32) | lo as u64;
+
 		Self {
-			block_usage_bitmap,
-			inode_usage_bitmap,
-			inode_table,
+			block_usage_bitmap: join(block_usage_bitmap, block_usage_bitmap_hi),
+			inode_usage_bitmap: join(inode_usage_bitmap, inode_usage_bitmap_hi),
+			inode_table: join(inode_table, inode_table_hi),
 			unallocated_blocks,
 			unallocated_inodes,
 			directories,
@@ -246,7 +313,9 @@ impl Superblock {
 
 	pub fn bgd_count(&self) -> usize {
 		let inode_count = self.total_inodes;
-		let block_count = self.total_blocks;
+		// Group 0 starts at the block holding the superblock.
+		let block_count =
+			self.total_blocks() - self.first_data_block() as u64;
 
 		let blocks_per_group = self.blocks_per_group;
 		let inodes_per_group = self.inodes_per_group;
@@ -262,6 +331,166 @@ impl Superblock {
 
 		blocks_bgd_count as usize
 	}
+
+	/// The number of blocks, including the upper 32 bits kept by
+	/// filesystems with the 64-bit feature.
+	pub fn total_blocks(&self) -> u64 {
+		let hi = match self.extended.as_ref() {
+			Some(ext) if self.is_64bit() => ext.total_blocks_hi,
+			_ => 0,
+		};
+
+		((hi as u64) << 32) | self.total_blocks as u64
+	}
+
+	pub fn is_64bit(&self) -> bool {
+		self.has_required_feature(RequiredFeatures::SIXTY_FOUR_BIT)
+	}
+
+	pub fn has_flex_block_groups(&self) -> bool {
+		self.has_required_feature(RequiredFeatures::FLEX_BLOCK_GROUPS)
+	}
+
+	fn has_required_feature(&self, feature: RequiredFeatures) -> bool {
+		self.extended
+			.as_ref()
+			.map_or(false, |ext| ext.required_features.contains(feature))
+	}
+
+	/// The size of an entry in the block group descriptor table.
+	pub fn descriptor_size(&self) -> usize {
+		match self.extended.as_ref() {
+			Some(ext) if self.is_64bit() => ext.descriptor_size as usize,
+			_ => BlockGroupDescriptor::SIZE,
+		}
+	}
+
+	pub fn blocks_per_group(&self) -> u32 {
+		self.blocks_per_group
+	}
+
+	/// The block holding the primary superblock (`s_first_data_block`).
+	pub fn first_data_block(&self) -> u32 {
+		self.this_superblock
+	}
+
+	pub fn is_clean(&self) -> bool {
+		matches!(self.state, FileSystemState::Clean)
+	}
+
+	pub fn has_sparse_superblocks(&self) -> bool {
+		self.extended.as_ref().map_or(false, |ext| {
+			ext.readonly_features
+				.contains(ReadOnlyFeatures::SPARESE_SUPERBLOCK)
+		})
+	}
+
+	/// Whether block group `group` holds a backup of the superblock and the
+	/// block group descriptor table. Without the sparse superblock feature
+	/// every group does; with it only groups 0, 1 and powers of 3, 5 and 7.
+	pub fn group_has_superblock(&self, group: usize) -> bool {
+		if !self.has_sparse_superblocks() || group <= 1 {
+			return true;
+		}
+
+		let is_power_of = |base: usize| {
+			let mut n = group;
+			while n % base == 0 {
+				n /= base;
+			}
+			n == 1
+		};
+
+		is_power_of(3) || is_power_of(5) || is_power_of(7)
+	}
+
+	/// Decides whether we can mount the filesystem, and whether writes are
+	/// allowed, from its revision and feature flags.
+	pub fn feature_support(&self) -> FeatureSupport {
+		let Some(ext) = self.extended.as_ref() else {
+			// Revision 0 has no feature flags.
+			return FeatureSupport::ReadWrite;
+		};
+
+		if ext.unknown_required_features != 0 {
+			return FeatureSupport::Unsupported("unknown incompatible features");
+		}
+
+		let required = ext.required_features;
+		if required.contains(RequiredFeatures::COMPRESSION) {
+			return FeatureSupport::Unsupported("compression");
+		}
+
+		if required.contains(RequiredFeatures::JOURNAL_DEVICE) {
+			return FeatureSupport::Unsupported("external journal device");
+		}
+
+		if required.contains(RequiredFeatures::META_BLOCK_GROUPS) {
+			return FeatureSupport::Unsupported("meta block groups");
+		}
+
+		// We can't replay the journal. Reading a filesystem whose latest
+		// changes are still in the journal would show stale data.
+		if required.contains(RequiredFeatures::JOURNAL_REPLAY) {
+			return FeatureSupport::Unsupported("journal needs recovery");
+		}
+
+		let inode_size = ext.inode_size_in_bytes as u32;
+		if inode_size < 128
+			|| !inode_size.is_power_of_two()
+			|| inode_size > self.block_size
+		{
+			return FeatureSupport::Unsupported("invalid inode size");
+		}
+
+		if self.is_64bit() {
+			let descriptor_size = self.descriptor_size();
+			if descriptor_size < BlockGroupDescriptor::SIZE_64
+				|| !descriptor_size.is_power_of_two()
+				|| descriptor_size > self.block_size as usize
+			{
+				return FeatureSupport::Unsupported(
+					"invalid block group descriptor size",
+				);
+			}
+		}
+
+		// Block bitmaps and block maps are updated with 32-bit block
+		// numbers.
+		if required.contains(RequiredFeatures::SIXTY_FOUR_BIT) {
+			return FeatureSupport::ReadOnly("64-bit block numbers");
+		}
+
+		// We can read extent mapped files and the ext4 group layout, but we
+		// can't allocate extents or update checksums.
+		if required.intersects(
+			RequiredFeatures::EXTENTS
+				| RequiredFeatures::FLEX_BLOCK_GROUPS
+				| RequiredFeatures::CHECKSUM_SEED
+				| RequiredFeatures::LARGE_DIR,
+		) {
+			return FeatureSupport::ReadOnly("ext4 features");
+		}
+
+		if ext.unknown_readonly_features != 0 {
+			return FeatureSupport::ReadOnly("unknown read-only features");
+		}
+
+		if !self.is_clean() {
+			return FeatureSupport::ReadOnly("filesystem not clean");
+		}
+
+		// An ext3 filesystem with a clean journal is a valid ext2 filesystem.
+		// Writes don't go through the journal, which is fine as long as it
+		// stays empty.
+		FeatureSupport::ReadWrite
+	}
+
+	pub fn has_journal(&self) -> bool {
+		self.extended.as_ref().map_or(false, |ext| {
+			ext.optional_features.contains(OptionalFeatures::HAS_JOURNAL)
+		})
+	}
 }
 
 impl ExtendedSuperblock {
@@ -283,17 +512,46 @@ impl ExtendedSuperblock {
 		let journal_inode = parser.consume_le_u32().unwrap();
 		let journal_device = parser.consume_le_u32().unwrap();
 		let head_orphan = parser.consume_le_u32().unwrap();
+		// ext4 fields. Older filesystems leave them zeroed.
+		let hash_seed = parser.consume_bytes(16).unwrap().try_into().unwrap();
+		let default_hash_version = parser.consume_u8().unwrap();
+		let journal_backup_type = parser.consume_u8().unwrap();
+		let descriptor_size = parser.consume_le_u16().unwrap();
+		let default_mount_options = parser.consume_le_u32().unwrap();
+		let first_meta_block_group = parser.consume_le_u32().unwrap();
+		let mkfs_time = posix::Timestamp(parser.consume_le_u32().unwrap());
+		let journal_blocks =
+			parser.consume_bytes(68).unwrap().try_into().unwrap();
+		let total_blocks_hi = parser.consume_le_u32().unwrap();
+		let superuser_reserved_hi = parser.consume_le_u32().unwrap();
+		let total_unallocated_blocks_hi = parser.consume_le_u32().unwrap();
+		let min_extra_inode_size = parser.consume_le_u16().unwrap();
+		let want_extra_inode_size = parser.consume_le_u16().unwrap();
+		let flags = parser.consume_le_u32().unwrap();
+		let raid_stride = parser.consume_le_u16().unwrap();
+		let mmp_interval = parser.consume_le_u16().unwrap();
+		let mmp_block = parser.consume_le_u64().unwrap();
+		let raid_stripe_width = parser.consume_le_u32().unwrap();
+		let log_groups_per_flex = parser.consume_u8().unwrap();
 
 		Self {
 			first_non_reserved,
 			inode_size_in_bytes,
 			this_superblock,
-			optional_features: OptionalFeatures::from_bits(optional_features)
-				.unwrap(),
-			required_features: RequiredFeatures::from_bits(required_features)
-				.unwrap(),
-			readonly_features: ReadOnlyFeatures::from_bits(readonly_features)
-				.unwrap(),
+			// Unknown optional features are safe to ignore by definition.
+			optional_features: OptionalFeatures::from_bits_truncate(
+				optional_features,
+			),
+			required_features: RequiredFeatures::from_bits_truncate(
+				required_features,
+			),
+			readonly_features: ReadOnlyFeatures::from_bits_truncate(
+				readonly_features,
+			),
+			unknown_required_features: required_features
+				& !RequiredFeatures::all().bits(),
+			unknown_readonly_features: readonly_features
+				& !ReadOnlyFeatures::all().bits(),
 			fsid,
 			volume_name,
 			last_mounted_path,
@@ -305,6 +563,25 @@ impl ExtendedSuperblock {
 			journal_inode,
 			journal_device,
 			head_orphan,
+			hash_seed,
+			default_hash_version,
+			journal_backup_type,
+			descriptor_size,
+			default_mount_options,
+			first_meta_block_group,
+			mkfs_time,
+			journal_blocks,
+			total_blocks_hi,
+			superuser_reserved_hi,
+			total_unallocated_blocks_hi,
+			min_extra_inode_size,
+			want_extra_inode_size,
+			flags,
+			raid_stride,
+			mmp_interval,
+			mmp_block,
+			raid_stripe_width,
+			log_groups_per_flex,
 		}
 	}
 }
@@ -0,0 +1,113 @@
+/// The flavour of the FAT, determined by the number of clusters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatKind {
+	Fat12,
+	Fat16,
+	Fat32,
+}
+
+/// The BIOS Parameter Block found in the first sector of the volume.
+#[derive(Debug, Clone)]
+pub struct BiosParameterBlock {
+	pub bytes_per_sector: u16,
+	pub sectors_per_cluster: u8,
+	pub reserved_sectors: u16,
+	pub num_fats: u8,
+	pub root_entries: u16,
+	pub total_sectors: u32,
+	/// Size of a single FAT in sectors.
+	pub fat_size: u32,
+	/// The first cluster of the root directory. Only valid on FAT32.
+	pub root_cluster: u32,
+	pub kind: FatKind,
+	pub cluster_count: u32,
+}
+
+fn le16(bytes: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn le32(bytes: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+impl BiosParameterBlock {
+	/// Parses the boot sector. Returns `None` if it doesn't look like a FAT
+	/// volume.
+	pub fn parse(sector: &[u8]) -> Option<Self> {
+		if sector.len() < 512 || sector[510] != 0x55 || sector[511] != 0xaa {
+			return None;
+		}
+
+		// The boot sector starts with a jump over the BPB.
+		if sector[0] != 0xeb && sector[0] != 0xe9 {
+			return None;
+		}
+
+		let bytes_per_sector = le16(sector, 0x0b);
+		let sectors_per_cluster = sector[0x0d];
+		let reserved_sectors = le16(sector, 0x0e);
+		let num_fats = sector[0x10];
+		let root_entries = le16(sector, 0x11);
+		let total_sectors = match le16(sector, 0x13) {
+			0 => le32(sector, 0x20),
+			total => total as u32,
+		};
+		let fat_size = match le16(sector, 0x16) {
+			0 => le32(sector, 0x24),
+			size => size as u32,
+		};
+
+		if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+			|| !sectors_per_cluster.is_power_of_two()
+			|| reserved_sectors == 0
+			|| num_fats == 0
+			|| fat_size == 0
+			|| total_sectors == 0
+		{
+			return None;
+		}
+
+		let root_dir_sectors = (root_entries as u32 * 32).div_ceil(bytes_per_sector as u32);
+		let metadata_sectors = reserved_sectors as u32 + num_fats as u32 * fat_size + root_dir_sectors;
+		let data_sectors = total_sectors.checked_sub(metadata_sectors)?;
+		let cluster_count = data_sectors / sectors_per_cluster as u32;
+
+		// This is how the specification tells the variants apart. Nothing
+		// else (including the file system type string) is reliable.
+		let kind = if cluster_count < 4085 {
+			FatKind::Fat12
+		} else if cluster_count < 65525 {
+			FatKind::Fat16
+		} else {
+			FatKind::Fat32
+		};
+
+		let root_cluster = if kind == FatKind::Fat32 {
+			le32(sector, 0x2c)
+		} else {
+			0
+		};
+
+		Some(Self {
+			bytes_per_sector,
+			sectors_per_cluster,
+			reserved_sectors,
+			num_fats,
+			root_entries,
+			total_sectors,
+			fat_size,
+			root_cluster,
+			kind,
+			cluster_count,
+		})
+	}
+
+	pub fn cluster_size(&self) -> usize {
+		self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+	}
+
+	pub fn root_dir_sectors(&self) -> u32 {
+		(self.root_entries as u32 * 32).div_ceil(self.bytes_per_sector as u32)
+	}
+}
@@ -0,0 +1,204 @@
+use alloc::{string::String, vec::Vec};
+use api::bitflags::bitflags;
+
+use crate::fat::{Fat, RootDirectory};
+
+pub const DIRENT_LEN: usize = 32;
+
+bitflags! {
+	#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+	pub struct Attributes: u8 {
+		const READ_ONLY = 0x01;
+		const HIDDEN = 0x02;
+		const SYSTEM = 0x04;
+		const VOLUME_ID = 0x08;
+		const DIRECTORY = 0x10;
+		const ARCHIVE = 0x20;
+		/// The combination marking a long file name entry.
+		const LONG_NAME = 0x0f;
+	}
+}
+
+/// Where a directory's entries are stored.
+#[derive(Debug, Clone, Copy)]
+pub enum DirLocation {
+	Root,
+	Chain(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Dirent {
+	pub name: String,
+	pub attributes: Attributes,
+	pub first_cluster: u32,
+	pub size: u32,
+	/// Byte offset of the short entry on the volume, used to update it.
+	pub offset: u64,
+}
+
+impl Dirent {
+	pub fn is_dir(&self) -> bool {
+		self.attributes.contains(Attributes::DIRECTORY)
+	}
+}
+
+/// Computes the checksum of a 8.3 name stored in LFN entries.
+fn short_name_checksum(name: &[u8]) -> u8 {
+	name.iter().fold(0u8, |sum, &c| {
+		((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c)
+	})
+}
+
+fn short_name(entry: &[u8]) -> String {
+	let lowercase_base = entry[12] & 0x08 != 0;
+	let lowercase_ext = entry[12] & 0x10 != 0;
+
+	let mut base = entry[0..8].to_vec();
+	// 0x05 stands for 0xe5, which marks deleted entries otherwise.
+	if base[0] == 0x05 {
+		base[0] = 0xe5;
+	}
+
+	let convert = |bytes: &[u8], lowercase: bool| -> String {
+		bytes
+			.iter()
+			.map(|&c| {
+				let c = c as char;
+				if lowercase {
+					c.to_ascii_lowercase()
+				} else {
+					c
+				}
+			})
+			.collect::<String>()
+			.trim_end_matches(' ')
+			.into()
+	};
+
+	let mut name = convert(&base, lowercase_base);
+	let ext = convert(&entry[8..11], lowercase_ext);
+	if !ext.is_empty() {
+		name.push('.');
+		name.push_str(&ext);
+	}
+
+	name
+}
+
+/// Accumulates the pieces of a long file name spread over several entries.
+#[derive(Default)]
+struct LongName {
+	parts: Vec<(u8, [u16; 13])>,
+	checksum: u8,
+}
+
+impl LongName {
+	fn push(&mut self, entry: &[u8]) {
+		// The last piece comes first and starts a new name.
+		if entry[0] & 0x40 != 0 {
+			self.parts.clear();
+			self.checksum = entry[13];
+		}
+
+		let mut chars = [0u16; 13];
+		let offsets = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+		for (c, offset) in chars.iter_mut().zip(offsets) {
+			*c = u16::from_le_bytes([entry[offset], entry[offset + 1]]);
+		}
+
+		self.parts.push((entry[0] & 0x1f, chars));
+	}
+
+	fn take(&mut self, short_entry: &[u8]) -> Option<String> {
+		if self.parts.is_empty() {
+			return None;
+		}
+
+		let mut parts = core::mem::take(&mut self.parts);
+		if short_name_checksum(&short_entry[0..11]) != self.checksum {
+			return None;
+		}
+
+		parts.sort_by_key(|(order, _)| *order);
+		let utf16 = parts
+			.iter()
+			.flat_map(|(_, chars)| chars.iter().copied())
+			.take_while(|c| *c != 0x0000 && *c != 0xffff)
+			.collect::<Vec<_>>();
+
+		String::from_utf16(&utf16).ok()
+	}
+}
+
+/// Returns the (offset, length) pairs on the volume holding the entries of
+/// the directory.
+fn dir_regions(fat: &Fat, location: DirLocation) -> Vec<(u64, usize)> {
+	let first_cluster = match (location, fat.root_dir()) {
+		(DirLocation::Root, RootDirectory::Fixed { offset, len }) => return vec![(offset, len)],
+		(DirLocation::Root, RootDirectory::Chain(cluster)) => cluster,
+		(DirLocation::Chain(cluster), _) => cluster,
+	};
+
+	fat
+		.cluster_chain(first_cluster)
+		.into_iter()
+		.map(|cluster| (fat.cluster_offset(cluster), fat.cluster_size()))
+		.collect()
+}
+
+/// Reads all the live entries of a directory, skipping volume labels.
+pub fn read_dir(fat: &Fat, location: DirLocation) -> Vec<Dirent> {
+	let mut entries = vec![];
+	let mut long_name = LongName::default();
+
+	for (region_offset, len) in dir_regions(fat, location) {
+		let mut buf = vec![0u8; len];
+		fat.read_bytes(region_offset, &mut buf);
+
+		for (i, entry) in buf.chunks_exact(DIRENT_LEN).enumerate() {
+			match entry[0] {
+				// No more entries in this directory.
+				0x00 => return entries,
+				// A deleted entry.
+				0xe5 => {
+					long_name.parts.clear();
+					continue;
+				}
+				_ => {}
+			}
+
+			let attributes = Attributes::from_bits_retain(entry[11]);
+			if attributes & Attributes::LONG_NAME == Attributes::LONG_NAME {
+				long_name.push(entry);
+				continue;
+			}
+
+			let name = long_name.take(entry);
+			if attributes.contains(Attributes::VOLUME_ID) {
+				continue;
+			}
+
+			let cluster_hi = u16::from_le_bytes([entry[20], entry[21]]) as u32;
+			let cluster_lo = u16::from_le_bytes([entry[26], entry[27]]) as u32;
+			entries.push(Dirent {
+				name: name.unwrap_or_else(|| short_name(entry)),
+				attributes,
+				first_cluster: (cluster_hi << 16) | cluster_lo,
+				size: u32::from_le_bytes(entry[28..32].try_into().unwrap()),
+				offset: region_offset + (i * DIRENT_LEN) as u64,
+			});
+		}
+	}
+
+	entries
+}
+
+/// Writes the first cluster and the size of a file back to its entry.
+pub fn update_dirent(fat: &Fat, dirent: &Dirent) {
+	let mut entry = [0u8; DIRENT_LEN];
+	fat.read_bytes(dirent.offset, &mut entry);
+	entry[20..22].copy_from_slice(&((dirent.first_cluster >> 16) as u16).to_le_bytes());
+	entry[26..28].copy_from_slice(&(dirent.first_cluster as u16).to_le_bytes());
+	entry[28..32].copy_from_slice(&dirent.size.to_le_bytes());
+	fat.write_bytes(dirent.offset, &entry);
+}
@@ -0,0 +1,247 @@
+use core::{
+	fmt,
+	sync::atomic::{AtomicU32, Ordering},
+};
+
+use alloc::{sync::Arc, vec::Vec};
+use api::{schema::fs::Partition, sync::SpinLock, vfs::FsId, ErrorKind, Result};
+
+use crate::bpb::{BiosParameterBlock, FatKind};
+
+/// Cluster numbers 0 and 1 are reserved. The first data cluster is 2.
+pub const FIRST_CLUSTER: u32 = 2;
+
+#[derive(Debug, Clone, Copy)]
+pub enum RootDirectory {
+	/// FAT12 and FAT16 keep the root directory in a fixed area right after
+	/// the FATs.
+	Fixed { offset: u64, len: usize },
+	/// FAT32 stores it in a cluster chain like any other directory.
+	Chain(u32),
+}
+
+pub struct Fat {
+	partition: Arc<SpinLock<dyn Partition>>,
+	bpb: BiosParameterBlock,
+	/// Byte offset of the first FAT.
+	fat_offset: u64,
+	/// Size of a single FAT in bytes.
+	fat_len: u64,
+	root_dir: RootDirectory,
+	/// Byte offset of cluster #2.
+	data_offset: u64,
+	next_free: AtomicU32,
+	read_only: bool,
+	fs_id: FsId,
+}
+
+impl fmt::Debug for Fat {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Fat")
+			.field("bpb", &self.bpb)
+			.field("root_dir", &self.root_dir)
+			.field("read_only", &self.read_only)
+			.finish()
+	}
+}
+
+impl Fat {
+	pub fn new(partition: Arc<SpinLock<dyn Partition>>, bpb: BiosParameterBlock) -> Self {
+		let sector_size = bpb.bytes_per_sector as u64;
+		let fat_offset = bpb.reserved_sectors as u64 * sector_size;
+		let fat_len = bpb.fat_size as u64 * sector_size;
+		let root_offset = fat_offset + bpb.num_fats as u64 * fat_len;
+		let root_len = bpb.root_dir_sectors() as u64 * sector_size;
+
+		let root_dir = match bpb.kind {
+			FatKind::Fat32 => RootDirectory::Chain(bpb.root_cluster),
+			_ => RootDirectory::Fixed {
+				offset: root_offset,
+				len: root_len as usize,
+			},
+		};
+
+		Self {
+			partition,
+			fat_offset,
+			fat_len,
+			root_dir,
+			data_offset: root_offset + root_len,
+			next_free: AtomicU32::new(FIRST_CLUSTER),
+			read_only: false,
+			fs_id: FsId::alloc(),
+			bpb,
+		}
+	}
+
+	pub fn fs_id(&self) -> FsId {
+		self.fs_id
+	}
+
+	pub fn kind(&self) -> FatKind {
+		self.bpb.kind
+	}
+
+	pub fn cluster_size(&self) -> usize {
+		self.bpb.cluster_size()
+	}
+
+	pub fn root_dir(&self) -> RootDirectory {
+		self.root_dir
+	}
+
+	pub fn is_read_only(&self) -> bool {
+		self.read_only
+	}
+
+	pub fn cluster_offset(&self, cluster: u32) -> u64 {
+		debug_assert!(cluster >= FIRST_CLUSTER);
+		self.data_offset + (cluster - FIRST_CLUSTER) as u64 * self.cluster_size() as u64
+	}
+
+	fn is_valid_cluster(&self, cluster: u32) -> bool {
+		(FIRST_CLUSTER..FIRST_CLUSTER + self.bpb.cluster_count).contains(&cluster)
+	}
+
+	/// Reads `buf.len()` bytes at `offset` from the start of the volume.
+	pub fn read_bytes(&self, offset: u64, buf: &mut [u8]) {
+		self.partition.lock().read_bytes(offset, buf);
+	}
+
+	/// Writes `buf` at `offset` from the start of the volume.
+	pub fn write_bytes(&self, offset: u64, buf: &[u8]) {
+		self.partition.lock().write_bytes(offset, buf);
+	}
+
+	pub fn sync(&self) {
+		self.partition.lock().sync();
+	}
+
+	fn end_of_chain(&self) -> u32 {
+		match self.kind() {
+			FatKind::Fat12 => 0xfff,
+			FatKind::Fat16 => 0xffff,
+			FatKind::Fat32 => 0x0fff_ffff,
+		}
+	}
+
+	fn is_end_of_chain(&self, entry: u32) -> bool {
+		match self.kind() {
+			FatKind::Fat12 => entry >= 0xff8,
+			FatKind::Fat16 => entry >= 0xfff8,
+			FatKind::Fat32 => entry >= 0x0fff_fff8,
+		}
+	}
+
+	fn fat_entry(&self, cluster: u32) -> u32 {
+		match self.kind() {
+			FatKind::Fat12 => {
+				let mut bytes = [0u8; 2];
+				self.read_bytes(self.fat_offset + (cluster + cluster / 2) as u64, &mut bytes);
+				let value = u16::from_le_bytes(bytes);
+				if cluster & 1 == 1 {
+					(value >> 4) as u32
+				} else {
+					(value & 0xfff) as u32
+				}
+			}
+			FatKind::Fat16 => {
+				let mut bytes = [0u8; 2];
+				self.read_bytes(self.fat_offset + cluster as u64 * 2, &mut bytes);
+				u16::from_le_bytes(bytes) as u32
+			}
+			FatKind::Fat32 => {
+				let mut bytes = [0u8; 4];
+				self.read_bytes(self.fat_offset + cluster as u64 * 4, &mut bytes);
+				u32::from_le_bytes(bytes) & 0x0fff_ffff
+			}
+		}
+	}
+
+	/// Updates the entry of `cluster` in every copy of the FAT.
+	fn set_fat_entry(&self, cluster: u32, value: u32) {
+		for i in 0..self.bpb.num_fats as u64 {
+			let fat_offset = self.fat_offset + i * self.fat_len;
+			match self.kind() {
+				FatKind::Fat12 => {
+					let offset = fat_offset + (cluster + cluster / 2) as u64;
+					let mut bytes = [0u8; 2];
+					self.read_bytes(offset, &mut bytes);
+					let old = u16::from_le_bytes(bytes);
+					let new = if cluster & 1 == 1 {
+						(old & 0x000f) | ((value as u16) << 4)
+					} else {
+						(old & 0xf000) | (value as u16 & 0x0fff)
+					};
+					self.write_bytes(offset, &new.to_le_bytes());
+				}
+				FatKind::Fat16 => {
+					self.write_bytes(
+						fat_offset + cluster as u64 * 2,
+						&(value as u16).to_le_bytes(),
+					);
+				}
+				FatKind::Fat32 => {
+					// The upper 4 bits are reserved and must be preserved.
+					let offset = fat_offset + cluster as u64 * 4;
+					let mut bytes = [0u8; 4];
+					self.read_bytes(offset, &mut bytes);
+					let old = u32::from_le_bytes(bytes);
+					let new = (old & 0xf000_0000) | (value & 0x0fff_ffff);
+					self.write_bytes(offset, &new.to_le_bytes());
+				}
+			}
+		}
+	}
+
+	/// Follows the chain starting at `first` and returns all of its clusters.
+	pub fn cluster_chain(&self, first: u32) -> Vec<u32> {
+		let mut chain = vec![];
+		let mut cluster = first;
+		// A corrupted FAT may contain loops. A chain can't be longer than the
+		// number of clusters.
+		while self.is_valid_cluster(cluster) && chain.len() < self.bpb.cluster_count as usize {
+			chain.push(cluster);
+
+			let next = self.fat_entry(cluster);
+			if self.is_end_of_chain(next) {
+				break;
+			}
+
+			cluster = next;
+		}
+
+		chain
+	}
+
+	/// Allocates a zero-filled cluster and appends it to the chain ending at
+	/// `last`, if any.
+	pub fn allocate_cluster(&self, last: Option<u32>) -> Result<u32> {
+		if self.read_only {
+			return Err(ErrorKind::EROFS.into());
+		}
+
+		let count = self.bpb.cluster_count;
+		let hint = self.next_free.load(Ordering::Relaxed);
+		let cluster = (0..count)
+			.map(|i| FIRST_CLUSTER + (hint - FIRST_CLUSTER + i) % count)
+			.find(|cluster| self.fat_entry(*cluster) == 0)
+			.ok_or(ErrorKind::ENOSPC)?;
+
+		self.set_fat_entry(cluster, self.end_of_chain());
+		if let Some(last) = last {
+			self.set_fat_entry(last, cluster);
+		}
+
+		self.write_bytes(
+			self.cluster_offset(cluster),
+			&vec![0u8; self.cluster_size()],
+		);
+		self.next_free.store(
+			FIRST_CLUSTER + (cluster - FIRST_CLUSTER + 1) % count,
+			Ordering::Relaxed,
+		);
+
+		Ok(cluster)
+	}
+}
@@ -0,0 +1,232 @@
+use core::cmp::min;
+
+use alloc::sync::Arc;
+use api::{
+	io,
+	sync::SpinLock,
+	user_buffer::{UserBufReader, UserBufWriter, UserBuffer, UserBufferMut},
+	vfs::{self, NodeId, Stat},
+	ErrorKind, Result,
+};
+
+use crate::{
+	dirent::{self, Attributes, DirLocation, Dirent, DIRENT_LEN},
+	fat::Fat,
+};
+
+/// FAT has no inode numbers. Files are identified by the position of their
+/// directory entry instead; the root directory has none.
+const ROOT_INO: usize = 1;
+
+fn node_id_of(fat: &Fat, dirent: &Dirent) -> NodeId {
+	NodeId::new(
+		fat.fs_id(),
+		(dirent.offset / DIRENT_LEN as u64) as usize + 2,
+	)
+}
+
+pub struct FatFilesystem {
+	fat: Arc<Fat>,
+}
+
+impl FatFilesystem {
+	pub fn new(fat: Arc<Fat>) -> Self {
+		Self { fat }
+	}
+
+	pub fn fat(&self) -> &Arc<Fat> {
+		&self.fat
+	}
+}
+
+impl vfs::Filesystem for FatFilesystem {
+	fn root(&self) -> Result<Arc<dyn vfs::Directory>> {
+		Ok(Arc::new(FatDirectory {
+			fat: self.fat.clone(),
+			location: DirLocation::Root,
+			node_id: NodeId::new(self.fat.fs_id(), ROOT_INO),
+		}))
+	}
+}
+
+#[derive(Debug)]
+struct FatDirectory {
+	fat: Arc<Fat>,
+	location: DirLocation,
+	node_id: NodeId,
+}
+
+impl FatDirectory {
+	fn node_of(&self, dirent: Dirent) -> vfs::Node {
+		if dirent.is_dir() {
+			// ".." entries of subdirectories of the root point to cluster 0.
+			let location = match dirent.first_cluster {
+				0 => DirLocation::Root,
+				cluster => DirLocation::Chain(cluster),
+			};
+
+			vfs::Node::Directory(Arc::new(FatDirectory {
+				fat: self.fat.clone(),
+				location,
+				node_id: node_id_of(&self.fat, &dirent),
+			}))
+		} else {
+			vfs::Node::File(Arc::new(FatFile {
+				fat: self.fat.clone(),
+				node_id: node_id_of(&self.fat, &dirent),
+				dirent: SpinLock::new(dirent),
+			}))
+		}
+	}
+}
+
+impl vfs::Directory for FatDirectory {
+	fn _lookup(&self, name: &str) -> Result<vfs::Node> {
+		// Names are case-insensitive on FAT.
+		dirent::read_dir(&self.fat, self.location)
+			.into_iter()
+			.find(|dirent| dirent.name.eq_ignore_ascii_case(name))
+			.map(|dirent| self.node_of(dirent))
+			.ok_or_else(|| ErrorKind::NoEntry.into())
+	}
+
+	fn read_dir(&self, index: usize) -> Result<Option<vfs::DirEntry>> {
+		let entry = dirent::read_dir(&self.fat, self.location)
+			.into_iter()
+			.nth(index)
+			.map(|dirent| vfs::DirEntry {
+				node_id: node_id_of(&self.fat, &dirent),
+				file_type: if dirent.is_dir() {
+					vfs::FileType::Directory
+				} else {
+					vfs::FileType::RegularFile
+				},
+				name: dirent.name,
+			});
+
+		Ok(entry)
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		Ok(Stat {
+			node_id: self.node_id,
+			size: 0,
+			kind: vfs::FileKind::Directory,
+		})
+	}
+}
+
+#[derive(Debug)]
+struct FatFile {
+	fat: Arc<Fat>,
+	node_id: NodeId,
+	dirent: SpinLock<Dirent>,
+}
+
+impl vfs::File for FatFile {
+	fn open(&self, _options: &io::OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+		Ok(None)
+	}
+
+	fn read(
+		&self,
+		offset: usize,
+		dst: UserBufferMut<'_>,
+		_options: &io::OpenOptions,
+	) -> Result<usize> {
+		let dirent = self.dirent.lock().clone();
+		let size = dirent.size as usize;
+		if offset >= size {
+			return Ok(0);
+		}
+
+		let cluster_size = self.fat.cluster_size();
+		let mut writer = UserBufWriter::from(dst);
+		let end = min(size, offset + writer.remaining_len());
+		let mut buf = vec![0u8; cluster_size];
+		let mut pos = offset;
+		for cluster in self
+			.fat
+			.cluster_chain(dirent.first_cluster)
+			.into_iter()
+			.skip(offset / cluster_size)
+		{
+			if pos >= end {
+				break;
+			}
+
+			let offset_in_cluster = pos % cluster_size;
+			let len = min(cluster_size - offset_in_cluster, end - pos);
+			self.fat.read_bytes(
+				self.fat.cluster_offset(cluster) + offset_in_cluster as u64,
+				&mut buf[..len],
+			);
+			writer.write_bytes(&buf[..len])?;
+			pos += len;
+		}
+
+		Ok(writer.written_len())
+	}
+
+	fn write(&self, offset: usize, buf: UserBuffer<'_>, _options: &io::OpenOptions) -> Result<usize> {
+		let mut dirent = self.dirent.lock();
+		if self.fat.is_read_only() || dirent.attributes.contains(Attributes::READ_ONLY) {
+			return Err(ErrorKind::EROFS.into());
+		}
+
+		let mut reader = UserBufReader::from(buf);
+		let end = offset + reader.remaining_len();
+		if end > u32::MAX as usize {
+			return Err(ErrorKind::TooBig.into());
+		}
+
+		// Grow the cluster chain to cover the written range. New clusters are
+		// zero-filled so that a hole before `offset` reads as zeroes.
+		let cluster_size = self.fat.cluster_size();
+		let mut chain = self.fat.cluster_chain(dirent.first_cluster);
+		while chain.len() * cluster_size < end {
+			let cluster = self.fat.allocate_cluster(chain.last().copied())?;
+			if chain.is_empty() {
+				dirent.first_cluster = cluster;
+			}
+			chain.push(cluster);
+		}
+
+		let mut data = vec![0u8; cluster_size];
+		let mut pos = offset;
+		for cluster in chain.into_iter().skip(offset / cluster_size) {
+			if pos >= end {
+				break;
+			}
+
+			let offset_in_cluster = pos % cluster_size;
+			let len = min(cluster_size - offset_in_cluster, end - pos);
+			reader.read_bytes(&mut data[..len])?;
+			self.fat.write_bytes(
+				self.fat.cluster_offset(cluster) + offset_in_cluster as u64,
+				&data[..len],
+			);
+			pos += len;
+		}
+
+		if end > dirent.size as usize {
+			dirent.size = end as u32;
+		}
+		dirent::update_dirent(&self.fat, &dirent);
+
+		Ok(end - offset)
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		Ok(Stat {
+			node_id: self.node_id,
+			size: self.dirent.lock().size as usize,
+			kind: vfs::FileKind::RegularFile,
+		})
+	}
+
+	fn fsync(&self) -> Result<()> {
+		self.fat.sync();
+		Ok(())
+	}
+}
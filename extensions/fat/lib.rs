@@ -0,0 +1,60 @@
+#![no_std]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc};
+use api::{
+	info,
+	trace,
+	vfs::{
+		self,
+		registry::{register_filesystem_type, FilesystemType, MountSource},
+	},
+};
+
+use crate::{bpb::BiosParameterBlock, fat::Fat, filesystem::FatFilesystem};
+
+pub struct FatType;
+
+impl FilesystemType for FatType {
+	fn name(&self) -> &'static str {
+		"vfat"
+	}
+
+	fn accepts(&self, fs_type: &str) -> bool {
+		matches!(fs_type, "vfat" | "fat" | "msdos")
+	}
+
+	fn mount(&self, source: MountSource) -> Option<Arc<dyn vfs::Filesystem>> {
+		let MountSource::Partition(partition, _) = source else {
+			return None;
+		};
+
+		let part = partition.lock();
+		let boot_sectors = part.in_sectors(512);
+		let mut buf = vec![0u8; boot_sectors * part.block_size()];
+		part.read_sectors(0..boot_sectors, &mut buf);
+
+		let Some(bpb) = BiosParameterBlock::parse(&buf) else {
+			info!("Partition {:?} is not FAT", part.name());
+			return None;
+		};
+
+		info!("Found {:?} partition {:?}", bpb.kind, part.name());
+		trace!("{:#?}", bpb);
+
+		drop(part);
+		let fat = Fat::new(partition, bpb);
+		Some(Arc::new(FatFilesystem::new(Arc::new(fat))))
+	}
+}
+
+pub fn init() {
+	register_filesystem_type(Box::new(FatType))
+}
+
+pub mod bpb;
+pub mod dirent;
+pub mod fat;
+pub mod filesystem;
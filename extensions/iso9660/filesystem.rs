@@ -0,0 +1,198 @@
+use core::cmp::min;
+
+use alloc::{string::String, sync::Arc};
+use api::{
+	io,
+	schema::unix::PathBuf,
+	user_buffer::{UserBufWriter, UserBuffer, UserBufferMut},
+	vfs::{self, NodeId, Stat},
+	ErrorKind, Result,
+};
+
+use crate::{
+	iso::{Dirent, Extent, Iso9660, Naming},
+	record::SECTOR_SIZE,
+};
+
+/// How much of a file is read from the partition at once.
+const READ_CHUNK_LEN: usize = 32 * SECTOR_SIZE;
+
+pub struct Iso9660Filesystem {
+	iso: Arc<Iso9660>,
+}
+
+impl Iso9660Filesystem {
+	pub fn new(iso: Arc<Iso9660>) -> Self {
+		Self { iso }
+	}
+
+	pub fn iso(&self) -> &Arc<Iso9660> {
+		&self.iso
+	}
+}
+
+impl vfs::Filesystem for Iso9660Filesystem {
+	fn root(&self) -> Result<Arc<dyn vfs::Directory>> {
+		let extent = self.iso.root_dir();
+		Ok(Arc::new(IsoDirectory {
+			iso: self.iso.clone(),
+			extent,
+			node_id: NodeId::new(self.iso.fs_id(), extent.offset as usize),
+		}))
+	}
+}
+
+#[derive(Debug)]
+struct IsoDirectory {
+	iso: Arc<Iso9660>,
+	extent: Extent,
+	node_id: NodeId,
+}
+
+impl IsoDirectory {
+	fn node_of(&self, dirent: Dirent) -> vfs::Node {
+		let node_id = NodeId::new(self.iso.fs_id(), dirent.ino());
+		match dirent.kind {
+			vfs::FileKind::Directory => vfs::Node::Directory(Arc::new(IsoDirectory {
+				iso: self.iso.clone(),
+				extent: dirent.extents[0],
+				node_id,
+			})),
+			vfs::FileKind::Symlink => vfs::Node::Symlink(Arc::new(IsoSymlink {
+				node_id,
+				target: dirent.symlink.unwrap_or_default(),
+			})),
+			_ => vfs::Node::File(Arc::new(IsoFile {
+				iso: self.iso.clone(),
+				node_id,
+				dirent,
+			})),
+		}
+	}
+}
+
+impl vfs::Directory for IsoDirectory {
+	fn _lookup(&self, name: &str) -> Result<vfs::Node> {
+		// Only Rock Ridge names are case-sensitive.
+		let case_sensitive = matches!(self.iso.naming(), Naming::RockRidge(_));
+		self.iso
+			.read_dir(self.extent)
+			.into_iter()
+			.find(|dirent| {
+				if case_sensitive {
+					dirent.name == name
+				} else {
+					dirent.name.eq_ignore_ascii_case(name)
+				}
+			})
+			.map(|dirent| self.node_of(dirent))
+			.ok_or_else(|| ErrorKind::NoEntry.into())
+	}
+
+	fn read_dir(&self, index: usize) -> Result<Option<vfs::DirEntry>> {
+		let entry = self
+			.iso
+			.read_dir(self.extent)
+			.into_iter()
+			.nth(index)
+			.map(|dirent| vfs::DirEntry {
+				node_id: NodeId::new(self.iso.fs_id(), dirent.ino()),
+				file_type: match dirent.kind {
+					vfs::FileKind::Directory => vfs::FileType::Directory,
+					vfs::FileKind::Symlink => vfs::FileType::Symlink,
+					_ => vfs::FileType::RegularFile,
+				},
+				name: dirent.name,
+			});
+
+		Ok(entry)
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		Ok(Stat {
+			node_id: self.node_id,
+			size: self.extent.len as usize,
+			kind: vfs::FileKind::Directory,
+		})
+	}
+}
+
+#[derive(Debug)]
+struct IsoFile {
+	iso: Arc<Iso9660>,
+	node_id: NodeId,
+	dirent: Dirent,
+}
+
+impl vfs::File for IsoFile {
+	fn open(&self, _options: &io::OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+		Ok(None)
+	}
+
+	fn read(
+		&self,
+		offset: usize,
+		dst: UserBufferMut<'_>,
+		_options: &io::OpenOptions,
+	) -> Result<usize> {
+		let size = self.dirent.size;
+		if offset >= size {
+			return Ok(0);
+		}
+
+		let mut writer = UserBufWriter::from(dst);
+		let end = min(size, offset + writer.remaining_len());
+		let mut buf = vec![0u8; READ_CHUNK_LEN];
+		let mut pos = offset;
+		// The offset within the file the current extent starts at.
+		let mut extent_start = 0;
+		for extent in &self.dirent.extents {
+			let extent_end = extent_start + extent.len as usize;
+			while pos < min(extent_end, end) {
+				let len = min(READ_CHUNK_LEN, min(extent_end, end) - pos);
+				self.iso.read_bytes(
+					extent.offset + (pos - extent_start) as u64,
+					&mut buf[..len],
+				);
+				writer.write_bytes(&buf[..len])?;
+				pos += len;
+			}
+
+			extent_start = extent_end;
+		}
+
+		Ok(writer.written_len())
+	}
+
+	fn write(&self, _offset: usize, _buf: UserBuffer<'_>, _options: &io::OpenOptions) -> Result<usize> {
+		Err(ErrorKind::EROFS.into())
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		Ok(Stat {
+			node_id: self.node_id,
+			size: self.dirent.size,
+			kind: self.dirent.kind,
+		})
+	}
+}
+
+#[derive(Debug)]
+struct IsoSymlink {
+	node_id: NodeId,
+	target: String,
+}
+
+impl vfs::Symlink for IsoSymlink {
+	fn link_target(&self) -> Result<PathBuf> {
+		Ok(PathBuf::from(self.target.as_str()))
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		Ok(Stat {
+			node_id: self.node_id,
+			size: self.target.len(),
+			kind: vfs::FileKind::Symlink,
+		})
+	}
+}
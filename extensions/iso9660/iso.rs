@@ -0,0 +1,268 @@
+use core::fmt;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use api::{
+	schema::fs::Partition,
+	sync::SpinLock,
+	vfs::{self, FsId, S_IFBLK, S_IFCHR, S_IFMT},
+};
+
+use crate::{
+	record::{DirectoryRecord, FileFlags, SECTOR_SIZE},
+	rock_ridge::{self, RockRidge},
+	volume::{DescriptorType, VolumeDescriptor, DESCRIPTORS_START},
+};
+
+/// Stops looking for the terminator after this many descriptors.
+const DESCRIPTORS_MAX: u64 = 64;
+
+/// Which of the naming conventions the tree in use follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Naming {
+	/// Rock Ridge, along with the number of bytes to skip at the start of
+	/// each system use area.
+	RockRidge(usize),
+	Joliet,
+	Plain,
+}
+
+/// A contiguous piece of a file or directory.
+#[derive(Debug, Clone, Copy)]
+pub struct Extent {
+	/// Byte offset on the volume.
+	pub offset: u64,
+	pub len: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dirent {
+	pub name: String,
+	pub kind: vfs::FileKind,
+	/// Files larger than 4 GiB are split over several extents.
+	pub extents: Vec<Extent>,
+	pub size: usize,
+	pub symlink: Option<String>,
+	/// Byte offset of the directory record on the volume.
+	pub offset: u64,
+}
+
+impl Dirent {
+	pub fn is_dir(&self) -> bool {
+		matches!(self.kind, vfs::FileKind::Directory)
+	}
+
+	/// ISO 9660 has no inode numbers. Directories are identified by their
+	/// extent, which their `.` record and the record in their parent share,
+	/// and everything else by the position of its record.
+	pub fn ino(&self) -> usize {
+		if self.is_dir() {
+			self.extents[0].offset as usize
+		} else {
+			self.offset as usize
+		}
+	}
+}
+
+fn file_kind(mode: u32) -> vfs::FileKind {
+	match mode & S_IFMT {
+		S_IFCHR => vfs::FileKind::CharDevice,
+		S_IFBLK => vfs::FileKind::BlockDevice,
+		_ => vfs::FileKind::RegularFile,
+	}
+}
+
+pub struct Iso9660 {
+	partition: Arc<SpinLock<dyn Partition>>,
+	volume_id: String,
+	block_size: u64,
+	root: DirectoryRecord,
+	naming: Naming,
+	fs_id: FsId,
+}
+
+impl fmt::Debug for Iso9660 {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Iso9660")
+			.field("volume_id", &self.volume_id)
+			.field("block_size", &self.block_size)
+			.field("naming", &self.naming)
+			.finish()
+	}
+}
+
+impl Iso9660 {
+	/// Reads the volume descriptors. Returns `None` if the partition doesn't
+	/// hold an ISO 9660 volume.
+	pub fn new(partition: Arc<SpinLock<dyn Partition>>) -> Option<Self> {
+		let mut primary = None;
+		let mut joliet = None;
+		let mut sector = vec![0u8; SECTOR_SIZE];
+		for i in 0..DESCRIPTORS_MAX {
+			partition
+				.lock()
+				.read_bytes(DESCRIPTORS_START + i * SECTOR_SIZE as u64, &mut sector);
+
+			match VolumeDescriptor::kind_of(&sector)? {
+				DescriptorType::Primary => primary = VolumeDescriptor::parse(&sector),
+				DescriptorType::Supplementary => {
+					if let Some(descriptor) = VolumeDescriptor::parse(&sector) {
+						if descriptor.joliet {
+							joliet = Some(descriptor);
+						}
+					}
+				}
+				DescriptorType::Terminator => break,
+				_ => {}
+			}
+		}
+
+		let primary = primary?;
+		let mut iso = Self {
+			partition,
+			volume_id: primary.volume_id,
+			block_size: primary.logical_block_size as u64,
+			root: primary.root,
+			naming: Naming::Plain,
+			fs_id: FsId::alloc(),
+		};
+
+		// Rock Ridge is only recorded in the primary tree. It is preferred over
+		// Joliet as it keeps modes and symbolic links.
+		let root_dir = iso.extent_of(&iso.root);
+		if let Some(skip) = iso
+			.read_record(root_dir.offset)
+			.and_then(|dot| rock_ridge::detect(&dot.system_use))
+		{
+			iso.naming = Naming::RockRidge(skip);
+		} else if let Some(joliet) = joliet {
+			iso.root = joliet.root;
+			iso.naming = Naming::Joliet;
+		}
+
+		Some(iso)
+	}
+
+	pub fn fs_id(&self) -> FsId {
+		self.fs_id
+	}
+
+	pub fn volume_id(&self) -> &str {
+		&self.volume_id
+	}
+
+	pub fn naming(&self) -> Naming {
+		self.naming
+	}
+
+	pub fn block_offset(&self, block: u32) -> u64 {
+		block as u64 * self.block_size
+	}
+
+	pub fn extent_of(&self, record: &DirectoryRecord) -> Extent {
+		Extent {
+			offset: self.block_offset(record.extent),
+			len: record.data_len,
+		}
+	}
+
+	pub fn root_dir(&self) -> Extent {
+		self.extent_of(&self.root)
+	}
+
+	/// Reads `buf.len()` bytes at `offset` from the start of the volume.
+	pub fn read_bytes(&self, offset: u64, buf: &mut [u8]) {
+		self.partition.lock().read_bytes(offset, buf)
+	}
+
+	/// Reads the directory record at `offset`.
+	fn read_record(&self, offset: u64) -> Option<DirectoryRecord> {
+		// Records are at most 255 bytes long and don't cross sectors.
+		let len = 255.min(SECTOR_SIZE - offset as usize % SECTOR_SIZE);
+		let mut buf = vec![0u8; len];
+		self.read_bytes(offset, &mut buf);
+		DirectoryRecord::parse(&buf)
+	}
+
+	/// Reads the entries of the directory stored in `dir`, leaving out `.`
+	/// and `..`.
+	pub fn read_dir(&self, dir: Extent) -> Vec<Dirent> {
+		let mut entries: Vec<Dirent> = Vec::new();
+		let mut continued = false;
+		// Records don't cross sectors: read them one at a time rather than
+		// trusting the length on the disk with a buffer of the whole directory.
+		let mut sector = vec![0u8; SECTOR_SIZE];
+		for sector_start in (0..dir.len as usize).step_by(SECTOR_SIZE) {
+			let sector_offset = dir.offset + sector_start as u64;
+			let sector_len = SECTOR_SIZE.min(dir.len as usize - sector_start);
+			self.read_bytes(sector_offset, &mut sector);
+
+			let mut pos = 0;
+			// The rest of the sector is padding.
+			while pos < sector_len && sector[pos] != 0 {
+				let Some(record) = DirectoryRecord::parse(&sector[pos..sector_len]) else {
+					return entries;
+				};
+				let offset = sector_offset + pos as u64;
+				pos += sector[pos] as usize;
+
+				if record.is_current() || record.is_parent() {
+					continue;
+				}
+
+				if continued {
+					if let Some(entry) = entries.last_mut() {
+						entry.extents.push(self.extent_of(&record));
+						entry.size += record.data_len as usize;
+					}
+				} else if let Some(entry) = self.dirent_of(&record, offset) {
+					entries.push(entry);
+				}
+
+				continued = record.flags.contains(FileFlags::MULTI_EXTENT);
+			}
+		}
+
+		entries
+	}
+
+	fn dirent_of(&self, record: &DirectoryRecord, offset: u64) -> Option<Dirent> {
+		let rock_ridge = match self.naming {
+			Naming::RockRidge(skip) => RockRidge::parse(self, &record.system_use, skip),
+			_ => RockRidge::default(),
+		};
+
+		if rock_ridge.relocated {
+			return None;
+		}
+
+		let name = match (rock_ridge.name, self.naming) {
+			(Some(name), _) => name,
+			(None, Naming::Joliet) => record.joliet_name(),
+			(None, _) => record.plain_name(),
+		};
+
+		let mut extent = self.extent_of(record);
+		let kind = if let Some(block) = rock_ridge.child_link {
+			// A placeholder for a directory relocated elsewhere. Its `.` record
+			// tells how large it is.
+			let dot = self.read_record(self.block_offset(block))?;
+			extent = self.extent_of(&dot);
+			vfs::FileKind::Directory
+		} else if rock_ridge.symlink.is_some() {
+			vfs::FileKind::Symlink
+		} else if record.is_dir() {
+			vfs::FileKind::Directory
+		} else {
+			rock_ridge.mode.map(file_kind).unwrap_or(vfs::FileKind::RegularFile)
+		};
+
+		Some(Dirent {
+			name,
+			kind,
+			extents: vec![extent],
+			size: extent.len as usize,
+			symlink: rock_ridge.symlink,
+			offset,
+		})
+	}
+}
@@ -0,0 +1,55 @@
+#![no_std]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc};
+use api::{
+	info,
+	trace,
+	vfs::{
+		self,
+		registry::{register_filesystem_type, FilesystemType, MountSource},
+	},
+};
+
+use crate::{filesystem::Iso9660Filesystem, iso::Iso9660};
+
+pub struct Iso9660Type;
+
+impl FilesystemType for Iso9660Type {
+	fn name(&self) -> &'static str {
+		"iso9660"
+	}
+
+	fn mount(&self, source: MountSource) -> Option<Arc<dyn vfs::Filesystem>> {
+		let MountSource::Partition(partition, _) = source else {
+			return None;
+		};
+
+		let Some(iso) = Iso9660::new(partition.clone()) else {
+			info!("Partition {:?} is not ISO 9660", partition.lock().name());
+			return None;
+		};
+
+		info!(
+			"Found ISO 9660 volume {:?} on partition {:?} ({:?} names)",
+			iso.volume_id(),
+			partition.lock().name(),
+			iso.naming()
+		);
+		trace!("{:#?}", iso);
+
+		Some(Arc::new(Iso9660Filesystem::new(Arc::new(iso))))
+	}
+}
+
+pub fn init() {
+	register_filesystem_type(Box::new(Iso9660Type))
+}
+
+pub mod filesystem;
+pub mod iso;
+pub mod record;
+pub mod rock_ridge;
+pub mod volume;
@@ -0,0 +1,115 @@
+use alloc::{string::String, vec::Vec};
+use api::bitflags::bitflags;
+
+/// Directory records never span two logical sectors.
+pub const SECTOR_SIZE: usize = 2048;
+
+/// The size of a directory record without its file identifier.
+pub const RECORD_HEADER_LEN: usize = 33;
+
+bitflags! {
+	#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+	pub struct FileFlags: u8 {
+		const HIDDEN = 0x01;
+		const DIRECTORY = 0x02;
+		const ASSOCIATED = 0x04;
+		const RECORD = 0x08;
+		const PROTECTION = 0x10;
+		/// The file continues in the next record.
+		const MULTI_EXTENT = 0x80;
+	}
+}
+
+pub(crate) fn le16(bytes: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+pub(crate) fn le32(bytes: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryRecord {
+	/// The logical block the data starts at, past the extended attribute
+	/// record if there is one.
+	pub extent: u32,
+	pub data_len: u32,
+	pub flags: FileFlags,
+	/// The raw file identifier.
+	pub identifier: Vec<u8>,
+	/// The system use area, holding the Rock Ridge entries.
+	pub system_use: Vec<u8>,
+}
+
+impl DirectoryRecord {
+	/// Parses the record at the start of `bytes`. Returns `None` if it is
+	/// malformed.
+	pub fn parse(bytes: &[u8]) -> Option<Self> {
+		let len = *bytes.first()? as usize;
+		if len < RECORD_HEADER_LEN || len > bytes.len() {
+			return None;
+		}
+
+		let ext_attr_len = bytes[1] as u32;
+		let name_len = bytes[32] as usize;
+		if RECORD_HEADER_LEN + name_len > len {
+			return None;
+		}
+
+		let name_end = RECORD_HEADER_LEN + name_len;
+		// The identifier is padded to an even length.
+		let system_use_start = (name_end + (name_len % 2 == 0) as usize).min(len);
+
+		Some(Self {
+			extent: le32(bytes, 2) + ext_attr_len,
+			data_len: le32(bytes, 10),
+			flags: FileFlags::from_bits_truncate(bytes[25]),
+			identifier: bytes[RECORD_HEADER_LEN..name_end].to_vec(),
+			system_use: bytes[system_use_start..len].to_vec(),
+		})
+	}
+
+	pub fn is_dir(&self) -> bool {
+		self.flags.contains(FileFlags::DIRECTORY)
+	}
+
+	/// Whether this is the `.` record.
+	pub fn is_current(&self) -> bool {
+		self.identifier == [0]
+	}
+
+	/// Whether this is the `..` record.
+	pub fn is_parent(&self) -> bool {
+		self.identifier == [1]
+	}
+
+	/// Decodes a plain ISO 9660 identifier: `FILE.TXT;1` becomes `file.txt`.
+	pub fn plain_name(&self) -> String {
+		let name = String::from_utf8_lossy(&self.identifier);
+		strip_version(&name).to_ascii_lowercase()
+	}
+
+	/// Decodes a Joliet identifier, stored as big-endian UCS-2.
+	pub fn joliet_name(&self) -> String {
+		let name = char::decode_utf16(
+			self.identifier
+				.chunks_exact(2)
+				.map(|c| u16::from_be_bytes([c[0], c[1]])),
+		)
+		.map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+		.collect::<String>();
+
+		strip_version(&name).into()
+	}
+}
+
+/// Strips the `;1` version suffix, and the dot left behind by names without
+/// an extension.
+fn strip_version(name: &str) -> &str {
+	let name = match name.rfind(';') {
+		Some(pos) => &name[..pos],
+		None => name,
+	};
+
+	name.strip_suffix('.').unwrap_or(name)
+}
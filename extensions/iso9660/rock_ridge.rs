@@ -0,0 +1,146 @@
+//! Rock Ridge, carried in the system use area of directory records as
+//! System Use Sharing Protocol (SUSP) entries.
+
+use core::cmp::min;
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+	iso::Iso9660,
+	record::{le32, SECTOR_SIZE},
+};
+
+/// Guards against continuation areas pointing at each other.
+const CONTINUATION_MAX: usize = 16;
+
+/// Flags of an `NM` entry. Names continued in the next `NM` entry are
+/// simply appended to.
+const NAME_CURRENT: u8 = 0x02;
+const NAME_PARENT: u8 = 0x04;
+
+/// Flags of a component record of an `SL` entry.
+const COMPONENT_CONTINUE: u8 = 0x01;
+const COMPONENT_CURRENT: u8 = 0x02;
+const COMPONENT_PARENT: u8 = 0x04;
+const COMPONENT_ROOT: u8 = 0x08;
+
+/// Checks whether the system use area of the root's `.` record starts with
+/// an `SP` entry. Returns the number of bytes to skip at the start of every
+/// other system use area if it does.
+pub fn detect(system_use: &[u8]) -> Option<usize> {
+	if system_use.len() < 7 || &system_use[0..2] != b"SP" || system_use[4..6] != [0xbe, 0xef] {
+		return None;
+	}
+
+	Some(system_use[6] as usize)
+}
+
+/// What the Rock Ridge entries of a directory record say about it.
+#[derive(Debug, Default)]
+pub struct RockRidge {
+	pub name: Option<String>,
+	/// The POSIX file mode from the `PX` entry.
+	pub mode: Option<u32>,
+	/// The target of a symbolic link.
+	pub symlink: Option<String>,
+	/// The block of a relocated directory this record stands for.
+	pub child_link: Option<u32>,
+	/// The directory was moved here to keep the tree shallow, it is listed
+	/// under its `child_link` elsewhere.
+	pub relocated: bool,
+	/// The last symlink component continues in the next component record.
+	component_continues: bool,
+}
+
+impl RockRidge {
+	pub fn parse(iso: &Iso9660, system_use: &[u8], skip: usize) -> Self {
+		let mut rock_ridge = Self::default();
+		let mut area: Vec<u8> = system_use.get(skip..).unwrap_or_default().to_vec();
+
+		for _ in 0..CONTINUATION_MAX {
+			let Some((block, offset, len)) = rock_ridge.parse_area(&area) else {
+				break;
+			};
+
+			// SUSP keeps the continuation area within one block: don't let a
+			// crafted length make us allocate more.
+			let len = min(len as usize, SECTOR_SIZE.saturating_sub(offset as usize));
+			area = vec![0u8; len];
+			iso.read_bytes(iso.block_offset(block) + offset as u64, &mut area);
+		}
+
+		rock_ridge
+	}
+
+	/// Parses the entries in `area`. Returns the location of the continuation
+	/// area, if there is one.
+	fn parse_area(&mut self, area: &[u8]) -> Option<(u32, u32, u32)> {
+		let mut continuation = None;
+		let mut pos = 0;
+		while pos + 4 <= area.len() {
+			let len = area[pos + 2] as usize;
+			if len < 4 || pos + len > area.len() {
+				break;
+			}
+
+			let entry = &area[pos..pos + len];
+			match &entry[0..2] {
+				b"CE" if len >= 28 => {
+					continuation = Some((le32(entry, 4), le32(entry, 12), le32(entry, 20)))
+				}
+				b"NM" if len >= 5 => self.push_name(entry[4], &entry[5..]),
+				b"PX" if len >= 12 => self.mode = Some(le32(entry, 4)),
+				b"SL" if len >= 5 => self.push_symlink(&entry[5..]),
+				b"CL" if len >= 12 => self.child_link = Some(le32(entry, 4)),
+				b"RE" => self.relocated = true,
+				b"ST" => break,
+				_ => {}
+			}
+
+			pos += len;
+		}
+
+		continuation
+	}
+
+	fn push_name(&mut self, flags: u8, name: &[u8]) {
+		// `.` and `..` are named by the VFS.
+		if flags & (NAME_CURRENT | NAME_PARENT) != 0 {
+			return;
+		}
+
+		let current = self.name.get_or_insert_with(String::new);
+		current.push_str(&String::from_utf8_lossy(name));
+	}
+
+	fn push_symlink(&mut self, mut records: &[u8]) {
+		let target = self.symlink.get_or_insert_with(String::new);
+		while records.len() >= 2 {
+			let flags = records[0];
+			let len = (records[1] as usize).min(records.len() - 2);
+			let content = &records[2..2 + len];
+			records = &records[2 + len..];
+
+			if flags & COMPONENT_ROOT != 0 {
+				target.clear();
+				target.push('/');
+				self.component_continues = false;
+				continue;
+			}
+
+			if !self.component_continues && !target.is_empty() && !target.ends_with('/') {
+				target.push('/');
+			}
+
+			if flags & COMPONENT_CURRENT != 0 {
+				target.push('.');
+			} else if flags & COMPONENT_PARENT != 0 {
+				target.push_str("..");
+			} else {
+				target.push_str(&String::from_utf8_lossy(content));
+			}
+
+			self.component_continues = flags & COMPONENT_CONTINUE != 0;
+		}
+	}
+}
@@ -0,0 +1,83 @@
+use alloc::string::String;
+
+use crate::record::{le16, DirectoryRecord, SECTOR_SIZE};
+
+/// The volume descriptor set starts after the 16 sectors of system area.
+pub const DESCRIPTORS_START: u64 = 16 * SECTOR_SIZE as u64;
+
+const STANDARD_ID: &[u8] = b"CD001";
+
+/// The escape sequences of a Joliet supplementary volume descriptor, one per
+/// UCS-2 level.
+const JOLIET_ESCAPES: [&[u8]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorType {
+	BootRecord,
+	Primary,
+	Supplementary,
+	Partition,
+	Terminator,
+	Unknown(u8),
+}
+
+impl From<u8> for DescriptorType {
+	fn from(value: u8) -> Self {
+		match value {
+			0 => Self::BootRecord,
+			1 => Self::Primary,
+			2 => Self::Supplementary,
+			3 => Self::Partition,
+			255 => Self::Terminator,
+			other => Self::Unknown(other),
+		}
+	}
+}
+
+/// A primary or supplementary volume descriptor.
+#[derive(Debug, Clone)]
+pub struct VolumeDescriptor {
+	pub kind: DescriptorType,
+	pub volume_id: String,
+	pub logical_block_size: u16,
+	pub root: DirectoryRecord,
+	/// Whether this is a Joliet supplementary descriptor.
+	pub joliet: bool,
+}
+
+impl VolumeDescriptor {
+	/// Returns the type of the descriptor in `sector`, or `None` if it isn't
+	/// a volume descriptor.
+	pub fn kind_of(sector: &[u8]) -> Option<DescriptorType> {
+		if sector.len() < SECTOR_SIZE || &sector[1..6] != STANDARD_ID {
+			return None;
+		}
+
+		Some(sector[0].into())
+	}
+
+	pub fn parse(sector: &[u8]) -> Option<Self> {
+		let kind = Self::kind_of(sector)?;
+		if !matches!(kind, DescriptorType::Primary | DescriptorType::Supplementary) {
+			return None;
+		}
+
+		let logical_block_size = le16(sector, 128);
+		if !logical_block_size.is_power_of_two() || !(512..=2048).contains(&logical_block_size) {
+			return None;
+		}
+
+		let joliet = kind == DescriptorType::Supplementary
+			&& JOLIET_ESCAPES.contains(&&sector[88..91]);
+
+		Some(Self {
+			kind,
+			volume_id: String::from_utf8_lossy(&sector[40..72])
+				.trim_end_matches(' ')
+				.into(),
+			logical_block_size,
+			root: DirectoryRecord::parse(&sector[156..190])?,
+			joliet,
+		})
+	}
+}
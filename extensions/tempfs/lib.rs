@@ -6,31 +6,35 @@ extern crate alloc;
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 
-use alloc::{borrow::ToOwned, fmt::Debug, string::String, sync::Arc, vec::Vec};
+use alloc::{borrow::ToOwned, boxed::Box, fmt::Debug, string::String, sync::Arc, vec::Vec};
 use api::{
 	hashbrown::HashMap,
 	io,
 	sync::SpinLock,
 	user_buffer::{UserBufReader, UserBufWriter},
-	vfs::{self, NodeId, Stat},
+	vfs::{
+		self,
+		registry::{register_filesystem_type, FilesystemType, MountSource},
+		FsId, NodeId, Stat,
+	},
 	ErrorKind, Result,
 };
 
+/// Inode #1 is reserved for the root dir.
+const ROOT_INO: usize = 1;
+
 #[derive(Debug)]
 pub struct Tempfs {
+	fs_id: FsId,
 	dir: Arc<TempfsDirectory>,
 }
 
 impl Tempfs {
 	pub fn new() -> Self {
+		let fs_id = FsId::alloc();
 		Self {
-			dir: Arc::new(TempfsDirectory::new(Self::alloc_inode_no())),
-		}
-	}
-
-	pub fn new_root() -> Self {
-		Self {
-			dir: Arc::new(TempfsDirectory::new(NodeId::new(1))),
+			fs_id,
+			dir: Arc::new(TempfsDirectory::new(NodeId::new(fs_id, ROOT_INO))),
 		}
 	}
 
@@ -38,20 +42,47 @@ impl Tempfs {
 		&self.dir
 	}
 
-	pub fn alloc_inode_no() -> NodeId {
-		// Inode #1 is reserved for the root dir.
-		static NEXT_INODE_NO: AtomicUsize = AtomicUsize::new(2);
-
-		NodeId::new(NEXT_INODE_NO.fetch_add(1, Ordering::Relaxed))
+	/// Allocates the ID of a node to be added to this filesystem.
+	pub fn alloc_node_id(&self) -> NodeId {
+		NodeId::new(self.fs_id, alloc_ino())
 	}
 }
 
+fn alloc_ino() -> usize {
+	static NEXT_INODE_NO: AtomicUsize = AtomicUsize::new(ROOT_INO + 1);
+
+	NEXT_INODE_NO.fetch_add(1, Ordering::Relaxed)
+}
+
 impl vfs::Filesystem for Tempfs {
 	fn root(&self) -> Result<Arc<dyn vfs::Directory>> {
 		Ok(self.dir.clone() as Arc<dyn vfs::Directory>)
 	}
 }
 
+pub struct TempfsType;
+
+impl FilesystemType for TempfsType {
+	fn name(&self) -> &'static str {
+		"tempfs"
+	}
+
+	fn accepts(&self, fs_type: &str) -> bool {
+		matches!(fs_type, "tempfs" | "tmpfs")
+	}
+
+	fn mount(&self, source: MountSource) -> Option<Arc<dyn vfs::Filesystem>> {
+		match source {
+			MountSource::Nodev(_) => Some(Arc::new(Tempfs::new())),
+			MountSource::Partition(..) => None,
+		}
+	}
+}
+
+pub fn init() {
+	register_filesystem_type(Box::new(TempfsType))
+}
+
 #[derive(Debug)]
 struct DirectoryInner {
 	files: HashMap<String, TempfsNode>,
@@ -74,9 +105,10 @@ impl TempfsDirectory {
 	}
 
 	pub fn add_dir(&self, name: &str) -> Arc<Self> {
-		let dir = Arc::new(Self::new(Tempfs::alloc_inode_no()));
-		self.0
-			.lock()
+		let mut inner = self.0.lock();
+		let fs_id = inner.stat.node_id.fs();
+		let dir = Arc::new(Self::new(NodeId::new(fs_id, alloc_ino())));
+		inner
 			.files
 			.insert(name.to_owned(), TempfsNode::Directory(dir.clone()));
 		dir
@@ -154,18 +186,21 @@ pub enum Snip {
 impl Debug for InMemoryFile {
 	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
 		f.debug_struct("InMemoryFile")
-			.field("data", &self.data.lock()[..16].to_vec())
+			.field(
+				"data",
+				&self.data.lock().iter().take(16).collect::<Vec<_>>(),
+			)
 			.field("data_rest", &"<snip>")
 			.finish()
 	}
 }
 
 impl InMemoryFile {
-	pub fn new(data: &[u8]) -> Self {
+	pub fn new(node_id: NodeId, data: &[u8]) -> Self {
 		Self {
 			data: SpinLock::new(data.to_owned()),
 			stat: Stat {
-				node_id: Tempfs::alloc_inode_no(),
+				node_id,
 				size: data.len(),
 				kind: vfs::FileKind::RegularFile,
 			},
@@ -204,14 +239,26 @@ impl vfs::File for InMemoryFile {
 	) -> Result<usize> {
 		let mut data = self.data.lock();
 		let mut reader = UserBufReader::from(buf);
-		data.resize(offset + reader.remaining_len(), 0);
+		let end = offset + reader.remaining_len();
+		if data.len() < end {
+			data.resize(end, 0);
+		}
+
 		reader
-			.read_bytes(&mut data[offset..])
+			.read_bytes(&mut data[offset..end])
 			.map_err(|_| ErrorKind::BufferError.into())
 	}
 
 	fn stat(&self) -> Result<vfs::Stat> {
-		Ok(self.stat)
+		Ok(Stat {
+			size: self.data.lock().len(),
+			..self.stat
+		})
+	}
+
+	fn truncate(&self, len: usize) -> Result<()> {
+		self.data.lock().resize(len, 0);
+		Ok(())
 	}
 }
 
@@ -222,11 +269,11 @@ pub struct InMemoryTextFile {
 }
 
 impl InMemoryTextFile {
-	pub fn new<S: AsRef<str>>(data: S) -> Self {
+	pub fn new<S: AsRef<str>>(node_id: NodeId, data: S) -> Self {
 		let sdata = data.as_ref().to_owned();
 		Self {
 			stat: Stat {
-				node_id: Tempfs::alloc_inode_no(),
+				node_id,
 				size: sdata.len(),
 				kind: vfs::FileKind::RegularFile,
 			},
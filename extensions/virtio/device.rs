@@ -7,6 +7,7 @@ use core::{
 use alloc::{sync::Arc, vec::Vec};
 use api::{
 	address::{PAddr, VAddr},
+	memoffset::offset_of,
 	arch::PAGE_SIZE,
 	bitflags::bitflags,
 	mm::{alloc_pages, AllocPageFlags},
@@ -266,6 +267,17 @@ impl VirtQueue {
 		unsafe { &*self.used.as_ptr::<VirtqUsed>() }
 	}
 
+	/// Reads the index of the used ring, which the device bumps each time it
+	/// is done with a chain.
+	pub fn used_index(&self) -> u16 {
+		unsafe {
+			self.used
+				.add(offset_of!(VirtqUsed, index))
+				.as_ptr::<u16>()
+				.read_volatile()
+		}
+	}
+
 	fn used_elem(&self, index: u16) -> &VirtqUsedElem {
 		unsafe {
 			&*self
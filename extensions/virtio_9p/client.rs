@@ -0,0 +1,261 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use api::{sync::SpinLock, warn, Error, ErrorKind, Result};
+use utils::bytes_parser::{BytesParser, BytesParserError};
+
+use crate::{
+	protocol::{
+		self, parse_str, Attr, DirEntry, Message, MessageType, Qid, HEADER_LEN, IO_HEADER_LEN, NOFID,
+		NOTAG,
+	},
+	VirtioNineP,
+};
+
+/// Requests are sent one at a time, so they can all share a tag.
+const TAG: u16 = 0;
+
+fn malformed(_error: BytesParserError) -> Error {
+	Error::with_message(ErrorKind::Invalid, "malformed 9P message")
+}
+
+/// Translates the Linux errno of an `Rlerror`.
+fn error_of(errno: u32) -> Error {
+	let kind = match errno {
+		1 | 13 => ErrorKind::PermissionDenied,
+		2 => ErrorKind::NoEntry,
+		9 => ErrorKind::EBADF,
+		16 => ErrorKind::Busy,
+		19 => ErrorKind::NoDevice,
+		20 => ErrorKind::NotADirectory,
+		21 => ErrorKind::IsADirectory,
+		22 => ErrorKind::EINVAL,
+		27 => ErrorKind::TooBig,
+		28 => ErrorKind::ENOSPC,
+		30 => ErrorKind::EROFS,
+		38 | 95 => ErrorKind::NotSupported,
+		40 => ErrorKind::TooManySymlinks,
+		_ => ErrorKind::Invalid,
+	};
+
+	kind.into()
+}
+
+/// A 9P2000.L session with the server behind a virtio-9p device.
+pub struct Client {
+	device: Arc<SpinLock<VirtioNineP>>,
+	msize: usize,
+	next_fid: AtomicU32,
+}
+
+impl Client {
+	/// Starts a session, negotiating the message size.
+	pub fn new(device: Arc<SpinLock<VirtioNineP>>) -> Result<Arc<Self>> {
+		let max_msize = device.lock().max_msize();
+		let mut client = Self {
+			device,
+			msize: max_msize,
+			next_fid: AtomicU32::new(0),
+		};
+
+		let request = Message::new(MessageType::Tversion, NOTAG)
+			.u32(max_msize as u32)
+			.str(protocol::VERSION);
+		let (msize, version) = client.rpc(request, MessageType::Tversion, |reply| {
+			Ok((reply.consume_le_u32()?, parse_str(reply)?))
+		})?;
+
+		if version != protocol::VERSION {
+			warn!("virtio-9p: the server speaks {:?}", version);
+			return Err(ErrorKind::NotSupported.into());
+		}
+
+		client.msize = (msize as usize).min(max_msize);
+		Ok(Arc::new(client))
+	}
+
+	/// The most data a single `Tread` or `Twrite` can carry.
+	pub fn max_io_len(&self) -> usize {
+		self.msize - IO_HEADER_LEN
+	}
+
+	fn alloc_fid(&self) -> u32 {
+		self.next_fid.fetch_add(1, Ordering::Relaxed)
+	}
+
+	/// Takes ownership of `id` once the server has accepted it.
+	fn fid(self: &Arc<Self>, id: u32) -> Fid {
+		Fid {
+			client: self.clone(),
+			id,
+		}
+	}
+
+	/// Sends `request` and decodes the reply with `parse`.
+	fn rpc<T, F>(&self, request: Message, kind: MessageType, parse: F) -> Result<T>
+	where
+		F: FnOnce(&mut BytesParser<'_>) -> core::result::Result<T, BytesParserError>,
+	{
+		let reply = self.device.lock().transact(&request.finish());
+		if reply.len() < HEADER_LEN {
+			return Err(malformed(BytesParserError::TooShort));
+		}
+
+		let mut parser = BytesParser::new(&reply[HEADER_LEN..]);
+		match reply[4] {
+			ty if ty == kind.reply() => parse(&mut parser).map_err(malformed),
+			ty if ty == MessageType::Rlerror as u8 => {
+				Err(error_of(parser.consume_le_u32().map_err(malformed)?))
+			}
+			_ => Err(malformed(BytesParserError::TooShort)),
+		}
+	}
+
+	/// Attaches to the tree `aname` exports, returning a fid for its root.
+	pub fn attach(self: &Arc<Self>, aname: &str) -> Result<(Fid, Qid)> {
+		let fid = self.alloc_fid();
+		let request = Message::new(MessageType::Tattach, TAG)
+			.u32(fid)
+			.u32(NOFID)
+			.str("root")
+			.str(aname)
+			.u32(0);
+		let qid = self.rpc(request, MessageType::Tattach, Qid::parse)?;
+
+		Ok((self.fid(fid), qid))
+	}
+
+	/// Walks from `fid` through `names`, returning a new fid for where it
+	/// ends. An empty `names` clones `fid`.
+	pub fn walk(self: &Arc<Self>, fid: &Fid, names: &[&str]) -> Result<(Fid, Option<Qid>)> {
+		let new_fid = self.alloc_fid();
+		let mut request = Message::new(MessageType::Twalk, TAG)
+			.u32(fid.id)
+			.u32(new_fid)
+			.u16(names.len() as u16);
+		for name in names {
+			request = request.str(name);
+		}
+
+		let qids = self.rpc(request, MessageType::Twalk, |reply| {
+			let count = reply.consume_le_u16()?;
+			(0..count).map(|_| Qid::parse(reply)).collect::<core::result::Result<Vec<_>, _>>()
+		})?;
+
+		// The server stops at the first name it can't find, without creating
+		// the new fid.
+		if qids.len() < names.len() {
+			return Err(ErrorKind::NoEntry.into());
+		}
+
+		Ok((self.fid(new_fid), qids.last().copied()))
+	}
+
+	pub fn lopen(&self, fid: &Fid, flags: u32) -> Result<Qid> {
+		let request = Message::new(MessageType::Tlopen, TAG).u32(fid.id).u32(flags);
+		self.rpc(request, MessageType::Tlopen, |reply| {
+			let qid = Qid::parse(reply)?;
+			let _iounit = reply.consume_le_u32()?;
+			Ok(qid)
+		})
+	}
+
+	/// Creates and opens the file `name` in the directory `fid` stands for.
+	/// `fid` then stands for the new file.
+	pub fn lcreate(&self, fid: &Fid, name: &str, flags: u32, mode: u32) -> Result<Qid> {
+		let request = Message::new(MessageType::Tlcreate, TAG)
+			.u32(fid.id)
+			.str(name)
+			.u32(flags)
+			.u32(mode)
+			.u32(0);
+		self.rpc(request, MessageType::Tlcreate, |reply| {
+			let qid = Qid::parse(reply)?;
+			let _iounit = reply.consume_le_u32()?;
+			Ok(qid)
+		})
+	}
+
+	/// Reads up to `len` bytes at `offset` from the opened `fid`.
+	pub fn read(&self, fid: &Fid, offset: u64, len: usize) -> Result<Vec<u8>> {
+		let request = Message::new(MessageType::Tread, TAG)
+			.u32(fid.id)
+			.u64(offset)
+			.u32(len.min(self.max_io_len()) as u32);
+		self.rpc(request, MessageType::Tread, |reply| {
+			let count = reply.consume_le_u32()? as usize;
+			Ok(reply.consume_bytes(count)?.to_vec())
+		})
+	}
+
+	/// Writes `data`, or as much of it as fits in a message, at `offset` to
+	/// the opened `fid`.
+	pub fn write(&self, fid: &Fid, offset: u64, data: &[u8]) -> Result<usize> {
+		let data = &data[..data.len().min(self.max_io_len())];
+		let request = Message::new(MessageType::Twrite, TAG)
+			.u32(fid.id)
+			.u64(offset)
+			.u32(data.len() as u32)
+			.bytes(data);
+		self.rpc(request, MessageType::Twrite, |reply| {
+			Ok(reply.consume_le_u32()? as usize)
+		})
+	}
+
+	/// Reads the entries of the opened directory `fid`, resuming from the
+	/// `offset` of the last entry returned by the previous call.
+	pub fn readdir(&self, fid: &Fid, offset: u64) -> Result<Vec<DirEntry>> {
+		let request = Message::new(MessageType::Treaddir, TAG)
+			.u32(fid.id)
+			.u64(offset)
+			.u32(self.max_io_len() as u32);
+		self.rpc(request, MessageType::Treaddir, |reply| {
+			let count = reply.consume_le_u32()? as usize;
+			let mut entries_parser = BytesParser::new(reply.consume_bytes(count)?);
+			let mut entries = Vec::new();
+			while entries_parser.remaining_len() > 0 {
+				entries.push(DirEntry::parse(&mut entries_parser)?);
+			}
+
+			Ok(entries)
+		})
+	}
+
+	pub fn getattr(&self, fid: &Fid) -> Result<Attr> {
+		let request = Message::new(MessageType::Tgetattr, TAG)
+			.u32(fid.id)
+			.u64(protocol::GETATTR_BASIC);
+		self.rpc(request, MessageType::Tgetattr, Attr::parse)
+	}
+
+	pub fn readlink(&self, fid: &Fid) -> Result<String> {
+		let request = Message::new(MessageType::Treadlink, TAG).u32(fid.id);
+		self.rpc(request, MessageType::Treadlink, parse_str)
+	}
+
+	fn clunk(&self, fid: u32) -> Result<()> {
+		let request = Message::new(MessageType::Tclunk, TAG).u32(fid);
+		self.rpc(request, MessageType::Tclunk, |_| Ok(()))
+	}
+}
+
+/// A fid, the handle through which the client refers to a file on the
+/// server. Clunked when dropped.
+pub struct Fid {
+	client: Arc<Client>,
+	id: u32,
+}
+
+impl core::fmt::Debug for Fid {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_tuple("Fid").field(&self.id).finish()
+	}
+}
+
+impl Drop for Fid {
+	fn drop(&mut self) {
+		if let Err(err) = self.client.clunk(self.id) {
+			warn!("virtio-9p: failed to clunk fid {}: {:?}", self.id, err);
+		}
+	}
+}
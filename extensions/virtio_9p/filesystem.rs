@@ -0,0 +1,275 @@
+use core::{cmp::min, fmt};
+
+use alloc::{sync::Arc, vec::Vec};
+use api::{
+	io,
+	schema::{posix::FileMode, unix::PathBuf},
+	sync::SpinLock,
+	user_buffer::{UserBufReader, UserBufWriter, UserBuffer, UserBufferMut},
+	vfs::{self, FsId, NodeId, Stat, S_IFBLK, S_IFCHR, S_IFDIR, S_IFLNK, S_IFMT},
+	ErrorKind, Result,
+};
+
+use crate::{
+	client::{Client, Fid},
+	protocol::{self, Qid},
+};
+
+fn file_kind(mode: u32) -> vfs::FileKind {
+	match mode & S_IFMT {
+		S_IFDIR => vfs::FileKind::Directory,
+		S_IFLNK => vfs::FileKind::Symlink,
+		S_IFCHR => vfs::FileKind::CharDevice,
+		S_IFBLK => vfs::FileKind::BlockDevice,
+		_ => vfs::FileKind::RegularFile,
+	}
+}
+
+/// What every node of a mounted tree shares.
+struct Session {
+	client: Arc<Client>,
+	fs_id: FsId,
+}
+
+impl fmt::Debug for Session {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Session").field("fs_id", &self.fs_id).finish()
+	}
+}
+
+impl Session {
+	fn node_id(&self, qid: &Qid) -> NodeId {
+		NodeId::new(self.fs_id, qid.path as usize)
+	}
+
+	fn stat(&self, fid: &Fid) -> Result<Stat> {
+		let attr = self.client.getattr(fid)?;
+		Ok(Stat {
+			node_id: self.node_id(&attr.qid),
+			size: attr.size as usize,
+			kind: file_kind(attr.mode),
+		})
+	}
+
+	fn node_of(self: &Arc<Self>, fid: Fid, qid: Qid) -> vfs::Node {
+		if qid.is_dir() {
+			vfs::Node::Directory(Arc::new(NinePDirectory {
+				session: self.clone(),
+				fid,
+			}))
+		} else if qid.is_symlink() {
+			vfs::Node::Symlink(Arc::new(NinePSymlink {
+				session: self.clone(),
+				fid,
+			}))
+		} else {
+			vfs::Node::File(Arc::new(NinePFile {
+				session: self.clone(),
+				fid,
+				opened: SpinLock::new(None),
+			}))
+		}
+	}
+}
+
+pub struct NinePFilesystem {
+	root: Arc<NinePDirectory>,
+}
+
+impl NinePFilesystem {
+	/// Attaches to the tree exported by the server `client` talks to.
+	pub fn attach(client: Arc<Client>) -> Result<Self> {
+		let (fid, _) = client.attach("")?;
+		let session = Arc::new(Session {
+			client,
+			fs_id: FsId::alloc(),
+		});
+
+		Ok(Self {
+			root: Arc::new(NinePDirectory { session, fid }),
+		})
+	}
+}
+
+impl vfs::Filesystem for NinePFilesystem {
+	fn root(&self) -> Result<Arc<dyn vfs::Directory>> {
+		Ok(self.root.clone())
+	}
+}
+
+#[derive(Debug)]
+struct NinePDirectory {
+	session: Arc<Session>,
+	fid: Fid,
+}
+
+impl NinePDirectory {
+	/// Lists the directory, leaving out `.` and `..`.
+	fn entries(&self) -> Result<Vec<protocol::DirEntry>> {
+		let client = &self.session.client;
+		let (fid, _) = client.walk(&self.fid, &[])?;
+		client.lopen(&fid, protocol::O_RDONLY | protocol::O_DIRECTORY)?;
+
+		let mut entries = Vec::new();
+		let mut offset = 0;
+		loop {
+			let chunk = client.readdir(&fid, offset)?;
+			let Some(last) = chunk.last() else {
+				break;
+			};
+
+			offset = last.offset;
+			entries.extend(
+				chunk
+					.into_iter()
+					.filter(|entry| entry.name != "." && entry.name != ".."),
+			);
+		}
+
+		Ok(entries)
+	}
+}
+
+impl vfs::Directory for NinePDirectory {
+	fn _lookup(&self, name: &str) -> Result<vfs::Node> {
+		let (fid, qid) = self.session.client.walk(&self.fid, &[name])?;
+		let qid = qid.ok_or(ErrorKind::NoEntry)?;
+		Ok(self.session.node_of(fid, qid))
+	}
+
+	fn read_dir(&self, index: usize) -> Result<Option<vfs::DirEntry>> {
+		let entry = self
+			.entries()?
+			.into_iter()
+			.nth(index)
+			.map(|entry| vfs::DirEntry {
+				node_id: self.session.node_id(&entry.qid),
+				file_type: if entry.qid.is_dir() {
+					vfs::FileType::Directory
+				} else if entry.qid.is_symlink() {
+					vfs::FileType::Symlink
+				} else {
+					vfs::FileType::RegularFile
+				},
+				name: entry.name,
+			});
+
+		Ok(entry)
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		self.session.stat(&self.fid)
+	}
+
+	fn create_file(&self, name: &str, mode: FileMode) -> Result<vfs::Node> {
+		let client = &self.session.client;
+		// `Tlcreate` turns the fid it is given into one for the new file, and
+		// opens it.
+		let (opened, _) = client.walk(&self.fid, &[])?;
+		client.lcreate(&opened, name, protocol::O_RDWR | protocol::O_CREAT, mode.0 & 0o7777)?;
+
+		let (fid, _) = client.walk(&self.fid, &[name])?;
+		Ok(vfs::Node::File(Arc::new(NinePFile {
+			session: self.session.clone(),
+			fid,
+			opened: SpinLock::new(Some(opened)),
+		})))
+	}
+}
+
+#[derive(Debug)]
+struct NinePFile {
+	session: Arc<Session>,
+	/// The fid the file was walked to, used for metadata.
+	fid: Fid,
+	/// The fid reads and writes go through, opened on first use.
+	opened: SpinLock<Option<Fid>>,
+}
+
+impl NinePFile {
+	fn with_opened<T>(&self, f: impl FnOnce(&Client, &Fid) -> Result<T>) -> Result<T> {
+		let client = &self.session.client;
+		let mut opened = self.opened.lock();
+		if opened.is_none() {
+			let (fid, _) = client.walk(&self.fid, &[])?;
+			// Fall back to reading only for files we can't write to.
+			if client.lopen(&fid, protocol::O_RDWR).is_err() {
+				client.lopen(&fid, protocol::O_RDONLY)?;
+			}
+
+			*opened = Some(fid);
+		}
+
+		f(client, opened.as_ref().unwrap())
+	}
+}
+
+impl vfs::File for NinePFile {
+	fn open(&self, _options: &io::OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+		Ok(None)
+	}
+
+	fn read(
+		&self,
+		offset: usize,
+		dst: UserBufferMut<'_>,
+		_options: &io::OpenOptions,
+	) -> Result<usize> {
+		let mut writer = UserBufWriter::from(dst);
+		self.with_opened(|client, fid| {
+			while writer.remaining_len() > 0 {
+				let len = min(writer.remaining_len(), client.max_io_len());
+				let data = client.read(fid, (offset + writer.written_len()) as u64, len)?;
+				writer.write_bytes(&data)?;
+				if data.len() < len {
+					break;
+				}
+			}
+
+			Ok(writer.written_len())
+		})
+	}
+
+	fn write(&self, offset: usize, buf: UserBuffer<'_>, _options: &io::OpenOptions) -> Result<usize> {
+		let mut reader = UserBufReader::from(buf);
+		let mut written = 0;
+		self.with_opened(|client, fid| {
+			let mut data = vec![0u8; min(reader.remaining_len(), client.max_io_len())];
+			while reader.remaining_len() > 0 {
+				let len = reader.read_bytes(&mut data)?;
+				let mut pos = 0;
+				while pos < len {
+					let count = client.write(fid, (offset + written) as u64, &data[pos..len])?;
+					if count == 0 {
+						return Ok(written);
+					}
+
+					pos += count;
+					written += count;
+				}
+			}
+
+			Ok(written)
+		})
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		self.session.stat(&self.fid)
+	}
+}
+
+#[derive(Debug)]
+struct NinePSymlink {
+	session: Arc<Session>,
+	fid: Fid,
+}
+
+impl vfs::Symlink for NinePSymlink {
+	fn link_target(&self) -> Result<PathBuf> {
+		Ok(PathBuf::from(self.session.client.readlink(&self.fid)?))
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		self.session.stat(&self.fid)
+	}
+}
@@ -0,0 +1,188 @@
+#![no_std]
+
+#[macro_use]
+extern crate alloc;
+
+use core::hint::spin_loop;
+
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use api::{
+	address::PAddr,
+	arch::PAGE_SIZE,
+	driver::{register_driver_prober, DeviceProber},
+	mm::{alloc_pages, AllocPageFlags},
+	sync::SpinLock,
+	vfs::{
+		self,
+		registry::{register_filesystem_type, FilesystemType, MountSource},
+	},
+	warn,
+};
+use virtio::{
+	device::{Virtio, VirtqDescBuffer},
+	transport::{
+		virtio_pci::{VirtioAttachError, VirtioPci},
+		VirtioTransport,
+	},
+};
+
+use crate::{client::Client, filesystem::NinePFilesystem};
+
+/// The device tells its mount tag in its configuration space.
+const VIRTIO_9P_F_MOUNT_TAG: u64 = 1 << 0;
+
+/// The size of the buffers requests and replies are exchanged through,
+/// which bounds the message size negotiated with the server.
+const MSIZE_PAGES: usize = 16;
+
+static DEVICES: SpinLock<Vec<(String, Arc<Client>)>> = SpinLock::new(Vec::new());
+
+pub struct VirtioNineP {
+	virtio: Virtio,
+	mount_tag: String,
+	request: PAddr,
+	reply: PAddr,
+}
+
+impl VirtioNineP {
+	pub fn new(transport: Arc<dyn VirtioTransport>) -> Result<Self, VirtioAttachError> {
+		let mut virtio = Virtio::new(transport);
+		virtio.initialize(VIRTIO_9P_F_MOUNT_TAG, 1)?;
+
+		// struct virtio_9p_config { le16 tag_len; u8 tag[]; }
+		let tag_len = virtio.read_device_config16(0);
+		let mount_tag = (0..tag_len)
+			.map(|i| virtio.read_device_config8(2 + i) as char)
+			.collect::<String>();
+
+		virtio::info!(virtio::Kind::Filesystem, "Mount tag is", mount_tag.as_str());
+
+		let request =
+			alloc_pages(MSIZE_PAGES, AllocPageFlags::KERNEL).expect("failed to allocate 9P buffers");
+		let reply =
+			alloc_pages(MSIZE_PAGES, AllocPageFlags::KERNEL).expect("failed to allocate 9P buffers");
+
+		Ok(Self {
+			virtio,
+			mount_tag,
+			request,
+			reply,
+		})
+	}
+
+	pub fn mount_tag(&self) -> &str {
+		&self.mount_tag
+	}
+
+	pub fn max_msize(&self) -> usize {
+		MSIZE_PAGES * PAGE_SIZE
+	}
+
+	/// Sends an encoded request and waits for the reply.
+	pub fn transact(&mut self, request: &[u8]) -> Vec<u8> {
+		let msize = self.max_msize();
+		assert!(request.len() <= msize);
+
+		unsafe {
+			self.request
+				.as_mut_ptr::<u8>()
+				.copy_from_nonoverlapping(request.as_ptr(), request.len());
+		}
+
+		let chain = &[
+			VirtqDescBuffer::ReadOnlyFromDevice {
+				addr: self.request,
+				len: request.len(),
+			},
+			VirtqDescBuffer::WritableFromDevice {
+				addr: self.reply,
+				len: msize,
+			},
+		];
+
+		let virtq = self.virtio.virtq_mut(0);
+		let used_index = virtq.used_index();
+		virtq.enqueue(chain);
+		virtq.notify();
+
+		while virtq.used_index() == used_index {
+			spin_loop();
+		}
+
+		let reply = unsafe { core::slice::from_raw_parts(self.reply.as_ptr::<u8>(), msize) };
+		let len = u32::from_le_bytes(reply[0..4].try_into().unwrap()) as usize;
+		reply[..len.min(msize)].to_vec()
+	}
+}
+
+pub struct VirtioNinePProber;
+
+impl DeviceProber for VirtioNinePProber {
+	fn probe_pci(&self, pci_device: &api::driver::pci::PciDevice) {
+		if !pci_device.is_virtio_kind(virtio::Kind::Filesystem) {
+			return;
+		}
+
+		virtio::info!(virtio::Kind::Filesystem, "Found device", "(over PCI)");
+		let device = match VirtioPci::probe_pci(pci_device, VirtioNineP::new) {
+			Ok(device) => Arc::new(SpinLock::new(device)),
+			Err(VirtioAttachError::InvalidVendorId) => {
+				return;
+			}
+			Err(err) => {
+				warn!("failed to attach a virtio-9p: {:?}", err);
+				return;
+			}
+		};
+
+		let mount_tag = String::from(device.lock().mount_tag());
+		// Replies are polled for, so the interrupt is left masked: the line
+		// may well be shared with another virtio device.
+		match Client::new(device) {
+			Ok(client) => DEVICES.lock().push((mount_tag, client)),
+			Err(err) => warn!("virtio-9p: failed to start a session: {:?}", err),
+		}
+	}
+
+	fn probe_virtio_mmio(&self, _mmio_device: &api::driver::VirtioMmioDevice) {
+		// Only attached over PCI for now.
+	}
+}
+
+pub struct NinePType;
+
+impl FilesystemType for NinePType {
+	fn name(&self) -> &'static str {
+		"9p"
+	}
+
+	/// Mounts the tree exported by the device whose mount tag is the source.
+	fn mount(&self, source: MountSource) -> Option<Arc<dyn vfs::Filesystem>> {
+		let MountSource::Nodev(mount_tag) = source else {
+			return None;
+		};
+
+		let client = DEVICES
+			.lock()
+			.iter()
+			.find(|(tag, _)| *tag == mount_tag)
+			.map(|(_, client)| client.clone())?;
+
+		match NinePFilesystem::attach(client) {
+			Ok(fs) => Some(Arc::new(fs)),
+			Err(err) => {
+				warn!("virtio-9p: failed to attach to {:?}: {:?}", mount_tag, err);
+				None
+			}
+		}
+	}
+}
+
+pub fn init() {
+	register_driver_prober(Box::new(VirtioNinePProber));
+	register_filesystem_type(Box::new(NinePType));
+}
+
+pub mod client;
+pub mod filesystem;
+pub mod protocol;
@@ -0,0 +1,197 @@
+//! Encoding and decoding of 9P2000.L messages. Every message starts with
+//! `size[4] type[1] tag[2]`, integers are little-endian and strings are
+//! prefixed with their length as a `u16`.
+
+use alloc::{string::String, vec::Vec};
+use api::bitflags::bitflags;
+use utils::bytes_parser::{BytesParser, BytesParserError};
+
+pub const VERSION: &str = "9P2000.L";
+
+/// The size of the header of every message.
+pub const HEADER_LEN: usize = 7;
+/// The overhead of `Rread` and `Twrite` on top of the data they carry.
+pub const IO_HEADER_LEN: usize = HEADER_LEN + 16;
+
+pub const NOTAG: u16 = !0;
+pub const NOFID: u32 = !0;
+
+/// Open flags, as found in Linux on x86.
+pub const O_RDONLY: u32 = 0o0;
+pub const O_RDWR: u32 = 0o2;
+pub const O_CREAT: u32 = 0o100;
+pub const O_DIRECTORY: u32 = 0o200000;
+
+/// The fields `Tgetattr` asks for: mode, nlink, uid, gid, rdev, times,
+/// inode number, size and blocks.
+pub const GETATTR_BASIC: u64 = 0x7ff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+	Rlerror = 7,
+	Tlopen = 12,
+	Rlopen = 13,
+	Tlcreate = 14,
+	Rlcreate = 15,
+	Treadlink = 22,
+	Rreadlink = 23,
+	Tgetattr = 24,
+	Rgetattr = 25,
+	Treaddir = 40,
+	Rreaddir = 41,
+	Tversion = 100,
+	Rversion = 101,
+	Tattach = 104,
+	Rattach = 105,
+	Twalk = 110,
+	Rwalk = 111,
+	Tread = 116,
+	Rread = 117,
+	Twrite = 118,
+	Rwrite = 119,
+	Tclunk = 120,
+	Rclunk = 121,
+}
+
+impl MessageType {
+	/// The type of the reply to a request of this type.
+	pub fn reply(self) -> u8 {
+		self as u8 + 1
+	}
+}
+
+bitflags! {
+	#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+	pub struct QidType: u8 {
+		const DIR = 0x80;
+		const APPEND = 0x40;
+		const EXCL = 0x20;
+		const MOUNT = 0x10;
+		const AUTH = 0x08;
+		const TMP = 0x04;
+		const SYMLINK = 0x02;
+		const LINK = 0x01;
+	}
+}
+
+/// The server's unique identification of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Qid {
+	pub kind: QidType,
+	pub version: u32,
+	/// Unique among the files of the server, like an inode number.
+	pub path: u64,
+}
+
+impl Qid {
+	pub fn parse(parser: &mut BytesParser<'_>) -> Result<Self, BytesParserError> {
+		Ok(Self {
+			kind: QidType::from_bits_truncate(parser.consume_u8()?),
+			version: parser.consume_le_u32()?,
+			path: parser.consume_le_u64()?,
+		})
+	}
+
+	pub fn is_dir(&self) -> bool {
+		self.kind.contains(QidType::DIR)
+	}
+
+	pub fn is_symlink(&self) -> bool {
+		self.kind.contains(QidType::SYMLINK)
+	}
+}
+
+/// The part of `Rgetattr` we care about.
+#[derive(Debug, Clone, Copy)]
+pub struct Attr {
+	pub qid: Qid,
+	pub mode: u32,
+	pub size: u64,
+}
+
+impl Attr {
+	pub fn parse(parser: &mut BytesParser<'_>) -> Result<Self, BytesParserError> {
+		let _valid = parser.consume_le_u64()?;
+		let qid = Qid::parse(parser)?;
+		let mode = parser.consume_le_u32()?;
+		// uid, gid, nlink and rdev.
+		parser.skip(4 + 4 + 8 + 8)?;
+		let size = parser.consume_le_u64()?;
+
+		Ok(Self { qid, mode, size })
+	}
+}
+
+/// An entry of `Rreaddir`.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+	pub qid: Qid,
+	/// Where the next `Treaddir` resumes after this entry.
+	pub offset: u64,
+	pub name: String,
+}
+
+impl DirEntry {
+	pub fn parse(parser: &mut BytesParser<'_>) -> Result<Self, BytesParserError> {
+		let qid = Qid::parse(parser)?;
+		let offset = parser.consume_le_u64()?;
+		let _kind = parser.consume_u8()?;
+		let name = parse_str(parser)?;
+
+		Ok(Self { qid, offset, name })
+	}
+}
+
+pub fn parse_str(parser: &mut BytesParser<'_>) -> Result<String, BytesParserError> {
+	let len = parser.consume_le_u16()? as usize;
+	let bytes = parser.consume_bytes(len)?;
+	String::from_utf8(bytes.to_vec()).map_err(|_| BytesParserError::Utf8Parse)
+}
+
+/// Builds a request.
+pub struct Message {
+	buf: Vec<u8>,
+}
+
+impl Message {
+	pub fn new(kind: MessageType, tag: u16) -> Self {
+		let mut buf = vec![0u8; 4];
+		buf.push(kind as u8);
+		buf.extend_from_slice(&tag.to_le_bytes());
+		Self { buf }
+	}
+
+	pub fn u16(mut self, value: u16) -> Self {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+		self
+	}
+
+	pub fn u32(mut self, value: u32) -> Self {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+		self
+	}
+
+	pub fn u64(mut self, value: u64) -> Self {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+		self
+	}
+
+	pub fn str(mut self, value: &str) -> Self {
+		self.buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+		self.buf.extend_from_slice(value.as_bytes());
+		self
+	}
+
+	pub fn bytes(mut self, value: &[u8]) -> Self {
+		self.buf.extend_from_slice(value);
+		self
+	}
+
+	/// Fills in the size and returns the encoded message.
+	pub fn finish(mut self) -> Vec<u8> {
+		let len = self.buf.len() as u32;
+		self.buf[0..4].copy_from_slice(&len.to_le_bytes());
+		self.buf
+	}
+}
@@ -6,7 +6,7 @@ use core::{hint::spin_loop, mem::size_of};
 
 use alloc::{boxed::Box, sync::Arc};
 use api::{
-	address::VAddr,
+	address::{PAddr, VAddr},
 	driver::{
 		attach_irq,
 		block::{register_block_driver, BlockDriver, BlockOp},
@@ -129,6 +129,17 @@ impl VirtioBlock {
 
 	pub fn operate(&mut self, op: BlockOp, sector: u64, buf: &mut [u8]) -> VirtioBlockStatus {
 		assert!(buf.len() == self.block_size);
+		let buf_addr = VAddr::new(buf.as_ptr() as usize).as_paddr();
+		self.submit(op, sector, buf_addr)
+	}
+
+	pub fn write(&mut self, sector: u64, buf: &[u8]) -> VirtioBlockStatus {
+		assert!(buf.len() == self.block_size);
+		let buf_addr = VAddr::new(buf.as_ptr() as usize).as_paddr();
+		self.submit(BlockOp::Write, sector, buf_addr)
+	}
+
+	fn submit(&mut self, op: BlockOp, sector: u64, buf_addr: PAddr) -> VirtioBlockStatus {
 		// trace!(
 		// 	"{}: {} sector {}",
 		// 	if op == BlockOp::Read {
@@ -161,11 +172,11 @@ impl VirtioBlock {
 			},
 			match op {
 				BlockOp::Read => VirtqDescBuffer::WritableFromDevice {
-					addr: VAddr::new(buf.as_ptr() as usize).as_paddr(),
+					addr: buf_addr,
 					len: self.block_size,
 				},
 				BlockOp::Write => VirtqDescBuffer::ReadOnlyFromDevice {
-					addr: VAddr::new(buf.as_ptr() as usize).as_paddr(),
+					addr: buf_addr,
 					len: self.block_size,
 				},
 			},
@@ -228,6 +239,26 @@ impl BlockDriver for VirtioBlockDriver {
 		}
 	}
 
+	fn write_sector(&self, sector: usize, buf: &[u8]) {
+		let status = self.device.lock().write(sector as u64, buf);
+		if status != VirtioBlockStatus::Ok {
+			warn!("virtio-blk: failed to write sector {}: {:?}", sector, status);
+		}
+	}
+
+	fn write_sectors(&self, sectors: core::ops::Range<usize>, buf: &[u8]) {
+		let mut device = self.device.lock();
+		let block_size = device.block_size;
+		assert!(buf.len() >= sectors.clone().count() * block_size);
+
+		for (i, sector) in sectors.enumerate() {
+			let status = device.write(sector as u64, &buf[i * block_size..(i + 1) * block_size]);
+			if status != VirtioBlockStatus::Ok {
+				warn!("virtio-blk: failed to write sector {}: {:?}", sector, status);
+			}
+		}
+	}
+
 	fn sector_size(&self) -> usize {
 		self.device.lock().block_size
 	}
@@ -4,10 +4,19 @@ use environment::{address::UserVAddr, arch::PAGE_SIZE};
 
 global_asm!(include_str!("usermode.S"));
 
+/// The size of a kernel stack, including the guard page at its bottom.
 pub const KERNEL_STACK_SIZE: usize = PAGE_SIZE * 512;
 pub const USER_VALLOC_END: UserVAddr = unsafe { UserVAddr::new_unchecked(0x0000_0fff_0000_0000) };
 pub const USER_VALLOC_BASE: UserVAddr = unsafe { UserVAddr::new_unchecked(0x0000_000a_0000_0000) };
 pub const USER_STACK_TOP: UserVAddr = USER_VALLOC_BASE;
+pub const USER_PIE_BASE: UserVAddr = unsafe { UserVAddr::new_unchecked(0x0000_0001_0000_0000) };
+
+// How far ASLR moves each region from its fixed address: the stack top
+// downwards, the others upwards.
+pub const USER_STACK_RANDOM_RANGE: usize = 0x4000_0000; // 1 GiB
+pub const USER_HEAP_RANDOM_RANGE: usize = 0x200_0000; // 32 MiB
+pub const USER_VALLOC_RANDOM_RANGE: usize = 0x100_0000_0000; // 1 TiB
+pub const USER_PIE_RANDOM_RANGE: usize = 0x4_0000_0000; // 16 GiB
 
 mod process;
 
@@ -5,7 +5,7 @@ use crossbeam::atomic::AtomicCell;
 use environment::{
   address::{UserVAddr, VAddr},
   arch::{
-    cpu_local_head,
+    cpu_local_head, remap_straight_page, unmap_straight_page,
     x64::{USER_CS64, USER_DS, USER_RPL},
     PtRegs, PAGE_SIZE, TSS,
   },
@@ -20,9 +20,9 @@ pub struct Process {
   rsp: UnsafeCell<u64>,
   pub(super) fsbase: AtomicCell<u64>,
   pub(super) xsave_area: Option<OwnedPages>,
-  kernel_stack: OwnedPages,
-  interrupt_stack: OwnedPages,
-  syscall_stack: OwnedPages,
+  kernel_stack: KernelStack,
+  interrupt_stack: KernelStack,
+  syscall_stack: KernelStack,
 }
 
 unsafe impl Sync for Process {}
@@ -34,6 +34,34 @@ extern "C" {
   fn do_switch_thread(prev_rsp: *const u64, next_rsp: *const u64);
 }
 
+/// A kernel stack. Its lowest page is left unmapped as a guard page so that
+/// an overflow faults instead of silently corrupting the memory below.
+struct KernelStack {
+  pages: OwnedPages,
+}
+
+impl KernelStack {
+  fn new() -> Result<KernelStack> {
+    let pages = alloc_pages_owned(
+      KERNEL_STACK_SIZE / PAGE_SIZE,
+      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
+    )?;
+    unmap_straight_page(*pages)?;
+    Ok(KernelStack { pages })
+  }
+
+  fn top(&self) -> VAddr {
+    self.pages.as_vaddr().add(KERNEL_STACK_SIZE)
+  }
+}
+
+impl Drop for KernelStack {
+  fn drop(&mut self) {
+    // The page allocator writes into the pages it gets back.
+    remap_straight_page(*self.pages);
+  }
+}
+
 unsafe fn push_stack(mut rsp: *mut u64, value: u64) -> *mut u64 {
   rsp = rsp.sub(1);
   rsp.write(value);
@@ -41,26 +69,14 @@ unsafe fn push_stack(mut rsp: *mut u64, value: u64) -> *mut u64 {
 }
 
 impl Process {
-  pub fn new_kthread(ip: VAddr, sp: VAddr) -> Self {
-    let interrupt_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocate kernel stack");
-    let syscall_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocate kernel stack");
+  pub fn new_kthread(ip: VAddr) -> Result<Self> {
+    let interrupt_stack = KernelStack::new()?;
+    let syscall_stack = KernelStack::new()?;
 
-    let kernel_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocat kernel stack");
+    let kernel_stack = KernelStack::new()?;
 
     let rsp = unsafe {
-      let mut rsp: *mut u64 = sp.as_mut_ptr();
+      let mut rsp: *mut u64 = kernel_stack.top().as_mut_ptr();
 
       // Registers to be restored in kthread_entry().
       rsp = push_stack(rsp, ip.value() as u64); // The entry point.
@@ -78,38 +94,24 @@ impl Process {
       rsp
     };
 
-    Self {
+    Ok(Self {
       rsp: UnsafeCell::new(rsp as u64),
       fsbase: AtomicCell::new(0),
       xsave_area: None,
       kernel_stack,
       interrupt_stack,
       syscall_stack,
-    }
+    })
   }
 
-  pub fn new_user_thread(ip: UserVAddr, sp: UserVAddr) -> Process {
-    let kernel_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocat kernel stack");
-    let interrupt_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocate kernel stack");
-    let syscall_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocate kernel stack");
-    let xsave_area =
-      alloc_pages_owned(1, AllocPageFlags::KERNEL).expect("failed to allocate xsave area");
+  pub fn new_user_thread(ip: UserVAddr, sp: UserVAddr) -> Result<Process> {
+    let kernel_stack = KernelStack::new()?;
+    let interrupt_stack = KernelStack::new()?;
+    let syscall_stack = KernelStack::new()?;
+    let xsave_area = alloc_pages_owned(1, AllocPageFlags::KERNEL)?;
 
     let rsp = unsafe {
-      let kernel_sp = kernel_stack.as_vaddr().add(KERNEL_STACK_SIZE);
-      let mut rsp: *mut u64 = kernel_sp.as_mut_ptr();
+      let mut rsp: *mut u64 = kernel_stack.top().as_mut_ptr();
 
       // Registers to be restored by IRET.
       rsp = push_stack(rsp, (USER_DS | USER_RPL) as u64); // SS
@@ -131,55 +133,37 @@ impl Process {
       rsp
     };
 
-    Process {
+    Ok(Process {
       rsp: UnsafeCell::new(rsp as u64),
       fsbase: AtomicCell::new(0),
       xsave_area: Some(xsave_area),
       interrupt_stack,
       syscall_stack,
       kernel_stack,
-    }
+    })
   }
 
-  pub fn new_idle_thread() -> Process {
-    let interrupt_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocate kernel stack");
-    let syscall_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocate kernel stack");
+  pub fn new_idle_thread() -> Result<Process> {
+    let interrupt_stack = KernelStack::new()?;
+    let syscall_stack = KernelStack::new()?;
 
-    let kernel_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocat kernel stack");
+    let kernel_stack = KernelStack::new()?;
 
-    Process {
+    Ok(Process {
       rsp: UnsafeCell::new(0),
       fsbase: AtomicCell::new(0),
       xsave_area: None,
       kernel_stack,
       interrupt_stack,
       syscall_stack,
-    }
+    })
   }
 
   pub fn fork(&self, frame: &PtRegs) -> Result<Process> {
-    let xsave_area =
-      alloc_pages_owned(1, AllocPageFlags::KERNEL).expect("failed to allocate xsave area");
-    let kernel_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed to allocat kernel stack");
+    let xsave_area = alloc_pages_owned(1, AllocPageFlags::KERNEL)?;
+    let kernel_stack = KernelStack::new()?;
     let rsp = unsafe {
-      let kernel_sp = kernel_stack.as_vaddr().add(KERNEL_STACK_SIZE);
-      let mut rsp: *mut u64 = kernel_sp.as_mut_ptr();
+      let mut rsp: *mut u64 = kernel_stack.top().as_mut_ptr();
 
       // Registers to be restored by IRET.
       rsp = push_stack(rsp, (USER_DS | USER_RPL) as u64); // SS
@@ -211,16 +195,8 @@ impl Process {
       rsp
     };
 
-    let interrupt_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed allocate kernel stack");
-    let syscall_stack = alloc_pages_owned(
-      KERNEL_STACK_SIZE / PAGE_SIZE,
-      AllocPageFlags::KERNEL | AllocPageFlags::DIRTY_OK,
-    )
-    .expect("failed allocate kernel stack");
+    let interrupt_stack = KernelStack::new()?;
+    let syscall_stack = KernelStack::new()?;
 
     Ok(Process {
       rsp: UnsafeCell::new(rsp as u64),
@@ -250,10 +226,8 @@ pub fn switch_thread(prev: &Process, next: &Process) {
   // Switch the kernel stack.
   let sstack = &next.syscall_stack;
   let istack = &next.interrupt_stack;
-  head.rsp0 = (sstack.as_vaddr().value() + KERNEL_STACK_SIZE) as u64;
-  TSS
-    .as_mut()
-    .set_rsp0((istack.as_vaddr().value() + KERNEL_STACK_SIZE) as u64);
+  head.rsp0 = sstack.top().value() as u64;
+  TSS.as_mut().set_rsp0(istack.top().value() as u64);
 
   // Save and restore the XSAVE area (i.e. XMM/YMM registrers).
   unsafe {
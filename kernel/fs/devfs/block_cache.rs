@@ -0,0 +1,64 @@
+use core::cmp::min;
+
+use alloc::{fmt, format};
+use api::{
+  io::OpenOptions,
+  user_buffer::{UserBufWriter, UserBuffer, UserBufferMut},
+  vfs::{self, NodeId, Stat},
+  ErrorKind, Result,
+};
+
+use crate::schema::block::read_block_cache_stats;
+
+/// Exposes the block cache statistics as text.
+pub struct BlockCacheStatsFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for BlockCacheStatsFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("BlockCacheStatsFile").finish()
+  }
+}
+
+impl BlockCacheStatsFile {
+  pub fn new(node_id: NodeId) -> Self {
+    BlockCacheStatsFile {
+      stat: Stat {
+        node_id,
+        size: 0,
+        kind: vfs::FileKind::RegularFile,
+      },
+    }
+  }
+}
+
+impl vfs::File for BlockCacheStatsFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<alloc::sync::Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    let stats = read_block_cache_stats();
+    let text = format!(
+      "hits {}\nmisses {}\nevictions {}\nwritebacks {}\ncached {}\ndirty {}\n",
+      stats.hits,
+      stats.misses,
+      stats.evictions,
+      stats.writebacks,
+      stats.cached_blocks,
+      stats.dirty_blocks
+    );
+
+    let mut writer = UserBufWriter::from(dst);
+    writer.write_bytes(&text.as_bytes()[min(offset, text.len())..])
+  }
+
+  fn write(&self, _offset: usize, _buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    Err(ErrorKind::NotSupported.into())
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
@@ -0,0 +1,79 @@
+use alloc::{fmt, sync::Arc, vec::Vec};
+use api::{
+  io::OpenOptions,
+  user_buffer::{UserBufReader, UserBufWriter, UserBuffer, UserBufferMut},
+  vfs::{self, NodeId, Stat},
+  Result,
+};
+use environment::print::get_debug_printer;
+
+use crate::logger::KERNEL_LOG_BUF;
+
+/// `kmsg`: the kernel log. Like `/proc/kmsg`, reading consumes the messages
+/// so the buffer makes room for new ones. Whatever is written is printed
+/// into the log.
+pub struct KmsgFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for KmsgFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("KmsgFile").finish()
+  }
+}
+
+impl KmsgFile {
+  pub fn new(node_id: NodeId) -> Self {
+    KmsgFile {
+      stat: Stat {
+        node_id,
+        size: 0,
+        kind: vfs::FileKind::CharDevice,
+      },
+    }
+  }
+}
+
+impl vfs::File for KmsgFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, _offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    // Copy the messages out first: touching the user buffer may fault, and
+    // the fault handler may well want to log something.
+    let mut messages = Vec::new();
+    {
+      let mut log = KERNEL_LOG_BUF.lock();
+      while messages.len() < dst.len() {
+        match log.pop_slice(dst.len() - messages.len()) {
+          Some(data) => messages.extend_from_slice(data),
+          None => break,
+        }
+      }
+    }
+
+    UserBufWriter::from(dst).write_bytes(&messages)
+  }
+
+  fn write(&self, _offset: usize, buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    let mut reader = UserBufReader::from(buf);
+    let mut chunk = [0u8; 256];
+    let mut written = 0;
+    loop {
+      let len = reader.read_bytes(&mut chunk)?;
+      if len == 0 {
+        break;
+      }
+
+      get_debug_printer().print_bytes(&chunk[..len]);
+      written += len;
+    }
+
+    Ok(written)
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
@@ -0,0 +1,132 @@
+use alloc::{fmt, sync::Arc};
+use api::{
+  io::OpenOptions,
+  user_buffer::{UserBufWriter, UserBuffer, UserBufferMut},
+  vfs::{self, NodeId, Stat},
+  ErrorKind, Result,
+};
+
+fn char_device_stat(node_id: NodeId) -> Stat {
+  Stat {
+    node_id,
+    size: 0,
+    kind: vfs::FileKind::CharDevice,
+  }
+}
+
+/// `null`: reads nothing and discards whatever is written to it.
+pub struct NullFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for NullFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("NullFile").finish()
+  }
+}
+
+impl NullFile {
+  pub fn new(node_id: NodeId) -> Self {
+    NullFile {
+      stat: char_device_stat(node_id),
+    }
+  }
+}
+
+impl vfs::File for NullFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, _offset: usize, _dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    Ok(0)
+  }
+
+  fn write(&self, _offset: usize, buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    Ok(buf.len())
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
+
+/// `zero`: reads an endless stream of zeroes and discards whatever is
+/// written to it.
+pub struct ZeroFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for ZeroFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ZeroFile").finish()
+  }
+}
+
+impl ZeroFile {
+  pub fn new(node_id: NodeId) -> Self {
+    ZeroFile {
+      stat: char_device_stat(node_id),
+    }
+  }
+}
+
+impl vfs::File for ZeroFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, _offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    let len = dst.len();
+    UserBufWriter::from(dst).fill(0, len)?;
+    Ok(len)
+  }
+
+  fn write(&self, _offset: usize, buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    Ok(buf.len())
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
+
+/// `full`: reads like `zero`, but every write fails as if the disk was
+/// full.
+pub struct FullFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for FullFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("FullFile").finish()
+  }
+}
+
+impl FullFile {
+  pub fn new(node_id: NodeId) -> Self {
+    FullFile {
+      stat: char_device_stat(node_id),
+    }
+  }
+}
+
+impl vfs::File for FullFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, _offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    let len = dst.len();
+    UserBufWriter::from(dst).fill(0, len)?;
+    Ok(len)
+  }
+
+  fn write(&self, _offset: usize, _buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    Err(ErrorKind::ENOSPC.into())
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
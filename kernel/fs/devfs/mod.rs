@@ -1,6 +1,13 @@
-use alloc::sync::Arc;
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc};
 use api::{
-  vfs::{self, File},
+  driver::device::{
+    alloc_major, register_device, register_device_listener, Device, DeviceEvent, DeviceKind,
+    DeviceListener, DeviceNumber,
+  },
+  io::OpenOptions,
+  sync::SpinLock,
+  user_buffer::{UserBuffer, UserBufferMut},
+  vfs::{self, File, NodeId, Stat},
   Result,
 };
 use tempfs::Tempfs;
@@ -8,7 +15,20 @@ use utils::once::Once;
 
 use crate::font::BIZCAT;
 
-use self::{devconsole::DevConsole, fb0::Framebuffer, font::Font, mouse::Mouse};
+use self::{
+  block_cache::BlockCacheStatsFile,
+  devconsole::DevConsole,
+  fb0::Framebuffer,
+  font::Font,
+  kmsg::KmsgFile,
+  mem::{FullFile, NullFile, ZeroFile},
+  mounts::MountsFile,
+  mouse::Mouse,
+  page_cache::PageCacheStatsFile,
+  random::RandomFile,
+  shm::SharedMemoryDirectory,
+  slab::SlabStatsFile,
+};
 
 pub static DEVFS: Once<Arc<Devfs>> = Once::new();
 pub static SERIAL_TTY: Once<Arc<DevConsole>> = Once::new();
@@ -16,47 +36,260 @@ pub static FRAMEBUFFER_FILE: Once<Arc<Framebuffer>> = Once::new();
 pub static FONT_FILE: Once<Arc<Font>> = Once::new();
 pub static MOUSE_FILE: Once<Arc<Mouse>> = Once::new();
 
-pub struct Devfs(Tempfs);
+const MEM_MAJOR: u32 = 1;
+const TTYAUX_MAJOR: u32 = 5;
+const MISC_INPUT_MAJOR: u32 = 13;
+const FB_MAJOR: u32 = 29;
+
+const SHM_DIR_NAME: &str = "SharedMemory";
+
+/// A live view of the device registry, along with a few files about the
+/// kernel itself.
+pub struct Devfs {
+  root: Arc<DevfsDirectory>,
+}
 
 impl Devfs {
   pub fn new() -> Self {
     let tempfs = Tempfs::new();
     let root_dir = tempfs.root();
 
-    SERIAL_TTY.init(|| Arc::new(DevConsole::new(Tempfs::alloc_inode_no())));
-    FRAMEBUFFER_FILE.init(|| Arc::new(Framebuffer::new(Tempfs::alloc_inode_no())));
-    FONT_FILE.init(|| {
-      Arc::new(Font::new(
-        Tempfs::alloc_inode_no(),
-        BIZCAT.width,
-        BIZCAT.height,
-        BIZCAT.stride,
-        BIZCAT.max_glyph as usize,
-        &BIZCAT.data,
-      ))
-    });
-    MOUSE_FILE.init(|| Arc::new(Mouse::new(Tempfs::alloc_inode_no())));
+    root_dir.add_file(
+      "BlockCache",
+      Arc::new(BlockCacheStatsFile::new(tempfs.alloc_node_id())) as Arc<dyn File>,
+    );
+    root_dir.add_file(
+      "Mounts",
+      Arc::new(MountsFile::new(tempfs.alloc_node_id())) as Arc<dyn File>,
+    );
+    root_dir.add_file(
+      "PageCache",
+      Arc::new(PageCacheStatsFile::new(tempfs.alloc_node_id())) as Arc<dyn File>,
+    );
+    root_dir.add_file(
+      "Slab",
+      Arc::new(SlabStatsFile::new(tempfs.alloc_node_id())) as Arc<dyn File>,
+    );
 
-    root_dir.add_file("devcon", SERIAL_TTY.clone() as Arc<dyn File>);
-    root_dir.add_file("Framebuffer", FRAMEBUFFER_FILE.clone() as Arc<dyn File>);
-    root_dir.add_file("Bizcat", FONT_FILE.clone() as Arc<dyn File>);
-    root_dir.add_file("Mouse", MOUSE_FILE.clone() as Arc<dyn File>);
+    let shm = Arc::new(SharedMemoryDirectory::new(tempfs.alloc_node_id()));
+    Self {
+      root: Arc::new(DevfsDirectory {
+        tempfs,
+        shm,
+        devices: SpinLock::new(BTreeMap::new()),
+      }),
+    }
+  }
 
-    Self(tempfs)
+  fn alloc_node_id(&self) -> NodeId {
+    self.root.tempfs.alloc_node_id()
   }
 }
 
 impl vfs::Filesystem for Devfs {
   fn root(&self) -> Result<Arc<dyn vfs::Directory>> {
-    vfs::Filesystem::root(&self.0)
+    Ok(self.root.clone())
+  }
+}
+
+#[derive(Debug)]
+struct DevfsDirectory {
+  /// Holds the files which aren't devices.
+  tempfs: Tempfs,
+  shm: Arc<SharedMemoryDirectory>,
+  devices: SpinLock<BTreeMap<String, Arc<DeviceNode>>>,
+}
+
+impl vfs::Directory for DevfsDirectory {
+  fn _lookup(&self, name: &str) -> Result<vfs::Node> {
+    if let Some(node) = self.devices.lock().get(name) {
+      return Ok(vfs::Node::File(node.clone() as Arc<dyn File>));
+    }
+
+    if name == SHM_DIR_NAME {
+      return Ok(vfs::Node::Directory(self.shm.clone()));
+    }
+
+    self.tempfs.root()._lookup(name)
+  }
+
+  /// Lists the devices first, then the shared memory directory and the
+  /// other files.
+  fn read_dir(&self, index: usize) -> Result<Option<vfs::DirEntry>> {
+    let devices = self.devices.lock();
+    match devices.iter().nth(index) {
+      Some((name, node)) => Ok(Some(vfs::DirEntry {
+        node_id: node.node_id,
+        file_type: vfs::FileType::RegularFile,
+        name: name.clone(),
+      })),
+      None if index == devices.len() => Ok(Some(vfs::DirEntry {
+        node_id: vfs::Directory::stat(self.shm.as_ref())?.node_id,
+        file_type: vfs::FileType::Directory,
+        name: String::from(SHM_DIR_NAME),
+      })),
+      None => self.tempfs.root().read_dir(index - devices.len() - 1),
+    }
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    vfs::Directory::stat(self.tempfs.root().as_ref())
+  }
+}
+
+/// Keeps devfs in sync with the device registry.
+struct DevfsListener(Arc<DevfsDirectory>);
+
+impl DeviceListener for DevfsListener {
+  fn on_event(&self, event: DeviceEvent<'_>) {
+    match event {
+      DeviceEvent::Added(device) => {
+        let node = Arc::new(DeviceNode {
+          node_id: self.0.tempfs.alloc_node_id(),
+          device: device.clone(),
+        });
+
+        self
+          .0
+          .devices
+          .lock()
+          .insert(String::from(device.name()), node);
+      }
+      DeviceEvent::Removed(device) => {
+        self.0.devices.lock().remove(device.name());
+
+        // Lookups of the node are cached: forget them so that it's gone
+        // from the tree. The registry is locked here, so don't wait for the
+        // rootfs lock.
+        vfs::mount::invalidate_dentries();
+      }
+    }
   }
 }
 
+/// A registered device, as it appears in devfs.
+#[derive(Debug)]
+struct DeviceNode {
+  node_id: NodeId,
+  device: Device,
+}
+
+impl vfs::File for DeviceNode {
+  fn open(&self, options: &OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+    self.device.file().open(options)
+  }
+
+  fn read(&self, offset: usize, dst: UserBufferMut<'_>, options: &OpenOptions) -> Result<usize> {
+    self.device.file().read(offset, dst, options)
+  }
+
+  fn write(&self, offset: usize, buf: UserBuffer<'_>, options: &OpenOptions) -> Result<usize> {
+    self.device.file().write(offset, buf, options)
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    let stat = self.device.file().stat()?;
+    Ok(Stat {
+      node_id: self.node_id,
+      size: stat.size,
+      kind: self.device.kind().into(),
+    })
+  }
+
+  fn fsync(&self) -> Result<()> {
+    self.device.file().fsync()
+  }
+}
+
+fn register_builtin_device(name: &str, major: u32, minor: u32, file: Arc<dyn File>) -> Result<()> {
+  register_device(
+    name,
+    DeviceKind::Char,
+    DeviceNumber::new(major, minor),
+    file,
+  )
+}
+
+/// Registers the devices the kernel provides itself.
+fn register_builtin_devices(devfs: &Devfs) -> Result<()> {
+  SERIAL_TTY.init(|| Arc::new(DevConsole::new(devfs.alloc_node_id())));
+  FRAMEBUFFER_FILE.init(|| Arc::new(Framebuffer::new(devfs.alloc_node_id())));
+  FONT_FILE.init(|| {
+    Arc::new(Font::new(
+      devfs.alloc_node_id(),
+      BIZCAT.width,
+      BIZCAT.height,
+      BIZCAT.stride,
+      BIZCAT.max_glyph as usize,
+      &BIZCAT.data,
+    ))
+  });
+  MOUSE_FILE.init(|| Arc::new(Mouse::new(devfs.alloc_node_id())));
+
+  register_builtin_device("devcon", TTYAUX_MAJOR, 1, SERIAL_TTY.clone())?;
+  register_builtin_device("Framebuffer", FB_MAJOR, 0, FRAMEBUFFER_FILE.clone())?;
+  register_builtin_device("Bizcat", alloc_major()?, 0, FONT_FILE.clone())?;
+  register_builtin_device("Mouse", MISC_INPUT_MAJOR, 63, MOUSE_FILE.clone())?;
+
+  register_builtin_device(
+    "null",
+    MEM_MAJOR,
+    3,
+    Arc::new(NullFile::new(devfs.alloc_node_id())),
+  )?;
+  register_builtin_device(
+    "zero",
+    MEM_MAJOR,
+    5,
+    Arc::new(ZeroFile::new(devfs.alloc_node_id())),
+  )?;
+  register_builtin_device(
+    "full",
+    MEM_MAJOR,
+    7,
+    Arc::new(FullFile::new(devfs.alloc_node_id())),
+  )?;
+  register_builtin_device(
+    "random",
+    MEM_MAJOR,
+    8,
+    Arc::new(RandomFile::new(devfs.alloc_node_id())),
+  )?;
+  register_builtin_device(
+    "urandom",
+    MEM_MAJOR,
+    9,
+    Arc::new(RandomFile::new(devfs.alloc_node_id())),
+  )?;
+  register_builtin_device(
+    "kmsg",
+    MEM_MAJOR,
+    11,
+    Arc::new(KmsgFile::new(devfs.alloc_node_id())),
+  )?;
+
+  Ok(())
+}
+
 pub fn init() {
-  DEVFS.init(|| Arc::new(Devfs::new()));
+  let devfs = Devfs::new();
+  // Devices drivers registered before devfs existed are replayed to the
+  // listener.
+  register_device_listener(Box::new(DevfsListener(devfs.root.clone())));
+  register_builtin_devices(&devfs).expect("failed to register the kernel's devices");
+
+  DEVFS.init(|| Arc::new(devfs));
 }
 
+pub mod block_cache;
 pub mod devconsole;
 pub mod fb0;
 pub mod font;
+pub mod kmsg;
+pub mod mem;
+pub mod mounts;
 pub mod mouse;
+pub mod page_cache;
+pub mod random;
+pub mod shm;
+pub mod slab;
@@ -0,0 +1,54 @@
+use core::cmp::min;
+
+use alloc::fmt;
+use api::{
+  io::OpenOptions,
+  user_buffer::{UserBufWriter, UserBuffer, UserBufferMut},
+  vfs::{self, NodeId, Stat},
+  ErrorKind, Process, Result,
+};
+
+/// Exposes the mount table of the current process in the `/proc/mounts`
+/// format.
+pub struct MountsFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for MountsFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("MountsFile").finish()
+  }
+}
+
+impl MountsFile {
+  pub fn new(node_id: NodeId) -> Self {
+    MountsFile {
+      stat: Stat {
+        node_id,
+        size: 0,
+        kind: vfs::FileKind::RegularFile,
+      },
+    }
+  }
+}
+
+impl vfs::File for MountsFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<alloc::sync::Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    let text = Process::rootfs().lock().mount_table();
+
+    let mut writer = UserBufWriter::from(dst);
+    writer.write_bytes(&text.as_bytes()[min(offset, text.len())..])
+  }
+
+  fn write(&self, _offset: usize, _buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    Err(ErrorKind::NotSupported.into())
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
@@ -0,0 +1,59 @@
+use core::cmp::min;
+
+use alloc::{fmt, format};
+use api::{
+  io::OpenOptions,
+  user_buffer::{UserBufWriter, UserBuffer, UserBufferMut},
+  vfs::{self, NodeId, Stat},
+  ErrorKind, Result,
+};
+
+use crate::mm::page_cache::read_page_cache_stats;
+
+/// Exposes the page cache statistics as text.
+pub struct PageCacheStatsFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for PageCacheStatsFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("PageCacheStatsFile").finish()
+  }
+}
+
+impl PageCacheStatsFile {
+  pub fn new(node_id: NodeId) -> Self {
+    PageCacheStatsFile {
+      stat: Stat {
+        node_id,
+        size: 0,
+        kind: vfs::FileKind::RegularFile,
+      },
+    }
+  }
+}
+
+impl vfs::File for PageCacheStatsFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<alloc::sync::Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    let stats = read_page_cache_stats();
+    let text = format!(
+      "cached {}\nmapped {}\n",
+      stats.cached_pages, stats.mapped_pages
+    );
+
+    let mut writer = UserBufWriter::from(dst);
+    writer.write_bytes(&text.as_bytes()[min(offset, text.len())..])
+  }
+
+  fn write(&self, _offset: usize, _buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    Err(ErrorKind::NotSupported.into())
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
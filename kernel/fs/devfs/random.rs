@@ -0,0 +1,64 @@
+use alloc::{fmt, sync::Arc};
+use api::{
+  io::OpenOptions,
+  user_buffer::{UserBufReader, UserBuffer, UserBufferMut},
+  vfs::{self, NodeId, Stat},
+  Result,
+};
+
+use crate::random::{add_entropy, read_secure_random};
+
+/// `random` and `urandom`: both read from the CRNG, which never blocks once
+/// seeded at boot. Whatever is written is mixed into the entropy pool.
+pub struct RandomFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for RandomFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RandomFile").finish()
+  }
+}
+
+impl RandomFile {
+  pub fn new(node_id: NodeId) -> Self {
+    RandomFile {
+      stat: Stat {
+        node_id,
+        size: 0,
+        kind: vfs::FileKind::CharDevice,
+      },
+    }
+  }
+}
+
+impl vfs::File for RandomFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, _offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    read_secure_random(dst)
+  }
+
+  fn write(&self, _offset: usize, buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    let mut reader = UserBufReader::from(buf);
+    let mut chunk = [0u8; 256];
+    let mut written = 0;
+    loop {
+      let len = reader.read_bytes(&mut chunk)?;
+      if len == 0 {
+        break;
+      }
+
+      add_entropy(&chunk[..len]);
+      written += len;
+    }
+
+    Ok(written)
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
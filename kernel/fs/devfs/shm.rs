@@ -0,0 +1,73 @@
+use alloc::{borrow::ToOwned, collections::BTreeMap, string::String, sync::Arc};
+use api::{
+  schema::posix::FileMode,
+  sync::SpinLock,
+  vfs::{self, File, NodeId, Stat},
+  ErrorKind, Result,
+};
+
+use crate::mm::shm::SharedMemory;
+
+/// `SharedMemory`: the shared memory objects of shm_open(3). Files created
+/// here live in memory and are mapped as they are by shared mappings.
+#[derive(Debug)]
+pub struct SharedMemoryDirectory {
+  stat: Stat,
+  files: SpinLock<BTreeMap<String, Arc<SharedMemory>>>,
+}
+
+impl SharedMemoryDirectory {
+  pub fn new(node_id: NodeId) -> Self {
+    SharedMemoryDirectory {
+      stat: Stat {
+        node_id,
+        size: 0,
+        kind: vfs::FileKind::Directory,
+      },
+      files: SpinLock::new(BTreeMap::new()),
+    }
+  }
+}
+
+impl vfs::Directory for SharedMemoryDirectory {
+  fn _lookup(&self, name: &str) -> Result<vfs::Node> {
+    self
+      .files
+      .lock()
+      .get(name)
+      .map(|file| vfs::Node::File(file.clone() as Arc<dyn File>))
+      .ok_or_else(|| ErrorKind::NoEntry.into())
+  }
+
+  fn read_dir(&self, index: usize) -> Result<Option<vfs::DirEntry>> {
+    let files = self.files.lock();
+    let Some((name, file)) = files.iter().nth(index) else {
+      return Ok(None);
+    };
+
+    Ok(Some(vfs::DirEntry {
+      node_id: file.stat()?.node_id,
+      file_type: vfs::FileType::RegularFile,
+      name: name.clone(),
+    }))
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+
+  fn create_file(&self, name: &str, _mode: FileMode) -> Result<vfs::Node> {
+    let file = Arc::new(SharedMemory::new(0));
+    self.files.lock().insert(name.to_owned(), file.clone());
+    Ok(vfs::Node::File(file))
+  }
+
+  fn unlink(&self, name: &str) -> Result<()> {
+    self
+      .files
+      .lock()
+      .remove(name)
+      .map(|_| ())
+      .ok_or_else(|| ErrorKind::NoEntry.into())
+  }
+}
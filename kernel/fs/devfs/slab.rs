@@ -0,0 +1,67 @@
+use core::{cmp::min, fmt::Write};
+
+use alloc::{fmt, string::String};
+use api::{
+  io::OpenOptions,
+  user_buffer::{UserBufWriter, UserBuffer, UserBufferMut},
+  vfs::{self, NodeId, Stat},
+  ErrorKind, Result,
+};
+use environment::slab::read_slab_stats;
+
+/// Exposes the statistics of the slab caches as text, one cache per line.
+pub struct SlabStatsFile {
+  stat: Stat,
+}
+
+impl fmt::Debug for SlabStatsFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SlabStatsFile").finish()
+  }
+}
+
+impl SlabStatsFile {
+  pub fn new(node_id: NodeId) -> Self {
+    SlabStatsFile {
+      stat: Stat {
+        node_id,
+        size: 0,
+        kind: vfs::FileKind::RegularFile,
+      },
+    }
+  }
+}
+
+impl vfs::File for SlabStatsFile {
+  fn open(&self, _options: &OpenOptions) -> Result<Option<alloc::sync::Arc<dyn vfs::File>>> {
+    Ok(None)
+  }
+
+  fn read(&self, offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+    let mut text = String::from("name object_size objects_per_slab slabs empty_slabs used total\n");
+    for stats in read_slab_stats() {
+      let _ = writeln!(
+        text,
+        "{} {} {} {} {} {} {}",
+        stats.name,
+        stats.object_size,
+        stats.objects_per_slab,
+        stats.num_slabs,
+        stats.num_empty_slabs,
+        stats.num_used_objects,
+        stats.num_total_objects
+      );
+    }
+
+    let mut writer = UserBufWriter::from(dst);
+    writer.write_bytes(&text.as_bytes()[min(offset, text.len())..])
+  }
+
+  fn write(&self, _offset: usize, _buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+    Err(ErrorKind::NotSupported.into())
+  }
+
+  fn stat(&self) -> Result<Stat> {
+    Ok(self.stat)
+  }
+}
@@ -0,0 +1,238 @@
+//! Advisory file locks. `flock` locks belong to an open file description and
+//! go away with it, while POSIX record locks belong to a process and go away
+//! as soon as it closes any descriptor of the file. Both are tracked per
+//! inode.
+
+use alloc::{
+  collections::BTreeMap,
+  sync::{Arc, Weak},
+  vec::Vec,
+};
+use api::{
+  process::Pid,
+  sync::SpinLock,
+  vfs::{opened_file::OpenedFile, NodeId},
+  ErrorKind, Result,
+};
+use utils::once::Once;
+
+use crate::process::wait_queue::WaitQueue;
+
+static LOCKS: SpinLock<BTreeMap<NodeId, InodeLocks>> = SpinLock::new(BTreeMap::new());
+/// Where processes wait for conflicting locks to go away.
+static LOCK_WAIT_QUEUE: Once<WaitQueue> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+  Shared,
+  Exclusive,
+}
+
+impl LockKind {
+  fn conflicts_with(self, other: LockKind) -> bool {
+    self == LockKind::Exclusive || other == LockKind::Exclusive
+  }
+}
+
+struct Flock {
+  owner: Weak<OpenedFile>,
+  kind: LockKind,
+}
+
+impl Flock {
+  fn is_owned_by(&self, file: &Arc<OpenedFile>) -> bool {
+    Weak::as_ptr(&self.owner) == Arc::as_ptr(file)
+  }
+}
+
+/// A lock on the bytes from `start` up to `end`, excluded.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordLock {
+  pub pid: Pid,
+  pub kind: LockKind,
+  pub start: usize,
+  pub end: usize,
+}
+
+impl RecordLock {
+  fn overlaps(&self, start: usize, end: usize) -> bool {
+    self.start < end && start < self.end
+  }
+
+  fn conflicts_with(&self, other: &RecordLock) -> bool {
+    self.pid != other.pid
+      && self.overlaps(other.start, other.end)
+      && self.kind.conflicts_with(other.kind)
+  }
+}
+
+#[derive(Default)]
+struct InodeLocks {
+  flocks: Vec<Flock>,
+  records: Vec<RecordLock>,
+}
+
+impl InodeLocks {
+  fn is_empty(&self) -> bool {
+    self.flocks.is_empty() && self.records.is_empty()
+  }
+
+  /// Forgets the locks of open file descriptions nobody refers to anymore.
+  fn drop_closed_flocks(&mut self) {
+    self.flocks.retain(|lock| lock.owner.strong_count() > 0);
+  }
+
+  /// Unlocks the bytes from `start` up to `end` for `pid`, splitting the
+  /// locks which cover more than that.
+  fn remove_records(&mut self, pid: Pid, start: usize, end: usize) {
+    let mut kept = Vec::with_capacity(self.records.len());
+    for lock in self.records.drain(..) {
+      if lock.pid != pid || !lock.overlaps(start, end) {
+        kept.push(lock);
+        continue;
+      }
+
+      if lock.start < start {
+        kept.push(RecordLock { end: start, ..lock });
+      }
+
+      if end < lock.end {
+        kept.push(RecordLock { start: end, ..lock });
+      }
+    }
+
+    self.records = kept;
+  }
+}
+
+/// Runs `f` on the locks of `node_id`, then forgets them if none is left.
+fn with_inode_locks<R>(node_id: NodeId, f: impl FnOnce(&mut InodeLocks) -> R) -> R {
+  let mut locks = LOCKS.lock();
+  let inode = locks.entry(node_id).or_default();
+  inode.drop_closed_flocks();
+  let ret = f(inode);
+  if inode.is_empty() {
+    locks.remove(&node_id);
+  }
+
+  ret
+}
+
+/// Tries `try_lock` once if `nonblock` is set, otherwise until it succeeds.
+fn lock_or_wait(nonblock: bool, mut try_lock: impl FnMut() -> bool) -> Result<()> {
+  if nonblock {
+    return if try_lock() {
+      Ok(())
+    } else {
+      Err(ErrorKind::WouldBlock.into())
+    };
+  }
+
+  LOCK_WAIT_QUEUE.sleep_signalable_until(|| Ok(try_lock().then_some(())))
+}
+
+/// Takes a `flock` lock on `node_id` for the open file description `file`,
+/// converting the lock it already holds if any.
+pub fn flock(
+  file: &Arc<OpenedFile>,
+  node_id: NodeId,
+  kind: LockKind,
+  nonblock: bool,
+) -> Result<()> {
+  // Like on Linux, a conversion drops the old lock first, so that two
+  // processes upgrading their shared locks at the same time don't deadlock.
+  funlock(file, node_id);
+
+  lock_or_wait(nonblock, || {
+    with_inode_locks(node_id, |inode| {
+      if inode
+        .flocks
+        .iter()
+        .any(|lock| lock.kind.conflicts_with(kind))
+      {
+        return false;
+      }
+
+      inode.flocks.push(Flock {
+        owner: Arc::downgrade(file),
+        kind,
+      });
+      true
+    })
+  })
+}
+
+/// Releases the `flock` lock `file` holds on `node_id`, if any.
+pub fn funlock(file: &Arc<OpenedFile>, node_id: NodeId) {
+  with_inode_locks(node_id, |inode| {
+    inode.flocks.retain(|lock| !lock.is_owned_by(file));
+  });
+
+  LOCK_WAIT_QUEUE.wake_all();
+}
+
+/// Returns a lock of another process which keeps `lock` from being taken.
+pub fn conflicting_record(node_id: NodeId, lock: &RecordLock) -> Option<RecordLock> {
+  with_inode_locks(node_id, |inode| {
+    inode
+      .records
+      .iter()
+      .find(|other| other.conflicts_with(lock))
+      .copied()
+  })
+}
+
+/// Takes `lock`, replacing the locks its process holds over the same bytes.
+pub fn lock_records(node_id: NodeId, lock: RecordLock, nonblock: bool) -> Result<()> {
+  lock_or_wait(nonblock, || {
+    with_inode_locks(node_id, |inode| {
+      if inode
+        .records
+        .iter()
+        .any(|other| other.conflicts_with(&lock))
+      {
+        return false;
+      }
+
+      inode.remove_records(lock.pid, lock.start, lock.end);
+      inode.records.push(lock);
+      true
+    })
+  })?;
+
+  // A lock turned from exclusive to shared may let others in.
+  LOCK_WAIT_QUEUE.wake_all();
+  Ok(())
+}
+
+/// Releases the locks `pid` holds on the bytes from `start` up to `end`.
+pub fn unlock_records(node_id: NodeId, pid: Pid, start: usize, end: usize) {
+  with_inode_locks(node_id, |inode| inode.remove_records(pid, start, end));
+  LOCK_WAIT_QUEUE.wake_all();
+}
+
+/// Called after `pid` closed a descriptor of `node_id`. The record locks
+/// of the process on the file are released even if it has other
+/// descriptors of it open, and the flock lock of the description goes away
+/// with its last descriptor.
+pub fn release_on_close(node_id: NodeId, pid: Pid) {
+  unlock_records(node_id, pid, 0, usize::MAX);
+}
+
+/// Called after `pid` exited and closed its files.
+pub fn release_on_exit(pid: Pid) {
+  let mut locks = LOCKS.lock();
+  for inode in locks.values_mut() {
+    inode.drop_closed_flocks();
+    inode.records.retain(|lock| lock.pid != pid);
+  }
+
+  locks.retain(|_, inode| !inode.is_empty());
+  drop(locks);
+
+  LOCK_WAIT_QUEUE.wake_all();
+}
+
+pub fn init() {
+  LOCK_WAIT_QUEUE.init(WaitQueue::new);
+}
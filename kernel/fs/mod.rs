@@ -1 +1,5 @@
 pub mod devfs;
+pub mod lock;
+mod write;
+
+pub use self::write::{truncate, truncate_file, write, write_at};
@@ -0,0 +1,45 @@
+//! Writing to files. Every write goes through here so that the pages of the
+//! file in the page cache, which might be mapped, are brought up to date.
+
+use alloc::sync::Arc;
+use api::{
+  user_buffer::UserBuffer,
+  vfs::{opened_file::OpenedFile, File},
+  Result,
+};
+
+use crate::mm::page_cache;
+
+/// Writes `buf` at the file position of `opened_file`.
+pub fn write(opened_file: &OpenedFile, buf: UserBuffer<'_>) -> Result<usize> {
+  let written_len = opened_file.write(buf)?;
+  written(opened_file, written_len)
+}
+
+/// Writes `buf` at `offset`, leaving the file position alone.
+pub fn write_at(opened_file: &OpenedFile, offset: usize, buf: UserBuffer<'_>) -> Result<usize> {
+  let written_len = opened_file.write_at(offset, buf)?;
+  written(opened_file, written_len)
+}
+
+pub fn truncate(opened_file: &OpenedFile, len: usize) -> Result<()> {
+  opened_file.truncate(len)?;
+  page_cache::invalidate(opened_file.as_file()?)
+}
+
+/// Like [`truncate`], for a file which hasn't been opened.
+pub fn truncate_file(file: &Arc<dyn File>, len: usize) -> Result<()> {
+  file.truncate(len)?;
+  page_cache::invalidate(file)
+}
+
+fn written(opened_file: &OpenedFile, written_len: usize) -> Result<usize> {
+  if written_len > 0 {
+    // Devices have nothing in the page cache.
+    if let Ok(file) = opened_file.as_file() {
+      page_cache::invalidate(file)?;
+    }
+  }
+
+  Ok(written_len)
+}
@@ -37,6 +37,8 @@ pub fn attach_irq(irq: u8, f: Box<dyn FnMut() + Send + Sync + 'static>) {
 }
 
 pub fn handle_irq(irq: u8) {
+	crate::random::add_interrupt_timing(irq);
+
 	{
 		let handler = &mut IRQ_HANDLERS.lock()[irq as usize];
 		unsafe {
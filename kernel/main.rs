@@ -14,13 +14,13 @@ use api::{
   driver::block::BlockDriver,
   io::OpenOptions,
   kernel::KernelOps,
-  schema::{
-    fs::{Partition, PARTITIONS},
-    unix::Path,
-  },
+  schema::{fs::Partition, unix::Path},
   sync::SpinLock,
   user_buffer::UserBufferMut,
-  vfs::mount::Rootfs,
+  vfs::{
+    mount::{MountFlags, Rootfs},
+    registry,
+  },
 };
 use environment::{
   arch::{idle, PtRegs},
@@ -35,7 +35,10 @@ use tempfs::{InMemoryFile, Tempfs};
 use utils::once::Once;
 
 use crate::{
-  fs::devfs::{self, DEVFS},
+  fs::{
+    devfs::{self, DEVFS},
+    lock,
+  },
   process::switch,
 };
 #[cfg(target_arch = "x86_64")]
@@ -56,13 +59,26 @@ impl environment::System for System {
     crate::timer::handle_timer_irq();
   }
 
+  fn on_irq_return_to_user(&self) {
+    // The OOM killer might have picked us while we were running.
+    Process::exit_if_killed();
+  }
+
   fn on_page_fault(
     &self,
     unaligned_vaddr: Option<environment::address::UserVAddr>,
-    ip: usize,
     reason: environment::arch::PageFaultReason,
-  ) {
-    crate::mm::page_fault::handle_page_fault(unaligned_vaddr, ip, reason);
+    frame: &environment::arch::InterruptFrame,
+  ) -> Result<(), environment::address::AccessError> {
+    crate::mm::page_fault::handle_page_fault(unaligned_vaddr, reason, frame)
+  }
+
+  fn on_general_protection_fault(&self, frame: &environment::arch::InterruptFrame) {
+    crate::mm::page_fault::handle_general_protection_fault(frame);
+  }
+
+  fn on_out_of_memory(&self) {
+    crate::mm::oom::reclaim_for_kernel_heap();
   }
 
   fn on_syscall(
@@ -106,7 +122,7 @@ impl KernelOps for ApiOps {
   }
 
   fn register_block_driver(&self, driver: Box<dyn BlockDriver>) {
-    block::register_block_driver(driver)
+    block::register_block_driver(driver);
   }
 
   fn request_partitions(&self) -> Vec<Arc<SpinLock<dyn Partition>>> {
@@ -129,42 +145,77 @@ pub fn boot_kernel(#[cfg_attr(debug_assertions, allow(unused))] bootinfo: &BootI
 
   environment::set_system(&System);
   api::kernel::set_kernel_ops(&ApiOps);
+  mm::init();
+  mm::aslr::init(bootinfo.aslr_enabled);
 
   virtio_net::init();
   virtio_blk::init();
+  virtio_9p::init();
 
   api::kernel::init_drivers(bootinfo.pci_enabled, &bootinfo.virtio_mmio_devices);
 
   schema::system::init();
 
   ext2::init();
-  api::schema::fs::init();
+  fat::init();
+  iso9660::init();
+  tempfs::init();
+  registry::init();
   interrupt::init();
+  random::init();
 
   devfs::init();
+  lock::init();
 
-  let tempfs = Tempfs::new_root();
+  let tempfs = Tempfs::new();
 
   tempfs.root().add_file(
     env!("INIT_FILE"),
-    Arc::new(InMemoryFile::new(include_bytes!(concat!(
-      "../userland/build/",
-      env!("INIT_FILE")
-    )))),
+    Arc::new(InMemoryFile::new(
+      tempfs.alloc_node_id(),
+      include_bytes!(concat!("../userland/build/", env!("INIT_FILE"))),
+    )),
   );
 
-  let dev_dir = tempfs.root().add_dir("Devices");
-  // let mnt_dir = tempfs.root().add_dir("Mounts");
-  // let ext2 = mnt_dir.add_dir("ext2");
-  let ext2 = tempfs.root().add_dir("ext2");
+  tempfs.root().add_dir("Devices");
+  tempfs.root().add_dir("ext2");
+  tempfs.root().add_dir("host");
 
   let mut rootfs = Rootfs::new(Arc::new(tempfs)).unwrap();
 
   rootfs
-    .mount(dev_dir, DEVFS.clone())
+    .mount(
+      Path::new("/Devices"),
+      DEVFS.clone(),
+      "devfs",
+      "devfs",
+      MountFlags::empty(),
+    )
     .expect("failed to mount /Devices");
 
-  rootfs.mount(ext2, PARTITIONS.lock().get(&0).unwrap().clone());
+  let boot_fs = registry::detected_filesystem(0).expect("no filesystem on the boot partition");
+  rootfs
+    .mount(
+      Path::new("/ext2"),
+      boot_fs.fs,
+      &boot_fs.source,
+      boot_fs.fs_type,
+      MountFlags::empty(),
+    )
+    .expect("failed to mount /ext2");
+
+  // QEMU shares a host directory when given a virtio-9p device tagged `host`.
+  if let Ok(host_fs) = registry::create_filesystem("9p", "host") {
+    rootfs
+      .mount(
+        Path::new("/host"),
+        host_fs,
+        "host",
+        "9p",
+        MountFlags::empty(),
+      )
+      .expect("failed to mount /host");
+  }
 
   let devcon = rootfs
     .lookup_path(Path::new("/Devices/devcon"), true)
@@ -0,0 +1,36 @@
+//! Address space layout randomization. Each process gets its stack, its heap,
+//! its mmap region and, if it's a PIE, its image at a random offset from the
+//! fixed bases in `crate::arch`.
+//!
+//! Pass `aslr=off` on the kernel command line to get the same layout in every
+//! process, which is handy to reproduce bugs.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use api::arch::PAGE_SIZE;
+use utils::alignment::align_down;
+
+use crate::random::fill_random;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn init(enabled: bool) {
+	if !enabled {
+		warn!("ASLR disabled: every process gets the same layout");
+	}
+
+	ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns a random offset below `range` aligned to `align`, or 0 if ASLR
+/// is disabled.
+pub fn random_offset(range: usize, align: usize) -> usize {
+	debug_assert!(align.is_power_of_two() && align >= PAGE_SIZE);
+	if !ENABLED.load(Ordering::Relaxed) || range < align {
+		return 0;
+	}
+
+	let mut bytes = [0u8; 8];
+	fill_random(&mut bytes);
+	align_down(usize::from_le_bytes(bytes) % range, align)
+}
@@ -1,2 +1,24 @@
+use api::vfs::{interface::PathComponent, opened_file::OpenedFile};
+use environment::slab::SlabCache;
+
+use crate::process::Process;
+
+static PATH_COMPONENT_CACHE: SlabCache = SlabCache::for_arc::<PathComponent>("path_component");
+static OPENED_FILE_CACHE: SlabCache = SlabCache::for_arc::<OpenedFile>("opened_file");
+static PROCESS_CACHE: SlabCache = SlabCache::for_arc::<Process>("process");
+
+pub fn init() {
+  // Give the objects allocated over and over their own slab caches.
+  PATH_COMPONENT_CACHE.register();
+  OPENED_FILE_CACHE.register();
+  PROCESS_CACHE.register();
+
+  shm::init();
+}
+
+pub mod aslr;
+pub mod oom;
+pub mod page_cache;
 pub mod page_fault;
+pub mod shm;
 pub mod vm;
@@ -0,0 +1,113 @@
+//! Running out of memory: the caches are shrunk first, and if it's not
+//! enough, the process with the largest resident set is killed.
+use api::{
+	address::PAddr,
+	arch::PAGE_SIZE,
+	mm::{alloc_pages, AllocPageFlags},
+	process::{Pid, ProcessState},
+	Error, ErrorKind, Result,
+};
+use environment::slab;
+use utils::byte_size::ByteSize;
+
+use crate::process::{current_process, signal::SIGKILL, switch, Process};
+
+use super::page_cache;
+
+/// The number of pages evicted from the page cache at once when a page
+/// allocation fails.
+const RECLAIM_BATCH: usize = 64;
+
+/// Allocates a page for the user, evicting unused pages from the page cache
+/// and giving the empty slabs back if we're running out of memory.
+pub fn alloc_user_page() -> Result<PAddr> {
+	if let Ok(paddr) = alloc_pages(1, AllocPageFlags::USER) {
+		return Ok(paddr);
+	}
+
+	page_cache::reclaim(RECLAIM_BATCH);
+	slab::shrink_all();
+	alloc_pages(1, AllocPageFlags::USER).map_err(|_| ErrorKind::OutOfMemory.into())
+}
+
+/// Evicts unused pages from the page cache for the kernel heap, which can't
+/// grow. The allocation might come from the page cache itself: it's left
+/// alone if it's locked.
+pub fn reclaim_for_kernel_heap() {
+	if !page_cache::is_locked() {
+		page_cache::reclaim(RECLAIM_BATCH);
+	}
+}
+
+pub fn is_out_of_memory(err: &Error) -> bool {
+	matches!(
+		err.kind(),
+		ErrorKind::AllocationError | ErrorKind::OutOfMemory
+	)
+}
+
+/// Frees memory for a page fault which can't be served otherwise: kills the
+/// process with the largest resident set, which might be the current one.
+/// Returns once the caller may retry. The caller must not hold any locks.
+///
+/// In usercopy, the kernel might hold locks that exiting or switching to the
+/// victim would need: `in_usercopy` is set and the victim is only marked as
+/// killed. The caller fails the copy, and the current process exits at the
+/// end of the system call if it was the victim.
+pub fn out_of_memory(in_usercopy: bool) {
+	let current = current_process();
+	if !in_usercopy {
+		Process::exit_if_killed();
+	}
+
+	let mut victim = None;
+	let mut victim_pages = 0;
+	for process in Process::all() {
+		if matches!(process.state(), ProcessState::Exited(_)) {
+			continue;
+		}
+
+		// Someone is already on the way out: wait for it to give its
+		// memory back instead of killing another one.
+		if process.is_killed() {
+			if !in_usercopy {
+				switch();
+			}
+
+			return;
+		}
+
+		// Kernel threads have no memory of their own, and init can't exit.
+		let resident_pages = match process.vm().as_ref() {
+			Some(vm) if process.pid() != Pid::new(1) => vm.lock().resident_pages(),
+			_ => continue,
+		};
+
+		if resident_pages > victim_pages {
+			victim = Some(process);
+			victim_pages = resident_pages;
+		}
+	}
+
+	let victim = match victim {
+		Some(victim) => victim,
+		None => panic!("out of memory and no process to kill"),
+	};
+
+	warn!(
+		"out of memory: killing {} (pid={}, {} resident)",
+		victim.cmdline().argv0(),
+		victim.pid().as_i32(),
+		ByteSize::new(victim_pages * PAGE_SIZE)
+	);
+
+	if victim.pid() == current.pid() && !in_usercopy {
+		drop(victim);
+		Process::exit_by_signal(SIGKILL);
+	}
+
+	victim.kill(SIGKILL);
+	if !in_usercopy {
+		switch();
+	}
+}
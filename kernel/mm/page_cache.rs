@@ -0,0 +1,314 @@
+//! A cache of file pages, shared by every process mapping the same file.
+//!
+//! Pages of private file mappings (e.g. the text segment of an executable)
+//! which haven't been written to are mapped read-only straight from this
+//! cache instead of being copied into a fresh page on every fault. A write
+//! fault on such a page gives the process its own copy.
+//!
+//! Shared file mappings (`MAP_SHARED`) map the cached pages read-only until
+//! the first write, which makes them dirty. [`writeback`] writes dirty pages
+//! to the file and write-protects them again.
+//!
+//! Each cached page counts how many page table entries point to it. Pages
+//! nobody maps anymore stay cached until memory runs low and [`reclaim`]
+//! evicts them, least recently used first.
+use core::{cmp::min, slice};
+
+use alloc::{
+	collections::{BTreeMap, BTreeSet},
+	sync::Arc,
+	vec::Vec,
+};
+use api::{
+	address::PAddr,
+	arch::PAGE_SIZE,
+	io::OpenOptions,
+	mm::{alloc_pages, AllocPageFlags},
+	sync::SpinLock,
+	vfs::{File, NodeId},
+	Result,
+};
+use environment::page_allocator::free_pages;
+use utils::alignment::is_aligned;
+
+use super::vm;
+
+static PAGE_CACHE: SpinLock<PageCache> = SpinLock::new(PageCache::new());
+
+type PageKey = (NodeId, usize);
+
+struct CachedPage {
+	paddr: PAddr,
+	/// The number of page table entries pointing to this page.
+	mappings: usize,
+	/// Written through a shared mapping and not yet written back.
+	dirty: bool,
+	/// Being written back, and not written to since then: the page is clean
+	/// once the write succeeds.
+	cleaning: bool,
+	last_used: u64,
+}
+
+struct PageCache {
+	pages: BTreeMap<PageKey, CachedPage>,
+	/// Reverse lookup from a physical page to its key in `pages`.
+	owners: BTreeMap<PAddr, PageKey>,
+	clock: u64,
+}
+
+impl PageCache {
+	const fn new() -> Self {
+		Self {
+			pages: BTreeMap::new(),
+			owners: BTreeMap::new(),
+			clock: 0,
+		}
+	}
+
+	fn tick(&mut self) -> u64 {
+		self.clock += 1;
+		self.clock
+	}
+
+	fn get(&mut self, key: PageKey) -> Option<&mut CachedPage> {
+		let last_used = self.tick();
+		let page = self.pages.get_mut(&key)?;
+		page.last_used = last_used;
+		Some(page)
+	}
+
+	fn insert(&mut self, key: PageKey, paddr: PAddr) -> &mut CachedPage {
+		let last_used = self.tick();
+		self.owners.insert(paddr, key);
+		self.pages.entry(key).or_insert(CachedPage {
+			paddr,
+			mappings: 0,
+			dirty: false,
+			cleaning: false,
+			last_used,
+		})
+	}
+
+	fn remove(&mut self, key: PageKey) {
+		if let Some(page) = self.pages.remove(&key) {
+			self.owners.remove(&page.paddr);
+			free_pages(page.paddr, 1);
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PageCacheStats {
+	pub cached_pages: usize,
+	pub mapped_pages: usize,
+}
+
+pub fn read_page_cache_stats() -> PageCacheStats {
+	let cache = PAGE_CACHE.lock();
+	PageCacheStats {
+		cached_pages: cache.pages.len(),
+		mapped_pages: cache.pages.values().filter(|page| page.mappings > 0).count(),
+	}
+}
+
+/// Fills `paddr` with the page of `file` starting at `offset`.
+fn read_page(file: &Arc<dyn File>, offset: usize, paddr: PAddr) -> Result<()> {
+	let buf = unsafe { slice::from_raw_parts_mut(paddr.as_mut_ptr::<u8>(), PAGE_SIZE) };
+	let size = file.stat()?.size;
+	let copy_len = min(size.saturating_sub(offset), PAGE_SIZE);
+	buf[copy_len..].fill(0);
+	if copy_len > 0 {
+		file.read(offset, (&mut buf[..copy_len]).into(), &OpenOptions::readwrite())?;
+	}
+
+	Ok(())
+}
+
+/// Returns the cached page of `file` at `offset` (which must be page
+/// aligned), reading it from the file if needed. The caller is going to map
+/// the page and must call [`release`] once it's unmapped.
+pub fn map_page(file: &Arc<dyn File>, offset: usize) -> Result<PAddr> {
+	debug_assert!(is_aligned(offset, PAGE_SIZE));
+	let key = (file.stat()?.node_id, offset / PAGE_SIZE);
+
+	if let Some(page) = PAGE_CACHE.lock().get(key) {
+		page.mappings += 1;
+		return Ok(page.paddr);
+	}
+
+	// Don't hold the lock while reading the file.
+	let paddr = alloc_pages(1, AllocPageFlags::USER | AllocPageFlags::DIRTY_OK)?;
+	if let Err(err) = read_page(file, offset, paddr) {
+		free_pages(paddr, 1);
+		return Err(err);
+	}
+
+	let mut cache = PAGE_CACHE.lock();
+	if let Some(page) = cache.get(key) {
+		// Someone else has read the same page in the meantime.
+		page.mappings += 1;
+		let paddr_in_cache = page.paddr;
+		drop(cache);
+		free_pages(paddr, 1);
+		return Ok(paddr_in_cache);
+	}
+
+	let page = cache.insert(key, paddr);
+	page.mappings += 1;
+	Ok(paddr)
+}
+
+/// Copies the page of `file` at `offset` into `dst` if it's in the cache.
+pub fn copy_cached_page(file: &Arc<dyn File>, offset: usize, dst: &mut [u8]) -> Result<bool> {
+	let key = (file.stat()?.node_id, offset / PAGE_SIZE);
+	let mut cache = PAGE_CACHE.lock();
+	match cache.get(key) {
+		Some(page) => {
+			let src = unsafe { slice::from_raw_parts(page.paddr.as_ptr::<u8>(), PAGE_SIZE) };
+			let len = min(dst.len(), PAGE_SIZE);
+			dst[..len].copy_from_slice(&src[..len]);
+			Ok(true)
+		}
+		None => Ok(false),
+	}
+}
+
+pub fn is_cached_page(paddr: PAddr) -> bool {
+	PAGE_CACHE.lock().owners.contains_key(&paddr)
+}
+
+/// Counts one more mapping of `paddr`, e.g. when fork(2) shares it with the
+/// child. Does nothing if it's not a page cache page.
+pub fn retain(paddr: PAddr) {
+	let mut cache = PAGE_CACHE.lock();
+	if let Some(key) = cache.owners.get(&paddr).copied() {
+		cache.pages.get_mut(&key).unwrap().mappings += 1;
+	}
+}
+
+/// Records that `paddr` has been written through a shared mapping.
+pub fn mark_dirty(paddr: PAddr) {
+	let mut cache = PAGE_CACHE.lock();
+	if let Some(key) = cache.owners.get(&paddr).copied() {
+		let page = cache.pages.get_mut(&key).unwrap();
+		page.dirty = true;
+		page.cleaning = false;
+	}
+}
+
+/// Writes the dirty pages of `file` back to it. The file doesn't grow: the
+/// part of a page past its end is dropped. The caller must not hold any `Vm`
+/// lock: the pages are write-protected in every process mapping them.
+pub fn writeback(file: &Arc<dyn File>) -> Result<()> {
+	let node_id = file.stat()?.node_id;
+	let dirty_pages = {
+		let mut cache = PAGE_CACHE.lock();
+		cache
+			.pages
+			.range_mut((node_id, 0)..=(node_id, usize::MAX))
+			.filter(|(_, page)| page.dirty)
+			.map(|(key, page)| {
+				page.cleaning = true;
+				(*key, page.paddr)
+			})
+			.collect::<Vec<_>>()
+	};
+
+	if dirty_pages.is_empty() {
+		return Ok(());
+	}
+
+	// Writes from now on fault and mark the pages dirty again.
+	let paddrs = dirty_pages.iter().map(|(_, paddr)| *paddr).collect::<BTreeSet<_>>();
+	vm::write_protect_shared_pages(node_id, &paddrs);
+
+	// Don't hold the lock while writing the file. Cached pages are only
+	// freed by `reclaim`, which leaves the dirty ones alone.
+	let size = file.stat()?.size;
+	for (key, paddr) in dirty_pages {
+		let offset = key.1 * PAGE_SIZE;
+		let len = min(size.saturating_sub(offset), PAGE_SIZE);
+		if len > 0 {
+			let buf = unsafe { slice::from_raw_parts(paddr.as_ptr::<u8>(), len) };
+			file.write(offset, buf.into(), &OpenOptions::readwrite())?;
+		}
+
+		if let Some(page) = PAGE_CACHE.lock().pages.get_mut(&key) {
+			if page.cleaning {
+				page.dirty = false;
+				page.cleaning = false;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Drops a mapping of `paddr`. Returns `false` if it's not a page cache page,
+/// i.e. the caller owns the page and should free it by itself.
+pub fn release(paddr: PAddr) -> bool {
+	let mut cache = PAGE_CACHE.lock();
+	let key = match cache.owners.get(&paddr) {
+		Some(key) => *key,
+		None => return false,
+	};
+
+	let page = cache.pages.get_mut(&key).unwrap();
+	debug_assert!(page.mappings > 0);
+	page.mappings -= 1;
+	true
+}
+
+/// Brings the cached pages of `file` up to date after it has been written
+/// to. Pages nobody maps are simply dropped. What has been written to the
+/// pages through a shared mapping and not written back yet is lost.
+pub fn invalidate(file: &Arc<dyn File>) -> Result<()> {
+	let node_id = file.stat()?.node_id;
+	let mut cache = PAGE_CACHE.lock();
+	let pages = cache
+		.pages
+		.range((node_id, 0)..=(node_id, usize::MAX))
+		.map(|(key, page)| (*key, page.paddr, page.mappings))
+		.collect::<Vec<_>>();
+
+	for (key, paddr, mappings) in pages {
+		if mappings == 0 {
+			cache.remove(key);
+		} else {
+			read_page(file, key.1 * PAGE_SIZE, paddr)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Whether the page cache is in use, i.e. by the current context since
+/// there's only one CPU.
+pub fn is_locked() -> bool {
+	PAGE_CACHE.is_locked()
+}
+
+/// Frees up to `num_pages` cached pages which are not mapped by anyone.
+/// Returns the number of pages freed.
+pub fn reclaim(num_pages: usize) -> usize {
+	let mut cache = PAGE_CACHE.lock();
+	let mut victims = cache
+		.pages
+		.iter()
+		.filter(|(_, page)| page.mappings == 0 && !page.dirty)
+		.map(|(key, page)| (page.last_used, *key))
+		.collect::<Vec<_>>();
+	victims.sort_unstable();
+
+	let mut freed = 0;
+	for (_, key) in victims.into_iter().take(num_pages) {
+		cache.remove(key);
+		freed += 1;
+	}
+
+	if freed > 0 {
+		trace!("page cache: reclaimed {} pages", freed);
+	}
+
+	freed
+}
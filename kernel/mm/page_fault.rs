@@ -1,55 +1,75 @@
-use core::{cmp::min, slice};
+use core::{cmp::min, fmt, mem::size_of, ptr, slice};
 
-use api::{
-	arch::PAGE_SIZE,
-	io::OpenOptions,
-	mm::{alloc_pages, AllocPageFlags},
+use api::{arch::PAGE_SIZE, io::OpenOptions};
+use environment::{
+	address::{AccessError, UserVAddr},
+	arch::{InterruptFrame, PageFaultReason, PageProt},
+	page_allocator::free_pages,
 };
-use environment::{address::UserVAddr, arch::PageFaultReason};
-use utils::alignment::align_down;
+use utils::alignment::{align_down, is_aligned};
 
-use crate::process::current_process;
+use crate::process::{
+	current_process,
+	rlimit::RLIMIT_STACK,
+	signal::{Signal, SIGBUS, SIGSEGV},
+	Process,
+};
+
+use super::{oom, page_cache, vm::VmAreaType};
+
+/// The maximum number of frames printed in a user backtrace.
+const USER_BACKTRACE_MAX: usize = 16;
 
-use super::vm::VmAreaType;
+/// Resolves a page fault. If the fault occurred in usercopy and can't be
+/// resolved, returns an error to make the copy fail with EFAULT: the kernel
+/// might hold locks that exiting would need.
+pub fn handle_page_fault(
+	unaligned_vaddr: Option<UserVAddr>,
+	reason: PageFaultReason,
+	frame: &InterruptFrame,
+) -> Result<(), AccessError> {
+	// The OOM killer might have picked us while we were running. Faults in
+	// usercopy are left to the end of the system call.
+	if frame.is_user() {
+		Process::exit_if_killed();
+	}
 
-pub fn handle_page_fault(unaligned_vaddr: Option<UserVAddr>, ip: usize, _reason: PageFaultReason) {
 	let unaligned_vaddr = match unaligned_vaddr {
 		Some(unaligned_vaddr) => unaligned_vaddr,
-		None => {
-			debug_warn!(
-				"null pointer access (vaddr={:x} ip={:x} reason={:?}), killing the current \
-				 process...",
-				unaligned_vaddr
-					.unwrap_or(unsafe {
-						environment::address::UserVAddr::new_unchecked(usize::MAX)
-					})
-					.value(),
-				ip,
-				_reason
-			);
-			// TODO: Process::exit_by_signal(signal::SIGSEGV);
-			loop {}
-		}
+		None => return bad_access(frame, format_args!("invalid memory access ({:?})", reason)),
 	};
 
-	let current = current_process();
 	let aligned_vaddr = match UserVAddr::new_nonnull(align_down(unaligned_vaddr.value(), PAGE_SIZE))
 	{
 		Ok(uaddr) => uaddr,
 		_ => {
-			debug_warn!(
-				"invalid memory access at {} (ip={:x}), killing the current process...",
-				unaligned_vaddr,
-				ip
-			);
-			// TODO: Process::exit_by_signal(SIGSEGV);
-			loop {}
+			return bad_access(
+				frame,
+				format_args!("null pointer access at {}", unaligned_vaddr),
+			)
 		}
 	};
 
 	// Look for the associated vma area.
+	let current = current_process();
+	let rlimits = current.rlimits().clone();
 	let vm_ref = current.vm();
 	let mut vm = vm_ref.as_ref().unwrap().lock();
+
+	// An access right below the stack: grow it down.
+	if vm.is_below_stack(unaligned_vaddr) && vm.grow_stack(unaligned_vaddr, &rlimits).is_err() {
+		drop(vm);
+		drop(vm_ref);
+		return bad_access(
+			frame,
+			format_args!(
+				"stack overflow at {} (RLIMIT_STACK is {} bytes)",
+				unaligned_vaddr,
+				rlimits.cur(RLIMIT_STACK)
+			),
+		);
+	}
+
 	let vma = match vm
 		.vm_areas()
 		.iter()
@@ -57,25 +77,181 @@ pub fn handle_page_fault(unaligned_vaddr: Option<UserVAddr>, ip: usize, _reason:
 	{
 		Some(vma) => vma,
 		None => {
-			debug_warn!(
-				"no VMAs for address {}, killing the current process...",
-				unaligned_vaddr
-			);
 			drop(vm);
 			drop(vm_ref);
-			// TODO: Process::exit_by_signal(SIGSEGV);
-			loop {}
+			return bad_access(
+				frame,
+				format_args!("no VMAs for address {}", unaligned_vaddr),
+			);
 		}
 	};
 
+	// Check the access against the permissions of the segment.
+	let prot = vma.prot();
+	let allowed = if reason.contains(PageFaultReason::CAUSED_BY_INST_FETCH) {
+		prot.contains(PageProt::EXEC)
+	} else if reason.contains(PageFaultReason::CAUSED_BY_WRITE) {
+		prot.contains(PageProt::WRITE)
+	} else {
+		prot.contains(PageProt::READ)
+	};
+
+	if !allowed {
+		drop(vm);
+		drop(vm_ref);
+		return bad_access(
+			frame,
+			format_args!("{:?} access to {} ({:?})", reason, unaligned_vaddr, prot),
+		);
+	}
+
+	// A write to a present page: it's a read-only page which is either shared
+	// with the page cache or has been duplicated by fork(2).
+	if reason.contains(PageFaultReason::PRESENT | PageFaultReason::CAUSED_BY_WRITE) {
+		if let Some(paddr) = vm.page_table().lookup_user_page(aligned_vaddr) {
+			let mapped = if vma.is_shared() {
+				// The first write to a page cache page through a shared mapping.
+				page_cache::mark_dirty(paddr);
+				vm.map_shared_page(aligned_vaddr, paddr, prot)
+			} else if page_cache::is_cached_page(paddr) {
+				let new_paddr = match oom::alloc_user_page() {
+					Ok(new_paddr) => new_paddr,
+					Err(_) => {
+						drop(vm);
+						drop(vm_ref);
+						return out_of_memory(frame);
+					}
+				};
+				unsafe {
+					ptr::copy_nonoverlapping::<u8>(paddr.as_ptr(), new_paddr.as_mut_ptr(), PAGE_SIZE);
+				}
+				let mapped = vm.map_page(aligned_vaddr, new_paddr, prot);
+				if mapped.is_ok() {
+					page_cache::release(paddr);
+				} else {
+					free_pages(new_paddr, 1);
+				}
+
+				mapped
+			} else {
+				vm.map_page(aligned_vaddr, paddr, prot)
+			};
+
+			if mapped.is_err() {
+				drop(vm);
+				drop(vm_ref);
+				return out_of_memory(frame);
+			}
+
+			return Ok(());
+		}
+	}
+
+	// Shared mappings map the pages of the file themselves. Pages from the
+	// page cache are mapped read-only until the process writes to them so
+	// that we know which ones to write back.
+	if let VmAreaType::Shared { file, offset } = vma.area_type() {
+		let offset_in_file = offset + vma.offset_in_vma(aligned_vaddr);
+		if file.stat().map_or(true, |stat| offset_in_file >= stat.size) {
+			drop(vm);
+			drop(vm_ref);
+			return bus_error(
+				frame,
+				format_args!("access to {} past the end of the file", unaligned_vaddr),
+			);
+		}
+
+		let is_write = reason.contains(PageFaultReason::CAUSED_BY_WRITE);
+		let page = match file.mappable_page(offset_in_file) {
+			Ok(Some(paddr)) => Ok((paddr, prot)),
+			Ok(None) => page_cache::map_page(file, offset_in_file).map(|paddr| {
+				if is_write {
+					page_cache::mark_dirty(paddr);
+					(paddr, prot)
+				} else {
+					(paddr, prot - PageProt::WRITE)
+				}
+			}),
+			Err(err) => Err(err),
+		};
+
+		let mapped = page.and_then(|(paddr, prot)| {
+			vm.map_shared_page(aligned_vaddr, paddr, prot)
+				.map_err(|err| {
+					page_cache::release(paddr);
+					err
+				})
+		});
+
+		match mapped {
+			Ok(()) => {}
+			Err(err) if oom::is_out_of_memory(&err) => {
+				drop(vm);
+				drop(vm_ref);
+				return out_of_memory(frame);
+			}
+			Err(err) => {
+				drop(vm);
+				drop(vm_ref);
+				return bad_access(
+					frame,
+					format_args!("failed to map {} from a file ({:?})", unaligned_vaddr, err),
+				);
+			}
+		}
+
+		return Ok(());
+	}
+
+	// If the whole page comes from the file, map the page in the page cache
+	// until the process writes to it.
+	if let VmAreaType::File {
+		file,
+		offset,
+		file_size,
+	} = vma.area_type()
+	{
+		if aligned_vaddr >= vma.start() && !reason.contains(PageFaultReason::CAUSED_BY_WRITE) {
+			let offset_in_vma = vma.offset_in_vma(aligned_vaddr);
+			let offset_in_file = offset + offset_in_vma;
+			if is_aligned(offset_in_file, PAGE_SIZE) && offset_in_vma + PAGE_SIZE <= *file_size {
+				match page_cache::map_page(file, offset_in_file) {
+					Ok(paddr) => {
+						// Shared with other processes: never writable.
+						let prot = prot - PageProt::WRITE;
+						if vm.map_page(aligned_vaddr, paddr, prot).is_err() {
+							page_cache::release(paddr);
+							drop(vm);
+							drop(vm_ref);
+							return out_of_memory(frame);
+						}
+
+						return Ok(());
+					}
+					Err(err) => {
+						debug_warn!("failed to read a page through the page cache: {:?}", err);
+					}
+				}
+			}
+		}
+	}
+
 	// Allocate and fill the page.
-	let paddr = alloc_pages(1, AllocPageFlags::USER).expect("failed to allocate an anonymous page");
+	let paddr = match oom::alloc_user_page() {
+		Ok(paddr) => paddr,
+		Err(_) => {
+			drop(vm);
+			drop(vm_ref);
+			return out_of_memory(frame);
+		}
+	};
 	unsafe {
 		paddr.as_mut_ptr::<u8>().write_bytes(0, PAGE_SIZE);
 	}
 	match vma.area_type() {
 		VmAreaType::Anonymous => { // The page is already filled with zeros. Nothing to do.
 		}
+		VmAreaType::Shared { .. } => unreachable!(),
 		VmAreaType::File {
 			file,
 			offset,
@@ -101,17 +277,131 @@ pub fn handle_page_fault(unaligned_vaddr: Option<UserVAddr>, ip: usize, _reason:
 				}
 			}
 
-			if copy_len > 0 {
-				file.read(
-					offset_in_file,
-					(&mut buf[offset_in_page..(offset_in_page + copy_len)]).into(),
-					&OpenOptions::readwrite(),
-				)
-				.expect("failed to read file");
+			let buf = &mut buf[offset_in_page..(offset_in_page + copy_len)];
+			let cached = copy_len == PAGE_SIZE
+				&& is_aligned(offset_in_file, PAGE_SIZE)
+				&& page_cache::copy_cached_page(file, offset_in_file, buf).unwrap_or(false);
+			if copy_len > 0 && !cached {
+				if let Err(err) = file.read(offset_in_file, buf.into(), &OpenOptions::readwrite()) {
+					free_pages(paddr, 1);
+					drop(vm);
+					drop(vm_ref);
+					return bus_error(
+						frame,
+						format_args!(
+							"failed to read the file at offset {}: {:?}",
+							offset_in_file, err
+						),
+					);
+				}
 			}
 		}
 	}
 
 	// Map the page in the page table.
-	vm.page_table_mut().map_user_page(aligned_vaddr, paddr);
+	if vm.map_page(aligned_vaddr, paddr, prot).is_err() {
+		free_pages(paddr, 1);
+		drop(vm);
+		drop(vm_ref);
+		return out_of_memory(frame);
+	}
+
+	Ok(())
+}
+
+pub fn handle_general_protection_fault(frame: &InterruptFrame) {
+	kill(frame, SIGSEGV, format_args!("general protection fault"));
+}
+
+/// Runs the OOM killer for a page which couldn't be allocated, and lets the
+/// access be retried. In usercopy, the copy fails with ENOMEM instead: the
+/// kernel might hold locks the victim needs to exit. The caller must not hold any locks.
+fn out_of_memory(frame: &InterruptFrame) -> Result<(), AccessError> {
+	oom::out_of_memory(!frame.is_user());
+	if !frame.is_user() {
+		debug_warn!("usercopy: out of memory");
+		return Err(AccessError::OutOfMemory);
+	}
+
+	Ok(())
+}
+
+/// Fails an access which can't be resolved: kills the process with SIGSEGV
+/// in the user mode, and makes the copy fail in usercopy. The caller must not
+/// hold any locks.
+fn bad_access(frame: &InterruptFrame, what: fmt::Arguments<'_>) -> Result<(), AccessError> {
+	fail_access(frame, SIGSEGV, what)
+}
+
+/// Like [`bad_access`], for an access to a shared mapping past the end of
+/// its file: the process gets SIGBUS.
+fn bus_error(frame: &InterruptFrame, what: fmt::Arguments<'_>) -> Result<(), AccessError> {
+	fail_access(frame, SIGBUS, what)
+}
+
+fn fail_access(
+	frame: &InterruptFrame,
+	signal: Signal,
+	what: fmt::Arguments<'_>,
+) -> Result<(), AccessError> {
+	if !frame.is_user() {
+		debug_warn!("usercopy: {}", what);
+		return Err(AccessError::Fault);
+	}
+
+	kill(frame, signal, what)
+}
+
+/// Logs what the current process did wrong and kills it with `signal`. The
+/// caller must not hold any locks.
+fn kill(frame: &InterruptFrame, signal: Signal, what: fmt::Arguments<'_>) -> ! {
+	let current = current_process();
+	warn!(
+		"{} (pid={}): {}, killing it with signal {}",
+		current.cmdline().argv0(),
+		current.pid().as_i32(),
+		what,
+		signal
+	);
+
+	warn!("{:?}", frame);
+	print_user_backtrace(frame);
+
+	Process::exit_by_signal(signal);
+}
+
+/// Prints the return addresses found by following the frame pointers on the
+/// user stack. Only the pages already mapped are read since we're handling a
+/// fault.
+fn print_user_backtrace(frame: &InterruptFrame) {
+	let vm_ref = current_process().vm();
+	let vm = vm_ref.as_ref().unwrap().lock();
+	let read_u64 = |addr: usize| -> Option<usize> {
+		if !is_aligned(addr, size_of::<u64>()) {
+			return None;
+		}
+
+		let page = UserVAddr::new_nonnull(align_down(addr, PAGE_SIZE)).ok()?;
+		let paddr = vm.page_table().lookup_user_page(page)?;
+		Some(unsafe { *paddr.add(addr % PAGE_SIZE).as_ptr::<u64>() } as usize)
+	};
+
+	warn!("user backtrace:");
+	warn!("    0: {:016x}", frame.ip());
+	let mut bp = frame.bp();
+	for i in 1..USER_BACKTRACE_MAX {
+		let (next_bp, return_addr) = match (read_u64(bp), read_u64(bp + size_of::<u64>())) {
+			(Some(next_bp), Some(return_addr)) if return_addr != 0 => (next_bp, return_addr),
+			_ => break,
+		};
+
+		warn!("    {}: {:016x}", i, return_addr);
+
+		// Callers' frames are above on the stack.
+		if next_bp <= bp {
+			break;
+		}
+
+		bp = next_bp;
+	}
 }
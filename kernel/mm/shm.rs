@@ -0,0 +1,214 @@
+//! Shared memory objects: the files created by shm_open(3) and
+//! memfd_create(2), and the memory behind shared anonymous mappings.
+//!
+//! They live in pages of their own instead of the page cache, and shared
+//! mappings map these pages as they are in every process.
+use core::{
+	cmp::{max, min},
+	slice,
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{collections::BTreeSet, sync::Arc, vec::Vec};
+use api::{
+	address::PAddr,
+	arch::PAGE_SIZE,
+	io::OpenOptions,
+	sync::SpinLock,
+	user_buffer::{UserBufReader, UserBufWriter, UserBuffer, UserBufferMut},
+	vfs::{self, FsId, NodeId, Stat},
+	ErrorKind, Result,
+};
+use environment::page_allocator::free_pages;
+use utils::{alignment::align_up, once::Once};
+
+use super::{oom, vm};
+
+/// Shared memory objects don't belong to any filesystem: they get inode
+/// numbers of their own.
+static FS_ID: Once<FsId> = Once::new();
+
+pub fn init() {
+	FS_ID.init(FsId::alloc);
+}
+
+fn alloc_node_id() -> NodeId {
+	static NEXT_INO: AtomicUsize = AtomicUsize::new(1);
+
+	NodeId::new(*FS_ID, NEXT_INO.fetch_add(1, Ordering::Relaxed))
+}
+
+#[derive(Debug)]
+struct SharedMemoryInner {
+	/// The pages allocated so far. Missing ones read as zeroes.
+	pages: Vec<Option<PAddr>>,
+	size: usize,
+}
+
+impl SharedMemoryInner {
+	/// Returns the page at `index`, allocating it if needed.
+	fn page(&mut self, index: usize) -> Result<PAddr> {
+		if self.pages.len() <= index {
+			self.pages.resize(index + 1, None);
+		}
+
+		if let Some(paddr) = self.pages[index] {
+			return Ok(paddr);
+		}
+
+		let paddr = oom::alloc_user_page()?;
+		self.pages[index] = Some(paddr);
+		Ok(paddr)
+	}
+}
+
+#[derive(Debug)]
+pub struct SharedMemory {
+	node_id: NodeId,
+	inner: SpinLock<SharedMemoryInner>,
+}
+
+impl SharedMemory {
+	pub fn new(size: usize) -> SharedMemory {
+		SharedMemory {
+			node_id: alloc_node_id(),
+			inner: SpinLock::new(SharedMemoryInner {
+				pages: Vec::new(),
+				size,
+			}),
+		}
+	}
+}
+
+impl vfs::File for SharedMemory {
+	fn open(&self, _options: &OpenOptions) -> Result<Option<Arc<dyn vfs::File>>> {
+		Ok(None)
+	}
+
+	// The lock is never held while copying from or to the user: the buffer
+	// may be a mapping of this very object. The data goes through a buffer
+	// of ours as truncate() may free the pages in the meantime.
+	fn read(&self, offset: usize, dst: UserBufferMut<'_>, _options: &OpenOptions) -> Result<usize> {
+		let mut writer = UserBufWriter::from(dst);
+		let mut buf = vec![0u8; PAGE_SIZE];
+		let mut pos = offset;
+		loop {
+			let len = {
+				let inner = self.inner.lock();
+				if pos >= inner.size || writer.remaining_len() == 0 {
+					break;
+				}
+
+				let offset_in_page = pos % PAGE_SIZE;
+				let len = min(
+					min(PAGE_SIZE - offset_in_page, inner.size - pos),
+					writer.remaining_len(),
+				);
+				match inner.pages.get(pos / PAGE_SIZE).copied().flatten() {
+					Some(paddr) => buf[..len].copy_from_slice(unsafe {
+						slice::from_raw_parts(paddr.add(offset_in_page).as_ptr(), len)
+					}),
+					None => buf[..len].fill(0),
+				}
+
+				len
+			};
+
+			writer.write_bytes(&buf[..len])?;
+			pos += len;
+		}
+
+		Ok(writer.written_len())
+	}
+
+	fn write(&self, offset: usize, buf: UserBuffer<'_>, _options: &OpenOptions) -> Result<usize> {
+		let mut reader = UserBufReader::from(buf);
+		let mut chunk = vec![0u8; PAGE_SIZE];
+		let mut pos = offset;
+		while reader.remaining_len() > 0 {
+			let offset_in_page = pos % PAGE_SIZE;
+			let len = min(PAGE_SIZE - offset_in_page, reader.remaining_len());
+			reader.read_bytes(&mut chunk[..len])?;
+
+			let mut inner = self.inner.lock();
+			let paddr = inner.page(pos / PAGE_SIZE)?;
+			let dst = unsafe { slice::from_raw_parts_mut(paddr.add(offset_in_page).as_mut_ptr(), len) };
+			dst.copy_from_slice(&chunk[..len]);
+
+			pos += len;
+			inner.size = max(inner.size, pos);
+		}
+
+		Ok(pos - offset)
+	}
+
+	fn stat(&self) -> Result<Stat> {
+		Ok(Stat {
+			node_id: self.node_id,
+			size: self.inner.lock().size,
+			kind: vfs::FileKind::RegularFile,
+		})
+	}
+
+	fn truncate(&self, len: usize) -> Result<()> {
+		let freed = {
+			let mut inner = self.inner.lock();
+
+			// The rest of the last page must read as zeroes if the object
+			// grows again.
+			let offset_in_page = len % PAGE_SIZE;
+			if offset_in_page > 0 {
+				if let Some(Some(paddr)) = inner.pages.get(len / PAGE_SIZE) {
+					unsafe {
+						paddr
+							.add(offset_in_page)
+							.as_mut_ptr::<u8>()
+							.write_bytes(0, PAGE_SIZE - offset_in_page);
+					}
+				}
+			}
+
+			let num_pages = align_up(len, PAGE_SIZE) / PAGE_SIZE;
+			let freed = if inner.pages.len() > num_pages {
+				inner
+					.pages
+					.split_off(num_pages)
+					.into_iter()
+					.flatten()
+					.collect()
+			} else {
+				BTreeSet::new()
+			};
+
+			inner.size = len;
+			freed
+		};
+
+		// Accesses past the end fault from now on instead of reaching the
+		// pages, which nothing maps anymore.
+		vm::unmap_shared_pages(self.node_id, &freed);
+		for paddr in freed {
+			free_pages(paddr, 1);
+		}
+
+		Ok(())
+	}
+
+	fn mappable_page(&self, offset: usize) -> Result<Option<PAddr>> {
+		let mut inner = self.inner.lock();
+		if offset >= inner.size {
+			return Err(ErrorKind::EINVAL.into());
+		}
+
+		inner.page(offset / PAGE_SIZE).map(Some)
+	}
+}
+
+impl Drop for SharedMemory {
+	fn drop(&mut self) {
+		// Mappings keep a reference to us: nobody maps the pages anymore.
+		for paddr in self.inner.lock().pages.iter().flatten() {
+			free_pages(*paddr, 1);
+		}
+	}
+}
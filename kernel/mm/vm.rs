@@ -1,11 +1,32 @@
-use core::fmt;
-
-use alloc::{sync::Arc, vec::Vec};
-use api::{arch::PAGE_SIZE, vfs::File, ErrorKind, Result};
-use environment::{address::UserVAddr, arch::PageTable};
-use utils::alignment::{align_up, is_aligned};
+use core::{
+	cmp::{max, min},
+	fmt,
+	ops::Range,
+};
+
+use alloc::{collections::BTreeSet, sync::Arc, vec::Vec};
+use api::{
+	address::PAddr,
+	arch::PAGE_SIZE,
+	vfs::{File, NodeId},
+	ErrorKind, Result,
+};
+use environment::{
+	address::UserVAddr,
+	arch::{PageProt, PageTable},
+	page_allocator::free_pages,
+};
+use utils::alignment::{align_down, align_up, is_aligned};
+
+use crate::{
+	arch::{USER_VALLOC_BASE, USER_VALLOC_END, USER_VALLOC_RANDOM_RANGE},
+	process::{
+		rlimit::{ResourceLimits, RLIMIT_AS, RLIMIT_DATA, RLIMIT_STACK},
+		Process,
+	},
+};
 
-use crate::arch::{USER_STACK_TOP, USER_VALLOC_BASE, USER_VALLOC_END};
+use super::{aslr, page_cache};
 
 #[derive(Debug, Clone)]
 pub enum VmAreaType {
@@ -15,6 +36,13 @@ pub enum VmAreaType {
 		offset: usize,
 		file_size: usize,
 	},
+	/// A `MAP_SHARED` mapping: the pages are the file's ones, so writes go to
+	/// the file and every process mapping it sees them. Shared anonymous
+	/// mappings are backed by a [`SharedMemory`](super::shm::SharedMemory).
+	Shared {
+		file: Arc<dyn File>,
+		offset: usize,
+	},
 }
 
 #[derive(Clone)]
@@ -22,6 +50,7 @@ pub struct VmArea {
 	start: UserVAddr,
 	len: usize,
 	area_type: VmAreaType,
+	prot: PageProt,
 }
 
 impl fmt::Debug for VmArea {
@@ -29,6 +58,7 @@ impl fmt::Debug for VmArea {
 		f.debug_struct("VmArea")
 			.field("start", &self.start)
 			.field("len", &self.len)
+			.field("prot", &self.prot)
 			.finish()
 	}
 }
@@ -38,6 +68,20 @@ impl VmArea {
 		&self.area_type
 	}
 
+	pub fn prot(&self) -> PageProt {
+		self.prot
+	}
+
+	pub fn is_shared(&self) -> bool {
+		matches!(self.area_type, VmAreaType::Shared { .. })
+	}
+
+	/// Returns `true` if the area counts against `RLIMIT_DATA`, i.e. it's
+	/// private and writable.
+	fn is_data(&self) -> bool {
+		!self.is_shared() && self.prot.contains(PageProt::WRITE)
+	}
+
 	pub fn start(&self) -> UserVAddr {
 		self.start
 	}
@@ -56,7 +100,95 @@ impl VmArea {
 	}
 
 	pub fn overlaps(&self, other: UserVAddr, len: usize) -> bool {
-		other.value() >= self.start.value() && other.value() + len <= self.start.value() + self.len
+		other.value() < self.end().value() && self.start.value() < other.value() + len
+	}
+
+	/// Returns the part of the area from `delta` bytes on.
+	fn tail(&self, delta: usize) -> VmArea {
+		let area_type = match &self.area_type {
+			VmAreaType::Anonymous => VmAreaType::Anonymous,
+			VmAreaType::File {
+				file,
+				offset,
+				file_size,
+			} => VmAreaType::File {
+				file: file.clone(),
+				offset: offset + delta,
+				file_size: file_size.saturating_sub(delta),
+			},
+			VmAreaType::Shared { file, offset } => VmAreaType::Shared {
+				file: file.clone(),
+				offset: offset + delta,
+			},
+		};
+
+		VmArea {
+			start: self.start.add(delta),
+			len: self.len - delta,
+			area_type,
+			prot: self.prot,
+		}
+	}
+}
+
+/// The pages overlapping `[start, end)`.
+fn pages_in(start: usize, end: usize) -> impl Iterator<Item = UserVAddr> {
+	(align_down(start, PAGE_SIZE)..align_up(end, PAGE_SIZE))
+		.step_by(PAGE_SIZE)
+		.filter_map(|vaddr| UserVAddr::new_nonnull(vaddr).ok())
+}
+
+/// Unmaps the pages of `area` in `[start, end)` and frees the ones the
+/// process owns. Returns the number of pages unmapped.
+fn unmap_pages(page_table: &mut PageTable, area: &VmArea, start: usize, end: usize) -> usize {
+	let mut num_unmapped = 0;
+	for vaddr in pages_in(start, end) {
+		if let Some(paddr) = page_table.unmap_user_page(vaddr) {
+			// The pages of shared mappings belong to the file.
+			if !page_cache::release(paddr) && !area.is_shared() {
+				free_pages(paddr, 1);
+			}
+
+			num_unmapped += 1;
+		}
+	}
+
+	num_unmapped
+}
+
+/// The files of the shared mappings removed from a [`Vm`], which have to be
+/// written back. Writing back write-protects the pages in every process, so
+/// it's done once the `Vm` is unlocked.
+#[must_use]
+pub struct PendingWriteback(Vec<Arc<dyn File>>);
+
+impl PendingWriteback {
+	/// Writes back every file, even if some fail. Returns the first error.
+	pub fn run(self) -> Result<()> {
+		self.0.iter().fold(Ok(()), |result, file| {
+			result.and(page_cache::writeback(file))
+		})
+	}
+}
+
+/// Maps read-only the pages in `paddrs` wherever a shared mapping of the file
+/// `node_id` maps them, so that the next write faults. The caller must not
+/// hold any `Vm` lock.
+pub fn write_protect_shared_pages(node_id: NodeId, paddrs: &BTreeSet<PAddr>) {
+	for_each_vm(|vm| vm.update_shared_pages(node_id, paddrs, false));
+}
+
+/// Unmaps the pages in `paddrs` wherever a shared mapping of the file
+/// `node_id` maps them. The caller must not hold any `Vm` lock.
+pub fn unmap_shared_pages(node_id: NodeId, paddrs: &BTreeSet<PAddr>) {
+	for_each_vm(|vm| vm.update_shared_pages(node_id, paddrs, true));
+}
+
+fn for_each_vm(mut f: impl FnMut(&mut Vm)) {
+	for process in Process::all() {
+		if let Some(vm) = process.vm().as_ref() {
+			f(&mut vm.lock());
+		}
 	}
 }
 
@@ -65,23 +197,28 @@ pub struct Vm {
 	page_table: PageTable,
 	vm_areas: Vec<VmArea>,
 	valloc_next: UserVAddr,
+	/// The number of pages mapped, which the OOM killer compares.
+	resident_pages: usize,
 }
 
 impl Vm {
-	pub fn new(stack_bottom: UserVAddr, heap_bottom: UserVAddr) -> Result<Vm> {
+	pub fn new(stack_bottom: UserVAddr, stack_top: UserVAddr, heap_bottom: UserVAddr) -> Result<Vm> {
 		debug_assert!(is_aligned(stack_bottom.value(), PAGE_SIZE));
+		debug_assert!(is_aligned(stack_top.value(), PAGE_SIZE));
 		debug_assert!(is_aligned(heap_bottom.value(), PAGE_SIZE));
 
 		let stack_vma = VmArea {
 			start: stack_bottom,
-			len: USER_STACK_TOP.value() - stack_bottom.value(),
+			len: stack_top.value() - stack_bottom.value(),
 			area_type: VmAreaType::Anonymous,
+			prot: PageProt::READ | PageProt::WRITE,
 		};
 
 		let heap_vma = VmArea {
 			start: heap_bottom,
 			len: 0,
 			area_type: VmAreaType::Anonymous,
+			prot: PageProt::READ | PageProt::WRITE,
 		};
 
 		Ok(Vm {
@@ -89,7 +226,8 @@ impl Vm {
 			// The order of elements must be unchanged because `stack_vma_mut()`
 			// and `heap_vma_mut` depends on it.
 			vm_areas: vec![stack_vma, heap_vma],
-			valloc_next: USER_VALLOC_BASE,
+			valloc_next: USER_VALLOC_BASE.add(aslr::random_offset(USER_VALLOC_RANDOM_RANGE, PAGE_SIZE)),
+			resident_pages: 0,
 		})
 	}
 
@@ -105,10 +243,127 @@ impl Vm {
 		&self.vm_areas
 	}
 
+	pub fn resident_pages(&self) -> usize {
+		self.resident_pages
+	}
+
+	/// Maps a page of a private mapping, counting it in the resident set if
+	/// nothing was mapped there. Fails if a page table can't be allocated.
+	pub fn map_page(&mut self, vaddr: UserVAddr, paddr: PAddr, prot: PageProt) -> Result<()> {
+		let is_new = self.page_table.lookup_user_page(vaddr).is_none();
+		self.page_table.map_user_page(vaddr, paddr, prot)?;
+		if is_new {
+			self.resident_pages += 1;
+		}
+
+		Ok(())
+	}
+
+	/// Maps a page of a shared mapping. See [`Vm::map_page`].
+	pub fn map_shared_page(&mut self, vaddr: UserVAddr, paddr: PAddr, prot: PageProt) -> Result<()> {
+		let is_new = self.page_table.lookup_user_page(vaddr).is_none();
+		self.page_table.map_shared_user_page(vaddr, paddr, prot)?;
+		if is_new {
+			self.resident_pages += 1;
+		}
+
+		Ok(())
+	}
+
+	/// Write-protects or unmaps the pages in `paddrs` mapped by the shared
+	/// mappings of the file `node_id`.
+	fn update_shared_pages(&mut self, node_id: NodeId, paddrs: &BTreeSet<PAddr>, unmap: bool) {
+		if paddrs.is_empty() {
+			return;
+		}
+
+		for area in &self.vm_areas {
+			let VmAreaType::Shared { file, .. } = &area.area_type else {
+				continue;
+			};
+
+			if !file.stat().map_or(false, |stat| stat.node_id == node_id) {
+				continue;
+			}
+
+			for vaddr in pages_in(area.start.value(), area.end().value()) {
+				match self.page_table.lookup_user_page(vaddr) {
+					Some(paddr) if paddrs.contains(&paddr) => {
+						if unmap {
+							self.page_table.unmap_user_page(vaddr);
+							self.resident_pages -= 1;
+						} else {
+							self.page_table.write_protect_user_page(vaddr);
+						}
+					}
+					_ => {}
+				}
+			}
+		}
+	}
+
+	/// Fails with ENOMEM if `len` more bytes of mappings would make the
+	/// address space larger than `RLIMIT_AS`, or the private writable memory
+	/// larger than `RLIMIT_DATA` if `is_data` is set.
+	pub fn check_rlimits(&self, len: usize, is_data: bool, rlimits: &ResourceLimits) -> Result<()> {
+		self.do_check_rlimits(len, is_data, rlimits, 0..0)
+	}
+
+	/// Like [`Vm::check_rlimits`], for a mapping at `start` which replaces
+	/// what's mapped there (`MAP_FIXED`).
+	pub fn check_rlimits_replacing(
+		&self,
+		start: UserVAddr,
+		len: usize,
+		is_data: bool,
+		rlimits: &ResourceLimits,
+	) -> Result<()> {
+		let replaced = start.value()..start.value().saturating_add(len);
+		self.do_check_rlimits(len, is_data, rlimits, replaced)
+	}
+
+	fn do_check_rlimits(
+		&self,
+		len: usize,
+		is_data: bool,
+		rlimits: &ResourceLimits,
+		replaced: Range<usize>,
+	) -> Result<()> {
+		// What's in `replaced` is about to be unmapped.
+		let size = |area: &VmArea| {
+			let start = max(area.start.value(), replaced.start);
+			let end = min(area.end().value(), replaced.end);
+			area.len - end.saturating_sub(start)
+		};
+
+		let total_size: usize = self.vm_areas.iter().map(size).sum();
+		if total_size.saturating_add(len) > rlimits.cur(RLIMIT_AS) {
+			return Err(ErrorKind::OutOfMemory.into());
+		}
+
+		// The stack has its own limit.
+		let data_size: usize = self
+			.vm_areas
+			.iter()
+			.skip(1)
+			.filter(|area| area.is_data())
+			.map(size)
+			.sum();
+		if is_data && data_size.saturating_add(len) > rlimits.cur(RLIMIT_DATA) {
+			return Err(ErrorKind::OutOfMemory.into());
+		}
+
+		Ok(())
+	}
+
 	fn stack_vma(&self) -> &VmArea {
 		&self.vm_areas[0]
 	}
 
+	fn stack_vma_mut(&mut self) -> &mut VmArea {
+		&mut self.vm_areas[0]
+	}
+
 	fn heap_vma(&self) -> &VmArea {
 		&self.vm_areas[1]
 	}
@@ -122,6 +377,7 @@ impl Vm {
 		start: UserVAddr,
 		len: usize,
 		area_type: VmAreaType,
+		prot: PageProt,
 	) -> Result<()> {
 		start.access_ok(len).unwrap();
 
@@ -138,6 +394,7 @@ impl Vm {
 			start,
 			len,
 			area_type,
+			prot,
 		});
 
 		Ok(())
@@ -147,22 +404,29 @@ impl Vm {
 		self.heap_vma().end()
 	}
 
-	pub fn expand_heap_to(&mut self, new_heap_end: UserVAddr) -> Result<()> {
+	pub fn expand_heap_to(
+		&mut self,
+		new_heap_end: UserVAddr,
+		rlimits: &ResourceLimits,
+	) -> Result<()> {
 		let current_heap_end = self.heap_vma().end();
 		if new_heap_end < current_heap_end {
 			return Err(ErrorKind::Invalid.into());
 		}
 
-		self.expand_heap_by(new_heap_end.value() - current_heap_end.value())
+		self.expand_heap_by(new_heap_end.value() - current_heap_end.value(), rlimits)
 	}
 
-	pub fn expand_heap_by(&mut self, increment: usize) -> Result<()> {
+	pub fn expand_heap_by(&mut self, increment: usize, rlimits: &ResourceLimits) -> Result<()> {
 		let stack_bottom = self.stack_vma().start();
 		let increment = align_up(increment, PAGE_SIZE);
+		self.check_rlimits(increment, true, rlimits)?;
+
 		let heap_vma = self.heap_vma_mut();
 		let new_heap_top = heap_vma.end().add(increment);
 
-		if new_heap_top >= stack_bottom {
+		// Leave the guard page below the stack unmapped.
+		if new_heap_top.value() + PAGE_SIZE > stack_bottom.value() {
 			return Err(ErrorKind::OutOfMemory.into());
 		}
 
@@ -170,14 +434,147 @@ impl Vm {
 		Ok(())
 	}
 
+	/// Returns `true` if `vaddr` is in the gap right below the stack, i.e.
+	/// the stack would cover it if it grew down.
+	pub fn is_below_stack(&self, vaddr: UserVAddr) -> bool {
+		let stack_bottom = self.stack_vma().start();
+		vaddr < stack_bottom
+			&& self.vm_areas[1..]
+				.iter()
+				.all(|area| area.end() <= vaddr || area.start() >= stack_bottom)
+	}
+
+	/// Grows the stack down to cover `vaddr`. Fails if the stack would get
+	/// larger than `RLIMIT_STACK`, if the address space would get larger
+	/// than `RLIMIT_AS`, or if there would be no guard page left between the
+	/// stack and the area below it.
+	pub fn grow_stack(&mut self, vaddr: UserVAddr, rlimits: &ResourceLimits) -> Result<()> {
+		let stack_bottom = self.stack_vma().start();
+		let stack_top = self.stack_vma().end();
+		let new_bottom = align_down(vaddr.value(), PAGE_SIZE);
+		if stack_top.value() - new_bottom > rlimits.cur(RLIMIT_STACK) || new_bottom < PAGE_SIZE {
+			return Err(ErrorKind::OutOfMemory.into());
+		}
+
+		self.check_rlimits(stack_bottom.value() - new_bottom, false, rlimits)?;
+
+		let guard = new_bottom - PAGE_SIZE;
+		let collides = self.vm_areas[1..]
+			.iter()
+			.any(|area| area.start().value() < new_bottom && area.end().value() > guard);
+		if collides {
+			return Err(ErrorKind::OutOfMemory.into());
+		}
+
+		let stack_vma = self.stack_vma_mut();
+		stack_vma.len = stack_top.value() - new_bottom;
+		stack_vma.start = UserVAddr::new_nonnull(new_bottom)?;
+		Ok(())
+	}
+
+	/// Removes the mappings in `[start, start + len)`, splitting the areas it
+	/// partially covers. The stack and the heap can't be unmapped.
+	pub fn unmap(&mut self, start: UserVAddr, len: usize) -> Result<PendingWriteback> {
+		debug_assert!(is_aligned(start.value(), PAGE_SIZE));
+		debug_assert!(is_aligned(len, PAGE_SIZE));
+
+		if self.vm_areas[..2]
+			.iter()
+			.any(|area| area.overlaps(start, len))
+		{
+			return Err(ErrorKind::EINVAL.into());
+		}
+
+		let end = start.value() + len;
+		let mut files = Vec::new();
+		let mut i = 2;
+		while i < self.vm_areas.len() {
+			if !self.vm_areas[i].overlaps(start, len) {
+				i += 1;
+				continue;
+			}
+
+			// The parts left are pushed at the end: they don't overlap the
+			// range so the loop skips them.
+			let area = self.vm_areas.swap_remove(i);
+			let unmap_start = max(area.start.value(), start.value());
+			let unmap_end = min(area.end().value(), end);
+			if area.start.value() < unmap_start {
+				self.vm_areas.push(VmArea {
+					len: unmap_start - area.start.value(),
+					..area.clone()
+				});
+			}
+
+			if unmap_end < area.end().value() {
+				self
+					.vm_areas
+					.push(area.tail(unmap_end - area.start.value()));
+			}
+
+			self.resident_pages -= unmap_pages(&mut self.page_table, &area, unmap_start, unmap_end);
+			if let VmAreaType::Shared { file, .. } = area.area_type {
+				files.push(file);
+			}
+		}
+
+		Ok(PendingWriteback(files))
+	}
+
+	/// Returns the files of the shared mappings in `[start, start + len)`,
+	/// which msync(2) writes back.
+	pub fn shared_files(&self, start: UserVAddr, len: usize) -> Vec<Arc<dyn File>> {
+		self
+			.vm_areas
+			.iter()
+			.filter(|area| area.overlaps(start, len))
+			.filter_map(|area| match &area.area_type {
+				VmAreaType::Shared { file, .. } => Some(file.clone()),
+				_ => None,
+			})
+			.collect()
+	}
+
 	pub fn fork(&self) -> Result<Vm> {
+		let page_table = PageTable::duplicate_from(&self.page_table)?;
+
+		// The pages of shared mappings are now mapped by the child as well.
+		for area in self.vm_areas.iter().filter(|area| area.is_shared()) {
+			for vaddr in pages_in(area.start.value(), area.end().value()) {
+				if let Some(paddr) = page_table.lookup_user_page(vaddr) {
+					page_cache::retain(paddr);
+				}
+			}
+		}
+
 		Ok(Vm {
-			page_table: PageTable::duplicate_from(&self.page_table)?,
+			page_table,
 			vm_areas: self.vm_areas.clone(),
 			valloc_next: self.valloc_next,
+			resident_pages: self.resident_pages,
 		})
 	}
 
+	/// Unmaps all the areas. Pages shared with the page cache are only
+	/// released, others are owned by us. The page table itself is kept: it
+	/// might be the one in use.
+	pub fn unmap_all(&mut self) -> PendingWriteback {
+		let mut files = Vec::new();
+		for area in self.vm_areas.drain(..) {
+			self.resident_pages -= unmap_pages(
+				&mut self.page_table,
+				&area,
+				area.start.value(),
+				area.end().value(),
+			);
+			if let VmAreaType::Shared { file, .. } = area.area_type {
+				files.push(file);
+			}
+		}
+
+		PendingWriteback(files)
+	}
+
 	pub fn is_free_vaddr_range(&mut self, start: UserVAddr, len: usize) -> bool {
 		self.vm_areas.iter().all(|area| !area.overlaps(start, len))
 	}
@@ -192,3 +589,11 @@ impl Vm {
 		Ok(next)
 	}
 }
+
+impl Drop for Vm {
+	fn drop(&mut self) {
+		if let Err(err) = self.unmap_all().run() {
+			debug_warn!("failed to write back a shared mapping: {:?}", err);
+		}
+	}
+}
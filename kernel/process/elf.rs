@@ -3,7 +3,7 @@ use core::{mem::size_of, slice::from_raw_parts};
 use api::{ErrorKind, Result};
 use environment::address::UserVAddr;
 use goblin::elf64::{
-	header::{Header, ELFMAG, EM_X86_64, ET_EXEC},
+	header::{Header, ELFMAG, EM_X86_64, ET_DYN, ET_EXEC},
 	program_header::ProgramHeader,
 };
 
@@ -30,7 +30,7 @@ impl<'a> Elf<'a> {
 			return Err(ErrorKind::NotExecutable.into());
 		}
 
-		if header.e_type != ET_EXEC {
+		if header.e_type != ET_EXEC && header.e_type != ET_DYN {
 			debug_warn!("ELF is not executable");
 			return Err(ErrorKind::NotExecutable.into());
 		}
@@ -53,6 +53,12 @@ impl<'a> Elf<'a> {
 		UserVAddr::new_nonnull(self.header.e_entry as usize).map_err(Into::into)
 	}
 
+	/// Whether the executable is position independent, i.e. can be loaded
+	/// anywhere.
+	pub fn is_pie(&self) -> bool {
+		self.header.e_type == ET_DYN
+	}
+
 	pub fn header(&self) -> &Header {
 		self.header
 	}
@@ -35,6 +35,8 @@ pub mod elf;
 pub mod init_stack;
 pub mod process;
 pub mod process_group;
+pub mod rlimit;
 pub mod scheduler;
+pub mod signal;
 pub mod switch;
 pub mod wait_queue;
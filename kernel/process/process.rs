@@ -12,7 +12,7 @@ use core::{
 
 use atomic_refcell::{AtomicRef, AtomicRefCell};
 use crossbeam::atomic::AtomicCell;
-use goblin::elf64::program_header::{ProgramHeader, PT_LOAD};
+use goblin::elf64::program_header::{ProgramHeader, PF_R, PF_W, PF_X, PT_INTERP, PT_LOAD};
 
 use api::{
   cmdline::Cmdline,
@@ -29,21 +29,29 @@ use api::{
 };
 use environment::{
   address::{UserVAddr, VAddr},
-  arch::{PtRegs, PAGE_SIZE},
+  arch::{PageProt, PtRegs, PAGE_SIZE},
   page_allocator::{alloc_pages, AllocPageFlags},
   spinlock::{SpinLock, SpinLockGuard},
 };
 use utils::alignment::align_up;
 
 use crate::{
-  arch::{self, KERNEL_STACK_SIZE, USER_STACK_TOP},
-  fs::devfs::SERIAL_TTY,
-  mm::vm::{Vm, VmAreaType},
+  arch::{
+    self, USER_HEAP_RANDOM_RANGE, USER_PIE_BASE, USER_PIE_RANDOM_RANGE,
+    USER_STACK_RANDOM_RANGE, USER_STACK_TOP,
+  },
+  fs::{devfs::SERIAL_TTY, lock},
+  mm::{
+    aslr,
+    vm::{Vm, VmAreaType},
+  },
   process::{
     current_process,
     elf::Elf,
     init_stack::{estimate_user_init_stack_size, init_user_stack, Auxv},
     process_group::ProcessGroup,
+    rlimit::{ResourceLimits, RLIMIT_STACK},
+    signal::Signal,
     switch, JOIN_WAIT_QUEUE, SCHEDULER,
   },
   random::read_secure_random,
@@ -91,13 +99,16 @@ pub struct Process {
   children: SpinLock<Vec<Arc<Process>>>,
   rootfs: Arc<SpinLock<Rootfs>>,
   opened_files: Arc<SpinLock<OpenedFileTable>>,
+  rlimits: SpinLock<ResourceLimits>,
+  /// The signal the process has been killed by, or 0.
+  pending_kill: AtomicI32,
 }
 
 impl Process {
   pub fn new_idle_thread() -> Result<Arc<Self>> {
     let process_group = ProcessGroup::new(PgId::new(0));
     let proc = Arc::new(Self {
-      arch: arch::Process::new_idle_thread(),
+      arch: arch::Process::new_idle_thread()?,
       is_idle: true,
       process_group: AtomicRefCell::new(Arc::downgrade(&process_group)),
       pid: Pid::new(0),
@@ -108,6 +119,8 @@ impl Process {
       vm: AtomicRefCell::new(None),
       rootfs: INITIAL_ROOT_FS.clone(),
       opened_files: Arc::new(SpinLock::new(OpenedFileTable::new())),
+      rlimits: SpinLock::new(ResourceLimits::new()),
+      pending_kill: AtomicI32::new(0),
     });
 
     process_group.lock().add(Arc::downgrade(&proc));
@@ -115,16 +128,13 @@ impl Process {
   }
 
   pub fn new_kernel_thread(f: fn() -> !) -> Result<()> {
-    let stack_bottom = alloc_pages(KERNEL_STACK_SIZE / PAGE_SIZE, AllocPageFlags::KERNEL)?;
-    let kernel_sp = stack_bottom.as_vaddr().add(KERNEL_STACK_SIZE);
-
     let ip = VAddr::new(f as *const u8 as usize);
     let pid = alloc_pid(&mut PROCESSES.lock())?;
 
     let process_group = ProcessGroup::new(PgId::new(0));
     let proc = Arc::new(Self {
       is_idle: false,
-      arch: arch::Process::new_kthread(ip, kernel_sp),
+      arch: arch::Process::new_kthread(ip)?,
       process_group: AtomicRefCell::new(Arc::downgrade(&process_group)),
       pid,
       state: AtomicCell::new(ProcessState::Runnable),
@@ -134,6 +144,8 @@ impl Process {
       vm: AtomicRefCell::new(None),
       rootfs: INITIAL_ROOT_FS.clone(),
       opened_files: Arc::new(SpinLock::new(OpenedFileTable::new())),
+      rlimits: SpinLock::new(ResourceLimits::new()),
+      pending_kill: AtomicI32::new(0),
     });
 
     process_group.lock().add(Arc::downgrade(&proc));
@@ -177,13 +189,20 @@ impl Process {
       OpenOptions::empty(),
     )?;
 
-    let entry = setup_userspace(executable_path, argv, &[], &rootfs)?;
+    let rlimits = ResourceLimits::new();
+    let entry = setup_userspace(
+      executable_path,
+      argv,
+      &[],
+      &rootfs,
+      rlimits.cur(RLIMIT_STACK),
+    )?;
     let pid = Pid::new(1);
     let process_group = ProcessGroup::new(PgId::new(1));
 
     let proc = Arc::new(Self {
       is_idle: false,
-      arch: arch::Process::new_user_thread(entry.ip, entry.user_sp),
+      arch: arch::Process::new_user_thread(entry.ip, entry.user_sp)?,
       process_group: AtomicRefCell::new(Arc::downgrade(&process_group)),
       pid,
       state: AtomicCell::new(ProcessState::Runnable),
@@ -193,6 +212,8 @@ impl Process {
       vm: AtomicRefCell::new(Some(Arc::new(SpinLock::new(entry.vm)))),
       rootfs,
       opened_files: Arc::new(SpinLock::new(opened_files)),
+      rlimits: SpinLock::new(rlimits),
+      pending_kill: AtomicI32::new(0),
     });
 
     process_group.lock().add(Arc::downgrade(&proc));
@@ -203,6 +224,16 @@ impl Process {
     Ok(())
   }
 
+  /// Looks for a process which hasn't exited yet.
+  pub fn find_by_pid(pid: Pid) -> Option<Arc<Process>> {
+    PROCESSES.lock().get(&pid).cloned()
+  }
+
+  /// Returns the processes which haven't exited yet.
+  pub fn all() -> Vec<Arc<Process>> {
+    PROCESSES.lock().values().cloned().collect()
+  }
+
   pub fn pid(&self) -> Pid {
     self.pid
   }
@@ -231,6 +262,10 @@ impl Process {
     &self.opened_files
   }
 
+  pub fn rlimits(&self) -> SpinLockGuard<'_, ResourceLimits> {
+    self.rlimits.lock()
+  }
+
   /// The virtual memory space. It's `None` if the process is a kernel thread.
   pub fn vm(&self) -> AtomicRef<'_, Option<Arc<SpinLock<Vm>>>> {
     self.vm.borrow()
@@ -252,12 +287,45 @@ impl Process {
   }
 
   fn exit(status: c_int) -> ! {
+    Process::exit_with_wait_status((status & 0xff) << 8)
+  }
+
+  /// Terminates the current process as if it was killed by `signal`.
+  pub fn exit_by_signal(signal: Signal) -> ! {
+    Process::exit_with_wait_status(signal & 0x7f)
+  }
+
+  /// Kills the process with `signal`. Since signals can't be delivered
+  /// yet, it exits the next time it makes a system call or takes a page
+  /// fault, and it's woken up if it's sleeping.
+  pub fn kill(&self, signal: Signal) {
+    if matches!(self.state(), ProcessState::Exited(_)) {
+      return;
+    }
+
+    self.pending_kill.store(signal, Ordering::SeqCst);
+    self._resume();
+  }
+
+  pub fn is_killed(&self) -> bool {
+    self.pending_kill.load(Ordering::SeqCst) != 0
+  }
+
+  /// Terminates the current process if it has been killed.
+  pub fn exit_if_killed() {
+    let signal = current_process().pending_kill.load(Ordering::SeqCst);
+    if signal != 0 {
+      Process::exit_by_signal(signal);
+    }
+  }
+
+  fn exit_with_wait_status(wait_status: c_int) -> ! {
     let current = current_process();
     if current.pid == Pid::new(1) {
       panic!("init (pid=0) tried to exit")
     }
 
-    api::Process::set_state(ProcessState::Exited(status));
+    api::Process::set_state(ProcessState::Exited(wait_status));
     // if let Some(parent) = current.parent.upgrade() {
     // if parent.signals().lock().get_action(SIGCHLD) == SigAction::Ignore
     // {
@@ -278,6 +346,16 @@ impl Process {
     // not dropped until it's joined by the parent process. Drop them to
     // make pipes closed.
     current.opened_files.lock().close_all();
+    lock::release_on_exit(current.pid);
+
+    // Give the memory back now instead of when the parent joins us: we
+    // might have been killed to make room.
+    if let Some(vm) = current.vm().as_ref() {
+      let writeback = vm.lock().unmap_all();
+      if let Err(err) = writeback.run() {
+        debug_warn!("failed to write back a shared mapping: {:?}", err);
+      }
+    }
 
     PROCESSES.lock().remove(&current.pid);
     JOIN_WAIT_QUEUE.wake_all();
@@ -286,7 +364,7 @@ impl Process {
   }
 
   fn _has_pending_signals(&self) -> bool {
-    false
+    self.is_killed()
   }
 
   pub fn _resume(&self) {
@@ -311,12 +389,19 @@ impl Process {
     current.opened_files().lock().close_cloexec_files();
     current.cmdline.borrow_mut().set_by_argv(argv);
 
-    let entry = setup_userspace(executable_path, argv, envp, &current.rootfs)?;
+    let stack_limit = current.rlimits().cur(RLIMIT_STACK);
+    let entry = setup_userspace(executable_path, argv, envp, &current.rootfs, stack_limit)?;
 
     // TODO: Signal?
 
     entry.vm.page_table().switch();
-    *current.vm.borrow_mut() = Some(Arc::new(SpinLock::new(entry.vm)));
+    // Dropping the old Vm writes back its shared mappings, which looks at
+    // the Vm of every process: don't drop it while ours is borrowed.
+    let old_vm = current
+      .vm
+      .borrow_mut()
+      .replace(Arc::new(SpinLock::new(entry.vm)));
+    drop(old_vm);
 
     current
       .arch
@@ -346,6 +431,8 @@ impl Process {
       vm: AtomicRefCell::new(Some(Arc::new(SpinLock::new(vm)))),
       opened_files: Arc::new(SpinLock::new(opened_files)),
       rootfs: parent.rootfs().clone(),
+      rlimits: SpinLock::new(parent.rlimits().clone()),
+      pending_kill: AtomicI32::new(0),
       arch,
       // TODO: Signals
     });
@@ -428,8 +515,9 @@ fn setup_userspace(
   argv: &[&[u8]],
   envp: &[&[u8]],
   root_fs: &Arc<SpinLock<Rootfs>>,
+  stack_limit: usize,
 ) -> Result<UserspaceEntry> {
-  do_setup_userspace(executable_path, argv, envp, root_fs, true)
+  do_setup_userspace(executable_path, argv, envp, root_fs, stack_limit, true)
 }
 
 fn do_setup_userspace(
@@ -437,11 +525,13 @@ fn do_setup_userspace(
   argv: &[&[u8]],
   envp: &[&[u8]],
   root_fs: &Arc<SpinLock<Rootfs>>,
+  stack_limit: usize,
   _handle_shebang: bool,
 ) -> Result<UserspaceEntry> {
   // Read the ELF header in the executable file.
+  let stack_top = USER_STACK_TOP.sub(aslr::random_offset(USER_STACK_RANDOM_RANGE, PAGE_SIZE));
   let file_header_len = PAGE_SIZE;
-  let file_header_top = USER_STACK_TOP;
+  let file_header_top = stack_top;
   let file_header_pages = alloc_pages(file_header_len / PAGE_SIZE, AllocPageFlags::KERNEL)?;
   let buf =
     unsafe { core::slice::from_raw_parts_mut(file_header_pages.as_mut_ptr(), file_header_len) };
@@ -450,36 +540,79 @@ fn do_setup_userspace(
   executable.read(0, buf.into(), &OpenOptions::readwrite())?;
 
   let elf = Elf::parse(buf)?;
-  let ip = elf.entry()?;
+
+  // We can't load the dynamic linker a dynamically linked PIE asks for.
+  if elf.is_pie()
+    && elf
+      .program_headers()
+      .iter()
+      .any(|phdr| phdr.p_type == PT_INTERP)
+  {
+    debug_warn!("ELF interpreters are not supported");
+    return Err(ErrorKind::NotExecutable.into());
+  }
+
+  // A PIE can be loaded anywhere as long as its segments stay aligned.
+  let load_base = if elf.is_pie() {
+    let align = elf
+      .program_headers()
+      .iter()
+      .filter(|phdr| phdr.p_type == PT_LOAD)
+      .try_fold(PAGE_SIZE, |align, phdr| match phdr.p_align {
+        // Both mean that the segment needs no alignment.
+        0 | 1 => Ok(align),
+        p_align if p_align.is_power_of_two() => Ok(max(align, p_align as usize)),
+        _ => Err(ErrorKind::NotExecutable),
+      })?;
+    USER_PIE_BASE.value() + aslr::random_offset(USER_PIE_RANDOM_RANGE, align)
+  } else {
+    0
+  };
+
+  let ip = elf.entry()?.add(load_base);
 
   let mut end_of_image = 0;
   for phdr in elf.program_headers() {
     if phdr.p_type == PT_LOAD {
-      end_of_image = max(end_of_image, (phdr.p_vaddr + phdr.p_memsz) as usize);
+      end_of_image = max(
+        end_of_image,
+        load_base + (phdr.p_vaddr + phdr.p_memsz) as usize,
+      );
     }
   }
 
+  // Point AT_PHDR at the program headers in the image if they're loaded: the
+  // libc finds out the load base of a PIE from it.
+  let phoff = elf.header().e_phoff;
+  let phdr_addr = elf
+    .program_headers()
+    .iter()
+    .find(|phdr| {
+      phdr.p_type == PT_LOAD && phdr.p_offset <= phoff && phoff < phdr.p_offset + phdr.p_filesz
+    })
+    .map(|phdr| UserVAddr::new_nonnull(load_base + (phdr.p_vaddr + phoff - phdr.p_offset) as usize))
+    .transpose()?
+    .unwrap_or_else(|| file_header_top.sub(file_header_len).add(phoff as usize));
+
   let mut random_bytes = [0u8; 16];
   read_secure_random(((&mut random_bytes) as &mut [u8]).into())?;
 
   // Set up the user stack.
   let auxv = &[
-    Auxv::Phdr(
-      file_header_top
-        .sub(file_header_len)
-        .add(elf.header().e_phoff as usize),
-    ),
+    Auxv::Phdr(phdr_addr),
     Auxv::Phnum(elf.program_headers().len()),
     Auxv::Phent(size_of::<ProgramHeader>()),
     Auxv::Pagesz(PAGE_SIZE),
     Auxv::Random(random_bytes),
   ];
-  const USER_STACK_LEN: usize = 1024 * 1024 * 16; // TODO: Implement rlimit
+  // The stack starts with the pages filled here and grows down on page
+  // faults up to `stack_limit`.
   let init_stack_top = file_header_top.sub(file_header_len);
-  let user_stack_bottom = init_stack_top.sub(USER_STACK_LEN).value();
-  let user_heap_bottom = align_up(end_of_image, PAGE_SIZE);
   let init_stack_len = align_up(estimate_user_init_stack_size(argv, envp, auxv), PAGE_SIZE);
-  if user_heap_bottom >= user_stack_bottom || init_stack_len >= USER_STACK_LEN {
+  let user_stack_bottom = init_stack_top.sub(init_stack_len).value();
+  let user_heap_bottom =
+    align_up(end_of_image, PAGE_SIZE) + aslr::random_offset(USER_HEAP_RANDOM_RANGE, PAGE_SIZE);
+  if user_heap_bottom >= user_stack_bottom || init_stack_len + file_header_len > stack_limit {
     return Err(ErrorKind::TooBig.into());
   }
 
@@ -495,20 +628,23 @@ fn do_setup_userspace(
 
   let mut vm = Vm::new(
     UserVAddr::new(user_stack_bottom).unwrap(),
+    stack_top,
     UserVAddr::new(user_heap_bottom).unwrap(),
   )?;
   for i in 0..(file_header_len / PAGE_SIZE) {
-    vm.page_table_mut().map_user_page(
+    vm.map_page(
       file_header_top.sub(((file_header_len / PAGE_SIZE) - i) * PAGE_SIZE),
       file_header_pages.add(i * PAGE_SIZE),
-    );
+      PageProt::READ,
+    )?;
   }
 
   for i in 0..(init_stack_len / PAGE_SIZE) {
-    vm.page_table_mut().map_user_page(
+    vm.map_page(
       init_stack_top.sub(((init_stack_len / PAGE_SIZE) - i) * PAGE_SIZE),
       init_stack_pages.add(i * PAGE_SIZE),
-    );
+      PageProt::READ | PageProt::WRITE,
+    )?;
   }
 
   // Register program headers in the virtual memory space.
@@ -517,6 +653,12 @@ fn do_setup_userspace(
       continue;
     }
 
+    let prot = segment_prot(phdr.p_flags);
+    if prot.contains(PageProt::WRITE | PageProt::EXEC) {
+      debug_warn!("refusing to load a writable and executable segment");
+      return Err(ErrorKind::NotExecutable.into());
+    }
+
     let area_type = if phdr.p_filesz > 0 {
       VmAreaType::File {
         file: executable.clone(),
@@ -528,11 +670,30 @@ fn do_setup_userspace(
     };
 
     vm.add_vm_area(
-      UserVAddr::new_nonnull(phdr.p_vaddr as usize)?,
+      UserVAddr::new_nonnull(load_base + phdr.p_vaddr as usize)?,
       phdr.p_memsz as usize,
       area_type,
+      prot,
     )?;
   }
 
   Ok(UserspaceEntry { vm, ip, user_sp })
 }
+
+/// Converts the `PF_*` flags of an ELF segment.
+fn segment_prot(p_flags: u32) -> PageProt {
+  let mut prot = PageProt::empty();
+  if p_flags & PF_R != 0 {
+    prot |= PageProt::READ;
+  }
+
+  if p_flags & PF_W != 0 {
+    prot |= PageProt::WRITE;
+  }
+
+  if p_flags & PF_X != 0 {
+    prot |= PageProt::EXEC;
+  }
+
+  prot
+}
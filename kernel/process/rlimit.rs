@@ -0,0 +1,81 @@
+use api::{ctypes::c_int, ErrorKind, Result};
+
+pub type Resource = c_int;
+
+/// The private writable memory: the heap and the mappings but the stack.
+pub const RLIMIT_DATA: Resource = 2;
+pub const RLIMIT_STACK: Resource = 3;
+/// The size of the address space.
+pub const RLIMIT_AS: Resource = 9;
+/// The number of resources, as on Linux.
+const RLIM_NLIMITS: usize = 16;
+
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// The default soft limit of the stack size, as on Linux.
+const DEFAULT_STACK_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// A `struct rlimit`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RLimit {
+  pub cur: u64,
+  pub max: u64,
+}
+
+impl RLimit {
+  const fn unlimited() -> RLimit {
+    RLimit {
+      cur: RLIM_INFINITY,
+      max: RLIM_INFINITY,
+    }
+  }
+}
+
+/// The resource limits of a process. Children inherit them, and they're
+/// kept across execve(2).
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+  limits: [RLimit; RLIM_NLIMITS],
+}
+
+impl ResourceLimits {
+  pub fn new() -> ResourceLimits {
+    let mut limits = [RLimit::unlimited(); RLIM_NLIMITS];
+    limits[RLIMIT_STACK as usize].cur = DEFAULT_STACK_LIMIT;
+    ResourceLimits { limits }
+  }
+
+  pub fn get(&self, resource: Resource) -> Result<RLimit> {
+    usize::try_from(resource)
+      .ok()
+      .and_then(|index| self.limits.get(index))
+      .copied()
+      .ok_or_else(|| ErrorKind::EINVAL.into())
+  }
+
+  pub fn set(&mut self, resource: Resource, limit: RLimit) -> Result<()> {
+    if limit.cur > limit.max {
+      return Err(ErrorKind::EINVAL.into());
+    }
+
+    // There are no users to keep from raising the hard limits.
+    let slot = usize::try_from(resource)
+      .ok()
+      .and_then(|index| self.limits.get_mut(index))
+      .ok_or(ErrorKind::EINVAL)?;
+    *slot = limit;
+    Ok(())
+  }
+
+  /// The soft limit of `resource`, in bytes for the ones which are sizes.
+  pub fn cur(&self, resource: Resource) -> usize {
+    self.limits[resource as usize].cur as usize
+  }
+}
+
+impl Default for ResourceLimits {
+  fn default() -> ResourceLimits {
+    ResourceLimits::new()
+  }
+}
@@ -0,0 +1,7 @@
+use api::ctypes::c_int;
+
+pub type Signal = c_int;
+
+pub const SIGBUS: Signal = 7;
+pub const SIGKILL: Signal = 9;
+pub const SIGSEGV: Signal = 11;
@@ -1,23 +1,176 @@
+//! The kernel's random number generator: a ChaCha20-based CSPRNG seeded from
+//! RDRAND and from the timing of interrupts.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use environment::spinlock::SpinLock;
+use utils::chacha20::{self, words_from_bytes};
+use x86::cpuid::CpuId;
+
+/// How many bytes the CRNG hands out before reseeding itself.
+const RESEED_INTERVAL: usize = 1024 * 1024;
+/// How many interrupt timings the pool collects before it's worth reseeding
+/// the CRNG from it.
+const RESEED_SAMPLES: usize = 64;
+
+static HAS_RDRAND: AtomicBool = AtomicBool::new(false);
+static POOL: SpinLock<EntropyPool> = SpinLock::new(EntropyPool::new());
+static CRNG: SpinLock<Crng> = SpinLock::new(Crng::new());
+
+/// The CRNG changes its key after each use, so the nonce can stay the same.
+const NONCE: [u32; 3] = [0; 3];
+
+fn rdtsc() -> u64 {
+	unsafe { x86::time::rdtsc() }
+}
+
+fn rdrand_key() -> Option<[u32; 8]> {
+	if !HAS_RDRAND.load(Ordering::Relaxed) {
+		return None;
+	}
+
+	let mut bytes = [0u8; 32];
+	if unsafe { x86::random::rdrand_slice(&mut bytes) } {
+		Some(words_from_bytes(&bytes))
+	} else {
+		warn_once!("RDRAND returned invalid data");
+		None
+	}
+}
+
+/// Where entropy is collected until the CRNG is reseeded.
+struct EntropyPool {
+	words: [u32; 8],
+	next: usize,
+	/// The number of interrupt timings mixed in since the last extraction.
+	samples: usize,
+}
+
+impl EntropyPool {
+	const fn new() -> Self {
+		Self {
+			words: [0; 8],
+			next: 0,
+			samples: 0,
+		}
+	}
+
+	fn mix(&mut self, value: u64) {
+		for word in [value as u32, (value >> 32) as u32] {
+			let i = self.next % 8;
+			self.words[i] =
+				(self.words[i].rotate_left(7) ^ word).wrapping_mul(0x9e37_79b1) ^ self.words[(i + 3) % 8];
+			self.next = self.next.wrapping_add(1);
+		}
+	}
+
+	/// Compresses what has been collected into a key. The pool is replaced
+	/// with the other half of the block so that the key can't be recovered
+	/// from it.
+	fn extract(&mut self) -> [u32; 8] {
+		let block = chacha20::block(&self.words, self.next as u32, &NONCE);
+		self.words = words_from_bytes(&block[32..]);
+		self.samples = 0;
+		words_from_bytes(&block[..32])
+	}
+}
+
+struct Crng {
+	key: [u32; 8],
+	/// The number of bytes generated since the last reseed.
+	generated: usize,
+}
+
+impl Crng {
+	const fn new() -> Self {
+		Self {
+			key: [0; 8],
+			generated: 0,
+		}
+	}
+
+	fn reseed(&mut self, seed: &[u32; 8]) {
+		for (word, seed) in self.key.iter_mut().zip(seed) {
+			*word ^= *seed;
+		}
+
+		self.generated = 0;
+	}
+
+	fn fill(&mut self, buf: &mut [u8]) {
+		let next = chacha20::keystream(&self.key, 0, &NONCE, buf);
+
+		// Fast key erasure: move on to a new key so that what has just been
+		// generated can't be reconstructed from the state afterwards.
+		let block = chacha20::block(&self.key, next, &NONCE);
+		self.key = words_from_bytes(&block[..32]);
+		self.generated += buf.len();
+	}
+}
+
+fn reseed(crng: &mut Crng) {
+	let mut seed = POOL.lock().extract();
+	if let Some(key) = rdrand_key() {
+		for (word, key) in seed.iter_mut().zip(key) {
+			*word ^= key;
+		}
+	}
+
+	crng.reseed(&seed);
+}
+
+/// Seeds the CRNG. Until then, it produces predictable output.
+pub fn init() {
+	let has_rdrand = CpuId::new()
+		.get_feature_info()
+		.map_or(false, |info| info.has_rdrand());
+	HAS_RDRAND.store(has_rdrand, Ordering::Relaxed);
+	if !has_rdrand {
+		warn!("RDRAND is not available, random numbers rely on interrupt timings only");
+	}
+
+	POOL.lock().mix(rdtsc());
+	reseed(&mut CRNG.lock());
+}
+
+/// Mixes the time an interrupt arrived at into the entropy pool.
+pub fn add_interrupt_timing(irq: u8) {
+	let mut pool = POOL.lock();
+	pool.mix(rdtsc() ^ ((irq as u64) << 56));
+	pool.samples += 1;
+}
+
+/// Mixes `data` into the entropy pool without crediting it as entropy.
+pub fn add_entropy(data: &[u8]) {
+	let mut pool = POOL.lock();
+	for chunk in data.chunks(8) {
+		let mut bytes = [0u8; 8];
+		bytes[..chunk.len()].copy_from_slice(chunk);
+		pool.mix(u64::from_le_bytes(bytes));
+	}
+}
+
+pub fn fill_random(buf: &mut [u8]) {
+	let mut crng = CRNG.lock();
+	if crng.generated >= RESEED_INTERVAL || POOL.lock().samples >= RESEED_SAMPLES {
+		reseed(&mut crng);
+	}
+
+	crng.fill(buf);
+}
+
 pub fn read_secure_random(
 	buf: api::user_buffer::UserBufferMut<'_>,
 ) -> api::Result<usize> {
-	// TODO: Implement arch-agnostic CRNG which does not fully depends on
-	// RDRAND.
-
 	api::user_buffer::UserBufWriter::from(buf).write_with(|slice| {
-		let valid = unsafe { x86::random::rdrand_slice(slice) };
-		if valid {
-			Ok(slice.len())
-		} else {
-			warn_once!("RDRAND returned invalid data");
-			Ok(0)
-		}
+		fill_random(slice);
+		Ok(slice.len())
 	})
 }
 
 pub fn read_insecure_random(
 	buf: api::user_buffer::UserBufferMut<'_>,
 ) -> api::Result<usize> {
-	// TODO:
+	// The CRNG is cheap enough to serve both.
 	read_secure_random(buf)
 }
@@ -1,24 +1,73 @@
-use alloc::boxed::Box;
-use api::driver::block::BlockDriver;
+use core::ops::Range;
+
+use alloc::{boxed::Box, vec::Vec};
+use api::{driver::block::BlockDriver, sync::SpinLock};
 use atomic_refcell::AtomicRefCell;
 use owo_colors::OwoColorize;
 
-static BLOCK_DRIVER: AtomicRefCell<Option<Box<dyn BlockDriver>>> =
-	AtomicRefCell::new(None);
+use super::block_cache::{BlockCache, BlockCacheStats, BlockDeviceId, BLOCK_CACHE_CAPACITY};
+
+/// The device the system partitions are looked up on.
+pub const BOOT_DEVICE: BlockDeviceId = 0;
+
+static BLOCK_DRIVERS: AtomicRefCell<Vec<Box<dyn BlockDriver>>> = AtomicRefCell::new(Vec::new());
+static BLOCK_CACHE: SpinLock<BlockCache> = SpinLock::new(BlockCache::new(BLOCK_CACHE_CAPACITY));
 
-pub fn register_block_driver(driver: Box<dyn BlockDriver>) {
-	assert!(
-		BLOCK_DRIVER.borrow().is_none(),
-		"multiple block drivers are not supported"
-	);
+pub fn register_block_driver(driver: Box<dyn BlockDriver>) -> BlockDeviceId {
+	let mut drivers = BLOCK_DRIVERS.borrow_mut();
 	info!("registered driver {}", driver.name().green());
-	*BLOCK_DRIVER.borrow_mut() = Some(driver)
+	drivers.push(driver);
+	drivers.len() - 1
+}
+
+pub fn has_block_driver() -> bool {
+	!BLOCK_DRIVERS.borrow().is_empty()
 }
 
 pub fn with_block_driver<F, R>(f: F) -> R
 where
 	F: FnOnce(&Box<dyn BlockDriver>) -> R,
 {
-	let driver = BLOCK_DRIVER.borrow();
-	f(driver.as_ref().expect("no block driver"))
+	let drivers = BLOCK_DRIVERS.borrow();
+	f(drivers.get(BOOT_DEVICE).expect("no block driver"))
+}
+
+/// Reads `sectors` from `device` through the block cache.
+pub fn read_sectors(device: BlockDeviceId, sectors: Range<usize>, buf: &mut [u8]) {
+	let drivers = BLOCK_DRIVERS.borrow();
+	BLOCK_CACHE
+		.lock()
+		.read_sectors(&drivers, device, sectors, buf)
+}
+
+/// Writes `sectors` to `device` through the block cache. The data reaches the
+/// device when the sectors are evicted or [`sync`] is called.
+pub fn write_sectors(device: BlockDeviceId, sectors: Range<usize>, buf: &[u8]) {
+	let drivers = BLOCK_DRIVERS.borrow();
+	BLOCK_CACHE
+		.lock()
+		.write_sectors(&drivers, device, sectors, buf)
+}
+
+/// Writes back every dirty sector of `device`.
+pub fn sync(device: BlockDeviceId) {
+	let drivers = BLOCK_DRIVERS.borrow();
+	BLOCK_CACHE.lock().sync(&drivers, device)
+}
+
+/// Writes back every dirty sector of every device.
+pub fn sync_all() {
+	let drivers = BLOCK_DRIVERS.borrow();
+	let mut cache = BLOCK_CACHE.lock();
+	for device in 0..drivers.len() {
+		cache.sync(&drivers, device);
+	}
+}
+
+pub fn invalidate(device: BlockDeviceId) {
+	BLOCK_CACHE.lock().invalidate(device)
+}
+
+pub fn read_block_cache_stats() -> BlockCacheStats {
+	BLOCK_CACHE.lock().stats()
 }
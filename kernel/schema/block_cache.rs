@@ -0,0 +1,210 @@
+//! A buffer cache for block devices.
+//!
+//! Every sector read or written through a
+//! [`Partition`](api::schema::fs::Partition) goes through this cache, so that
+//! filesystems don't need to keep their own copies of hot metadata blocks
+//! around. Entries are keyed by the device and the sector number on that device
+//! and are evicted in LRU order. Dirty entries are written back when evicted or
+//! when the device is synced.
+use core::ops::Range;
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use api::driver::block::BlockDriver;
+
+/// The number of sectors kept in the cache before the least recently used
+/// ones start being evicted.
+pub const BLOCK_CACHE_CAPACITY: usize = 8192;
+
+/// Index of a block device in the block driver registry.
+pub type BlockDeviceId = usize;
+
+type BlockKey = (BlockDeviceId, usize);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockCacheStats {
+	pub hits: usize,
+	pub misses: usize,
+	pub evictions: usize,
+	pub writebacks: usize,
+	pub cached_blocks: usize,
+	pub dirty_blocks: usize,
+}
+
+struct CachedBlock {
+	data: Vec<u8>,
+	dirty: bool,
+	last_used: u64,
+}
+
+pub struct BlockCache {
+	blocks: BTreeMap<BlockKey, CachedBlock>,
+	/// Keys of `blocks` ordered by the time they were last used.
+	lru: BTreeMap<u64, BlockKey>,
+	clock: u64,
+	capacity: usize,
+	stats: BlockCacheStats,
+}
+
+impl BlockCache {
+	pub const fn new(capacity: usize) -> Self {
+		Self {
+			blocks: BTreeMap::new(),
+			lru: BTreeMap::new(),
+			clock: 0,
+			capacity,
+			stats: BlockCacheStats {
+				hits: 0,
+				misses: 0,
+				evictions: 0,
+				writebacks: 0,
+				cached_blocks: 0,
+				dirty_blocks: 0,
+			},
+		}
+	}
+
+	pub fn stats(&self) -> BlockCacheStats {
+		BlockCacheStats {
+			cached_blocks: self.blocks.len(),
+			dirty_blocks: self.blocks.values().filter(|block| block.dirty).count(),
+			..self.stats
+		}
+	}
+
+	pub fn read_sectors(
+		&mut self,
+		drivers: &[Box<dyn BlockDriver>],
+		device: BlockDeviceId,
+		sectors: Range<usize>,
+		buf: &mut [u8],
+	) {
+		let sector_size = drivers[device].sector_size();
+		assert!(buf.len() >= sectors.clone().count() * sector_size);
+
+		for (i, sector) in sectors.enumerate() {
+			let block = self.get_or_fetch(drivers, (device, sector));
+			buf[i * sector_size..(i + 1) * sector_size].copy_from_slice(&block.data);
+		}
+	}
+
+	pub fn write_sectors(
+		&mut self,
+		drivers: &[Box<dyn BlockDriver>],
+		device: BlockDeviceId,
+		sectors: Range<usize>,
+		buf: &[u8],
+	) {
+		let sector_size = drivers[device].sector_size();
+		assert!(buf.len() >= sectors.clone().count() * sector_size);
+
+		for (i, sector) in sectors.enumerate() {
+			let key = (device, sector);
+			let src = &buf[i * sector_size..(i + 1) * sector_size];
+			let last_used = self.tick();
+			match self.blocks.get_mut(&key) {
+				Some(block) => {
+					self.stats.hits += 1;
+					self.lru.remove(&block.last_used);
+					block.last_used = last_used;
+					block.data.copy_from_slice(src);
+					block.dirty = true;
+				}
+				None => {
+					// The whole sector is overwritten, there's no need to
+					// read it from the device first.
+					self.stats.misses += 1;
+					self.make_room(drivers);
+					self.blocks.insert(
+						key,
+						CachedBlock {
+							data: src.to_vec(),
+							dirty: true,
+							last_used,
+						},
+					);
+				}
+			}
+			self.lru.insert(last_used, key);
+		}
+	}
+
+	/// Writes all the dirty sectors of `device` back to it.
+	pub fn sync(&mut self, drivers: &[Box<dyn BlockDriver>], device: BlockDeviceId) {
+		let driver = &drivers[device];
+		for (&(_, sector), block) in self
+			.blocks
+			.range_mut((device, 0)..=(device, usize::MAX))
+			.filter(|(_, block)| block.dirty)
+		{
+			driver.write_sector(sector, &block.data);
+			block.dirty = false;
+			self.stats.writebacks += 1;
+		}
+
+		driver.flush();
+	}
+
+	/// Drops every clean sector of `device` from the cache.
+	pub fn invalidate(&mut self, device: BlockDeviceId) {
+		let victims = self
+			.blocks
+			.range((device, 0)..=(device, usize::MAX))
+			.filter(|(_, block)| !block.dirty)
+			.map(|(key, block)| (*key, block.last_used))
+			.collect::<Vec<_>>();
+
+		for (key, last_used) in victims {
+			self.blocks.remove(&key);
+			self.lru.remove(&last_used);
+		}
+	}
+
+	fn tick(&mut self) -> u64 {
+		self.clock += 1;
+		self.clock
+	}
+
+	fn get_or_fetch(&mut self, drivers: &[Box<dyn BlockDriver>], key: BlockKey) -> &CachedBlock {
+		let last_used = self.tick();
+		if let Some(block) = self.blocks.get_mut(&key) {
+			self.stats.hits += 1;
+			self.lru.remove(&block.last_used);
+			block.last_used = last_used;
+		} else {
+			self.stats.misses += 1;
+			self.make_room(drivers);
+
+			let driver = &drivers[key.0];
+			let mut data = vec![0u8; driver.sector_size()];
+			driver.read_sector(key.1, &mut data);
+			self.blocks.insert(
+				key,
+				CachedBlock {
+					data,
+					dirty: false,
+					last_used,
+				},
+			);
+		}
+
+		self.lru.insert(last_used, key);
+		&self.blocks[&key]
+	}
+
+	/// Evicts the least recently used sector if the cache is full, writing it
+	/// back first if it's dirty.
+	fn make_room(&mut self, drivers: &[Box<dyn BlockDriver>]) {
+		if self.blocks.len() < self.capacity {
+			return;
+		}
+
+		if let Some((_, key)) = self.lru.pop_first() {
+			let block = self.blocks.remove(&key).unwrap();
+			if block.dirty {
+				drivers[key.0].write_sector(key.1, &block.data);
+				self.stats.writebacks += 1;
+			}
+			self.stats.evictions += 1;
+		}
+	}
+}
@@ -1,2 +1,3 @@
 pub mod block;
+pub mod block_cache;
 pub mod system;
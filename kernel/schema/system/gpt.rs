@@ -1,10 +1,15 @@
+use core::ops::Range;
+
 use alloc::{fmt, string::String, sync::Arc, vec::Vec};
 use api::{guid::Guid, schema::fs::Partition, sync::SpinLock};
 use atomic_refcell::AtomicRefCell;
 use spin::Mutex;
 use utils::{alignment::align_up, bytes_parser::BytesParser, once::Once};
 
-use crate::schema::block::with_block_driver;
+use crate::schema::{
+	block::{self, with_block_driver, BOOT_DEVICE},
+	block_cache::BlockDeviceId,
+};
 
 pub struct GptHeader {
 	signature: [u8; 8],
@@ -79,6 +84,7 @@ pub struct PartitionEntry {
 	end_lba: u64,
 	attributes: u64,
 	name: String,
+	device: BlockDeviceId,
 	block_size: AtomicRefCell<Option<usize>>,
 }
 
@@ -124,6 +130,7 @@ impl PartitionEntry {
 			end_lba,
 			attributes,
 			name,
+			device: BOOT_DEVICE,
 			block_size: AtomicRefCell::new(None),
 		}))
 	}
@@ -131,11 +138,25 @@ impl PartitionEntry {
 
 impl Partition for PartitionEntry {
 	fn read_sector(&self, sector: usize, buf: &mut [u8]) {
-		with_block_driver(|block| {
-			assert!(buf.len() >= block.sector_size());
-			assert!((self.start_lba + sector as u64) < self.end_lba);
-			block.read_sector(self.start_lba as usize + sector, buf)
-		})
+		self.read_sectors(sector..sector + 1, buf)
+	}
+
+	fn read_sectors(&self, sectors: Range<usize>, buf: &mut [u8]) {
+		let sectors = self.to_device_sectors(sectors);
+		block::read_sectors(self.device, sectors, buf)
+	}
+
+	fn write_sector(&self, sector: usize, buf: &[u8]) {
+		self.write_sectors(sector..sector + 1, buf)
+	}
+
+	fn write_sectors(&self, sectors: Range<usize>, buf: &[u8]) {
+		let sectors = self.to_device_sectors(sectors);
+		block::write_sectors(self.device, sectors, buf)
+	}
+
+	fn sync(&self) {
+		block::sync(self.device)
 	}
 
 	fn in_sectors(&self, size: usize) -> usize {
@@ -156,6 +177,18 @@ impl Partition for PartitionEntry {
 	}
 }
 
+impl PartitionEntry {
+	/// Translates a sector range relative to the partition into one relative
+	/// to the start of the device.
+	fn to_device_sectors(&self, sectors: Range<usize>) -> Range<usize> {
+		let start = self.start_lba as usize + sectors.start;
+		let end = self.start_lba as usize + sectors.end;
+		// `end_lba` is inclusive.
+		assert!(end <= self.end_lba as usize + 1);
+		start..end
+	}
+}
+
 #[derive(Debug)]
 pub enum GptError {
 	HeaderSignatureNotFound,
@@ -177,19 +210,19 @@ impl Gpt {
 	}
 
 	pub fn parse_partition_table(&mut self) -> Result<(), GptError> {
-		with_block_driver(|block| {
+		with_block_driver(|driver| {
 			let partition_table_size = align_up(
 				(self.header.partition_entry_bytes
 					* self.header.partition_count) as usize,
-				block.sector_size(),
+				driver.sector_size(),
 			);
 			let partition_table_sectors =
-				partition_table_size / block.sector_size();
+				partition_table_size / driver.sector_size();
 
 			let mut buf = vec![0u8; partition_table_size];
 			let start = self.header.partition_entry as usize;
 			let end = start + partition_table_sectors;
-			block.read_sectors(start..end, &mut buf);
+			block::read_sectors(BOOT_DEVICE, start..end, &mut buf);
 
 			let mut bytes = BytesParser::new(&mut buf);
 
@@ -222,12 +255,12 @@ pub fn partitions<'a>() -> Vec<Arc<SpinLock<dyn Partition>>> {
 }
 
 pub fn init() {
-	let header = with_block_driver(|block| {
-		let mut header_bytes = vec![0u8; 512];
-		block.read_sector(1, &mut header_bytes);
+	let header = {
+		let mut header_bytes = vec![0u8; with_block_driver(|block| block.sector_size())];
+		block::read_sectors(BOOT_DEVICE, 1..2, &mut header_bytes);
 
 		GptHeader::parse_bytes(&mut header_bytes)
-	});
+	};
 
 	if let Ok(header) = header {
 		GPT.init(|| {
@@ -11,7 +11,7 @@ impl<'a> SyscallHandler<'a> {
 		let vm_ref = current.vm();
 		let mut vm = vm_ref.as_ref().unwrap().lock();
 		if let Some(new_heap_end) = new_heap_end {
-			vm.expand_heap_to(new_heap_end)?;
+			vm.expand_heap_to(new_heap_end, &current.rlimits())?;
 		}
 		trace!("brk {:x}", vm.heap_end().value());
 		Ok(vm.heap_end().value() as isize)
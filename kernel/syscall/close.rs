@@ -1,10 +1,26 @@
 use api::{vfs::Fd, Process};
 
+use crate::fs::lock;
+
 use super::SyscallHandler;
 
 impl<'a> SyscallHandler<'a> {
   pub fn sys_close(&mut self, fd: Fd) -> api::Result<isize> {
-    Process::opened_files().lock().close(fd)?;
+    let opened_files = Process::opened_files();
+    let mut opened_files = opened_files.lock();
+    let node_id = opened_files
+      .get(fd)?
+      .node()
+      .stat()
+      .ok()
+      .map(|stat| stat.node_id);
+    opened_files.close(fd)?;
+    drop(opened_files);
+
+    if let Some(node_id) = node_id {
+      lock::release_on_close(node_id, Process::pid());
+    }
+
     Ok(0)
   }
 }
@@ -1,7 +1,7 @@
 use core::mem::size_of;
 
 use alloc::vec::Vec;
-use api::{schema::unix::Path, user_buffer::UserCStr, Result};
+use api::{schema::unix::Path, user_buffer::UserCStr, ErrorKind, Result};
 use environment::address::UserVAddr;
 
 use crate::process::{current_process, Process};
@@ -23,6 +23,9 @@ impl<'a> SyscallHandler<'a> {
     let current = current_process();
     trace!("Execute process: {}", path);
     let executable = current.rootfs().lock().lookup_path(path, true)?;
+    if executable.mount.is_noexec() {
+      return Err(ErrorKind::PermissionDenied.into());
+    }
 
     let mut argv = Vec::new();
     for i in 0..ARG_MAX {
@@ -1,14 +1,44 @@
-use api::{ctypes::c_int, io::OpenFlags, vfs::Fd, Error, ErrorKind, Result};
+use api::{
+  ctypes::{c_int, c_short},
+  io::OpenFlags,
+  vfs::{opened_file::OpenedFile, Fd},
+  Error, ErrorKind, Process, Result,
+};
+use environment::address::UserVAddr;
 
-use crate::process::current_process;
+use crate::{
+  fs::lock::{self, LockKind, RecordLock},
+  process::current_process,
+};
 
-use super::SyscallHandler;
+use super::{
+  lseek::{SEEK_CUR, SEEK_END, SEEK_SET},
+  SyscallHandler,
+};
 
 const _F_DUPFD: c_int = 0;
 const _F_GETFD: c_int = 1;
 const F_SETFD: c_int = 2;
 const _F_GETFL: c_int = 3;
 const F_SETFL: c_int = 4;
+const F_GETLK: c_int = 5;
+const F_SETLK: c_int = 6;
+const F_SETLKW: c_int = 7;
+
+const F_RDLCK: c_short = 0;
+const F_WRLCK: c_short = 1;
+const F_UNLCK: c_short = 2;
+
+/// A `struct flock`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Flock {
+  l_type: c_short,
+  l_whence: c_short,
+  l_start: i64,
+  l_len: i64,
+  l_pid: c_int,
+}
 
 impl<'a> SyscallHandler<'a> {
   pub fn sys_fcntl(&mut self, fd: Fd, cmd: c_int, arg: usize) -> Result<isize> {
@@ -26,7 +56,108 @@ impl<'a> SyscallHandler<'a> {
           .set_flags(OpenFlags::from_bits_truncate(arg as i32))?;
         Ok(0)
       }
+      F_GETLK | F_SETLK | F_SETLKW => {
+        let opened_file = opened_files.get(fd)?.clone();
+        // Don't keep the table locked while waiting for the lock.
+        drop(opened_files);
+        self.fcntl_lock(&opened_file, cmd, UserVAddr::new_nonnull(arg)?)
+      }
       _ => Err(ErrorKind::ENOSYS.into()),
     }
   }
+
+  fn fcntl_lock(
+    &mut self,
+    opened_file: &OpenedFile,
+    cmd: c_int,
+    uaddr: UserVAddr,
+  ) -> Result<isize> {
+    let mut flock = uaddr.read::<Flock>()?;
+    let node_id = opened_file.node().stat()?.node_id;
+
+    let base = match flock.l_whence as isize {
+      SEEK_SET => 0,
+      SEEK_CUR => opened_file.pos() as i128,
+      SEEK_END => opened_file.size()? as i128,
+      _ => return Err(ErrorKind::EINVAL.into()),
+    };
+
+    // A negative length locks the bytes before the start, a zero one
+    // everything from the start on, however far the file grows.
+    let start = base + flock.l_start as i128;
+    let (start, end) = match flock.l_len as i128 {
+      0 => (start, None),
+      len if len > 0 => (start, Some(start + len)),
+      len => (start + len, Some(start)),
+    };
+    let start = usize::try_from(start).map_err(|_| ErrorKind::EINVAL)?;
+    let end = match end {
+      Some(end) => usize::try_from(end).map_err(|_| ErrorKind::EINVAL)?,
+      None => usize::MAX,
+    };
+
+    let kind = match flock.l_type {
+      F_RDLCK => Some(LockKind::Shared),
+      F_WRLCK => Some(LockKind::Exclusive),
+      F_UNLCK => None,
+      _ => return Err(ErrorKind::EINVAL.into()),
+    };
+
+    let pid = Process::pid();
+    match (cmd, kind) {
+      (F_GETLK, None) => Err(ErrorKind::EINVAL.into()),
+      (F_GETLK, Some(kind)) => {
+        let lock = RecordLock {
+          pid,
+          kind,
+          start,
+          end,
+        };
+
+        match lock::conflicting_record(node_id, &lock) {
+          Some(other) => {
+            flock.l_type = match other.kind {
+              LockKind::Shared => F_RDLCK,
+              LockKind::Exclusive => F_WRLCK,
+            };
+            flock.l_whence = SEEK_SET as c_short;
+            flock.l_start = other.start as i64;
+            flock.l_len = match other.end {
+              usize::MAX => 0,
+              end => (end - other.start) as i64,
+            };
+            flock.l_pid = other.pid.as_i32();
+          }
+          None => flock.l_type = F_UNLCK,
+        }
+
+        uaddr.write(&flock)?;
+        Ok(0)
+      }
+      (_, None) => {
+        lock::unlock_records(node_id, pid, start, end);
+        Ok(0)
+      }
+      (_, Some(kind)) => {
+        let options = opened_file.options();
+        let allowed = match kind {
+          LockKind::Shared => options.read,
+          LockKind::Exclusive => options.write,
+        };
+        if !allowed {
+          return Err(ErrorKind::EBADF.into());
+        }
+
+        let lock = RecordLock {
+          pid,
+          kind,
+          start,
+          end,
+        };
+
+        lock::lock_records(node_id, lock, cmd == F_SETLK)?;
+        Ok(0)
+      }
+    }
+  }
 }
@@ -0,0 +1,42 @@
+use api::{bitflags::bitflags, ctypes::c_int, vfs::Fd, Error, ErrorKind, Result};
+
+use crate::{
+  fs::lock::{self, LockKind},
+  process::current_process,
+};
+
+use super::SyscallHandler;
+
+bitflags! {
+    pub struct FlockOperation: c_int {
+        const LOCK_SH = 1;
+        const LOCK_EX = 2;
+        const LOCK_NB = 4;
+        const LOCK_UN = 8;
+    }
+}
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_flock(&mut self, fd: Fd, operation: FlockOperation) -> Result<isize> {
+    // Don't keep the table locked while waiting for the lock.
+    let opened_file = current_process().opened_files().lock().get(fd)?.clone();
+    let node_id = opened_file.node().stat()?.node_id;
+
+    let kind =
+      operation & (FlockOperation::LOCK_SH | FlockOperation::LOCK_EX | FlockOperation::LOCK_UN);
+    let nonblock = operation.contains(FlockOperation::LOCK_NB);
+    match kind {
+      FlockOperation::LOCK_SH => lock::flock(&opened_file, node_id, LockKind::Shared, nonblock)?,
+      FlockOperation::LOCK_EX => lock::flock(&opened_file, node_id, LockKind::Exclusive, nonblock)?,
+      FlockOperation::LOCK_UN => lock::funlock(&opened_file, node_id),
+      _ => {
+        return Err(Error::with_message(
+          ErrorKind::EINVAL,
+          "exactly one of LOCK_SH, LOCK_EX and LOCK_UN is required",
+        ))
+      }
+    }
+
+    Ok(0)
+  }
+}
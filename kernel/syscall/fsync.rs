@@ -0,0 +1,28 @@
+use api::{
+  vfs::{Fd, Node},
+  Result,
+};
+
+use crate::{mm::page_cache, process::current_process};
+
+use super::SyscallHandler;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_fsync(&mut self, fd: Fd) -> Result<isize> {
+    let file_table = current_process().opened_files().lock();
+    let opened_file = file_table.get(fd)?;
+    // Include what has been written through shared mappings.
+    if let Node::File(file) = opened_file.node() {
+      page_cache::writeback(file)?;
+    }
+
+    opened_file.fsync()?;
+    Ok(0)
+  }
+
+  /// Metadata is written back along with the data anyway, so this is the
+  /// same as fsync.
+  pub fn sys_fdatasync(&mut self, fd: Fd) -> Result<isize> {
+    self.sys_fsync(fd)
+  }
+}
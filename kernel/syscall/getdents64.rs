@@ -32,7 +32,7 @@ impl<'a> SyscallHandler<'a> {
       }
 
       // d_ino
-      writer.write::<u64>(entry.node_id.as_u64())?;
+      writer.write::<u64>(entry.node_id.ino() as u64)?;
       // d_off
       writer.write::<u64>(dir.pos() as u64)?;
       // d_reclen
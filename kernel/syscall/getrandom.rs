@@ -0,0 +1,36 @@
+use api::{bitflags::bitflags, ctypes::c_uint, user_buffer::UserBufferMut, Error, ErrorKind};
+use environment::address::UserVAddr;
+
+use crate::random::read_secure_random;
+
+use super::SyscallHandler;
+
+bitflags! {
+    pub struct GetRandomFlags: c_uint {
+        const GRND_NONBLOCK = 1;
+        const GRND_RANDOM   = 2;
+        const GRND_INSECURE = 4;
+    }
+}
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_getrandom(
+    &mut self,
+    buf: UserVAddr,
+    len: usize,
+    flags: GetRandomFlags,
+  ) -> api::Result<isize> {
+    if flags.contains(GetRandomFlags::GRND_RANDOM | GetRandomFlags::GRND_INSECURE) {
+      return Err(Error::with_message(
+        ErrorKind::EINVAL,
+        "GRND_RANDOM and GRND_INSECURE are exclusive",
+      ));
+    }
+
+    // The CRNG is seeded before any process runs, so none of the flags can
+    // make a difference.
+    let len = core::cmp::min(len, super::MAX_READ_WRITE_LEN);
+    let read_len = read_secure_random(UserBufferMut::from_uaddr(buf, len))?;
+    Ok(read_len as isize)
+  }
+}
@@ -0,0 +1,57 @@
+use alloc::{format, sync::Arc};
+use api::{
+  bitflags::bitflags,
+  ctypes::c_uint,
+  io::OpenFlags,
+  schema::unix::Path,
+  user_buffer::UserCStr,
+  vfs::{interface::PathComponent, Node},
+  Result,
+};
+use environment::address::UserVAddr;
+
+use crate::{mm::shm::SharedMemory, process::current_process};
+
+use super::SyscallHandler;
+
+/// Where devfs lists the shared memory objects.
+const SHM_DIR: &str = "/Devices/SharedMemory";
+/// The longest name, as on Linux.
+const MFD_NAME_MAX: usize = 249;
+
+bitflags! {
+    pub struct MemfdFlags: c_uint {
+        const MFD_CLOEXEC = 1;
+    }
+}
+
+impl<'a> SyscallHandler<'a> {
+  /// Creates a shared memory object which only exists as long as a file
+  /// descriptor or a mapping refers to it, like an unlinked file of the
+  /// shared memory directory.
+  pub fn sys_memfd_create(&mut self, name: UserVAddr, flags: MemfdFlags) -> Result<isize> {
+    let name = UserCStr::new(name, MFD_NAME_MAX + 1)?;
+    let current = current_process();
+    let shm_dir = current
+      .rootfs()
+      .lock()
+      .lookup_path(Path::new(SHM_DIR), true)?;
+    let path = Arc::new(PathComponent {
+      parent_dir: Some(shm_dir.clone()),
+      name: format!("memfd:{}", name.as_str()),
+      node: Node::File(Arc::new(SharedMemory::new(0))),
+      mount: shm_dir.mount.clone(),
+    });
+
+    let mut open_flags = OpenFlags::O_RDWR;
+    if flags.contains(MemfdFlags::MFD_CLOEXEC) {
+      open_flags |= OpenFlags::O_CLOEXEC;
+    }
+
+    let fd = current
+      .opened_files()
+      .lock()
+      .open(path, open_flags.into())?;
+    Ok(fd.as_usize() as isize)
+  }
+}
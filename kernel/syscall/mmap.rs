@@ -0,0 +1,140 @@
+use core::cmp::min;
+
+use alloc::sync::Arc;
+use api::{arch::PAGE_SIZE, bitflags::bitflags, ctypes::c_int, vfs::Fd, ErrorKind, Result};
+use environment::{address::UserVAddr, arch::PageProt};
+use utils::alignment::{align_up, is_aligned};
+
+use crate::{
+  mm::{shm::SharedMemory, vm::VmAreaType},
+  process::current_process,
+};
+
+use super::SyscallHandler;
+
+bitflags! {
+    pub struct MmapFlags: c_int {
+        const MAP_SHARED    = 0x01;
+        const MAP_PRIVATE   = 0x02;
+        const MAP_FIXED     = 0x10;
+        const MAP_ANONYMOUS = 0x20;
+    }
+}
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_mmap(
+    &mut self,
+    addr: Option<UserVAddr>,
+    len: usize,
+    prot: PageProt,
+    flags: MmapFlags,
+    fd: Fd,
+    offset: usize,
+  ) -> Result<isize> {
+    if len == 0 || !is_aligned(offset, PAGE_SIZE) {
+      return Err(ErrorKind::EINVAL.into());
+    }
+
+    let shared = flags.contains(MmapFlags::MAP_SHARED);
+    if shared == flags.contains(MmapFlags::MAP_PRIVATE) {
+      return Err(ErrorKind::EINVAL.into());
+    }
+
+    if prot.contains(PageProt::WRITE | PageProt::EXEC) {
+      debug_warn!("mmap: refusing a writable and executable mapping");
+      return Err(ErrorKind::PermissionDenied.into());
+    }
+
+    let len = align_up(len, PAGE_SIZE);
+    let area_type = if flags.contains(MmapFlags::MAP_ANONYMOUS) {
+      if shared {
+        // Shared with the children, which only the memory of a file
+        // can be.
+        VmAreaType::Shared {
+          file: Arc::new(SharedMemory::new(len)),
+          offset: 0,
+        }
+      } else {
+        VmAreaType::Anonymous
+      }
+    } else {
+      let opened_files = current_process().opened_files().lock();
+      let opened_file = opened_files.get(fd)?;
+      let options = opened_file.options();
+      let writes_back = shared && prot.contains(PageProt::WRITE);
+      if !options.read || (writes_back && !options.write) {
+        return Err(ErrorKind::PermissionDenied.into());
+      }
+
+      if writes_back && opened_file.path().mount.is_read_only() {
+        return Err(ErrorKind::EROFS.into());
+      }
+
+      let file = opened_file.as_file()?.clone();
+      if writes_back && !file.is_writable() {
+        return Err(ErrorKind::PermissionDenied.into());
+      }
+
+      if shared {
+        VmAreaType::Shared { file, offset }
+      } else {
+        let file_size = min(len, file.stat()?.size.saturating_sub(offset));
+        VmAreaType::File {
+          file,
+          offset,
+          file_size,
+        }
+      }
+    };
+
+    let current = current_process();
+    let vm_ref = current.vm();
+    let mut vm = vm_ref.as_ref().unwrap().lock();
+    // A MAP_FIXED mapping replaces what's mapped there: check the limits
+    // before unmapping it.
+    let is_data = !shared && prot.contains(PageProt::WRITE);
+    match addr {
+      Some(addr) if flags.contains(MmapFlags::MAP_FIXED) => {
+        vm.check_rlimits_replacing(addr, len, is_data, &current.rlimits())?
+      }
+      _ => vm.check_rlimits(len, is_data, &current.rlimits())?,
+    }
+
+    let (start, writeback) = match addr {
+      Some(addr) if flags.contains(MmapFlags::MAP_FIXED) => {
+        if !is_aligned(addr.value(), PAGE_SIZE) {
+          return Err(ErrorKind::EINVAL.into());
+        }
+
+        addr.access_ok(len)?;
+        (addr, Some(vm.unmap(addr, len)?))
+      }
+      _ if flags.contains(MmapFlags::MAP_FIXED) => return Err(ErrorKind::EINVAL.into()),
+      // The address is only a hint: ignore it.
+      _ => (vm.alloc_vaddr_range(len)?, None),
+    };
+
+    vm.add_vm_area(start, len, area_type, prot)?;
+    drop(vm);
+    if let Some(writeback) = writeback {
+      writeback.run()?;
+    }
+
+    Ok(start.value() as isize)
+  }
+
+  pub fn sys_munmap(&mut self, addr: UserVAddr, len: usize) -> Result<isize> {
+    if len == 0 || !is_aligned(addr.value(), PAGE_SIZE) {
+      return Err(ErrorKind::EINVAL.into());
+    }
+
+    let len = align_up(len, PAGE_SIZE);
+    addr.access_ok(len)?;
+
+    let current = current_process();
+    let vm_ref = current.vm();
+    let writeback = vm_ref.as_ref().unwrap().lock().unmap(addr, len)?;
+    writeback.run()?;
+    Ok(0)
+  }
+}
@@ -1,5 +1,8 @@
+use core::{cmp::min, mem::size_of};
+
+use alloc::vec::Vec;
 use api::{
-  ctypes::{c_clockid, c_int, c_size},
+  ctypes::{c_clockid, c_int, c_size, c_uint},
   io::OpenFlags,
   kernel::KernelOps,
   process::Pid,
@@ -8,14 +11,23 @@ use api::{
     unix::{Path, PathBuf},
   },
   user_buffer::UserCStr,
-  vfs::Fd,
+  vfs::{
+    mount::{MountFlags, UmountFlags},
+    Fd,
+  },
   Error, ErrorKind, ProcessOps, Result,
 };
-use environment::{address::UserVAddr, arch::PtRegs};
+use environment::{
+  address::UserVAddr,
+  arch::{PageProt, PtRegs},
+};
 
-use crate::process::current_process;
+use crate::process::{current_process, Process};
 
-use self::wait4::WaitOptions;
+use self::{
+  flock::FlockOperation, getrandom::GetRandomFlags, memfd_create::MemfdFlags, mmap::MmapFlags,
+  msync::MsyncFlags, wait4::WaitOptions,
+};
 
 const SYS_WRITE: usize = 1;
 const SYS_READ: usize = 2;
@@ -30,18 +42,76 @@ const SYS_FCNTL: usize = 10;
 const SYS_LSEEK: usize = 11;
 const SYS_CLOCK_GETTIME: usize = 12;
 const SYS_CLOCK_NANOSLEEP: usize = 13;
+const SYS_MOUNT: usize = 14;
+const SYS_UMOUNT2: usize = 15;
+const SYS_GETRANDOM: usize = 16;
+const SYS_PREAD64: usize = 17;
+const SYS_PWRITE64: usize = 18;
+const SYS_READV: usize = 19;
+const SYS_WRITEV: usize = 20;
+const SYS_PREADV: usize = 21;
+const SYS_PWRITEV: usize = 22;
+const SYS_SENDFILE: usize = 23;
+const SYS_TRUNCATE: usize = 24;
+const SYS_FTRUNCATE: usize = 25;
+const SYS_FSYNC: usize = 26;
+const SYS_FDATASYNC: usize = 27;
+const SYS_FLOCK: usize = 28;
+const SYS_PRLIMIT64: usize = 29;
+const SYS_MMAP: usize = 30;
+const SYS_MUNMAP: usize = 31;
+const SYS_MSYNC: usize = 32;
+const SYS_MEMFD_CREATE: usize = 33;
+const SYS_UNLINK: usize = 34;
 const SYS_WAIT4: usize = 126;
 const SYS_FORK: usize = 127;
 const SYS_BRK: usize = 128;
 const SYS_EXIT: usize = -1isize as usize;
 
 pub(self) const MAX_READ_WRITE_LEN: usize = core::isize::MAX as usize;
+/// The most buffers `readv` and friends take at once.
+const IOV_MAX: c_int = 1024;
 
 fn resolve_path(uaddr: usize) -> Result<PathBuf> {
   const PATH_MAX: usize = 512;
   Ok(Path::new(UserCStr::new(UserVAddr::new_nonnull(uaddr)?, PATH_MAX)?.as_str()).to_path_buf())
 }
 
+/// A `struct iovec`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct IoVec {
+  base: usize,
+  len: usize,
+}
+
+impl IoVec {
+  fn base(&self) -> Result<UserVAddr> {
+    UserVAddr::new_nonnull(self.base)
+  }
+}
+
+/// Reads the buffers passed to `readv` and friends, leaving out the empty
+/// ones and trimming them to `MAX_READ_WRITE_LEN` in total.
+fn read_iovecs(uaddr: UserVAddr, count: c_int) -> Result<Vec<IoVec>> {
+  if !(0..=IOV_MAX).contains(&count) {
+    return Err(ErrorKind::EINVAL.into());
+  }
+
+  let mut iovecs = Vec::with_capacity(count as usize);
+  let mut total_len = 0;
+  for i in 0..count as usize {
+    let mut iovec = uaddr.add(i * size_of::<IoVec>()).read::<IoVec>()?;
+    iovec.len = min(iovec.len, MAX_READ_WRITE_LEN - total_len);
+    if iovec.len > 0 {
+      total_len += iovec.len;
+      iovecs.push(iovec);
+    }
+  }
+
+  Ok(iovecs)
+}
+
 pub struct SyscallHandler<'a> {
   pub frame: &'a mut PtRegs,
 }
@@ -66,6 +136,10 @@ impl<'a> SyscallHandler<'a> {
       err
     });
 
+    // Don't go back to the user if we've been killed in the meantime, e.g.
+    // by the OOM killer.
+    Process::exit_if_killed();
+
     // TODO:
     // if let Err(err) = Process::try_delivering_signal(self.frame) {
     // debug_warn!("failed to setup the signal stack: {:?}", err);
@@ -113,6 +187,21 @@ impl<'a> SyscallHandler<'a> {
         UserVAddr::new_nonnull(a3)?,
         UserVAddr::new(a4),
       ),
+      SYS_MOUNT => self.sys_mount(
+        &resolve_path(a1)?,
+        &resolve_path(a2)?,
+        UserVAddr::new(a3),
+        bitflags_from_user!(MountFlags, a4 as u64)?,
+      ),
+      SYS_UMOUNT2 => self.sys_umount2(
+        &resolve_path(a1)?,
+        bitflags_from_user!(UmountFlags, a2 as c_int)?,
+      ),
+      SYS_GETRANDOM => self.sys_getrandom(
+        UserVAddr::new_nonnull(a1)?,
+        a2,
+        bitflags_from_user!(GetRandomFlags, a3 as c_uint)?,
+      ),
       SYS_WAIT4 => self.sys_wait4(
         Pid::new(a1 as i32),
         UserVAddr::new(a2),
@@ -120,6 +209,71 @@ impl<'a> SyscallHandler<'a> {
         UserVAddr::new(a4),
       ),
       SYS_FORK => self.sys_fork(),
+      SYS_PREAD64 => self.sys_pread64(
+        Fd::new(a1 as i32),
+        UserVAddr::new_nonnull(a2)?,
+        a3,
+        a4 as isize,
+      ),
+      SYS_PWRITE64 => self.sys_pwrite64(
+        Fd::new(a1 as i32),
+        UserVAddr::new_nonnull(a2)?,
+        a3,
+        a4 as isize,
+      ),
+      SYS_READV => self.sys_readv(Fd::new(a1 as i32), UserVAddr::new_nonnull(a2)?, a3 as c_int),
+      SYS_WRITEV => self.sys_writev(Fd::new(a1 as i32), UserVAddr::new_nonnull(a2)?, a3 as c_int),
+      SYS_PREADV => self.sys_preadv(
+        Fd::new(a1 as i32),
+        UserVAddr::new_nonnull(a2)?,
+        a3 as c_int,
+        a4 as isize,
+      ),
+      SYS_PWRITEV => self.sys_pwritev(
+        Fd::new(a1 as i32),
+        UserVAddr::new_nonnull(a2)?,
+        a3 as c_int,
+        a4 as isize,
+      ),
+      SYS_SENDFILE => self.sys_sendfile(
+        Fd::new(a1 as i32),
+        Fd::new(a2 as i32),
+        UserVAddr::new(a3),
+        a4,
+      ),
+      SYS_TRUNCATE => self.sys_truncate(&resolve_path(a1)?, a2 as isize),
+      SYS_FTRUNCATE => self.sys_ftruncate(Fd::new(a1 as i32), a2 as isize),
+      SYS_FSYNC => self.sys_fsync(Fd::new(a1 as i32)),
+      SYS_FDATASYNC => self.sys_fdatasync(Fd::new(a1 as i32)),
+      SYS_FLOCK => self.sys_flock(
+        Fd::new(a1 as i32),
+        bitflags_from_user!(FlockOperation, a2 as c_int)?,
+      ),
+      SYS_PRLIMIT64 => self.sys_prlimit64(
+        Pid::new(a1 as i32),
+        a2 as c_int,
+        UserVAddr::new(a3),
+        UserVAddr::new(a4),
+      ),
+      SYS_MMAP => self.sys_mmap(
+        UserVAddr::new(a1),
+        a2,
+        bitflags_from_user!(PageProt, a3 as u32)?,
+        bitflags_from_user!(MmapFlags, a4 as c_int)?,
+        Fd::new(a5 as i32),
+        a6,
+      ),
+      SYS_MUNMAP => self.sys_munmap(UserVAddr::new_nonnull(a1)?, a2),
+      SYS_MSYNC => self.sys_msync(
+        UserVAddr::new_nonnull(a1)?,
+        a2,
+        bitflags_from_user!(MsyncFlags, a3 as c_int)?,
+      ),
+      SYS_MEMFD_CREATE => self.sys_memfd_create(
+        UserVAddr::new_nonnull(a1)?,
+        bitflags_from_user!(MemfdFlags, a2 as c_uint)?,
+      ),
+      SYS_UNLINK => self.sys_unlink(&resolve_path(a1)?),
       _ => {
         debug_warn!(
           "unimplemented system call: {} (n={})",
@@ -147,6 +301,27 @@ fn syscall_name_by_number(n: usize) -> &'static str {
     11 => "lseek",
     12 => "clock_gettime",
     13 => "clock_nanosleep",
+    14 => "mount",
+    15 => "umount2",
+    16 => "getrandom",
+    17 => "pread64",
+    18 => "pwrite64",
+    19 => "readv",
+    20 => "writev",
+    21 => "preadv",
+    22 => "pwritev",
+    23 => "sendfile",
+    24 => "truncate",
+    25 => "ftruncate",
+    26 => "fsync",
+    27 => "fdatasync",
+    28 => "flock",
+    29 => "prlimit64",
+    30 => "mmap",
+    31 => "munmap",
+    32 => "msync",
+    33 => "memfd_create",
+    34 => "unlink",
     126 => "wait4",
     127 => "fork",
     128 => "brk",
@@ -163,12 +338,28 @@ pub(self) mod close;
 pub(self) mod execve;
 pub(self) mod exit;
 pub(self) mod fcntl;
+pub(self) mod flock;
 pub(self) mod fork;
+pub(self) mod fsync;
 pub(self) mod getcwd;
 pub(self) mod getdents64;
+pub(self) mod getrandom;
 pub(self) mod lseek;
+pub(self) mod memfd_create;
+pub(self) mod mmap;
+pub(self) mod mount;
+pub(self) mod msync;
 pub(self) mod open;
+pub(self) mod pread64;
+pub(self) mod prlimit64;
+pub(self) mod pwrite64;
 pub(self) mod read;
+pub(self) mod readv;
+pub(self) mod sendfile;
 pub(self) mod stat;
+pub(self) mod truncate;
+pub(self) mod umount2;
+pub(self) mod unlink;
 pub(self) mod wait4;
 pub(self) mod write;
+pub(self) mod writev;
@@ -0,0 +1,38 @@
+use api::{
+  schema::unix::Path,
+  user_buffer::UserCStr,
+  vfs::{mount::MountFlags, registry},
+  ErrorKind, Process, Result,
+};
+use environment::address::UserVAddr;
+
+use super::SyscallHandler;
+
+const FS_TYPE_MAX: usize = 64;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_mount(
+    &mut self,
+    source: &Path,
+    target: &Path,
+    fs_type: Option<UserVAddr>,
+    flags: MountFlags,
+  ) -> Result<isize> {
+    if flags.contains(MountFlags::MS_BIND) {
+      Process::rootfs().lock().bind_mount(source, target, flags)?;
+      return Ok(0);
+    }
+
+    let Some(fs_type) = fs_type else {
+      return Err(ErrorKind::EINVAL.into());
+    };
+    let fs_type = UserCStr::new(fs_type, FS_TYPE_MAX)?;
+
+    // Probe before taking the lock, it has to read the partition.
+    let fs = registry::create_filesystem(fs_type.as_str(), source.as_str())?;
+    Process::rootfs()
+      .lock()
+      .mount(target, fs, source.as_str(), fs_type.as_str(), flags)?;
+    Ok(0)
+  }
+}
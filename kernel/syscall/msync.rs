@@ -0,0 +1,39 @@
+use api::{arch::PAGE_SIZE, bitflags::bitflags, ctypes::c_int, ErrorKind, Result};
+use environment::address::UserVAddr;
+use utils::alignment::{align_up, is_aligned};
+
+use crate::{mm::page_cache, process::current_process};
+
+use super::SyscallHandler;
+
+bitflags! {
+    pub struct MsyncFlags: c_int {
+        const MS_ASYNC      = 1;
+        const MS_INVALIDATE = 2;
+        const MS_SYNC       = 4;
+    }
+}
+
+impl<'a> SyscallHandler<'a> {
+  /// Pages are written back right away whatever the flags are.
+  pub fn sys_msync(&mut self, addr: UserVAddr, len: usize, flags: MsyncFlags) -> Result<isize> {
+    if !is_aligned(addr.value(), PAGE_SIZE)
+      || flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC)
+    {
+      return Err(ErrorKind::EINVAL.into());
+    }
+
+    let len = align_up(len, PAGE_SIZE);
+    addr.access_ok(len)?;
+
+    let current = current_process();
+    let vm_ref = current.vm();
+    let files = vm_ref.as_ref().unwrap().lock().shared_files(addr, len);
+    for file in files {
+      page_cache::writeback(&file)?;
+      file.fsync()?;
+    }
+
+    Ok(0)
+  }
+}
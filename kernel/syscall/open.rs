@@ -5,10 +5,11 @@ use api::{
     posix::{FileMode, O_RDWR, O_WRONLY},
     unix::Path,
   },
+  vfs::FileKind,
   ErrorKind, Process, ProcessOps,
 };
 
-use crate::process::current_process;
+use crate::{fs, process::current_process};
 
 use super::SyscallHandler;
 
@@ -21,28 +22,52 @@ impl<'a> SyscallHandler<'a> {
       path.as_str()
     );
 
-    if flags.contains(OpenFlags::O_CREAT) {
-      unimplemented!()
-    }
-
-    let path_comp = Process::rootfs().lock().lookup_path_at(
-      &*Process::opened_files().lock(),
-      &CwdOrFd::AtCwd,
-      &path,
-      true,
-    )?;
+    // With O_EXCL, the file must be created by us: a symlink at `path`
+    // is not followed but makes open(2) fail.
+    let exclusive = flags.contains(OpenFlags::O_CREAT | OpenFlags::O_EXCL);
+    let path_comp = {
+      let rootfs = Process::rootfs().lock();
+      let opened_files = Process::opened_files().lock();
+      match rootfs.lookup_path_at(&*opened_files, &CwdOrFd::AtCwd, &path, !exclusive) {
+        Ok(_) if exclusive => return Err(ErrorKind::AlreadyExists.into()),
+        // Filesystems report missing files with either kind.
+        Err(err)
+          if flags.contains(OpenFlags::O_CREAT)
+            && matches!(err.kind(), ErrorKind::NoEntry | ErrorKind::NotFound) =>
+        {
+          rootfs.create_file_at(&*opened_files, &CwdOrFd::AtCwd, &path, mode)?
+        }
+        result => result?,
+      }
+    };
     if flags.contains(OpenFlags::O_DIRECTORY) && !path_comp.node.is_dir() {
       return Err(ErrorKind::NotADirectory.into());
     }
 
+    if path_comp.mount.is_read_only() && flags.intersects(OpenFlags::O_WRONLY | OpenFlags::O_RDWR) {
+      return Err(ErrorKind::EROFS.into());
+    }
+
     let access_mode = mode.access_mode();
     if path_comp.node.is_dir() && (access_mode == O_WRONLY || access_mode == O_RDWR) {
       return Err(ErrorKind::IsADirectory.into());
     }
 
-    let fd = Process::opened_files()
-      .lock()
-      .open(path_comp, flags.into())?;
+    let mut opened_files = Process::opened_files().lock();
+    let fd = opened_files.open(path_comp, flags.into())?;
+    if flags.contains(OpenFlags::O_TRUNC)
+      && flags.intersects(OpenFlags::O_WRONLY | OpenFlags::O_RDWR)
+    {
+      let opened_file = opened_files.get(fd)?;
+      // O_TRUNC is ignored on devices.
+      if matches!(opened_file.as_file()?.stat()?.kind, FileKind::RegularFile) {
+        if let Err(err) = fs::truncate(opened_file, 0) {
+          opened_files.close(fd)?;
+          return Err(err);
+        }
+      }
+    }
+
     Ok(fd.as_usize() as isize)
   }
 }
@@ -0,0 +1,25 @@
+use api::{user_buffer::UserBufferMut, vfs::Fd, ErrorKind, Result};
+use environment::address::UserVAddr;
+
+use crate::process::current_process;
+
+use super::SyscallHandler;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_pread64(
+    &mut self,
+    fd: Fd,
+    uaddr: UserVAddr,
+    len: usize,
+    offset: isize,
+  ) -> Result<isize> {
+    let offset = usize::try_from(offset).map_err(|_| ErrorKind::EINVAL)?;
+    let len = core::cmp::min(len, super::MAX_READ_WRITE_LEN);
+
+    let file_table = current_process().opened_files().lock();
+    let opened_file = file_table.get(fd)?;
+    let read_len = opened_file.read_at(offset, UserBufferMut::from_uaddr(uaddr, len))?;
+
+    Ok(read_len as isize)
+  }
+}
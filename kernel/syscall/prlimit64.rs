@@ -0,0 +1,46 @@
+use api::{process::Pid, ErrorKind, Result};
+use environment::address::UserVAddr;
+
+use crate::process::{
+  current_process,
+  rlimit::{RLimit, Resource},
+  Process,
+};
+
+use super::SyscallHandler;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_prlimit64(
+    &mut self,
+    pid: Pid,
+    resource: Resource,
+    new_limit: Option<UserVAddr>,
+    old_limit: Option<UserVAddr>,
+  ) -> Result<isize> {
+    let process = if pid.as_i32() == 0 {
+      current_process().clone()
+    } else {
+      Process::find_by_pid(pid).ok_or(ErrorKind::NotFound)?
+    };
+
+    // Don't touch the user memory with the limits locked: a page fault
+    // growing the stack reads them.
+    let new_limit = match new_limit {
+      Some(uaddr) => Some(uaddr.read::<RLimit>()?),
+      None => None,
+    };
+
+    let mut rlimits = process.rlimits();
+    let old = rlimits.get(resource)?;
+    if let Some(new_limit) = new_limit {
+      rlimits.set(resource, new_limit)?;
+    }
+    drop(rlimits);
+
+    if let Some(uaddr) = old_limit {
+      uaddr.write(&old)?;
+    }
+
+    Ok(0)
+  }
+}
@@ -0,0 +1,24 @@
+use api::{user_buffer::UserBuffer, vfs::Fd, ErrorKind, Result};
+use environment::address::UserVAddr;
+
+use crate::{fs, process::current_process};
+
+use super::SyscallHandler;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_pwrite64(
+    &mut self,
+    fd: Fd,
+    uaddr: UserVAddr,
+    len: usize,
+    offset: isize,
+  ) -> Result<isize> {
+    let offset = usize::try_from(offset).map_err(|_| ErrorKind::EINVAL)?;
+    let len = core::cmp::min(len, super::MAX_READ_WRITE_LEN);
+
+    let file_table = current_process().opened_files().lock();
+    let opened_file = file_table.get(fd)?;
+    let written_len = fs::write_at(opened_file, offset, UserBuffer::from_uaddr(uaddr, len))?;
+    Ok(written_len as isize)
+  }
+}
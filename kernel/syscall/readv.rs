@@ -0,0 +1,54 @@
+use api::{ctypes::c_int, user_buffer::UserBufferMut, vfs::Fd, ErrorKind, Result};
+use environment::address::UserVAddr;
+
+use crate::process::current_process;
+
+use super::{read_iovecs, SyscallHandler};
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_readv(&mut self, fd: Fd, iov: UserVAddr, iovcnt: c_int) -> Result<isize> {
+    readv(fd, iov, iovcnt, None)
+  }
+
+  pub fn sys_preadv(
+    &mut self,
+    fd: Fd,
+    iov: UserVAddr,
+    iovcnt: c_int,
+    offset: isize,
+  ) -> Result<isize> {
+    let offset = usize::try_from(offset).map_err(|_| ErrorKind::EINVAL)?;
+    readv(fd, iov, iovcnt, Some(offset))
+  }
+}
+
+/// Fills the buffers one after the other, from the file position or from
+/// `offset` if any. Stops at the first short read.
+fn readv(fd: Fd, iov: UserVAddr, iovcnt: c_int, offset: Option<usize>) -> Result<isize> {
+  let iovecs = read_iovecs(iov, iovcnt)?;
+
+  let file_table = current_process().opened_files().lock();
+  let opened_file = file_table.get(fd)?;
+  let mut total_len = 0;
+  for iovec in iovecs {
+    let buf = UserBufferMut::from_uaddr(iovec.base()?, iovec.len);
+    let result = match offset {
+      Some(offset) => opened_file.read_at(offset + total_len, buf),
+      None => opened_file.read(buf),
+    };
+
+    let read_len = match result {
+      Ok(read_len) => read_len,
+      // What has been read so far has been consumed: report it.
+      Err(_) if total_len > 0 => break,
+      Err(err) => return Err(err),
+    };
+
+    total_len += read_len;
+    if read_len < iovec.len {
+      break;
+    }
+  }
+
+  Ok(total_len as isize)
+}
@@ -0,0 +1,88 @@
+use core::cmp::min;
+
+use api::{
+  ctypes::c_long,
+  user_buffer::{UserBuffer, UserBufferMut},
+  vfs::Fd,
+  ErrorKind, Result,
+};
+use environment::address::UserVAddr;
+
+use crate::{fs, process::current_process};
+
+use super::SyscallHandler;
+
+/// How much is copied from one file to the other at a time.
+const SENDFILE_CHUNK_LEN: usize = 4096;
+
+impl<'a> SyscallHandler<'a> {
+  /// Copies up to `count` bytes from `in_fd` to `out_fd` through a kernel
+  /// buffer. Reads from `*offset` and updates it if given, leaving the
+  /// position of `in_fd` alone.
+  pub fn sys_sendfile(
+    &mut self,
+    out_fd: Fd,
+    in_fd: Fd,
+    offset: Option<UserVAddr>,
+    count: usize,
+  ) -> Result<isize> {
+    let count = min(count, super::MAX_READ_WRITE_LEN);
+    let start = match offset {
+      Some(uaddr) => Some(usize::try_from(uaddr.read::<c_long>()?).map_err(|_| ErrorKind::EINVAL)?),
+      None => None,
+    };
+
+    let file_table = current_process().opened_files().lock();
+    let in_file = file_table.get(in_fd)?;
+    let out_file = file_table.get(out_fd)?;
+
+    let mut buf = vec![0u8; min(count, SENDFILE_CHUNK_LEN)];
+    let mut total_len = 0;
+    while total_len < count {
+      let chunk = &mut buf[..min(SENDFILE_CHUNK_LEN, count - total_len)];
+      let result = match start {
+        Some(start) => in_file.read_at(start + total_len, UserBufferMut::from(chunk)),
+        None => in_file.read(UserBufferMut::from(chunk)),
+      };
+
+      let read_len = match result {
+        Ok(0) => break,
+        Ok(read_len) => read_len,
+        Err(_) if total_len > 0 => break,
+        Err(err) => return Err(err),
+      };
+
+      let mut written_len = 0;
+      let mut error = None;
+      while written_len < read_len {
+        match fs::write(out_file, UserBuffer::from(&buf[written_len..read_len])) {
+          Ok(0) => break,
+          Ok(len) => written_len += len,
+          Err(err) => {
+            error = Some(err);
+            break;
+          }
+        }
+      }
+
+      total_len += written_len;
+      if written_len < read_len {
+        // Give back what was read but couldn't be written.
+        if start.is_none() {
+          in_file.seek(in_file.pos() - (read_len - written_len))?;
+        }
+
+        match error {
+          Some(err) if total_len == 0 => return Err(err),
+          _ => break,
+        }
+      }
+    }
+
+    if let (Some(uaddr), Some(start)) = (offset, start) {
+      uaddr.write(&((start + total_len) as c_long))?;
+    }
+
+    Ok(total_len as isize)
+  }
+}
@@ -0,0 +1,34 @@
+use api::{schema::unix::Path, vfs::Fd, ErrorKind, Process, Result};
+
+use crate::{fs, process::current_process};
+
+use super::SyscallHandler;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_truncate(&mut self, path: &Path, len: isize) -> Result<isize> {
+    let len = usize::try_from(len).map_err(|_| ErrorKind::EINVAL)?;
+    let path_comp = Process::rootfs().lock().lookup_path(path, true)?;
+    if path_comp.node.is_dir() {
+      return Err(ErrorKind::IsADirectory.into());
+    }
+
+    if path_comp.mount.is_read_only() {
+      return Err(ErrorKind::EROFS.into());
+    }
+
+    fs::truncate_file(path_comp.node.as_file()?, len)?;
+    Ok(0)
+  }
+
+  pub fn sys_ftruncate(&mut self, fd: Fd, len: isize) -> Result<isize> {
+    let len = usize::try_from(len).map_err(|_| ErrorKind::EINVAL)?;
+    let file_table = current_process().opened_files().lock();
+    let opened_file = file_table.get(fd)?;
+    if !opened_file.options().write {
+      return Err(ErrorKind::EINVAL.into());
+    }
+
+    fs::truncate(opened_file, len)?;
+    Ok(0)
+  }
+}
@@ -0,0 +1,10 @@
+use api::{schema::unix::Path, vfs::mount::UmountFlags, Process, Result};
+
+use super::SyscallHandler;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_umount2(&mut self, target: &Path, flags: UmountFlags) -> Result<isize> {
+    Process::rootfs().lock().unmount(target, flags)?;
+    Ok(0)
+  }
+}
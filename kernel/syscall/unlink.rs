@@ -0,0 +1,15 @@
+use api::{posix::CwdOrFd, schema::unix::Path, Result};
+
+use crate::process::current_process;
+
+use super::SyscallHandler;
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_unlink(&mut self, path: &Path) -> Result<isize> {
+    let current = current_process();
+    let rootfs = current.rootfs().lock();
+    let opened_files = current.opened_files().lock();
+    rootfs.unlink_at(&*opened_files, &CwdOrFd::AtCwd, &path)?;
+    Ok(0)
+  }
+}
@@ -51,7 +51,6 @@ impl<'a> SyscallHandler<'a> {
     current_process().children().retain(|p| p.pid() != got_pid);
 
     if let Some(status) = status {
-      // FIXME: This is not the correct format of `status`
       status.write::<c_int>(&status_value)?;
     }
 
@@ -1,6 +1,6 @@
 use api::ProcessOps;
 
-use crate::process::current_process;
+use crate::{fs, process::current_process};
 
 impl<'a> super::SyscallHandler<'a> {
   pub fn sys_write(
@@ -21,7 +21,10 @@ impl<'a> super::SyscallHandler<'a> {
       len
     );
 
-    let written_len = opened_file.write(api::user_buffer::UserBuffer::from_uaddr(uaddr, len))?;
+    let written_len = fs::write(
+      opened_file,
+      api::user_buffer::UserBuffer::from_uaddr(uaddr, len),
+    )?;
     Ok(written_len as isize)
   }
 }
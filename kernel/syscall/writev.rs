@@ -0,0 +1,54 @@
+use api::{ctypes::c_int, user_buffer::UserBuffer, vfs::Fd, ErrorKind, Result};
+use environment::address::UserVAddr;
+
+use crate::{fs, process::current_process};
+
+use super::{read_iovecs, SyscallHandler};
+
+impl<'a> SyscallHandler<'a> {
+  pub fn sys_writev(&mut self, fd: Fd, iov: UserVAddr, iovcnt: c_int) -> Result<isize> {
+    writev(fd, iov, iovcnt, None)
+  }
+
+  pub fn sys_pwritev(
+    &mut self,
+    fd: Fd,
+    iov: UserVAddr,
+    iovcnt: c_int,
+    offset: isize,
+  ) -> Result<isize> {
+    let offset = usize::try_from(offset).map_err(|_| ErrorKind::EINVAL)?;
+    writev(fd, iov, iovcnt, Some(offset))
+  }
+}
+
+/// Writes the buffers one after the other, at the file position or at
+/// `offset` if any. Stops at the first short write.
+fn writev(fd: Fd, iov: UserVAddr, iovcnt: c_int, offset: Option<usize>) -> Result<isize> {
+  let iovecs = read_iovecs(iov, iovcnt)?;
+
+  let file_table = current_process().opened_files().lock();
+  let opened_file = file_table.get(fd)?;
+  let mut total_len = 0;
+  for iovec in iovecs {
+    let buf = UserBuffer::from_uaddr(iovec.base()?, iovec.len);
+    let result = match offset {
+      Some(offset) => fs::write_at(opened_file, offset + total_len, buf),
+      None => fs::write(opened_file, buf),
+    };
+
+    let written_len = match result {
+      Ok(written_len) => written_len,
+      // What has been written so far is in the file: report it.
+      Err(_) if total_len > 0 => break,
+      Err(err) => return Err(err),
+    };
+
+    total_len += written_len;
+    if written_len < iovec.len {
+      break;
+    }
+  }
+
+  Ok(total_len as isize)
+}
@@ -5,7 +5,7 @@
 extern "C" {
 #endif
 
-// TODO: uid_t, gid_t, pid_t
+// TODO: uid_t, gid_t
 
 #include <bits/stddef.h>
 
@@ -31,6 +31,20 @@ typedef __OFF_TYPE__ off_t;
 
 #endif
 
+#ifndef __CILIBC_PID_TYPE__
+#define __CILIBC_PID_TYPE__ 1
+
+typedef int pid_t;
+
+#endif
+
+#ifndef __CILIBC_MODE_TYPE__
+#define __CILIBC_MODE_TYPE__ 1
+
+typedef unsigned int mode_t;
+
+#endif
+
 #if defined(__cplusplus)
 } /* extern "C" */
 #endif
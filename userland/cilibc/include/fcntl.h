@@ -5,7 +5,9 @@
 extern "C" {
 #endif
 
-// TODO: mode_t, off_t, pid_t
+// TODO: mode_t
+
+#include <bits/sys/types.h>
 
 #define O_RDONLY    000000000
 #define O_WRONLY    000000001
@@ -19,8 +21,32 @@ extern "C" {
 #define O_DIRECTORY 000200000
 #define O_CLOEXEC   002000000
 
+#define F_DUPFD  0
+#define F_GETFD  1
+#define F_SETFD  2
+#define F_GETFL  3
+#define F_SETFL  4
+#define F_GETLK  5
+#define F_SETLK  6
+#define F_SETLKW 7
+
+#define FD_CLOEXEC 1
+
+#define F_RDLCK 0
+#define F_WRLCK 1
+#define F_UNLCK 2
+
+  struct flock {
+    short l_type;
+    short l_whence;
+    off_t l_start;
+    off_t l_len;
+    pid_t l_pid;
+  };
+
   int open(const char *, int, ...);
   int close(int);
+  int fcntl(int, int, ...);
 
 #if defined(__cplusplus)
 } /* extern "C" */
@@ -5,6 +5,8 @@
 extern "C" {
 #endif
 
+#define PATH_MAX 4096
+
 #if defined(__cplusplus)
 } /* extern "C" */
 #endif
@@ -0,0 +1,19 @@
+#ifndef _CILIBC_SYS_FILE_H
+#define _CILIBC_SYS_FILE_H 1
+
+#if defined(__cplusplus)
+extern "C" {
+#endif
+
+#define LOCK_SH 1
+#define LOCK_EX 2
+#define LOCK_NB 4
+#define LOCK_UN 8
+
+int flock(int fd, int operation);
+
+#if defined(__cplusplus)
+} /* extern "C" */
+#endif
+
+#endif /* _CILIBC_SYS_FILE_H */
@@ -0,0 +1,41 @@
+#ifndef _CILIBC_SYS_MMAN_H
+#define _CILIBC_SYS_MMAN_H 1
+
+#if defined(__cplusplus)
+extern "C" {
+#endif
+
+#include <bits/sys/types.h>
+
+#define PROT_NONE  0
+#define PROT_READ  1
+#define PROT_WRITE 2
+#define PROT_EXEC  4
+
+#define MAP_SHARED    0x01
+#define MAP_PRIVATE   0x02
+#define MAP_FIXED     0x10
+#define MAP_ANONYMOUS 0x20
+#define MAP_ANON      MAP_ANONYMOUS
+
+#define MAP_FAILED ((void *)-1)
+
+#define MS_ASYNC      1
+#define MS_INVALIDATE 2
+#define MS_SYNC       4
+
+#define MFD_CLOEXEC 1
+
+void *mmap(void *addr, size_t length, int prot, int flags, int fd,
+           off_t offset);
+int munmap(void *addr, size_t length);
+int msync(void *addr, size_t length, int flags);
+int memfd_create(const char *name, unsigned int flags);
+int shm_open(const char *name, int oflag, mode_t mode);
+int shm_unlink(const char *name);
+
+#if defined(__cplusplus)
+} /* extern "C" */
+#endif
+
+#endif /* _CILIBC_SYS_MMAN_H */
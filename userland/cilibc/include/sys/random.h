@@ -0,0 +1,20 @@
+#ifndef _CILIBC_SYS_RANDOM_H
+#define _CILIBC_SYS_RANDOM_H 1
+
+#if defined(__cplusplus)
+extern "C" {
+#endif
+
+#include <bits/sys/types.h>
+
+#define GRND_NONBLOCK 1
+#define GRND_RANDOM 2
+#define GRND_INSECURE 4
+
+ssize_t getrandom(void *buf, size_t buflen, unsigned int flags);
+
+#if defined(__cplusplus)
+} /* extern "C" */
+#endif
+
+#endif /* _CILIBC_SYS_RANDOM_H */
@@ -0,0 +1,30 @@
+#ifndef _CILIBC_SYS_RESOURCE_H
+#define _CILIBC_SYS_RESOURCE_H 1
+
+#if defined(__cplusplus)
+extern "C" {
+#endif
+
+#include <bits/sys/types.h>
+
+typedef unsigned long long rlim_t;
+
+struct rlimit {
+  rlim_t rlim_cur;
+  rlim_t rlim_max;
+};
+
+#define RLIM_INFINITY (~0ULL)
+
+#define RLIMIT_DATA  2
+#define RLIMIT_STACK 3
+#define RLIMIT_AS    9
+
+int getrlimit(int resource, struct rlimit *rlim);
+int setrlimit(int resource, const struct rlimit *rlim);
+
+#if defined(__cplusplus)
+} /* extern "C" */
+#endif
+
+#endif /* _CILIBC_SYS_RESOURCE_H */
@@ -0,0 +1,16 @@
+#ifndef _CILIBC_SYS_SENDFILE_H
+#define _CILIBC_SYS_SENDFILE_H 1
+
+#if defined(__cplusplus)
+extern "C" {
+#endif
+
+#include <bits/sys/types.h>
+
+ssize_t sendfile(int out_fd, int in_fd, off_t *offset, size_t count);
+
+#if defined(__cplusplus)
+} /* extern "C" */
+#endif
+
+#endif /* _CILIBC_SYS_SENDFILE_H */
@@ -0,0 +1,24 @@
+#ifndef _CILIBC_SYS_UIO_H
+#define _CILIBC_SYS_UIO_H 1
+
+#if defined(__cplusplus)
+extern "C" {
+#endif
+
+#include <bits/sys/types.h>
+
+struct iovec {
+  void *iov_base;
+  size_t iov_len;
+};
+
+ssize_t readv(int fd, const struct iovec *iov, int iovcnt);
+ssize_t writev(int fd, const struct iovec *iov, int iovcnt);
+ssize_t preadv(int fd, const struct iovec *iov, int iovcnt, off_t offset);
+ssize_t pwritev(int fd, const struct iovec *iov, int iovcnt, off_t offset);
+
+#if defined(__cplusplus)
+} /* extern "C" */
+#endif
+
+#endif /* _CILIBC_SYS_UIO_H */
@@ -5,6 +5,18 @@
 extern "C" {
 #endif
 
+#include <bits/sys/types.h>
+
+#define WNOHANG 1
+#define WUNTRACED 2
+
+#define WEXITSTATUS(s) (((s) >> 8) & 0xff)
+#define WTERMSIG(s) ((s) & 0x7f)
+#define WIFEXITED(s) (WTERMSIG(s) == 0)
+#define WIFSIGNALED(s) (WTERMSIG(s) != 0 && WTERMSIG(s) != 0x7f)
+
+pid_t waitpid(pid_t pid, int *status, int options);
+
 #if defined(__cplusplus)
 } /* extern "C" */
 #endif
@@ -9,6 +9,13 @@ extern "C" {
 
 ssize_t write(int fd, const void *buf, size_t count);
 ssize_t read(int fd, void *, size_t);
+ssize_t pread(int fd, void *buf, size_t count, off_t offset);
+ssize_t pwrite(int fd, const void *buf, size_t count, off_t offset);
+int truncate(const char *path, off_t length);
+int ftruncate(int fd, off_t length);
+int fsync(int fd);
+int fdatasync(int fd);
+int unlink(const char *path);
 
 void *brk(void *addr);
 
@@ -1,4 +1,5 @@
 #include <fcntl.h>
+#include <stdarg.h>
 #include "../sys/syscall.h"
 
 int open(const char *path, int oflag, ...) {
@@ -7,4 +8,13 @@ int open(const char *path, int oflag, ...) {
 
 int close(int fd) {
   return (int)syscall1((void *)SYS_CLOSE, (void *)fd);
+}
+
+int fcntl(int fd, int cmd, ...) {
+  va_list ap;
+  va_start(ap, cmd);
+  void *arg = va_arg(ap, void *);
+  va_end(ap);
+
+  return (int)syscall3((void *)SYS_FCNTL, (void *)fd, (void *)cmd, arg);
 }
\ No newline at end of file
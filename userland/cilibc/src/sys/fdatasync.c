@@ -0,0 +1,6 @@
+#include <unistd.h>
+#include "syscall.h"
+
+int fdatasync(int fd) {
+  return (int)syscall1((void *)SYS_FDATASYNC, (void *)fd);
+}
\ No newline at end of file
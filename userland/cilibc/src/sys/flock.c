@@ -0,0 +1,6 @@
+#include <sys/file.h>
+#include "syscall.h"
+
+int flock(int fd, int operation) {
+  return (int)syscall2((void *)SYS_FLOCK, (void *)fd, (void *)operation);
+}
\ No newline at end of file
@@ -0,0 +1,4 @@
+#include <unistd.h>
+#include "syscall.h"
+
+int fsync(int fd) { return (int)syscall1((void *)SYS_FSYNC, (void *)fd); }
\ No newline at end of file
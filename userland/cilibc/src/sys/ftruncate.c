@@ -0,0 +1,6 @@
+#include <unistd.h>
+#include "syscall.h"
+
+int ftruncate(int fd, off_t length) {
+  return (int)syscall2((void *)SYS_FTRUNCATE, (void *)fd, (void *)length);
+}
\ No newline at end of file
@@ -0,0 +1,7 @@
+#include <sys/random.h>
+#include "syscall.h"
+
+ssize_t getrandom(void *buf, size_t buflen, unsigned int flags) {
+  return (ssize_t)syscall3((void *)SYS_GETRANDOM, buf, (void *)buflen,
+                           (void *)(unsigned long)flags);
+}
\ No newline at end of file
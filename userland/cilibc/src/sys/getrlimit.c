@@ -0,0 +1,7 @@
+#include <sys/resource.h>
+#include "syscall.h"
+
+int getrlimit(int resource, struct rlimit *rlim) {
+  return (int)syscall4((void *)SYS_PRLIMIT64, (void *)0, (void *)resource,
+                       (void *)0, rlim);
+}
\ No newline at end of file
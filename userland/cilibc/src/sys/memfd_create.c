@@ -0,0 +1,7 @@
+#include <sys/mman.h>
+#include "syscall.h"
+
+int memfd_create(const char *name, unsigned int flags) {
+  return (int)syscall2((void *)SYS_MEMFD_CREATE, (void *)name,
+                       (void *)flags);
+}
\ No newline at end of file
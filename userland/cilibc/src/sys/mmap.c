@@ -0,0 +1,8 @@
+#include <sys/mman.h>
+#include "syscall.h"
+
+void *mmap(void *addr, size_t length, int prot, int flags, int fd,
+           off_t offset) {
+  return syscall6((void *)SYS_MMAP, addr, (void *)length, (void *)prot,
+                  (void *)flags, (void *)fd, (void *)offset);
+}
\ No newline at end of file
@@ -0,0 +1,7 @@
+#include <sys/mman.h>
+#include "syscall.h"
+
+int msync(void *addr, size_t length, int flags) {
+  return (int)syscall3((void *)SYS_MSYNC, addr, (void *)length,
+                       (void *)flags);
+}
\ No newline at end of file
@@ -0,0 +1,6 @@
+#include <sys/mman.h>
+#include "syscall.h"
+
+int munmap(void *addr, size_t length) {
+  return (int)syscall2((void *)SYS_MUNMAP, addr, (void *)length);
+}
\ No newline at end of file
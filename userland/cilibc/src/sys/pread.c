@@ -0,0 +1,7 @@
+#include <unistd.h>
+#include "syscall.h"
+
+ssize_t pread(int fd, void *buf, size_t count, off_t offset) {
+  return (ssize_t)syscall4((void *)SYS_PREAD64, (void *)fd, buf, (void *)count,
+                          (void *)offset);
+}
\ No newline at end of file
@@ -0,0 +1,7 @@
+#include <sys/uio.h>
+#include "syscall.h"
+
+ssize_t preadv(int fd, const struct iovec *iov, int iovcnt, off_t offset) {
+  return (ssize_t)syscall4((void *)SYS_PREADV, (void *)fd, (void *)iov,
+                          (void *)iovcnt, (void *)offset);
+}
\ No newline at end of file
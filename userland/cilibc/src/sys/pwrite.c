@@ -0,0 +1,7 @@
+#include <unistd.h>
+#include "syscall.h"
+
+ssize_t pwrite(int fd, const void *buf, size_t count, off_t offset) {
+  return (ssize_t)syscall4((void *)SYS_PWRITE64, (void *)fd, (void *)buf,
+                          (void *)count, (void *)offset);
+}
\ No newline at end of file
@@ -0,0 +1,7 @@
+#include <sys/uio.h>
+#include "syscall.h"
+
+ssize_t readv(int fd, const struct iovec *iov, int iovcnt) {
+  return (ssize_t)syscall3((void *)SYS_READV, (void *)fd, (void *)iov,
+                          (void *)iovcnt);
+}
\ No newline at end of file
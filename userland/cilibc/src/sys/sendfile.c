@@ -0,0 +1,7 @@
+#include <sys/sendfile.h>
+#include "syscall.h"
+
+ssize_t sendfile(int out_fd, int in_fd, off_t *offset, size_t count) {
+  return (ssize_t)syscall4((void *)SYS_SENDFILE, (void *)out_fd, (void *)in_fd,
+                          offset, (void *)count);
+}
\ No newline at end of file
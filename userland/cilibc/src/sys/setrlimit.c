@@ -0,0 +1,7 @@
+#include <sys/resource.h>
+#include "syscall.h"
+
+int setrlimit(int resource, const struct rlimit *rlim) {
+  return (int)syscall4((void *)SYS_PRLIMIT64, (void *)0, (void *)resource,
+                       (void *)rlim, (void *)0);
+}
\ No newline at end of file
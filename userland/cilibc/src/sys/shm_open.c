@@ -0,0 +1,42 @@
+#include <fcntl.h>
+#include <limits.h>
+#include <string.h>
+#include <sys/mman.h>
+#include "syscall.h"
+
+// Shared memory objects are the files of this directory.
+#define SHM_DIR "/Devices/SharedMemory/"
+
+// Builds the path of the object `name` ("/foo") into `path`.
+int __shm_path(char *path, const char *name) {
+  while (*name == '/') {
+    name++;
+  }
+
+  size_t dir_len = sizeof(SHM_DIR) - 1;
+  size_t name_len = strlen(name);
+  if (name_len == 0 || strchr(name, '/') || dir_len + name_len >= PATH_MAX) {
+    return -1;
+  }
+
+  for (size_t i = 0; i < dir_len; i++) {
+    path[i] = SHM_DIR[i];
+  }
+
+  for (size_t i = 0; i <= name_len; i++) {
+    path[dir_len + i] = name[i];
+  }
+
+  return 0;
+}
+
+int shm_open(const char *name, int oflag, mode_t mode) {
+  char path[PATH_MAX];
+  if (__shm_path(path, name) < 0) {
+    return -1;
+  }
+
+  // Like on Linux, the descriptor is closed on execve(2).
+  return (int)syscall3((void *)SYS_OPEN, (void *)path,
+                       (void *)(oflag | O_CLOEXEC), (void *)mode);
+}
\ No newline at end of file
@@ -0,0 +1,14 @@
+#include <limits.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+int __shm_path(char *path, const char *name);
+
+int shm_unlink(const char *name) {
+  char path[PATH_MAX];
+  if (__shm_path(path, name) < 0) {
+    return -1;
+  }
+
+  return unlink(path);
+}
\ No newline at end of file
@@ -12,14 +12,37 @@ void *syscall3(void *num, void *arg1, void *arg2, void *arg3);
 void *syscall4(void *num, void *arg1, void *arg2, void *arg3, void *arg4);
 void *syscall5(void *num, void *arg1, void *arg2, void *arg3, void *arg4,
                void *arg5);
+void *syscall6(void *num, void *arg1, void *arg2, void *arg3, void *arg4,
+               void *arg5, void *arg6);
 
 #define SYS_EXIT -1
 #define SYS_WRITE 1
 #define SYS_READ 2
 #define SYS_OPEN 4
 #define SYS_CLOSE 8
+#define SYS_FCNTL 10
 #define SYS_CLOCK_GETTIME 12
 #define SYS_CLOCK_NANOSLEEP 13
+#define SYS_GETRANDOM 16
+#define SYS_PREAD64 17
+#define SYS_PWRITE64 18
+#define SYS_READV 19
+#define SYS_WRITEV 20
+#define SYS_PREADV 21
+#define SYS_PWRITEV 22
+#define SYS_SENDFILE 23
+#define SYS_TRUNCATE 24
+#define SYS_FTRUNCATE 25
+#define SYS_FSYNC 26
+#define SYS_FDATASYNC 27
+#define SYS_FLOCK 28
+#define SYS_PRLIMIT64 29
+#define SYS_MMAP 30
+#define SYS_MUNMAP 31
+#define SYS_MSYNC 32
+#define SYS_MEMFD_CREATE 33
+#define SYS_UNLINK 34
+#define SYS_WAIT4 126
 #define SYS_BRK 128
 
 #if defined(__cplusplus)
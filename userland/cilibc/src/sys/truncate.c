@@ -0,0 +1,6 @@
+#include <unistd.h>
+#include "syscall.h"
+
+int truncate(const char *path, off_t length) {
+  return (int)syscall2((void *)SYS_TRUNCATE, (void *)path, (void *)length);
+}
\ No newline at end of file
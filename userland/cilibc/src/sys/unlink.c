@@ -0,0 +1,6 @@
+#include <unistd.h>
+#include "syscall.h"
+
+int unlink(const char *path) {
+  return (int)syscall1((void *)SYS_UNLINK, (void *)path);
+}
\ No newline at end of file
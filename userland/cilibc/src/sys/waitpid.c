@@ -0,0 +1,7 @@
+#include <sys/wait.h>
+#include "syscall.h"
+
+pid_t waitpid(pid_t pid, int *status, int options) {
+  return (pid_t)syscall4((void *)SYS_WAIT4, (void *)pid, (void *)status,
+                         (void *)options, (void *)0);
+}
\ No newline at end of file
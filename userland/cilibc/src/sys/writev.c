@@ -0,0 +1,7 @@
+#include <sys/uio.h>
+#include "syscall.h"
+
+ssize_t writev(int fd, const struct iovec *iov, int iovcnt) {
+  return (ssize_t)syscall3((void *)SYS_WRITEV, (void *)fd, (void *)iov,
+                          (void *)iovcnt);
+}
\ No newline at end of file
@@ -63,7 +63,7 @@ int sh_launch(char **args) {
     // Parent process
     do {
       wpid = waitpid(pid, &status, WUNTRACED);
-    } while (!WIFEXITED(status) /* TODO: Signals && !WIFSIGNALED(status)*/);
+    } while (!WIFEXITED(status) && !WIFSIGNALED(status));
   }
 
   return 1;
@@ -0,0 +1,308 @@
+//! A buddy allocator. Free memory is kept in naturally aligned blocks of
+//! 2^order pages, with a free list per order. Allocations split larger
+//! blocks in halves, and a freed block is merged back with its buddy, the
+//! other half of the block it was split from, whenever that one is free too.
+
+use crate::alignment::align_up;
+
+const PAGE_SIZE: usize = 4096;
+/// The largest blocks are made of 2^MAX_ORDER pages.
+pub const MAX_ORDER: usize = 15;
+/// Ends the free lists.
+const NIL: usize = usize::MAX;
+
+/// The links of a free list, kept in the first page of each free block.
+#[repr(C)]
+struct FreeBlock {
+	prev: usize,
+	next: usize,
+}
+
+pub struct BuddyAllocator {
+	/// One byte per page: `order + 1` if the page starts a free block of
+	/// that order, 0 otherwise.
+	free_heads: &'static mut [u8],
+	/// The physical address of the first free block of each order.
+	free_lists: [usize; MAX_ORDER + 1],
+	num_free_blocks: [usize; MAX_ORDER + 1],
+	num_free_pages: usize,
+	/// What to add to a physical address to access it.
+	vaddr_offset: usize,
+	base: usize,
+	end: usize,
+}
+
+impl BuddyAllocator {
+	/// # Safety
+	///
+	/// The caller must ensure that the memory passed to this function is
+	/// aligned to a page boundary and not used for anything else.
+	pub unsafe fn new(
+		base: *mut u8,
+		base_paddr: usize,
+		len: usize,
+	) -> BuddyAllocator {
+		// The free heads take the first pages of the area.
+		let num_pages = len / PAGE_SIZE;
+		let reserved_pages =
+			(align_up(num_pages, PAGE_SIZE) / PAGE_SIZE).min(num_pages);
+		let free_heads =
+			core::slice::from_raw_parts_mut(base, num_pages - reserved_pages);
+		free_heads.fill(0);
+
+		let mut allocator = BuddyAllocator {
+			free_heads,
+			free_lists: [NIL; MAX_ORDER + 1],
+			num_free_blocks: [0; MAX_ORDER + 1],
+			num_free_pages: 0,
+			vaddr_offset: (base as usize).wrapping_sub(base_paddr),
+			base: base_paddr + reserved_pages * PAGE_SIZE,
+			end: base_paddr + num_pages * PAGE_SIZE,
+		};
+
+		// Carve the area into the largest blocks its alignment allows.
+		let mut paddr = allocator.base;
+		while paddr < allocator.end {
+			let order = (0..=MAX_ORDER)
+				.rev()
+				.find(|&order| {
+					let block_len = PAGE_SIZE << order;
+					paddr % block_len == 0 && paddr + block_len <= allocator.end
+				})
+				.unwrap();
+
+			allocator.push_free(paddr, order);
+			allocator.num_free_pages += 1 << order;
+			paddr += PAGE_SIZE << order;
+		}
+
+		allocator
+	}
+
+	pub fn num_total_pages(&self) -> usize {
+		(self.end - self.base) / PAGE_SIZE
+	}
+
+	pub fn num_free_pages(&self) -> usize {
+		self.num_free_pages
+	}
+
+	/// The number of free blocks of each order.
+	pub fn num_free_blocks(&self) -> [usize; MAX_ORDER + 1] {
+		self.num_free_blocks
+	}
+
+	pub fn includes(&self, ptr: usize) -> bool {
+		self.base <= ptr && ptr < self.end
+	}
+
+	pub fn alloc_pages(&mut self, order: usize) -> Option<usize> {
+		if order > MAX_ORDER {
+			return None;
+		}
+
+		let mut current =
+			(order..=MAX_ORDER).find(|&order| self.free_lists[order] != NIL)?;
+		let paddr = self.free_lists[current];
+		self.remove_free(paddr, current);
+
+		// Give back the upper halves until the block has the right size.
+		while current > order {
+			current -= 1;
+			self.push_free(paddr + (PAGE_SIZE << current), current);
+		}
+
+		self.num_free_pages -= 1 << order;
+		Some(paddr)
+	}
+
+	pub fn free_pages(&mut self, ptr: usize, order: usize) {
+		debug_assert!(self.includes(ptr));
+		debug_assert!(ptr % (PAGE_SIZE << order) == 0, "misaligned block");
+		debug_assert!(self.free_order(ptr).is_none(), "double free");
+
+		self.num_free_pages += 1 << order;
+
+		let mut paddr = ptr;
+		let mut order = order;
+		while order < MAX_ORDER {
+			let buddy = paddr ^ (PAGE_SIZE << order);
+			if !self.includes(buddy) || self.free_order(buddy) != Some(order) {
+				break;
+			}
+
+			self.remove_free(buddy, order);
+			paddr = paddr.min(buddy);
+			order += 1;
+		}
+
+		self.push_free(paddr, order);
+	}
+
+	fn page_index(&self, paddr: usize) -> usize {
+		(paddr - self.base) / PAGE_SIZE
+	}
+
+	fn free_block(&self, paddr: usize) -> *mut FreeBlock {
+		paddr.wrapping_add(self.vaddr_offset) as *mut FreeBlock
+	}
+
+	/// Returns the order of the free block starting at `paddr`, if any.
+	fn free_order(&self, paddr: usize) -> Option<usize> {
+		match self.free_heads[self.page_index(paddr)] {
+			0 => None,
+			head => Some(head as usize - 1),
+		}
+	}
+
+	fn push_free(&mut self, paddr: usize, order: usize) {
+		let next = self.free_lists[order];
+		unsafe {
+			self.free_block(paddr).write(FreeBlock { prev: NIL, next });
+			if next != NIL {
+				(*self.free_block(next)).prev = paddr;
+			}
+		}
+
+		self.free_lists[order] = paddr;
+		self.free_heads[self.page_index(paddr)] = order as u8 + 1;
+		self.num_free_blocks[order] += 1;
+	}
+
+	fn remove_free(&mut self, paddr: usize, order: usize) {
+		let FreeBlock { prev, next } = unsafe { self.free_block(paddr).read() };
+		if prev == NIL {
+			self.free_lists[order] = next;
+		} else {
+			unsafe { (*self.free_block(prev)).next = next };
+		}
+
+		if next != NIL {
+			unsafe { (*self.free_block(next)).prev = prev };
+		}
+
+		self.free_heads[self.page_index(paddr)] = 0;
+		self.num_free_blocks[order] -= 1;
+	}
+}
+
+#[cfg(all(test, not(feature = "no_std")))]
+mod tests {
+	use super::*;
+
+	/// Backs an allocator with host memory, pretending that it starts at
+	/// the physical address `base_paddr`.
+	struct TestArea {
+		_memory: Vec<u64>,
+		allocator: BuddyAllocator,
+	}
+
+	impl TestArea {
+		fn new(base_paddr: usize, num_pages: usize) -> TestArea {
+			let mut memory = vec![0u64; num_pages * PAGE_SIZE / 8];
+			let allocator = unsafe {
+				BuddyAllocator::new(
+					memory.as_mut_ptr() as *mut u8,
+					base_paddr,
+					num_pages * PAGE_SIZE,
+				)
+			};
+
+			TestArea {
+				_memory: memory,
+				allocator,
+			}
+		}
+	}
+
+	/// Base address of an area whose allocatable part, following the page
+	/// of free heads, is aligned to the largest block.
+	const BASE: usize = 0x1000_0000 - PAGE_SIZE;
+	const FIRST: usize = BASE + PAGE_SIZE;
+
+	#[test]
+	fn split_and_coalesce() {
+		let mut area = TestArea::new(BASE, 17);
+		let allocator = &mut area.allocator;
+		assert_eq!(allocator.num_total_pages(), 16);
+		assert_eq!(allocator.num_free_blocks()[4], 1);
+
+		assert_eq!(allocator.alloc_pages(0), Some(FIRST));
+		assert_eq!(allocator.num_free_pages(), 15);
+		assert_eq!(&allocator.num_free_blocks()[..5], &[1, 1, 1, 1, 0]);
+
+		assert_eq!(allocator.alloc_pages(0), Some(FIRST + PAGE_SIZE));
+		assert_eq!(allocator.alloc_pages(1), Some(FIRST + 2 * PAGE_SIZE));
+		assert_eq!(&allocator.num_free_blocks()[..5], &[0, 0, 1, 1, 0]);
+
+		allocator.free_pages(FIRST, 0);
+		assert_eq!(&allocator.num_free_blocks()[..5], &[1, 0, 1, 1, 0]);
+		allocator.free_pages(FIRST + 2 * PAGE_SIZE, 1);
+		assert_eq!(&allocator.num_free_blocks()[..5], &[1, 1, 1, 1, 0]);
+
+		// The last free page brings everything back together.
+		allocator.free_pages(FIRST + PAGE_SIZE, 0);
+		assert_eq!(&allocator.num_free_blocks()[..5], &[0, 0, 0, 0, 1]);
+		assert_eq!(allocator.num_free_pages(), 16);
+	}
+
+	#[test]
+	fn exhaust_and_refill() {
+		let mut area = TestArea::new(BASE, 64 + 1);
+		let allocator = &mut area.allocator;
+		let before = allocator.num_free_blocks();
+
+		let mut pages = Vec::new();
+		while let Some(paddr) = allocator.alloc_pages(0) {
+			assert!(allocator.includes(paddr));
+			assert!(!pages.contains(&paddr));
+			pages.push(paddr);
+		}
+
+		assert_eq!(pages.len(), 64);
+		assert_eq!(allocator.num_free_pages(), 0);
+		assert_eq!(allocator.alloc_pages(0), None);
+
+		// Free every other page first so that merging happens late.
+		for paddr in pages
+			.iter()
+			.step_by(2)
+			.chain(pages.iter().skip(1).step_by(2))
+		{
+			allocator.free_pages(*paddr, 0);
+		}
+
+		assert_eq!(allocator.num_free_pages(), 64);
+		assert_eq!(allocator.num_free_blocks(), before);
+	}
+
+	#[test]
+	fn blocks_are_naturally_aligned() {
+		// An area which isn't aligned to anything but pages.
+		let mut area = TestArea::new(0x123_4000, 100);
+		let allocator = &mut area.allocator;
+		let total = allocator.num_total_pages();
+
+		let mut num_pages = 0;
+		while let Some(paddr) = allocator.alloc_pages(2) {
+			assert_eq!(paddr % (4 * PAGE_SIZE), 0);
+			num_pages += 4;
+		}
+
+		// What's left are the odd pages at the edges of the area.
+		while allocator.alloc_pages(0).is_some() {
+			num_pages += 1;
+		}
+
+		assert_eq!(num_pages, total);
+		assert_eq!(allocator.num_free_pages(), 0);
+	}
+
+	#[test]
+	fn too_large_orders() {
+		let mut area = TestArea::new(BASE, 17);
+		assert_eq!(area.allocator.alloc_pages(5), None);
+		assert_eq!(area.allocator.alloc_pages(MAX_ORDER + 1), None);
+		assert_eq!(area.allocator.num_free_pages(), 16);
+	}
+}
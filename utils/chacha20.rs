@@ -0,0 +1,137 @@
+//! The ChaCha20 block function, as specified by RFC 8439.
+
+/// "expand 32-byte k".
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+pub const BLOCK_LEN: usize = 64;
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+	state[a] = state[a].wrapping_add(state[b]);
+	state[d] = (state[d] ^ state[a]).rotate_left(16);
+	state[c] = state[c].wrapping_add(state[d]);
+	state[b] = (state[b] ^ state[c]).rotate_left(12);
+	state[a] = state[a].wrapping_add(state[b]);
+	state[d] = (state[d] ^ state[a]).rotate_left(8);
+	state[c] = state[c].wrapping_add(state[d]);
+	state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Computes the block `counter` of the keystream of `key` and `nonce`.
+pub fn block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u8; BLOCK_LEN] {
+	let mut initial = [0u32; 16];
+	initial[..4].copy_from_slice(&CONSTANTS);
+	initial[4..12].copy_from_slice(key);
+	initial[12] = counter;
+	initial[13..].copy_from_slice(nonce);
+
+	let mut state = initial;
+	for _ in 0..10 {
+		quarter_round(&mut state, 0, 4, 8, 12);
+		quarter_round(&mut state, 1, 5, 9, 13);
+		quarter_round(&mut state, 2, 6, 10, 14);
+		quarter_round(&mut state, 3, 7, 11, 15);
+		quarter_round(&mut state, 0, 5, 10, 15);
+		quarter_round(&mut state, 1, 6, 11, 12);
+		quarter_round(&mut state, 2, 7, 8, 13);
+		quarter_round(&mut state, 3, 4, 9, 14);
+	}
+
+	let mut block = [0u8; BLOCK_LEN];
+	for (i, bytes) in block.chunks_exact_mut(4).enumerate() {
+		bytes.copy_from_slice(&state[i].wrapping_add(initial[i]).to_le_bytes());
+	}
+
+	block
+}
+
+/// Fills `buf` with the keystream of `key` and `nonce`, starting from the
+/// block `counter`. Returns the counter of the first block left unused.
+pub fn keystream(key: &[u32; 8], mut counter: u32, nonce: &[u32; 3], buf: &mut [u8]) -> u32 {
+	for chunk in buf.chunks_mut(BLOCK_LEN) {
+		let block = block(key, counter, nonce);
+		chunk.copy_from_slice(&block[..chunk.len()]);
+		counter = counter.wrapping_add(1);
+	}
+
+	counter
+}
+
+/// Reads a key or a nonce from its little-endian serialization.
+pub fn words_from_bytes<const N: usize>(bytes: &[u8]) -> [u32; N] {
+	let mut words = [0u32; N];
+	for (word, bytes) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+		*word = u32::from_le_bytes(bytes.try_into().unwrap());
+	}
+
+	words
+}
+
+#[cfg(all(test, not(feature = "no_std")))]
+mod tests {
+	use super::*;
+
+	/// The key of the examples of RFC 8439: 00 01 02 ... 1f.
+	fn test_key() -> [u32; 8] {
+		let bytes: Vec<u8> = (0..32).collect();
+		words_from_bytes(&bytes)
+	}
+
+	/// RFC 8439, section 2.3.2.
+	#[test]
+	fn test_block() {
+		let nonce = words_from_bytes(&[
+			0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00,
+		]);
+		let expected = [
+			0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71,
+			0xc4, 0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03, 0x04, 0x22, 0xaa, 0x9a, 0xc3, 0xd4,
+			0x6c, 0x4e, 0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09, 0x14, 0xc2, 0xd7, 0x05, 0xd9,
+			0x8b, 0x02, 0xa2, 0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9, 0xcb, 0xd0, 0x83, 0xe8,
+			0xa2, 0x50, 0x3c, 0x4e,
+		];
+		assert_eq!(block(&test_key(), 1, &nonce), expected);
+	}
+
+	/// RFC 8439, section 2.4.2: the keystream is what the plaintext is
+	/// XORed with, and it spans two blocks and a bit.
+	#[test]
+	fn test_keystream() {
+		let nonce = words_from_bytes(&[
+			0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00,
+		]);
+		let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+			only one tip for the future, sunscreen would be it.";
+		let ciphertext = [
+			0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d, 0x69,
+			0x81, 0xe9, 0x7e, 0x7a, 0xec, 0x1d, 0x43, 0x60, 0xc2, 0x0a, 0x27, 0xaf, 0xcc, 0xfd, 0x9f,
+			0xae, 0x0b, 0xf9, 0x1b, 0x65, 0xc5, 0x52, 0x47, 0x33, 0xab, 0x8f, 0x59, 0x3d, 0xab, 0xcd,
+			0x62, 0xb3, 0x57, 0x16, 0x39, 0xd6, 0x24, 0xe6, 0x51, 0x52, 0xab, 0x8f, 0x53, 0x0c, 0x35,
+			0x9f, 0x08, 0x61, 0xd8, 0x07, 0xca, 0x0d, 0xbf, 0x50, 0x0d, 0x6a, 0x61, 0x56, 0xa3, 0x8e,
+			0x08, 0x8a, 0x22, 0xb6, 0x5e, 0x52, 0xbc, 0x51, 0x4d, 0x16, 0xcc, 0xf8, 0x06, 0x81, 0x8c,
+			0xe9, 0x1a, 0xb7, 0x79, 0x37, 0x36, 0x5a, 0xf9, 0x0b, 0xbf, 0x74, 0xa3, 0x5b, 0xe6, 0xb4,
+			0x0b, 0x8e, 0xed, 0xf2, 0x78, 0x5e, 0x42, 0x87, 0x4d,
+		];
+
+		let mut stream = [0u8; 114];
+		assert_eq!(keystream(&test_key(), 1, &nonce, &mut stream), 3);
+		let encrypted: Vec<u8> = plaintext
+			.iter()
+			.zip(stream.iter())
+			.map(|(p, k)| p ^ k)
+			.collect();
+		assert_eq!(encrypted, ciphertext);
+	}
+
+	/// RFC 8439, appendix A.1, test vector #1: the all-zero key.
+	#[test]
+	fn test_zero_key() {
+		let expected = [
+			0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86, 0xbd,
+			0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc, 0x8b, 0x77,
+			0x0d, 0xc7, 0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d, 0x77, 0x24, 0xe0, 0x3f, 0xb8,
+			0xd8, 0x4a, 0x37, 0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c, 0xc3, 0x87, 0xb6, 0x69,
+			0xb2, 0xee, 0x65, 0x86,
+		];
+		assert_eq!(block(&[0; 8], 0, &[0; 3]), expected);
+	}
+}
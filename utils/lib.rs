@@ -6,10 +6,11 @@ extern crate alloc;
 
 pub mod alignment;
 pub mod bitmap;
-pub mod bitmap_allocator;
+pub mod buddy_allocator;
 pub mod bump_allocator;
 pub mod byte_size;
 pub mod bytes_parser;
+pub mod chacha20;
 pub mod lazy;
 pub mod once;
 pub mod ring_buffer;
@@ -82,7 +82,7 @@ impl<T, const CAP: usize> RingBuffer<T, CAP> {
         let range = if self.rp < self.wp {
             self.rp..min(self.rp + len, self.wp)
         } else {
-            self.wp..min(self.wp + len, CAP)
+            self.rp..min(self.rp + len, CAP)
         };
 
         self.rp = (self.rp + range.len()) % CAP;
@@ -156,5 +156,9 @@ mod tests {
         assert_eq!(rb.slice(0..4), b"23c1");
         assert_eq!(rb.wp, 2);
         assert_eq!(rb.rp, 2);
+
+        assert_eq!(rb.pop_slice(4), Some("c1".as_bytes()));
+        assert_eq!(rb.pop_slice(4), Some("23".as_bytes()));
+        assert_eq!(rb.pop_slice(4), None);
     }
 }